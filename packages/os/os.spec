@@ -24,6 +24,7 @@ Source3: eni-max-pods
 Source5: updog-toml
 Source6: metricdog-toml
 Source7: host-ctr-toml
+Source8: healthdog-toml
 
 # 1xx sources: systemd units
 Source100: apiserver.service
@@ -38,6 +39,13 @@ Source111: metricdog.service
 Source112: metricdog.timer
 Source113: send-boot-success.service
 Source114: bootstrap-containers@.service
+Source115: updog-auto-update.service
+Source116: updog-auto-update.timer
+Source117: healthdog-check-boot.service
+Source118: drift-check.service
+Source119: drift-check.timer
+Source120: certdog-check.service
+Source121: certdog-check.timer
 
 # 2xx sources: tmpfilesd configs
 Source200: migration-tmpfiles.conf
@@ -45,6 +53,8 @@ Source201: host-containers-tmpfiles.conf
 Source202: thar-be-updates-tmpfiles.conf
 Source203: bootstrap-containers-tmpfiles.conf
 Source204: netdog-tmpfiles.conf
+Source205: drift-tmpfiles.conf
+Source206: certdog-tmpfiles.conf
 
 # 3xx sources: udev rules
 Source300: ephemeral-storage.rules
@@ -59,6 +69,7 @@ Requires: %{_cross_os}certdog
 Requires: %{_cross_os}early-boot-config
 Requires: %{_cross_os}ghostdog
 Requires: %{_cross_os}growpart
+Requires: %{_cross_os}healthdog
 Requires: %{_cross_os}host-containers
 Requires: %{_cross_os}logdog
 Requires: %{_cross_os}metricdog
@@ -182,6 +193,12 @@ Summary: Bottlerocket GPT priority querier/switcher
 %description -n %{_cross_os}signpost
 %{summary}.
 
+%package -n %{_cross_os}healthdog
+Summary: Bottlerocket boot health checker
+Requires: %{_cross_os}signpost
+%description -n %{_cross_os}healthdog
+%{summary}.
+
 %package -n %{_cross_os}updog
 Summary: Bottlerocket updater CLI
 %description -n %{_cross_os}updog
@@ -297,6 +314,7 @@ echo "** Output from non-static builds:"
     -p settings-committer \
     -p migrator \
     -p signpost \
+    -p healthdog \
     -p updog \
     -p logdog \
     -p metricdog \
@@ -336,7 +354,7 @@ for p in \
   thar-be-settings thar-be-updates servicedog host-containers \
   storewolf settings-committer \
   migrator prairiedog certdog \
-  signpost updog metricdog logdog \
+  signpost healthdog updog metricdog logdog \
   ghostdog bootstrap-containers \
 %if "%{_cross_variant}" == "aws-ecs-1"
   ecs-settings-applier \
@@ -370,6 +388,15 @@ for version_path in %{_builddir}/sources/api/migration/migrations/v[0-9]*; do
     [ -e "${migration_path}" ] || continue
 
     version="${version_path##*/}"
+    # Declarative migrations are TOML files that migrator runs itself, so we install them as-is.
+    if [ -f "${migration_path}" ]; then
+      [ "${migration_path##*.}" = "toml" ] || continue
+      migration_file_name="${migration_path##*/}"
+      target_path="%{buildroot}%{_cross_datadir}/migrations/migrate_${version}_${migration_file_name}"
+      install -m 0444 "${migration_path}" "${target_path}"
+      continue
+    fi
+
     crate_name="${migration_path##*/}"
     migration_binary_name="migrate_${version}_${crate_name#migrate-}"
     built_path="${HOME}/.cache/.static/%{__cargo_target_static}/release/${crate_name}"
@@ -395,13 +422,14 @@ install -d %{buildroot}%{_cross_datadir}/updog
 install -p -m 0644 %{_cross_repo_root_json} %{buildroot}%{_cross_datadir}/updog
 
 install -d %{buildroot}%{_cross_templatedir}
-install -p -m 0644 %{S:5} %{S:6} %{S:7} %{buildroot}%{_cross_templatedir}
+install -p -m 0644 %{S:5} %{S:6} %{S:7} %{S:8} %{buildroot}%{_cross_templatedir}
 
 install -d %{buildroot}%{_cross_unitdir}
 install -p -m 0644 \
   %{S:100} %{S:101} %{S:102} %{S:103} %{S:105} \
   %{S:106} %{S:107} %{S:110} %{S:111} %{S:112} \
-  %{S:113} %{S:114} \
+  %{S:113} %{S:114} %{S:115} %{S:116} %{S:117} \
+  %{S:118} %{S:119} %{S:120} %{S:121} \
   %{buildroot}%{_cross_unitdir}
 
 install -d %{buildroot}%{_cross_tmpfilesdir}
@@ -410,6 +438,8 @@ install -p -m 0644 %{S:201} %{buildroot}%{_cross_tmpfilesdir}/host-containers.co
 install -p -m 0644 %{S:202} %{buildroot}%{_cross_tmpfilesdir}/thar-be-updates.conf
 install -p -m 0644 %{S:203} %{buildroot}%{_cross_tmpfilesdir}/bootstrap-containers.conf
 install -p -m 0644 %{S:204} %{buildroot}%{_cross_tmpfilesdir}/netdog.conf
+install -p -m 0644 %{S:205} %{buildroot}%{_cross_tmpfilesdir}/drift.conf
+install -p -m 0644 %{S:206} %{buildroot}%{_cross_tmpfilesdir}/certdog.conf
 
 install -d %{buildroot}%{_cross_udevrulesdir}
 install -p -m 0644 %{S:300} %{buildroot}%{_cross_udevrulesdir}/80-ephemeral-storage.rules
@@ -453,6 +483,9 @@ install -p -m 0644 %{S:300} %{buildroot}%{_cross_udevrulesdir}/80-ephemeral-stor
 %files -n %{_cross_os}thar-be-settings
 %{_cross_bindir}/thar-be-settings
 %{_cross_unitdir}/settings-applier.service
+%{_cross_unitdir}/drift-check.service
+%{_cross_unitdir}/drift-check.timer
+%{_cross_tmpfilesdir}/drift.conf
 
 %files -n %{_cross_os}thar-be-updates
 %{_cross_bindir}/thar-be-updates
@@ -492,13 +525,21 @@ install -p -m 0644 %{S:300} %{buildroot}%{_cross_udevrulesdir}/80-ephemeral-stor
 
 %files -n %{_cross_os}signpost
 %{_cross_bindir}/signpost
+
+%files -n %{_cross_os}healthdog
+%{_cross_bindir}/healthdog
+%dir %{_cross_templatedir}
+%{_cross_templatedir}/healthdog-toml
 %{_cross_unitdir}/mark-successful-boot.service
+%{_cross_unitdir}/healthdog-check-boot.service
 
 %files -n %{_cross_os}updog
 %{_cross_bindir}/updog
 %{_cross_datadir}/updog
 %dir %{_cross_templatedir}
 %{_cross_templatedir}/updog-toml
+%{_cross_unitdir}/updog-auto-update.service
+%{_cross_unitdir}/updog-auto-update.timer
 
 %files -n %{_cross_os}metricdog
 %{_cross_bindir}/metricdog
@@ -538,6 +579,9 @@ install -p -m 0644 %{S:300} %{buildroot}%{_cross_udevrulesdir}/80-ephemeral-stor
 
 %files -n %{_cross_os}certdog
 %{_cross_bindir}/certdog
+%{_cross_unitdir}/certdog-check.service
+%{_cross_unitdir}/certdog-check.timer
+%{_cross_tmpfilesdir}/certdog.conf
 
 %files -n %{_cross_os}bootstrap-containers
 %{_cross_bindir}/bootstrap-containers
@@ -15,6 +15,7 @@ Source200: motd.template
 Source201: proxy-env
 Source202: hostname-env
 Source203: hosts.template
+Source204: modprobe-acl.template
 
 Source1000: eth0.xml
 Source1001: multi-user.target
@@ -35,6 +36,8 @@ Source1012: opt-cni-bin.mount
 # CD-ROM mount & associated udev rules
 Source1015: media-cdrom.mount
 Source1016: mount-cdrom.rules
+Source1017: media-nocloud.mount
+Source1018: mount-nocloud.rules
 
 # Mounts that require build-time edits.
 Source1020: var-lib-kernel-devel-lower.mount.in
@@ -118,7 +121,7 @@ install -d %{buildroot}%{_cross_unitdir}
 install -p -m 0644 \
   %{S:1001} %{S:1002} %{S:1003} %{S:1004} %{S:1005} \
   %{S:1006} %{S:1007} %{S:1008} %{S:1009} %{S:1010} %{S:1011} %{S:1012} \
-  %{S:1015} %{S:1040} %{S:1041} %{S:1060} %{S:1061} %{S:1062} %{S:1080} \
+  %{S:1015} %{S:1017} %{S:1040} %{S:1041} %{S:1060} %{S:1061} %{S:1062} %{S:1080} \
   %{buildroot}%{_cross_unitdir}
 
 install -d %{buildroot}%{_cross_unitdir}/systemd-tmpfiles-setup.service.d
@@ -144,9 +147,11 @@ install -p -m 0644 %{S:200} %{buildroot}%{_cross_templatedir}/motd
 install -p -m 0644 %{S:201} %{buildroot}%{_cross_templatedir}/proxy-env
 install -p -m 0644 %{S:202} %{buildroot}%{_cross_templatedir}/hostname-env
 install -p -m 0644 %{S:203} %{buildroot}%{_cross_templatedir}/hosts
+install -p -m 0644 %{S:204} %{buildroot}%{_cross_templatedir}/modprobe-acl
 
 install -d %{buildroot}%{_cross_udevrulesdir}
 install -p -m 0644 %{S:1016} %{buildroot}%{_cross_udevrulesdir}/61-mount-cdrom.rules
+install -p -m 0644 %{S:1018} %{buildroot}%{_cross_udevrulesdir}/61-mount-nocloud.rules
 
 ln -s %{_cross_unitdir}/preconfigured.target %{buildroot}%{_cross_unitdir}/default.target
 
@@ -174,6 +179,7 @@ ln -s %{_cross_unitdir}/preconfigured.target %{buildroot}%{_cross_unitdir}/defau
 %{_cross_unitdir}/etc-cni.mount
 %{_cross_unitdir}/opt-cni-bin.mount
 %{_cross_unitdir}/media-cdrom.mount
+%{_cross_unitdir}/media-nocloud.mount
 %{_cross_unitdir}/*-lower.mount
 %{_cross_unitdir}/*-kernels.mount
 %{_cross_unitdir}/*-licenses.mount
@@ -187,6 +193,8 @@ ln -s %{_cross_unitdir}/preconfigured.target %{buildroot}%{_cross_unitdir}/defau
 %{_cross_templatedir}/proxy-env
 %{_cross_templatedir}/hostname-env
 %{_cross_templatedir}/hosts
+%{_cross_templatedir}/modprobe-acl
 %{_cross_udevrulesdir}/61-mount-cdrom.rules
+%{_cross_udevrulesdir}/61-mount-nocloud.rules
 
 %changelog
@@ -5,7 +5,7 @@ Current version: {{version}}
 ## apiclient binary
 
 The `apiclient` binary provides high-level methods to interact with the Bottlerocket API.
-There's a [set](#set-mode) subcommand for changing settings, an [update](#update-mode) subcommand for updating the host, and an [exec](#exec-mode) subcommand for running commands in host containers.
+There's a [set](#set-mode) subcommand for changing settings, a [get](#get-mode) subcommand for reading them, an [update](#update-mode) subcommand for updating the host, and an [exec](#exec-mode) subcommand for running commands in host containers.
 There's also a low-level [raw](#raw-mode) subcommand for direct interaction with the HTTP API.
 
 It talks to the Bottlerocket socket by default.
@@ -13,6 +13,12 @@ It can be pointed to another socket using `--socket-path`, for example for local
 
 The most important use is probably checking your current settings:
 
+```
+apiclient get settings
+```
+
+You can also use the low-level [raw](#raw-mode) subcommand to request all settings:
+
 ```
 apiclient -u /settings
 ```
@@ -76,6 +82,61 @@ You can use JSON form to set it:
 apiclient set --json '{"motd": "42"}'
 ```
 
+### Apply mode
+
+This applies settings from TOML or JSON files, in the same format as user data.
+Give the URIs of the files, or "-" (or nothing) to read from standard input, and the settings are applied in a single transaction:
+
+```
+apiclient apply file:///tmp/settings.toml https://example.com/settings.toml
+```
+
+To check the settings first without changing anything, add `--dry-run`.
+apiclient will print each invalid setting by name with the reason it's invalid, and the services and configuration files that applying the settings would affect.
+
+```
+apiclient apply --dry-run file:///tmp/settings.toml
+```
+
+### Get mode
+
+This allows you to see settings and other data from the API, like OS information.
+
+Give the names of the settings you want to see.
+A name can be a single setting, or a prefix of whole name segments to see everything under it:
+
+```
+apiclient get settings.motd settings.kubernetes os
+```
+
+As with `set`, the "settings." prefix is optional, so `apiclient get kubernetes` works too.
+Other parts of the API model can be requested with the `os`, `services`, and `configuration-files` prefixes, for example `apiclient get services.containerd`.
+If you don't give any names, you'll see everything.
+
+The output is in JSON by default, and always includes the full path to the requested data, so output for several names can be easily combined.
+You can request other output formats with `-f` or `--format`:
+* `json`: a JSON object, like the API returns.
+* `toml`: a TOML document, in the same format as user data.
+* `key-value`: one `key=value` line per setting, which is convenient in scripts.  Values are in JSON form, so strings are quoted.
+
+```
+apiclient get --format key-value settings.kernel
+```
+
+### Watch mode
+
+This prints settings changes as they're committed, so you don't have to poll for them.
+Each change is printed as a single line of JSON, with the generation created by the commit, the name of the committed transaction, and the changed settings with their new values.
+Removed settings have a null value.
+
+```
+apiclient watch --prefix kubernetes.node-labels
+```
+
+The `--prefix` option limits output to settings starting with the given prefix; the "settings." prefix is optional.
+apiclient keeps watching until the server closes the connection.
+The server disconnects clients that fall far behind, so if you need to see every change, re-read settings after reconnecting.
+
 ### Update mode
 
 To start, you can check what updates are available:
@@ -93,8 +154,19 @@ apiclient update apply
 ```
 
 This downloads and writes the update to the alternate partition set, then marks it as active.
+While it waits, apiclient shows which part of the update is being downloaded or written, how much of it is complete, and how fast it's going.
 The next time you reboot, for example with `apiclient reboot`, the update will take effect.
 
+You can also download the update ahead of time, without writing it to disk:
+
+```
+apiclient update download
+```
+
+The update status then shows the "Downloaded" state, and a later `apiclient update apply` writes the update from the download cache, without network access.
+This lets you download updates whenever it's convenient and apply them during a maintenance window.
+If a later check for updates chooses a different version, for example because `settings.updates.version-lock` changed, the state goes back to "Available" until that version is downloaded.
+
 If you're confident that you want to update immediately to the latest version, you can do all of the above in one step:
 
 ```
@@ -1,6 +1,9 @@
 //! This module allows application of settings from URIs or stdin.  The inputs are expected to be
 //! TOML settings files, in the same format as user data, or the JSON equivalent.  The inputs are
 //! pulled and applied to the API server in a single transaction.
+//!
+//! The inputs can instead be checked without applying them, using `dry_run`; the API server
+//! reports every invalid setting and what applying the valid ones would affect.
 
 use crate::rando;
 use futures::future::{join, ready, TryFutureExt};
@@ -17,20 +20,7 @@ pub async fn apply<P>(socket_path: P, input_sources: Vec<String>) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    // We want to retrieve URIs in parallel because they're arbitrary and could be slow.  First, we
-    // build a list of request futures, and we store the source of the data with the future for
-    // inclusion in later error messages.
-    let mut get_requests = Vec::with_capacity(input_sources.len());
-    for input_source in &input_sources {
-        let get_future = get(input_source);
-        let info_future = ready(input_source);
-        get_requests.push(join(info_future, get_future));
-    }
-
-    // Stream out the requests and await responses (in order).
-    let get_request_stream = stream::iter(get_requests).buffered(4);
-    let get_responses: Vec<(&String, Result<String>)> = get_request_stream.collect().await;
-
+    let get_responses = get_all(&input_sources).await;
     // Reformat the responses to (model-verified) JSON we can send to the API.
     let mut changes = Vec::with_capacity(get_responses.len());
     for (input_source, get_response) in get_responses {
@@ -66,6 +56,55 @@ where
     Ok(())
 }
 
+/// Reads settings like `apply`, but rather than committing them, asks the API server to check
+/// them against the model.  Returns the server's report for each input source, keyed by source.
+/// Nothing is changed on the system.
+pub async fn dry_run<P>(
+    socket_path: P,
+    input_sources: Vec<String>,
+) -> Result<serde_json::Map<String, serde_json::Value>>
+where
+    P: AsRef<Path>,
+{
+    let get_responses = get_all(&input_sources).await;
+
+    let mut reports = serde_json::Map::new();
+    for (input_source, get_response) in get_responses {
+        let response = get_response?;
+        // Don't check the model locally; we want the server to report every problem at once.
+        let json = serde_json::to_string(&parse_change(&response, input_source)?)
+            .context(error::JsonSerialize { input_source })?;
+
+        let uri = "/settings/validate";
+        let method = "POST";
+        let (_status, body) = crate::raw_request(&socket_path, uri, method, Some(json))
+            .await
+            .context(error::Validate { input_source, uri })?;
+        let report = serde_json::from_str(&body).context(error::ResponseJson { uri })?;
+        reports.insert(input_source.clone(), report);
+    }
+
+    Ok(reports)
+}
+
+/// Retrieves all of the given source locations, returning the result for each along with its
+/// source.
+async fn get_all(input_sources: &[String]) -> Vec<(&String, Result<String>)> {
+    // We want to retrieve URIs in parallel because they're arbitrary and could be slow.  First, we
+    // build a list of request futures, and we store the source of the data with the future for
+    // inclusion in later error messages.
+    let mut get_requests = Vec::with_capacity(input_sources.len());
+    for input_source in input_sources {
+        let get_future = get(input_source);
+        let info_future = ready(input_source);
+        get_requests.push(join(info_future, get_future));
+    }
+
+    // Stream out the requests and await responses (in order).
+    let get_request_stream = stream::iter(get_requests).buffered(4);
+    get_request_stream.collect().await
+}
+
 /// Retrieves the given source location and returns the result in a String.
 async fn get<S>(input_source: S) -> Result<String>
 where
@@ -112,6 +151,19 @@ where
 /// Takes a string of TOML or JSON settings data, verifies that it fits the model, and reserializes
 /// it to JSON for sending to the API.
 fn format_change(input: &str, input_source: &str) -> Result<String> {
+    let json_inner = parse_change(input, input_source)?;
+
+    // Deserialize into the model to confirm the settings are valid.
+    let _settings = model::Settings::deserialize(&json_inner)
+        .context(error::ModelDeserialize { input_source })?;
+
+    // Return JSON text we can send to the API.
+    serde_json::to_string(&json_inner).context(error::JsonSerialize { input_source })
+}
+
+/// Takes a string of TOML or JSON settings data and returns the JSON value of the settings inside
+/// its "settings" table, without checking them against the model.
+fn parse_change(input: &str, input_source: &str) -> Result<serde_json::Value> {
     // Try to parse the input as (arbitrary) TOML.  If that fails, try to parse it as JSON.
     let mut json_val = match toml::from_str::<toml::Value>(&input) {
         Ok(toml_val) => {
@@ -135,16 +187,9 @@ fn format_change(input: &str, input_source: &str) -> Result<String> {
     let json_object = json_val
         .as_object_mut()
         .context(error::ModelType { input_source })?;
-    let json_inner = json_object
+    json_object
         .remove("settings")
-        .context(error::MissingSettings { input_source })?;
-
-    // Deserialize into the model to confirm the settings are valid.
-    let _settings = model::Settings::deserialize(&json_inner)
-        .context(error::ModelDeserialize { input_source })?;
-
-    // Return JSON text we can send to the API.
-    serde_json::to_string(&json_inner).context(error::JsonSerialize { input_source })
+        .context(error::MissingSettings { input_source })
 }
 
 mod error {
@@ -227,6 +272,12 @@ mod error {
             source: reqwest::Error,
         },
 
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+
         #[snafu(display("Failed to read standard input: {}", source))]
         StdinRead { source: std::io::Error },
 
@@ -245,6 +296,18 @@ mod error {
             input_source: String,
             source: url::ParseError,
         },
+
+        #[snafu(display(
+            "Failed to validate settings from '{}' with '{}': {}",
+            input_source,
+            uri,
+            source
+        ))]
+        Validate {
+            input_source: String,
+            uri: String,
+            source: crate::Error,
+        },
     }
 }
 pub use error::Error;
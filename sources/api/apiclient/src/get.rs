@@ -0,0 +1,346 @@
+//! This module allows reading settings and other API data by name.  Names are dotted datastore
+//! keys, like `settings.motd`, and may name a whole subtree, like `settings.kubernetes`.  The
+//! requested data is fetched from the API and returned as a single JSON object that mirrors the
+//! structure of the full API model, so the results of several names can be merged together.
+
+use datastore::{Key, KeyType};
+use serde_json::{Map, Value};
+use snafu::{ensure, ResultExt};
+use std::path::Path;
+use url::form_urlencoded;
+
+/// The top-level sections of the API model that can be requested by name.
+const SETTINGS: &str = "settings";
+const SERVICES: &str = "services";
+const CONFIGURATION_FILES: &str = "configuration-files";
+const OS: &str = "os";
+
+/// The output formats we can render results in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Format {
+    /// Pretty-printed JSON object.
+    Json,
+    /// TOML document, in the same format as user data.
+    Toml,
+    /// One `key=value` line per populated key, with JSON-formatted values.
+    KeyValue,
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "key-value" => Ok(Format::KeyValue),
+            _ => error::InvalidFormat { format: s }.fail(),
+        }
+    }
+}
+
+/// Parses a user-supplied name into the segments of its key.  If the name doesn't start with a
+/// known section of the API model, it's assumed to be a setting and "settings" is prepended.
+pub fn parse_name<S>(name: S) -> Result<Vec<String>>
+where
+    S: AsRef<str>,
+{
+    let name = name.as_ref();
+    let key = Key::new(KeyType::Data, name).context(error::InvalidName { name })?;
+    let mut segments = key.segments().clone();
+
+    match segments[0].as_ref() {
+        SETTINGS | SERVICES | CONFIGURATION_FILES | OS => {}
+        _ => segments.insert(0, SETTINGS.to_string()),
+    }
+
+    Ok(segments)
+}
+
+/// Fetches the data for each of the given names from the API and merges them into a single JSON
+/// object, keyed the same way as the full API model.  Names with no populated data are omitted.
+/// If no names are given, the full API model is returned.
+pub async fn get_names<P, S>(socket_path: P, names: &[S]) -> Result<Value>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    if names.is_empty() {
+        return get_json(&socket_path, "/").await;
+    }
+
+    let mut result = Map::new();
+    // These are quick local requests, so don't add the complexity of making them concurrent.
+    for name in names {
+        let segments = parse_name(name)?;
+        let uri = uri_for(&segments)?;
+        let response = get_json(&socket_path, &uri).await?;
+
+        // The response is rooted at the top-level section we requested, so find the requested
+        // subtree beneath it.
+        if let Some(value) = find_path(&response, &segments[1..]) {
+            insert_path(&mut result, &segments, value.clone());
+        }
+    }
+
+    Ok(Value::Object(result))
+}
+
+/// Renders the given JSON value in the requested format.
+pub fn format(value: &Value, format: Format) -> Result<String> {
+    match format {
+        Format::Json => Ok(format!("{:#}", value)),
+        Format::Toml => {
+            // Convert to a TOML Value first, which knows to serialize tables after scalars.
+            let toml_value = toml::Value::try_from(value).context(error::TomlSerialize)?;
+            toml::to_string(&toml_value).context(error::TomlSerialize)
+        }
+        Format::KeyValue => {
+            let mut lines = Vec::new();
+            flatten(value, &mut Vec::new(), &mut lines)?;
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+/// Returns the API URI that serves the data for the given key segments.  Where the API supports
+/// it, we ask the server to filter, but the response is still filtered client-side by segment,
+/// because server-side prefixes are plain string prefixes.  Names in the query are percent-encoded,
+/// since they can contain characters like '"' and '/'.
+fn uri_for(segments: &[String]) -> Result<String> {
+    let uri = match (segments[0].as_ref(), segments.get(1)) {
+        (SETTINGS, None) => "/settings".to_string(),
+        (SETTINGS, Some(_)) => {
+            // The settings prefix query assumes "settings." so we don't include it.
+            let prefix =
+                Key::from_segments(KeyType::Data, &segments[1..]).context(error::InvalidName {
+                    name: segments.join("."),
+                })?;
+            format!("/settings?{}", query("prefix", prefix.name()))
+        }
+        (SERVICES, None) => "/services".to_string(),
+        (SERVICES, Some(name)) => format!("/services?{}", query("names", name)),
+        (CONFIGURATION_FILES, None) => "/configuration-files".to_string(),
+        (CONFIGURATION_FILES, Some(name)) => {
+            format!("/configuration-files?{}", query("names", name))
+        }
+        (OS, _) => "/os".to_string(),
+        (other, _) => return error::UnknownSection { section: other }.fail(),
+    };
+    Ok(uri)
+}
+
+/// Returns a query string with the given parameter, percent-encoded.
+fn query(key: &str, value: &str) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .append_pair(key, value)
+        .finish()
+}
+
+/// Makes a GET request to the given URI and parses the response as JSON.
+async fn get_json<P>(socket_path: P, uri: &str) -> Result<Value>
+where
+    P: AsRef<Path>,
+{
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, uri, method, None)
+        .await
+        .context(error::Request { uri, method })?;
+    serde_json::from_str(&body).context(error::ResponseJson { uri })
+}
+
+/// Walks the given JSON value along the given key segments, returning the value found there.
+fn find_path<'a>(value: &'a Value, segments: &[String]) -> Option<&'a Value> {
+    let mut current = value;
+    for segment in segments {
+        current = current.as_object()?.get(segment)?;
+    }
+    Some(current)
+}
+
+/// Inserts the given value into the given JSON object at the given key segments, creating any
+/// intermediate objects as needed.
+fn insert_path(target: &mut Map<String, Value>, segments: &[String], value: Value) {
+    let (last, parents) = match segments.split_last() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let mut current = target;
+    for segment in parents {
+        let entry = current
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if !entry.is_object() {
+            *entry = Value::Object(Map::new());
+        }
+        current = entry
+            .as_object_mut()
+            .expect("value was just made an object");
+    }
+    current.insert(last.clone(), value);
+}
+
+/// Recursively turns the given JSON value into `key=value` lines, using datastore key naming so
+/// that segments containing dots are quoted.
+fn flatten(value: &Value, path: &mut Vec<String>, lines: &mut Vec<String>) -> Result<()> {
+    match value {
+        Value::Object(map) => {
+            for (name, inner) in map {
+                path.push(name.clone());
+                flatten(inner, path, lines)?;
+                path.pop();
+            }
+        }
+        scalar => {
+            ensure!(!path.is_empty(), error::NotAnObject);
+            let key = Key::from_segments(KeyType::Data, &path[..]).context(error::InvalidName {
+                name: path.join("."),
+            })?;
+            let value = serde_json::to_string(scalar).context(error::JsonSerialize)?;
+            lines.push(format!("{}={}", key.name(), value));
+        }
+    }
+    Ok(())
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility = "pub(super)")]
+    pub enum Error {
+        #[snafu(display(
+            "Invalid output format '{}', expected one of: json, toml, key-value",
+            format
+        ))]
+        InvalidFormat { format: String },
+
+        #[snafu(display("Invalid name '{}': {}", name, source))]
+        InvalidName {
+            name: String,
+            source: datastore::Error,
+        },
+
+        #[snafu(display("Failed to serialize JSON: {}", source))]
+        JsonSerialize { source: serde_json::Error },
+
+        #[snafu(display("Response is not a JSON object"))]
+        NotAnObject,
+
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            source: crate::Error,
+        },
+
+        #[snafu(display("Response from '{}' was not valid JSON: {}", uri, source))]
+        ResponseJson {
+            uri: String,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to serialize TOML: {}", source))]
+        TomlSerialize { source: toml::ser::Error },
+
+        #[snafu(display("Unknown section of the API model: '{}'", section))]
+        UnknownSection { section: String },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_name_adds_settings() {
+        assert_eq!(parse_name("motd").unwrap(), vec!["settings", "motd"]);
+        assert_eq!(
+            parse_name("settings.kubernetes").unwrap(),
+            vec!["settings", "kubernetes"]
+        );
+        assert_eq!(parse_name("os").unwrap(), vec!["os"]);
+        assert_eq!(
+            parse_name("services.containerd").unwrap(),
+            vec!["services", "containerd"]
+        );
+        assert_eq!(
+            parse_name(r#"kubernetes.node-labels."my.label""#).unwrap(),
+            vec!["settings", "kubernetes", "node-labels", "my.label"]
+        );
+    }
+
+    #[test]
+    fn parse_name_invalid() {
+        parse_name("settings..motd").unwrap_err();
+    }
+
+    #[test]
+    fn uris() {
+        let uri = |name| uri_for(&parse_name(name).unwrap()).unwrap();
+        assert_eq!(uri("settings"), "/settings");
+        assert_eq!(
+            uri("settings.kubernetes.cluster-name"),
+            "/settings?prefix=kubernetes.cluster-name"
+        );
+        assert_eq!(
+            uri(r#"settings.kubernetes.node-labels."example.com/role""#),
+            "/settings?prefix=kubernetes.node-labels.%22example.com%2Frole%22"
+        );
+        assert_eq!(uri("services.containerd"), "/services?names=containerd");
+        assert_eq!(uri("configuration-files"), "/configuration-files");
+        assert_eq!(uri("os.arch"), "/os");
+    }
+
+    #[test]
+    fn find_and_insert() {
+        let response = json!({"kubernetes": {"cluster-name": "x", "cluster-name2": "y"}});
+        let segments = parse_name("kubernetes.cluster-name").unwrap();
+        let found = find_path(&response, &segments[1..]).unwrap();
+
+        let mut result = Map::new();
+        insert_path(&mut result, &segments, found.clone());
+        assert_eq!(
+            Value::Object(result),
+            json!({"settings": {"kubernetes": {"cluster-name": "x"}}})
+        );
+
+        assert!(find_path(&response, &["missing".to_string()]).is_none());
+    }
+
+    #[test]
+    fn format_key_value() {
+        let value = json!({"settings": {
+            "motd": "hi",
+            "kubernetes": {"node-labels": {"my.label": "a"}},
+            "kernel": {"lockdown": "none"},
+            "ntp": {"time-servers": ["a", "b"]},
+        }});
+        let output = format(&value, Format::KeyValue).unwrap();
+        let mut lines: Vec<_> = output.lines().collect();
+        lines.sort_unstable();
+        assert_eq!(
+            lines,
+            vec![
+                r#"settings.kernel.lockdown="none""#,
+                r#"settings.kubernetes.node-labels."my.label"="a""#,
+                r#"settings.motd="hi""#,
+                r#"settings.ntp.time-servers=["a","b"]"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn format_toml() {
+        let value = json!({"settings": {"kernel": {"lockdown": "none"}, "motd": "hi"}});
+        let output = format(&value, Format::Toml).unwrap();
+        assert_eq!(
+            output,
+            "[settings]\nmotd = \"hi\"\n\n[settings.kernel]\nlockdown = \"none\"\n"
+        );
+    }
+}
@@ -6,14 +6,14 @@
 // library calls based on the given flags, etc.)  The library modules contain the code for talking
 // to the API, which is intended to be reusable by other crates.
 
-use apiclient::{apply, exec, reboot, set, update};
+use apiclient::{apply, exec, get, reboot, set, update, watch};
 use constants;
 use datastore::{serialize_scalar, Key, KeyType};
 use log::{info, log_enabled, trace, warn};
 use simplelog::{
     ColorChoice, ConfigBuilder as LogConfigBuilder, LevelFilter, TermLogger, TerminalMode,
 };
-use snafu::ResultExt;
+use snafu::{ensure, ResultExt};
 use std::collections::HashMap;
 use std::env;
 use std::ffi::OsString;
@@ -44,16 +44,19 @@ impl Default for Args {
 enum Subcommand {
     Apply(ApplyArgs),
     Exec(ExecArgs),
+    Get(GetArgs),
     Raw(RawArgs),
     Reboot(RebootArgs),
     Set(SetArgs),
     Update(UpdateSubcommand),
+    Watch(WatchArgs),
 }
 
 /// Stores user-supplied arguments for the 'apply' subcommand.
 #[derive(Debug)]
 struct ApplyArgs {
     input_sources: Vec<String>,
+    dry_run: bool,
 }
 
 /// Stores user-supplied arguments for the 'exec' subcommand.
@@ -64,6 +67,13 @@ struct ExecArgs {
     tty: Option<bool>,
 }
 
+/// Stores user-supplied arguments for the 'get' subcommand.
+#[derive(Debug)]
+struct GetArgs {
+    names: Vec<String>,
+    format: get::Format,
+}
+
 /// Stores user-supplied arguments for the 'raw' subcommand.
 #[derive(Debug)]
 struct RawArgs {
@@ -83,10 +93,17 @@ enum SetArgs {
     Json(serde_json::Value),
 }
 
+/// Stores user-supplied arguments for the 'watch' subcommand.
+#[derive(Debug)]
+struct WatchArgs {
+    prefix: Option<String>,
+}
+
 /// Stores the 'update' subcommand specified by the user.
 #[derive(Debug)]
 enum UpdateSubcommand {
     Check(UpdateCheckArgs),
+    Download(UpdateDownloadArgs),
     Apply(UpdateApplyArgs),
     Cancel(UpdateCancelArgs),
 }
@@ -95,6 +112,12 @@ enum UpdateSubcommand {
 #[derive(Debug)]
 struct UpdateCheckArgs {}
 
+/// Stores user-supplied arguments for the 'update download' subcommand.
+#[derive(Debug)]
+struct UpdateDownloadArgs {
+    check: bool,
+}
+
 /// Stores user-supplied arguments for the 'update apply' subcommand.
 #[derive(Debug)]
 struct UpdateApplyArgs {
@@ -123,11 +146,14 @@ fn usage() -> ! {
             apply                      Applies settings from TOML/JSON files at given URIs,
                                        or from stdin.
             set                        Changes settings and applies them to the system.
+            get                        Prints settings or other API data by name.
             update check               Prints information about available updates.
+            update download            Downloads available updates without applying them.
             update apply               Applies available updates.
             update cancel              Deactivates an applied update.
             reboot                     Reboots the host.
             exec                       Execute a command in a host container.
+            watch                      Prints settings changes as they're committed.
 
         raw options:
             -u, --uri URI              Required; URI to request from the server, e.g. /tx
@@ -138,6 +164,8 @@ fn usage() -> ! {
             [ URI ...]                 The list of URIs to TOML or JSON settings files that you
                                        want to apply to the system.  If no URI is specified, or
                                        if "-" is given, reads from stdin.
+            --dry-run                  Check the settings and print what applying them would
+                                       affect, without changing anything.
 
         reboot options:
             None.
@@ -153,9 +181,21 @@ fn usage() -> ! {
                                        for some numeric settings.  For example:
                                           -j '{{"kernel": {{"sysctl": {{"vm.max_map_count": "262144"}}}}}}'
 
+        get options:
+            [ NAME ...]                The names of the settings or other API data you want to
+                                       see, for example: settings.motd kubernetes os
+                                       Names can be a prefix of whole key segments, like
+                                       settings.kubernetes, to see everything beneath them.
+                                       The "settings." prefix is optional.  If no name is
+                                       given, prints everything.
+            -f, --format FORMAT        Output format; json|toml|key-value.  Default: json
+
         update check options:
             None.
 
+        update download options:
+            -c, --check                Automatically `update check` and download whatever is found.
+
         update apply options:
             -c, --check                Automatically `update check` and apply whatever is found.
             -r, --reboot               Automatically reboot if an update was found and applied.
@@ -169,7 +209,12 @@ fn usage() -> ! {
 
             TARGET                     Required; the name of the container in which to run the command.
             COMMAND                    Required; the command to run.
-            [ ARG ...]                 Any desired arguments to the command."#,
+            [ ARG ...]                 Any desired arguments to the command.
+
+        watch options:
+            -p, --prefix PREFIX        Only print changes to settings starting with this prefix,
+                                       for example: kubernetes.node-labels
+                                       The "settings." prefix is optional."#,
         socket = constants::API_SOCKET,
         method = DEFAULT_METHOD,
     );
@@ -217,7 +262,7 @@ fn parse_args(args: env::Args) -> (Args, Subcommand) {
             }
 
             // Subcommands
-            "raw" | "apply" | "exec" | "reboot" | "set" | "update"
+            "raw" | "apply" | "exec" | "get" | "reboot" | "set" | "update" | "watch"
                 if subcommand.is_none() && !arg.starts_with('-') =>
             {
                 subcommand = Some(arg)
@@ -233,9 +278,11 @@ fn parse_args(args: env::Args) -> (Args, Subcommand) {
         None | Some("raw") => return (global_args, parse_raw_args(subcommand_args)),
         Some("apply") => return (global_args, parse_apply_args(subcommand_args)),
         Some("exec") => return (global_args, parse_exec_args(subcommand_args)),
+        Some("get") => return (global_args, parse_get_args(subcommand_args)),
         Some("reboot") => return (global_args, parse_reboot_args(subcommand_args)),
         Some("set") => return (global_args, parse_set_args(subcommand_args)),
         Some("update") => return (global_args, parse_update_args(subcommand_args)),
+        Some("watch") => return (global_args, parse_watch_args(subcommand_args)),
         _ => usage_msg("Missing or unknown subcommand"),
     }
 }
@@ -285,14 +332,17 @@ fn parse_raw_args(args: Vec<String>) -> Subcommand {
 /// Parses arguments for the 'apply' subcommand.
 fn parse_apply_args(args: Vec<String>) -> Subcommand {
     let mut input_sources = Vec::new();
+    let mut dry_run = false;
 
     let mut iter = args.into_iter();
     while let Some(arg) = iter.next() {
         match arg {
+            x if x == "--dry-run" => dry_run = true,
+
             // Allow "-" for stdin, but we have no other parameters.
-            x if x.starts_with("-") && x != "-" => {
-                usage_msg("apiclient apply takes no parameters, just a list of URIs.")
-            }
+            x if x.starts_with("-") && x != "-" => usage_msg(
+                "apiclient apply takes no parameters other than --dry-run, just a list of URIs.",
+            ),
 
             x => input_sources.push(x),
         }
@@ -303,7 +353,10 @@ fn parse_apply_args(args: Vec<String>) -> Subcommand {
         input_sources.push("-".to_string());
     }
 
-    Subcommand::Apply(ApplyArgs { input_sources })
+    Subcommand::Apply(ApplyArgs {
+        input_sources,
+        dry_run,
+    })
 }
 
 /// Parses arguments for the 'exec' subcommand.
@@ -347,6 +400,70 @@ fn parse_exec_args(args: Vec<String>) -> Subcommand {
     })
 }
 
+/// Parses arguments for the 'get' subcommand.
+fn parse_get_args(args: Vec<String>) -> Subcommand {
+    let mut names = Vec::new();
+    let mut format = None;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "-f" | "--format" => {
+                let format_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to -f | --format"));
+                format = Some(
+                    get::Format::from_str(&format_str).unwrap_or_else(|e| usage_msg(e.to_string())),
+                )
+            }
+
+            x if x.starts_with('-') => usage_msg(format!("Unknown argument '{}'", x)),
+
+            _ => {
+                // Check names up front so we can give a usage message rather than a request error.
+                if let Err(e) = get::parse_name(&arg) {
+                    usage_msg(e.to_string());
+                }
+                names.push(arg)
+            }
+        }
+    }
+
+    Subcommand::Get(GetArgs {
+        names,
+        format: format.unwrap_or(get::Format::Json),
+    })
+}
+
+/// Parses arguments for the 'watch' subcommand.
+fn parse_watch_args(args: Vec<String>) -> Subcommand {
+    let mut prefix = None;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "-p" | "--prefix" => {
+                let prefix_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to -p | --prefix"));
+                // Like 'get' and 'set', allow the user to include "settings." or not.
+                let prefix_str = prefix_str
+                    .strip_prefix("settings.")
+                    .unwrap_or(&prefix_str)
+                    .to_string();
+                if prefix_str.is_empty() || prefix_str == "settings" {
+                    usage_msg("Prefix must name settings beneath 'settings.'");
+                }
+                prefix = Some(prefix_str);
+            }
+
+            x => usage_msg(format!("Unknown argument '{}'", x)),
+        }
+    }
+
+    Subcommand::Watch(WatchArgs { prefix })
+}
+
 /// Parses arguments for the 'reboot' subcommand.
 fn parse_reboot_args(args: Vec<String>) -> Subcommand {
     if !args.is_empty() {
@@ -447,7 +564,9 @@ fn parse_update_args(args: Vec<String>) -> Subcommand {
     while let Some(arg) = iter.next() {
         match arg.as_ref() {
             // Subcommands
-            "check" | "apply" | "cancel" if subcommand.is_none() && !arg.starts_with('-') => {
+            "check" | "download" | "apply" | "cancel"
+                if subcommand.is_none() && !arg.starts_with('-') =>
+            {
                 subcommand = Some(arg)
             }
 
@@ -458,6 +577,7 @@ fn parse_update_args(args: Vec<String>) -> Subcommand {
 
     let update = match subcommand.as_deref() {
         Some("check") => parse_update_check_args(subcommand_args),
+        Some("download") => parse_update_download_args(subcommand_args),
         Some("apply") => parse_update_apply_args(subcommand_args),
         Some("cancel") => parse_update_cancel_args(subcommand_args),
         _ => usage_msg("Missing or unknown subcommand for 'update'"),
@@ -474,6 +594,21 @@ fn parse_update_check_args(args: Vec<String>) -> UpdateSubcommand {
     UpdateSubcommand::Check(UpdateCheckArgs {})
 }
 
+/// Parses arguments for the 'update download' subcommand.
+fn parse_update_download_args(args: Vec<String>) -> UpdateSubcommand {
+    let mut check = false;
+
+    for arg in args {
+        match arg.as_ref() {
+            "-c" | "--check" => check = true,
+
+            x => usage_msg(format!("Unknown argument '{}'", x)),
+        }
+    }
+
+    UpdateSubcommand::Download(UpdateDownloadArgs { check })
+}
+
 /// Parses arguments for the 'update apply' subcommand.
 fn parse_update_apply_args(args: Vec<String>) -> UpdateSubcommand {
     let mut check = false;
@@ -596,9 +731,19 @@ async fn run() -> Result<()> {
         }
 
         Subcommand::Apply(apply) => {
-            apply::apply(&args.socket_path, apply.input_sources)
-                .await
-                .context(error::Apply)?;
+            if apply.dry_run {
+                let reports = apply::dry_run(&args.socket_path, apply.input_sources)
+                    .await
+                    .context(error::Apply)?;
+                let valid = reports.values().all(|r| r["valid"] == true);
+                println!("{:#}", serde_json::Value::Object(reports));
+                // Let scripts know if anything was invalid.
+                ensure!(valid, error::InvalidSettings);
+            } else {
+                apply::apply(&args.socket_path, apply.input_sources)
+                    .await
+                    .context(error::Apply)?;
+            }
         }
 
         Subcommand::Exec(exec) => {
@@ -607,6 +752,25 @@ async fn run() -> Result<()> {
                 .context(error::Exec)?;
         }
 
+        Subcommand::Get(get) => {
+            let value = get::get_names(&args.socket_path, &get.names)
+                .await
+                .context(error::Get)?;
+            let output = get::format(&value, get.format).context(error::Get)?;
+            if !output.is_empty() {
+                println!("{}", output.trim_end());
+            }
+        }
+
+        Subcommand::Watch(watch) => {
+            // Print one compact JSON object per line, so each change is easy to handle in scripts.
+            watch::watch(&args.socket_path, watch.prefix, |change| {
+                println!("{}", change)
+            })
+            .await
+            .context(error::Watch)?;
+        }
+
         Subcommand::Reboot(_reboot) => {
             reboot::reboot(&args.socket_path)
                 .await
@@ -644,6 +808,20 @@ async fn run() -> Result<()> {
                 check(&args).await?;
             }
 
+            UpdateSubcommand::Download(download) => {
+                if download.check {
+                    let output = check(&args).await?;
+                    if !update::required(&output) {
+                        return Ok(());
+                    }
+                }
+
+                update::download(&args.socket_path)
+                    .await
+                    .context(error::UpdateDownload)?;
+                info!("Update has been downloaded; apply it to write it to disk.");
+            }
+
             UpdateSubcommand::Apply(apply) => {
                 if apply.check {
                     let output = check(&args).await?;
@@ -692,7 +870,7 @@ async fn main() {
 }
 
 mod error {
-    use apiclient::{apply, exec, reboot, set, update};
+    use apiclient::{apply, exec, get, reboot, set, update, watch};
     use snafu::Snafu;
 
     #[derive(Debug, Snafu)]
@@ -715,6 +893,12 @@ mod error {
         #[snafu(display("Failed to exec: {}", source))]
         Exec { source: exec::Error },
 
+        #[snafu(display("Failed to get settings: {}", source))]
+        Get { source: get::Error },
+
+        #[snafu(display("Settings are not valid; see the errors above"))]
+        InvalidSettings,
+
         #[snafu(display("Logger setup error: {}", source))]
         Logger { source: log::SetLoggerError },
 
@@ -742,6 +926,12 @@ mod error {
 
         #[snafu(display("Failed to check for updates: {}", source))]
         UpdateCheck { source: update::Error },
+
+        #[snafu(display("Failed to download update: {}", source))]
+        UpdateDownload { source: update::Error },
+
+        #[snafu(display("Failed to watch settings: {}", source))]
+        Watch { source: watch::Error },
     }
 }
 type Result<T> = std::result::Result<T, error::Error>;
@@ -58,6 +58,10 @@ pub fn required(check_output: &str) -> bool {
             info!("No updates available.");
             false
         }
+        "Downloaded" => {
+            info!("Update already downloaded; applying it will write it from the download cache.");
+            true
+        }
         "Ready" => {
             info!("Update already applied; reboot for it to take effect, or request a cancel.");
             false
@@ -69,6 +73,26 @@ pub fn required(check_output: &str) -> bool {
     }
 }
 
+/// Downloads the update shown as selected in the output of check(), without writing it to disk.  A
+/// later apply() writes it from the download cache, without network access.
+pub async fn download<P>(socket_path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    info!("Downloading update...");
+    let (_body, _status) = wait_request(
+        &socket_path,
+        "/actions/download-update",
+        "POST",
+        None,
+        "download",
+        &WaitPolicy::new(Duration::from_millis(500), 2 * 60 * 10),
+    )
+    .await?;
+
+    Ok(())
+}
+
 /// Applies the update shown as selected in the output of check(), and makes it active.
 pub async fn apply<P>(socket_path: P) -> Result<()>
 where
@@ -146,6 +170,22 @@ fn response_field(
     result.as_str().map(|s| s.to_string())
 }
 
+/// Describes the progress of the running update command, if the status includes it, e.g.
+/// "root: 42.5% complete, 12.3 MiB/s".
+fn progress_line(response_str: &str) -> Option<String> {
+    let response: serde_json::Value = serde_json::from_str(response_str).ok()?;
+    let progress = response.get("progress")?;
+    let phase = progress.get("phase")?.as_str()?;
+    let percent = progress.get("percent_complete")?.as_f64()?;
+    let bytes_per_second = progress.get("bytes_per_second")?.as_u64()?;
+    Some(format!(
+        "{}: {:.1}% complete, {:.1} MiB/s",
+        phase,
+        percent,
+        bytes_per_second as f64 / (1024.0 * 1024.0)
+    ))
+}
+
 /// Represents how a caller wants wait_request to handle waiting for status updates after
 /// requesting an action from an API call.  After `max_attempts` checks with `between_attempts`
 /// duration between them, the call will be timed out and fail.
@@ -220,6 +260,8 @@ where
     let notify_every = Duration::from_secs(5);
     // Counter so we can tell whether we should notify.
     let mut waited = Duration::from_millis(0);
+    // The latest progress of our command, if the status reports it.
+    let mut progress = None;
 
     loop {
         // Check if we've timed out or failed too many requests.
@@ -244,10 +286,13 @@ where
         // Let the user know what's going on every once in a while, as we wait.
         if attempt > 1 && waited >= notify_every {
             waited = Duration::from_millis(0);
-            info!(
-                "Still waiting for updated status, will wait up to {:?} longer...",
-                (wait.max_attempts * wait.between_attempts) - (attempt * wait.between_attempts)
-            );
+            match progress.take() {
+                Some(line) => info!("{}", line),
+                None => info!(
+                    "Still waiting for updated status, will wait up to {:?} longer...",
+                    (wait.max_attempts * wait.between_attempts) - (attempt * wait.between_attempts)
+                ),
+            }
         }
         time::sleep(wait.between_attempts).await;
         waited += wait.between_attempts;
@@ -266,7 +311,7 @@ where
             }
         };
 
-        // Mutating actions will return a LOCKED status if they're not yet complete.
+        // Older API servers return a LOCKED status while mutating actions are running.
         if code == StatusCode::LOCKED {
             trace!("Lock still held, presumably by our request...");
             continue;
@@ -279,6 +324,10 @@ where
             continue;
         }
 
+        if let Some(line) = progress_line(&status_body) {
+            progress = Some(line);
+        }
+
         // Get the specific status fields we check.
         let after_timestamp = response_field(&["most_recent_command", "timestamp"], &status_body)
             .unwrap_or_else(|| "missing".to_string());
@@ -391,3 +440,20 @@ mod error {
 }
 pub use error::Error;
 pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let status = r#"{"update_state": "Available", "progress": {"phase": "root",
+            "percent_complete": 42.5, "bytes_per_second": 12897485,
+            "updated": "2021-11-01T00:00:00Z"}}"#;
+        assert_eq!(
+            progress_line(status).unwrap(),
+            "root: 42.5% complete, 12.3 MiB/s"
+        );
+        assert_eq!(progress_line(r#"{"update_state": "Idle"}"#), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! This module allows following changes to settings as they're committed.  The API server sends
+//! a Server-Sent Event for each commit that changes settings, containing the generation created by
+//! the commit, the name of the transaction, and the changed settings with their new values.
+
+use hyper::body::HttpBody;
+use hyper::{Body, Client};
+use hyper_unix_connector::{UnixClient, Uri};
+use serde_json::Value;
+use snafu::{ensure, ResultExt};
+use std::path::Path;
+
+/// Watches for settings changes, calling `on_change` with each change as a JSON object.  If
+/// `prefix` is given, only changes to settings starting with it are included; like the settings
+/// GET, it doesn't need the "settings." prefix.
+///
+/// This runs until the server closes the stream.  The server disconnects clients that fall too
+/// far behind, so callers that need every change should re-read settings after reconnecting.
+pub async fn watch<P, F>(socket_path: P, prefix: Option<String>, mut on_change: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(Value),
+{
+    let uri = match prefix {
+        Some(prefix) => format!("/settings/watch?prefix={}", prefix),
+        None => "/settings/watch".to_string(),
+    };
+
+    // We can't use raw_request because it reads the whole body; we want to handle each event as
+    // it comes in.
+    let client = Client::builder().build::<_, Body>(UnixClient);
+    let hyper_uri: hyper::Uri = Uri::new(socket_path, &uri).into();
+    let response = client
+        .get(hyper_uri)
+        .await
+        .context(error::Request { uri: &uri })?;
+    let status = response.status();
+    ensure!(
+        status.is_success(),
+        error::ResponseStatus { uri, code: status }
+    );
+
+    // Events are separated by a blank line, and may be split across chunks.
+    let mut body = response.into_body();
+    let mut buffer = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.context(error::ResponseBodyRead)?;
+        buffer.extend_from_slice(&chunk);
+
+        while let Some(end) = find_event_end(&buffer) {
+            let event: Vec<u8> = buffer.drain(..end + 2).collect();
+            let event = String::from_utf8(event).context(error::NonUtf8Response)?;
+            if let Some(change) = parse_event(&event)? {
+                on_change(change);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the index of the blank line ending the first event in the buffer, if there is one.
+fn find_event_end(buffer: &[u8]) -> Option<usize> {
+    buffer.windows(2).position(|w| w == b"\n\n")
+}
+
+/// Parses the JSON data from a Server-Sent Event.  Returns None for events without data, like
+/// comments the server may send to keep the connection alive.
+fn parse_event(event: &str) -> Result<Option<Value>> {
+    let data: Vec<&str> = event
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect();
+    if data.is_empty() {
+        return Ok(None);
+    }
+
+    let data = data.join("\n");
+    serde_json::from_str(&data)
+        .map(Some)
+        .context(error::EventJson { data })
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility = "pub(super)")]
+    pub enum Error {
+        #[snafu(display("Settings change from server was not valid JSON: {}: {}", data, source))]
+        EventJson {
+            data: String,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Response was not UTF-8: {}", source))]
+        NonUtf8Response { source: std::string::FromUtf8Error },
+
+        #[snafu(display("Failed GET request to '{}': {}", uri, source))]
+        Request { uri: String, source: hyper::Error },
+
+        #[snafu(display("Failed to read body of response: {}", source))]
+        ResponseBodyRead { source: hyper::Error },
+
+        #[snafu(display("Status {} when watching {}", code.as_str(), uri))]
+        ResponseStatus { uri: String, code: http::StatusCode },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_events() {
+        assert_eq!(
+            parse_event("data: {\"generation\": 2}\n\n").unwrap(),
+            Some(json!({"generation": 2}))
+        );
+        assert_eq!(parse_event(": keep-alive\n\n").unwrap(), None);
+        parse_event("data: nope\n\n").unwrap_err();
+    }
+
+    #[test]
+    fn event_boundaries() {
+        assert_eq!(find_event_end(b"data: {}\n\ndata: "), Some(8));
+        assert_eq!(find_event_end(b"data: {}\n"), None);
+    }
+}
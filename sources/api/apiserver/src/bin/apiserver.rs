@@ -48,6 +48,8 @@ struct Args {
     socket_gid: Option<Gid>,
     socket_path: String,
     exec_socket_path: String,
+    access_policy_path: Option<String>,
+    audit_log_path: Option<String>,
 }
 
 /// Informs the user about proper usage of the program and exits.
@@ -59,11 +61,15 @@ fn usage() -> ! {
             [ --socket-path PATH ]
             [ --socket-gid GROUP_ID ]
             [ --exec-socket-path PATH ]
+            [ --access-policy PATH ]
+            [ --audit-log PATH ]
             [ --no-color ]
             [ --log-level trace|debug|info|warn|error ]
 
     --socket-path defaults to {}
-    --exec-socket-path (for apiclient exec) defaults to {}",
+    --exec-socket-path (for apiclient exec) defaults to {}
+    --access-policy limits what each caller can do; by default, callers have full access
+    --audit-log records requests that change the system; by default, they're not recorded",
         program_name, DEFAULT_BIND_PATH, DEFAULT_EXEC_SOCKET
     );
     process::exit(2);
@@ -82,6 +88,8 @@ fn parse_args(args: env::Args) -> Args {
     let mut socket_gid = None;
     let mut socket_path = None;
     let mut exec_socket_path = None;
+    let mut access_policy_path = None;
+    let mut audit_log_path = None;
 
     let mut iter = args.skip(1);
     while let Some(arg) = iter.next() {
@@ -129,6 +137,20 @@ fn parse_args(args: env::Args) -> Args {
                     }))
             }
 
+            "--access-policy" => {
+                access_policy_path = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --access-policy")),
+                )
+            }
+
+            "--audit-log" => {
+                audit_log_path = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --audit-log")),
+                )
+            }
+
             _ => usage(),
         }
     }
@@ -139,6 +161,8 @@ fn parse_args(args: env::Args) -> Args {
         log_level: log_level.unwrap_or_else(|| LevelFilter::Info),
         socket_path: socket_path.unwrap_or_else(|| DEFAULT_BIND_PATH.to_string()),
         exec_socket_path: exec_socket_path.unwrap_or_else(|| DEFAULT_EXEC_SOCKET.to_string()),
+        access_policy_path,
+        audit_log_path,
     }
 }
 
@@ -176,6 +200,8 @@ async fn run() -> Result<()> {
         threads,
         args.socket_gid,
         args.exec_socket_path,
+        args.access_policy_path.as_deref().map(Path::new),
+        args.audit_log_path.as_deref().map(Path::new),
     )
     .await
     .context(error::Server)
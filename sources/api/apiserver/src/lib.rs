@@ -8,6 +8,17 @@ The server listens to HTTP requests on a Unix-domain socket.
 There is no built-in authentication - local access to the socket should be limited to processes and containers that should be able to configure the system.
 Remote access should only be allowed through an authenticated control channel such as SSH or SSM.
 
+For finer control, the server can be given an access policy with `--access-policy`.
+The kernel tells the server the user and group IDs of each process that connects to the socket, and the policy maps them to the routes and settings they're allowed to use, for example read-only access to `/settings`, or write access only to `settings.kubernetes.node-labels`.
+Denied requests get a 403 Forbidden response and are logged.
+The policy format is documented in [src/server/access.rs](src/server/access.rs).
+
+With `--audit-log`, the server appends a JSON record to the given file for each request that changes the system, such as settings changes, commits, reboots, update actions, and exec sessions.
+Each record includes the caller's credentials, the transaction, the changed settings with sensitive values redacted, and the result.
+Each record also includes the hash of the line before it, so changes to the log can be detected.
+The log is rotated at 10 MiB, keeping one older file with a `.1` suffix.
+Records can be read, with optional filters, from `/audit`, which also reports any breaks in the chain of hashes.
+
 # Design
 
 ## API
@@ -20,11 +31,24 @@ You can GET settings from the `/settings` endpoint.
 You can also PATCH changes to the `/settings` endpoint.
 Settings are stored as a pending transaction until a commit API is called.
 Pending settings can be retrieved from `/tx` to see what will change.
+To check settings without staging them, POST them to `/settings/validate`; every invalid setting is reported by name, along with the services and configuration files the valid settings would affect.
 
 Upon making a `/tx/commit` POST call, the pending transaction is made live.
 Upon making an `/tx/apply` POST call, an external settings applier tool is called to apply the changes to the system and restart services as necessary.
 There's also `/tx/commit_and_apply` to do both, which is the most common case.
 
+Each commit that changes settings creates a new generation of the data store, and a bounded history of recent commits is kept, including the old and new values of changed keys.
+You can see it with a `/tx/history` GET call.
+To undo changes, make a `/tx/rollback?id=N` POST call, which reverts any transactions committed after generation N and applies the changes.
+The rollback itself is recorded in history, so it can be undone too.
+
+To make changes safely when other clients may be changing the same settings, you can use optimistic concurrency.
+Settings GET responses have an `ETag` header naming the latest generation in which any of the returned settings changed.
+Give that value in an `If-Match` header when you PATCH settings or commit a transaction, and the request will fail with 409 Conflict if any of the settings you're changing have changed since.
+
+Rather than polling for changes, clients can make a `/settings/watch` GET call, optionally with a `prefix` parameter.
+The response is a stream of Server-Sent Events, one for each commit that changes matching settings, containing the changed settings and their new values.
+
 If you don't specify a transaction, the "default" transaction is used, so you usually don't have to think about it.
 If you want to group changes into transactions yourself, you can add a `tx` parameter to the APIs mentioned above.
 For example, if you want the name "FOO", you can `PATCH` to `/settings?tx=FOO` and `POST` to `/tx/commit_and_apply?tx=FOO`.
@@ -57,7 +81,7 @@ For more detail, see [datastore](../datastore).
 # Current limitations
 
 * Data store locking is coarse; read requests can happen in parallel, but a write request will block everything else.
-* There's no support for rolling back commits.
+* Rollback only covers the most recent commits, and doesn't track metadata changes.
 * There are no metrics.
 
 # Example usage
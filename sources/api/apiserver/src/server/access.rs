@@ -0,0 +1,348 @@
+//! The 'access' module restricts what local callers can do through the API, based on who they
+//! are.  The kernel tells us the user and group of the process on the other end of the socket
+//! (SO_PEERCRED) when it connects, and an access policy maps users and groups to the routes and
+//! settings they're allowed to use.
+//!
+//! The policy is a TOML file with a list of rules, for example:
+//!
+//! ```toml
+//! # System services run as root and need full access.
+//! [[rules]]
+//! uids = [0]
+//! write = ["/"]
+//!
+//! # Monitoring agents can read settings and OS information.
+//! [[rules]]
+//! gids = [1000]
+//! read = ["/settings", "/os"]
+//!
+//! # This agent can only change node labels.
+//! [[rules]]
+//! uids = [1001]
+//! read = ["/settings"]
+//! write = ["/settings", "/tx"]
+//! settings = ["settings.kubernetes.node-labels"]
+//! ```
+//!
+//! A rule applies to a caller if the caller's user ID is in `uids` or its primary group ID is in
+//! `gids`.  (SO_PEERCRED doesn't include supplementary groups.)  Routes are path prefixes made of
+//! whole segments, and "/" means every route.  `read` routes allow GET requests, and `write`
+//! routes allow any request.  `/exec` runs commands on the host, so it requires write access even
+//! though it's a GET request, while `/settings/validate` only needs read access because it doesn't
+//! change anything, even though it's a POST request.  If `settings` is given, the rule only allows changing settings that
+//! start with one of the given prefixes, and doesn't allow rollbacks, which could change anything.
+//!
+//! A request is allowed if any rule that applies to the caller allows it.  Callers that don't
+//! match any rule are denied, so remember to include a rule for system services.  If no policy is
+//! given, every caller has full access, as before.
+
+use crate::server::error::{self, Result};
+use crate::server::SharedData;
+use actix_web::dev::{Extensions, ServiceRequest};
+use actix_web::rt::net::UnixStream;
+use actix_web::{web, HttpMessage, HttpRequest};
+use datastore::Key;
+use log::{debug, warn};
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::any::Any;
+use std::fs;
+use std::path::Path;
+
+/// The identity of the process on the other end of a connection, as given by the kernel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct PeerCredentials {
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) pid: Option<i32>,
+}
+
+/// The rules that decide what each caller can do.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AccessPolicy {
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
+/// The access given to a set of users and groups.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    #[serde(default)]
+    uids: Vec<u32>,
+    #[serde(default)]
+    gids: Vec<u32>,
+    /// Route prefixes the callers can GET.
+    #[serde(default)]
+    read: Vec<String>,
+    /// Route prefixes the callers can use with any method.
+    #[serde(default)]
+    write: Vec<String>,
+    /// If given, the only setting prefixes the callers can change.
+    settings: Option<Vec<String>>,
+}
+
+impl AccessPolicy {
+    /// Loads the policy from the TOML file at the given path.
+    pub(crate) fn from_path<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let policy_str = fs::read_to_string(path).context(error::ReadPolicy { path })?;
+        toml::from_str(&policy_str).context(error::ParsePolicy { path })
+    }
+
+    /// Returns the rules that apply to the given caller.
+    fn rules_for<'a>(&'a self, peer: &'a PeerCredentials) -> impl Iterator<Item = &'a Rule> {
+        self.rules
+            .iter()
+            .filter(move |rule| rule.uids.contains(&peer.uid) || rule.gids.contains(&peer.gid))
+    }
+
+    /// Returns true if the given caller can make a request with the given method to the given
+    /// path.
+    fn allows_route(&self, peer: &PeerCredentials, method: &str, path: &str) -> bool {
+        let write = is_write(method, path);
+        self.rules_for(peer).any(|rule| {
+            rule.write.iter().any(|route| route_matches(route, path))
+                || (!write && rule.read.iter().any(|route| route_matches(route, path)))
+        })
+    }
+
+    /// Returns true if the given caller can change all of the given settings through the given
+    /// path.  `None` means the change could touch any setting.
+    fn allows_settings<'a, I>(&self, peer: &PeerCredentials, path: &str, keys: Option<I>) -> bool
+    where
+        I: IntoIterator<Item = &'a Key>,
+    {
+        let mut prefixes = Vec::new();
+        for rule in self.rules_for(peer) {
+            if !rule.write.iter().any(|route| route_matches(route, path)) {
+                continue;
+            }
+            match &rule.settings {
+                // This rule allows changing any setting.
+                None => return true,
+                Some(rule_prefixes) => prefixes.extend(rule_prefixes),
+            }
+        }
+
+        match keys {
+            None => false,
+            Some(keys) => keys.into_iter().all(|key| {
+                prefixes
+                    .iter()
+                    .any(|prefix| setting_matches(prefix, key.name()))
+            }),
+        }
+    }
+}
+
+/// Requests that change the system need write access.  `/exec` is a GET request because it's
+/// upgraded to a WebSocket, but it can do anything, so it needs write access too.  Validation is a
+/// POST request only so it can carry the settings to check.
+pub(crate) fn is_write(method: &str, path: &str) -> bool {
+    if path == "/settings/validate" {
+        return false;
+    }
+    !(method == "GET" || method == "HEAD") || route_matches("/exec", path)
+}
+
+/// Checks whether a route prefix from the policy covers the requested path, by whole segments.
+fn route_matches(route: &str, path: &str) -> bool {
+    let route = route.trim_end_matches('/');
+    route.is_empty()
+        || path == route
+        || (path.starts_with(route) && path[route.len()..].starts_with('/'))
+}
+
+/// Checks whether a setting prefix from the policy covers the given key, by whole segments.
+fn setting_matches(prefix: &str, key: &str) -> bool {
+    key == prefix || (key.starts_with(prefix) && key[prefix.len()..].starts_with('.'))
+}
+
+/// Called by the server for each new connection; saves the credentials of the connecting process
+/// so we can check them for each request on the connection.
+pub(crate) fn save_peer_credentials(connection: &dyn Any, extensions: &mut Extensions) {
+    if let Some(stream) = connection.downcast_ref::<UnixStream>() {
+        match stream.peer_cred() {
+            Ok(cred) => {
+                debug!(
+                    "Connection from uid {} gid {} pid {:?}",
+                    cred.uid(),
+                    cred.gid(),
+                    cred.pid()
+                );
+                extensions.insert(PeerCredentials {
+                    uid: cred.uid(),
+                    gid: cred.gid(),
+                    pid: cred.pid(),
+                });
+            }
+            // We'll deny any requests that need credentials.
+            Err(e) => warn!("Failed to get credentials of connecting process: {}", e),
+        }
+    }
+}
+
+/// Checks that the caller can use the requested route, before the request reaches its handler.
+pub(crate) fn authorize_request(req: &ServiceRequest) -> Result<()> {
+    let data = match req.app_data::<web::Data<SharedData>>() {
+        Some(data) => data,
+        // Shouldn't happen; the server always sets up SharedData.
+        None => return deny(None, req.method().as_str(), req.path(), "no server data"),
+    };
+    let policy = match &data.access_policy {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+
+    let peer = req.extensions().get::<PeerCredentials>().copied();
+    let method = req.method().as_str();
+    let path = req.path();
+    match peer {
+        Some(peer) if policy.allows_route(&peer, method, path) => Ok(()),
+        Some(_) => deny(peer, method, path, "route not allowed"),
+        None => deny(None, method, path, "unknown caller"),
+    }
+}
+
+/// Checks that the caller can change the given settings.  Give None if the change could touch any
+/// setting.
+pub(crate) fn authorize_settings<'a, I>(
+    req: &HttpRequest,
+    data: &SharedData,
+    keys: Option<I>,
+) -> Result<()>
+where
+    I: IntoIterator<Item = &'a Key>,
+{
+    let policy = match &data.access_policy {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+
+    let peer = req.extensions().get::<PeerCredentials>().copied();
+    let method = req.method().as_str();
+    let path = req.path();
+    match peer {
+        Some(peer) if policy.allows_settings(&peer, path, keys) => Ok(()),
+        Some(_) => deny(peer, method, path, "settings not allowed"),
+        None => deny(None, method, path, "unknown caller"),
+    }
+}
+
+/// Logs an audit line for the denied request and returns the error to send to the caller.
+fn deny(peer: Option<PeerCredentials>, method: &str, path: &str, reason: &str) -> Result<()> {
+    let caller = match peer {
+        Some(peer) => format!(
+            "uid={} gid={} pid={}",
+            peer.uid,
+            peer.gid,
+            peer.pid.map(|p| p.to_string()).unwrap_or_default()
+        ),
+        None => "caller=unknown".to_string(),
+    };
+    warn!(
+        "audit: access denied: {} method={} path={} reason=\"{}\"",
+        caller, method, path, reason
+    );
+    error::AccessDenied { reason }.fail()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use datastore::KeyType;
+
+    fn policy() -> AccessPolicy {
+        toml::from_str(
+            r#"
+            [[rules]]
+            uids = [0]
+            write = ["/"]
+
+            [[rules]]
+            gids = [1000]
+            read = ["/settings", "/os"]
+
+            [[rules]]
+            uids = [1001]
+            read = ["/settings"]
+            write = ["/settings", "/tx"]
+            settings = ["settings.kubernetes.node-labels"]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn peer(uid: u32, gid: u32) -> PeerCredentials {
+        PeerCredentials {
+            uid,
+            gid,
+            pid: None,
+        }
+    }
+
+    fn keys(names: &[&str]) -> Vec<Key> {
+        names
+            .iter()
+            .map(|name| Key::new(KeyType::Data, name).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn routes() {
+        let policy = policy();
+        let root = peer(0, 0);
+        assert!(policy.allows_route(&root, "POST", "/actions/reboot"));
+        assert!(policy.allows_route(&root, "GET", "/exec"));
+
+        let monitor = peer(2000, 1000);
+        assert!(policy.allows_route(&monitor, "GET", "/settings"));
+        assert!(policy.allows_route(&monitor, "GET", "/settings/watch"));
+        assert!(!policy.allows_route(&monitor, "GET", "/settingsx"));
+        assert!(!policy.allows_route(&monitor, "PATCH", "/settings"));
+        assert!(!policy.allows_route(&monitor, "GET", "/tx"));
+
+        let stranger = peer(3000, 3000);
+        assert!(!policy.allows_route(&stranger, "GET", "/os"));
+    }
+
+    #[test]
+    fn exec_needs_write() {
+        let policy: AccessPolicy = toml::from_str("[[rules]]\nuids = [5]\nread = [\"/\"]").unwrap();
+        let caller = peer(5, 5);
+        assert!(policy.allows_route(&caller, "GET", "/settings"));
+        assert!(!policy.allows_route(&caller, "GET", "/exec"));
+    }
+
+    #[test]
+    fn validate_needs_read() {
+        let policy = policy();
+        let monitor = peer(2000, 1000);
+        assert!(policy.allows_route(&monitor, "POST", "/settings/validate"));
+        assert!(!policy.allows_route(&monitor, "POST", "/settings"));
+
+        let stranger = peer(3000, 3000);
+        assert!(!policy.allows_route(&stranger, "POST", "/settings/validate"));
+    }
+
+    #[test]
+    fn settings() {
+        let policy = policy();
+        let labeler = peer(1001, 1001);
+        let labels = keys(&["settings.kubernetes.node-labels.\"my.label\""]);
+        assert!(policy.allows_settings(&labeler, "/settings", Some(&labels)));
+
+        let other = keys(&["settings.kubernetes.node-labels-x", "settings.motd"]);
+        assert!(!policy.allows_settings(&labeler, "/settings", Some(&other)));
+        assert!(!policy.allows_settings(&labeler, "/tx/rollback", None::<&Vec<Key>>));
+
+        let root = peer(0, 0);
+        assert!(policy.allows_settings(&root, "/settings", Some(&other)));
+        assert!(policy.allows_settings(&root, "/tx/rollback", None::<&Vec<Key>>));
+    }
+}
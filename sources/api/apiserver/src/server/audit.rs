@@ -0,0 +1,622 @@
+//! The 'audit' module keeps a record of every request that changes the system, so we can tell who
+//! changed what.  Records are appended to a file as JSON lines, and can be read back through the
+//! API, optionally filtered.
+//!
+//! Each record includes the SHA-256 hash of the line before it, so if a record is changed or
+//! removed, the chain of hashes is broken.  The first record ever written has an empty previous
+//! hash.  The chain is checked whenever records are read, and any breaks are reported.
+//!
+//! Once the log reaches MAX_LOG_SIZE it's moved to a file with a ".1" suffix, replacing any older
+//! one, and a new log is started.  The chain continues into the new log, but the first record we
+//! still have can't be checked once an older log has been replaced.
+//!
+//! Values of sensitive settings, like user data, are redacted using the same patterns as logdog.
+
+use crate::server::access::{self, PeerCredentials};
+use crate::server::error::{self, Result};
+use crate::server::SharedData;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{web, HttpMessage, HttpRequest};
+use chrono::{DateTime, Utc};
+use datastore::Value;
+use futures::Future;
+use glob::Pattern;
+use log::{debug, error, trace};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snafu::{OptionExt, ResultExt};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// What we record in place of the value of a sensitive setting.
+const REDACTED: &str = "<redacted>";
+
+/// The size at which the log is rotated.  At a few hundred bytes per record, this keeps tens of
+/// thousands of records in each file.
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+
+/// A record of a request that changed, or tried to change, the system.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct AuditRecord {
+    pub(crate) timestamp: DateTime<Utc>,
+    /// The caller's credentials; None if we couldn't get them.
+    pub(crate) uid: Option<u32>,
+    pub(crate) gid: Option<u32>,
+    pub(crate) pid: Option<i32>,
+    pub(crate) method: String,
+    pub(crate) path: String,
+    /// The transaction the request used, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) transaction: Option<String>,
+    /// The keys changed by the request and their new values; null means removed or unknown.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) changes: BTreeMap<String, Value>,
+    /// Any other details of the request, like the command run by an exec session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) details: Option<String>,
+    /// The HTTP status code of the response.
+    pub(crate) status: u16,
+    /// The error message, if the request failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    /// The SHA-256 hash of the previous line in the log, in hex.
+    pub(crate) previous_hash: String,
+}
+
+/// A place where the chain of hashes is broken, meaning records before it were changed or removed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ChainBreak {
+    /// The log file and line number of the first record after the break.
+    pub(crate) file: PathBuf,
+    pub(crate) line: usize,
+    /// The hash of the previous line, as computed and as recorded.
+    pub(crate) expected_hash: String,
+    pub(crate) previous_hash: String,
+}
+
+/// The records read from the log, and any breaks found in the chain of hashes.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct AuditReport {
+    pub(crate) records: Vec<AuditRecord>,
+    pub(crate) chain_breaks: Vec<ChainBreak>,
+}
+
+/// Details of a request that only its handler knows; handlers add these to the request with
+/// `add_details` so they're included in the audit record.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct AuditDetails {
+    pub(crate) transaction: Option<String>,
+    pub(crate) changes: BTreeMap<String, Value>,
+    pub(crate) details: Option<String>,
+}
+
+/// Adds the given details to the audit record for the request.
+pub(crate) fn add_details(req: &HttpRequest, details: AuditDetails) {
+    req.extensions_mut().insert(details);
+}
+
+/// AuditLog appends records to the audit log file.  It's cheap to clone, and clones share the
+/// same file.
+#[derive(Debug, Clone)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    state: Arc<Mutex<LogState>>,
+}
+
+#[derive(Debug)]
+struct LogState {
+    file: File,
+    /// The size of the file, so we know when to rotate it.
+    size: u64,
+    /// The hash of the last line written, to include in the next record.
+    last_hash: String,
+}
+
+impl AuditLog {
+    /// Opens the audit log at the given path for appending, creating it if needed.
+    pub(crate) fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(error::AuditLogOpen { path })?;
+        }
+
+        // Continue the chain of hashes from the last line already in the logs.
+        let mut last_hash = String::new();
+        for log_path in &[rotated_path(path), path.to_owned()] {
+            if log_path.exists() {
+                let file = File::open(log_path).context(error::AuditLogOpen { path: log_path })?;
+                for line in BufReader::new(file).lines() {
+                    last_hash = hash_line(&line.context(error::AuditLogRead { path: log_path })?);
+                }
+            }
+        }
+
+        let file = open_for_append(path).context(error::AuditLogOpen { path })?;
+        let size = file.metadata().context(error::AuditLogOpen { path })?.len();
+
+        Ok(Self {
+            path: path.to_owned(),
+            state: Arc::new(Mutex::new(LogState {
+                file,
+                size,
+                last_hash,
+            })),
+        })
+    }
+
+    /// Appends a record for the given request.  The write is done on a thread that's allowed to
+    /// block, since we wait for it to reach the disk; the returned future completes once it's
+    /// done.  Failures are logged rather than returned, because the request has already been
+    /// handled.
+    pub(crate) fn append(
+        &self,
+        peer: Option<PeerCredentials>,
+        method: &str,
+        path: &str,
+        details: AuditDetails,
+        status: u16,
+        error: Option<String>,
+    ) -> impl Future<Output = ()> {
+        let log = self.clone();
+        let method = method.to_string();
+        let path = path.to_string();
+        let write = web::block(move || log.write(peer, &method, &path, details, status, error));
+        async move {
+            if write.await.is_err() {
+                error!("Audit log writer failed to complete");
+            }
+        }
+    }
+
+    /// Writes a record for the given request and waits for it to reach the disk, rotating the
+    /// log if it's full.
+    fn write(
+        &self,
+        peer: Option<PeerCredentials>,
+        method: &str,
+        path: &str,
+        details: AuditDetails,
+        status: u16,
+        error: Option<String>,
+    ) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => {
+                error!("Another thread poisoned the audit log lock by panicking");
+                return;
+            }
+        };
+
+        let record = AuditRecord {
+            timestamp: Utc::now(),
+            uid: peer.map(|p| p.uid),
+            gid: peer.map(|p| p.gid),
+            pid: peer.and_then(|p| p.pid),
+            method: method.to_string(),
+            path: path.to_string(),
+            transaction: details.transaction,
+            changes: redact(details.changes),
+            details: details.details,
+            status,
+            error,
+            previous_hash: state.last_hash.clone(),
+        };
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit record: {}", e);
+                return;
+            }
+        };
+
+        trace!("Writing audit record: {}", line);
+        if let Err(e) = writeln!(state.file, "{}", line).and_then(|_| state.file.sync_data()) {
+            error!(
+                "Failed to write audit record to '{}': {}",
+                self.path.display(),
+                e
+            );
+            return;
+        }
+        state.last_hash = hash_line(&line);
+        state.size += line.len() as u64 + 1;
+
+        if state.size >= MAX_LOG_SIZE {
+            if let Err(e) = self.rotate(&mut state) {
+                error!(
+                    "Failed to rotate audit log '{}': {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Moves the full log aside, replacing any older one, and starts a new log.
+    fn rotate(&self, state: &mut LogState) -> std::io::Result<()> {
+        let rotated = rotated_path(&self.path);
+        debug!("Rotating audit log to '{}'", rotated.display());
+        fs::rename(&self.path, &rotated)?;
+        state.file = open_for_append(&self.path)?;
+        state.size = 0;
+        Ok(())
+    }
+
+    /// Reads the records in the logs that match the given filter, oldest first, and checks the
+    /// chain of hashes across all records.
+    pub(crate) fn read(&self, filter: &AuditFilter) -> Result<AuditReport> {
+        let rotated = rotated_path(&self.path);
+        // If we've never rotated, the first record must start the chain.  Otherwise, the record
+        // before the first one we have may have been rotated away, so we can't check it.
+        let mut expected_hash = if rotated.exists() {
+            None
+        } else {
+            Some(String::new())
+        };
+
+        let mut report = AuditReport::default();
+        for path in &[rotated, self.path.clone()] {
+            if !path.exists() {
+                continue;
+            }
+            let reader = BufReader::new(File::open(path).context(error::AuditLogOpen { path })?);
+            for (index, line) in reader.lines().enumerate() {
+                let line = line.context(error::AuditLogRead { path })?;
+                let record: AuditRecord =
+                    serde_json::from_str(&line).context(error::AuditRecordParse { path })?;
+
+                if let Some(expected_hash) = expected_hash {
+                    if record.previous_hash != expected_hash {
+                        report.chain_breaks.push(ChainBreak {
+                            file: path.clone(),
+                            line: index + 1,
+                            expected_hash,
+                            previous_hash: record.previous_hash.clone(),
+                        });
+                    }
+                }
+                expected_hash = Some(hash_line(&line));
+
+                if filter.matches(&record) {
+                    report.records.push(record);
+                }
+            }
+        }
+
+        if let Some(limit) = filter.limit {
+            let excess = report.records.len().saturating_sub(limit);
+            report.records.drain(..excess);
+        }
+        Ok(report)
+    }
+}
+
+/// Returns the path the log is moved to when it's rotated.
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Opens the log at the given path for appending, creating it if needed.
+fn open_for_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+}
+
+/// Limits which audit records are returned.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct AuditFilter {
+    /// Only records at or after this time.
+    since: Option<DateTime<Utc>>,
+    /// Only records before this time.
+    until: Option<DateTime<Utc>>,
+    /// Only records from this user.
+    uid: Option<u32>,
+    /// Only records for requests to paths starting with this prefix.
+    path: Option<String>,
+    /// Only this many of the most recent matching records.
+    limit: Option<usize>,
+}
+
+impl AuditFilter {
+    /// Builds a filter from request query parameters.
+    pub(crate) fn from_query(query: &HashMap<String, String>) -> Result<Self> {
+        let time = |name: &str| -> Result<Option<DateTime<Utc>>> {
+            query
+                .get(name)
+                .map(|t| {
+                    DateTime::parse_from_rfc3339(t)
+                        .map(|t| t.with_timezone(&Utc))
+                        .context(error::InvalidAuditTime { input: t })
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            since: time("since")?,
+            until: time("until")?,
+            uid: parse_number(query, "uid")?,
+            path: query.get("path").cloned(),
+            limit: parse_number(query, "limit")?,
+        })
+    }
+
+    fn matches(&self, record: &AuditRecord) -> bool {
+        // Each filter that's given has to match.
+        self.since.iter().all(|since| record.timestamp >= *since)
+            && self.until.iter().all(|until| record.timestamp < *until)
+            && self.uid.iter().all(|uid| record.uid == Some(*uid))
+            && self.path.iter().all(|path| record.path.starts_with(path))
+    }
+}
+
+/// Parses the named query parameter, if given, as a number of the requested type; numbers out of
+/// its range are rejected.
+fn parse_number<T: FromStr>(query: &HashMap<String, String>, name: &str) -> Result<Option<T>> {
+    query
+        .get(name)
+        .map(|n| {
+            n.parse::<T>()
+                .ok()
+                .context(error::InvalidAuditNumber { name, input: n })
+        })
+        .transpose()
+}
+
+/// Returns the hex SHA-256 hash of a line of the log.
+fn hash_line(line: &str) -> String {
+    format!("{:x}", Sha256::digest(line.as_bytes()))
+}
+
+/// Replaces the values of sensitive settings.
+fn redact(mut changes: BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+    let patterns: Vec<Pattern> = constants::SENSITIVE_SETTINGS_PATTERNS
+        .iter()
+        .filter_map(|p| Pattern::new(p).ok())
+        .collect();
+    for (key, value) in changes.iter_mut() {
+        if !value.is_null() && patterns.iter().any(|p| p.matches(key)) {
+            *value = Value::String(REDACTED.to_string());
+        }
+    }
+    changes
+}
+
+/// An audit record to be written once the request is handled.
+pub(crate) struct PendingRecord {
+    log: AuditLog,
+    peer: Option<PeerCredentials>,
+    method: String,
+    path: String,
+}
+
+impl PendingRecord {
+    /// Returns a PendingRecord if the given request should be audited, meaning it could change
+    /// the system and the server is keeping an audit log.
+    pub(crate) fn start(req: &ServiceRequest) -> Option<Self> {
+        let data = req.app_data::<web::Data<SharedData>>()?;
+        let log = data.audit_log.as_ref()?;
+        let method = req.method().as_str();
+        let path = req.path();
+        if !access::is_write(method, path) {
+            return None;
+        }
+
+        Some(Self {
+            log: log.clone(),
+            peer: req.extensions().get::<PeerCredentials>().copied(),
+            method: method.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// Writes the record, given the result of handling the request.
+    pub(crate) async fn finish(
+        self,
+        result: &std::result::Result<ServiceResponse, actix_web::Error>,
+    ) {
+        let (status, error, details) = match result {
+            Ok(response) => (
+                response.status(),
+                response.response().error().map(|e| e.to_string()),
+                response
+                    .request()
+                    .extensions()
+                    .get::<AuditDetails>()
+                    .cloned(),
+            ),
+            // Errors from middleware, like access checks, don't make it to a response.
+            Err(e) => (
+                e.as_response_error().status_code(),
+                Some(e.to_string()),
+                None,
+            ),
+        };
+        self.log
+            .append(
+                self.peer,
+                &self.method,
+                &self.path,
+                details.unwrap_or_default(),
+                status.as_u16(),
+                error,
+            )
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::commit_transaction;
+    use crate::server::watch::Watchers;
+    use actix_web::test::TestRequest;
+    use datastore::{Committed, DataStore, FilesystemDataStore, Key, KeyType};
+    use maplit::{btreemap, hashmap};
+
+    #[test]
+    fn records_are_chained() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::open(&path).unwrap();
+        let peer = PeerCredentials {
+            uid: 1000,
+            gid: 1000,
+            pid: Some(42),
+        };
+        let details = AuditDetails {
+            transaction: Some("default".to_string()),
+            changes: btreemap!(
+                "settings.motd".to_string() => "hi".into(),
+                "settings.host-containers.admin.user-data".to_string() => "secret".into(),
+            ),
+            details: None,
+        };
+        log.write(Some(peer), "PATCH", "/settings", details, 204, None);
+        // Reopening continues the chain.
+        let log = AuditLog::open(&path).unwrap();
+        log.write(
+            None,
+            "POST",
+            "/actions/reboot",
+            AuditDetails::default(),
+            403,
+            None,
+        );
+
+        let lines: Vec<String> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        let report = log.read(&AuditFilter::default()).unwrap();
+        assert_eq!(report.chain_breaks, vec![]);
+        let records = report.records;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].previous_hash, "");
+        assert_eq!(records[1].previous_hash, hash_line(&lines[0]));
+        assert_eq!(records[0].changes["settings.motd"], "hi");
+        assert_eq!(
+            records[0].changes["settings.host-containers.admin.user-data"],
+            REDACTED
+        );
+
+        let filter = AuditFilter::from_query(&hashmap!(
+            "uid".to_string() => "1000".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(log.read(&filter).unwrap().records, vec![records[0].clone()]);
+
+        let filter = AuditFilter::from_query(&hashmap!(
+            "limit".to_string() => "1".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(log.read(&filter).unwrap().records, vec![records[1].clone()]);
+
+        let filter = AuditFilter::from_query(&hashmap!(
+            "path".to_string() => "/actions".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(log.read(&filter).unwrap().records, vec![records[1].clone()]);
+    }
+
+    #[test]
+    fn bad_filters() {
+        AuditFilter::from_query(&hashmap!("since".to_string() => "yesterday".to_string()))
+            .unwrap_err();
+        AuditFilter::from_query(&hashmap!("limit".to_string() => "-1".to_string())).unwrap_err();
+        AuditFilter::from_query(&hashmap!("uid".to_string() => "4294967296".to_string()))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn chain_checked_across_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::open(&path).unwrap();
+        let write = |method| log.write(None, method, "/tx", AuditDetails::default(), 204, None);
+        write("DELETE");
+        write("PATCH");
+        log.rotate(&mut log.state.lock().unwrap()).unwrap();
+        write("POST");
+
+        let report = log.read(&AuditFilter::default()).unwrap();
+        assert_eq!(report.chain_breaks, vec![]);
+        let methods: Vec<_> = report.records.iter().map(|r| r.method.as_str()).collect();
+        assert_eq!(methods, vec!["DELETE", "PATCH", "POST"]);
+
+        // Changing a record in the rotated log breaks the chain at the next record, in the new log.
+        let rotated = rotated_path(&path);
+        let data = fs::read_to_string(&rotated).unwrap();
+        fs::write(&rotated, data.replace("PATCH", "PUT")).unwrap();
+        let report = log.read(&AuditFilter::default()).unwrap();
+        assert_eq!(report.records.len(), 3);
+        assert_eq!(report.chain_breaks.len(), 1);
+        assert_eq!(report.chain_breaks[0].file, path);
+        assert_eq!(report.chain_breaks[0].line, 1);
+
+        // Without the rotated log, the first record we have should start the chain.
+        fs::remove_file(&rotated).unwrap();
+        let report = log.read(&AuditFilter::default()).unwrap();
+        assert_eq!(report.records.len(), 1);
+        assert_eq!(report.chain_breaks.len(), 1);
+        assert_eq!(report.chain_breaks[0].expected_hash, "");
+    }
+
+    #[actix::test]
+    async fn commit_with_unwritable_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::open(&path).unwrap();
+        // Swap in a read-only handle so writes fail, even when running as root.
+        log.state.lock().unwrap().file = File::open(&path).unwrap();
+
+        let mut datastore = FilesystemDataStore::new(dir.path().join("datastore"));
+        let key = Key::new(KeyType::Data, "settings.motd").unwrap();
+        let pending = Committed::Pending {
+            tx: "default".to_string(),
+        };
+        datastore.set_key(&key, "\"hi\"", &pending).unwrap();
+        let data = web::Data::new(SharedData {
+            ds: std::sync::RwLock::new(datastore),
+            exec_socket_path: PathBuf::new(),
+            watchers: Watchers::new(),
+            access_policy: None,
+            audit_log: Some(log.clone()),
+        });
+
+        // The commit succeeds, and failing to write its record is only logged.
+        let req = TestRequest::post().uri("/tx/commit").to_http_request();
+        let query = web::Query::from_query("").unwrap();
+        let changed = commit_transaction(req.clone(), query, data.clone())
+            .await
+            .unwrap();
+        assert!(changed.0.contains(&key));
+        let details = req.extensions().get::<AuditDetails>().cloned().unwrap();
+        log.append(None, "POST", "/tx/commit", details, 200, None)
+            .await;
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        let datastore = data.ds.read().unwrap();
+        assert_eq!(
+            datastore.get_key(&key, &Committed::Live).unwrap(),
+            Some("\"hi\"".to_string())
+        );
+    }
+}
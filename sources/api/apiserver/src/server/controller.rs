@@ -3,8 +3,9 @@
 
 use bottlerocket_release::BottlerocketRelease;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::io::Write;
 use std::process::{Command, Stdio};
 
@@ -12,7 +13,10 @@ use crate::server::error::{self, Result};
 use actix_web::HttpResponse;
 use datastore::deserialization::{from_map, from_map_with_prefix};
 use datastore::serialization::to_pairs;
-use datastore::{deserialize_scalar, Committed, DataStore, Key, KeyType, ScalarError, Value};
+use datastore::{
+    deserialize_scalar, Commit, Committed, DataStore, Key, KeyType, ScalarError, TransactionRecord,
+    Value,
+};
 use model::{ConfigurationFiles, Services, Settings};
 use num::FromPrimitive;
 use std::os::unix::process::ExitStatusExt;
@@ -247,6 +251,256 @@ pub(crate) fn set_settings<D: DataStore>(
         .context(error::DataStore { op: "set_keys" })
 }
 
+/// Returns the latest generation in which any live setting starting with the given prefix was
+/// changed.  The prefix should not include "settings."
+pub(crate) fn get_settings_prefix_generation<D: DataStore, S: AsRef<str>>(
+    datastore: &D,
+    prefix: S,
+) -> Result<u64> {
+    let prefix = "settings.".to_string() + prefix.as_ref();
+    datastore
+        .prefix_generation(&prefix)
+        .context(error::DataStore {
+            op: "prefix_generation",
+        })
+}
+
+/// Returns the latest generation in which any of the live settings with the given names was
+/// changed.
+pub(crate) fn get_settings_keys_generation<D: DataStore>(
+    datastore: &D,
+    keys: &HashSet<&str>,
+) -> Result<u64> {
+    let keys = keys
+        .iter()
+        .map(|key_str| {
+            Key::new(KeyType::Data, key_str).context(error::NewKey {
+                key_type: "data",
+                name: *key_str,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    max_key_generation(datastore, &keys)
+}
+
+/// Returns the latest generation in which any of the given live settings was changed.
+fn max_key_generation<'a, D, I>(datastore: &D, keys: I) -> Result<u64>
+where
+    D: DataStore,
+    I: IntoIterator<Item = &'a Key>,
+{
+    let generations = datastore
+        .key_generations("settings.")
+        .context(error::DataStore {
+            op: "key_generations",
+        })?;
+    Ok(keys
+        .into_iter()
+        .filter_map(|key| generations.get(key))
+        .copied()
+        .max()
+        .unwrap_or(0))
+}
+
+/// Confirms that none of the given live settings has changed since the expected generation, so
+/// that a caller's changes are based on current data.
+fn check_generation<'a, D, I>(datastore: &D, keys: I, expected: u64) -> Result<()>
+where
+    D: DataStore,
+    I: IntoIterator<Item = &'a Key>,
+{
+    let actual = max_key_generation(datastore, keys)?;
+    ensure!(
+        actual <= expected,
+        error::GenerationMismatch { expected, actual }
+    );
+    Ok(())
+}
+
+/// Returns the data store keys of the settings populated in the given Settings.
+pub(crate) fn settings_keys(settings: &Settings) -> Result<HashSet<Key>> {
+    let pairs = to_pairs(settings).context(error::DataStoreSerialization { given: "Settings" })?;
+    Ok(pairs.into_keys().collect())
+}
+
+/// Returns the given settings as a map of full dotted names to values.
+pub(crate) fn settings_values(settings: &Settings) -> Result<BTreeMap<String, Value>> {
+    let pairs = to_pairs(settings).context(error::DataStoreSerialization { given: "Settings" })?;
+    let mut result = BTreeMap::new();
+    for (key, value_str) in pairs {
+        let value = deserialize_scalar::<_, ScalarError>(&value_str)
+            .context(error::InvalidValue { key: key.name() })?;
+        result.insert(key.name().clone(), value);
+    }
+    Ok(result)
+}
+
+/// Returns the keys of the settings pending in the given transaction.
+pub(crate) fn get_transaction_keys<D: DataStore>(
+    datastore: &D,
+    transaction: &str,
+) -> Result<HashSet<Key>> {
+    let pending = Committed::Pending {
+        tx: transaction.into(),
+    };
+    datastore
+        .list_populated_keys("settings.", &pending)
+        .context(error::DataStore {
+            op: "list_populated_keys",
+        })
+}
+
+/// Confirms that none of the settings in the given Settings has changed in live data since the
+/// expected generation.
+pub(crate) fn check_settings_generation<D: DataStore>(
+    datastore: &D,
+    settings: &Settings,
+    expected: u64,
+) -> Result<()> {
+    check_generation(datastore, &settings_keys(settings)?, expected)
+}
+
+/// Confirms that none of the settings pending in the given transaction has changed in live data
+/// since the expected generation.
+pub(crate) fn check_transaction_generation<D: DataStore>(
+    datastore: &D,
+    transaction: &str,
+    expected: u64,
+) -> Result<()> {
+    check_generation(
+        datastore,
+        &get_transaction_keys(datastore, transaction)?,
+        expected,
+    )
+}
+
+/// The result of checking proposed settings against the model without writing them.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct SettingsValidation {
+    /// True if all given settings fit the model.
+    pub(crate) valid: bool,
+    /// Problems found, one per invalid setting.
+    pub(crate) errors: Vec<InvalidSetting>,
+    /// The services that would be restarted if the valid settings were applied.
+    pub(crate) affected_services: BTreeSet<String>,
+    /// The configuration files that would be rewritten if the valid settings were applied.
+    pub(crate) configuration_files: BTreeSet<String>,
+}
+
+/// A setting that doesn't fit the model, and why.
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct InvalidSetting {
+    /// The full dotted name of the setting, e.g. "settings.kubernetes.cluster-name".
+    pub(crate) key: String,
+    /// The error from the model type.
+    pub(crate) error: String,
+}
+
+/// Checks the given settings, in the JSON form accepted by PATCH /settings, against the model.
+/// Each setting is deserialized on its own so that every invalid one is reported, rather than
+/// just the first.  Nothing is written to the data store.
+pub(crate) fn validate_settings<D: DataStore>(
+    datastore: &D,
+    input: &serde_json::Value,
+) -> Result<SettingsValidation> {
+    let mut validation = SettingsValidation::default();
+
+    let mut pairs = HashMap::new();
+    if let Err(e) = flatten_settings(input, &mut vec!["settings".to_string()], &mut pairs) {
+        // We can't split the input into settings, so the problem is with the whole input.
+        validation.errors.push(InvalidSetting {
+            key: "settings".to_string(),
+            error: e.to_string(),
+        });
+        return Ok(validation);
+    }
+
+    let mut valid_keys = HashSet::new();
+    for (key, value) in &pairs {
+        let single: HashMap<_, _> = std::iter::once((key.clone(), value.clone())).collect();
+        match from_map_with_prefix::<_, _, Settings, _>(Some("settings".to_string()), &single) {
+            Ok(_) => {
+                valid_keys.insert(key.name().as_str());
+            }
+            Err(e) => validation.errors.push(InvalidSetting {
+                key: key.name().clone(),
+                error: e.to_string(),
+            }),
+        }
+    }
+    validation.errors.sort_by(|a, b| a.key.cmp(&b.key));
+
+    // Settings that are fine on their own could still conflict as a whole.
+    if validation.errors.is_empty() {
+        if let Err(e) =
+            from_map_with_prefix::<_, _, Settings, _>(Some("settings".to_string()), &pairs)
+        {
+            validation.errors.push(InvalidSetting {
+                key: "settings".to_string(),
+                error: e.to_string(),
+            });
+        }
+    }
+    validation.valid = validation.errors.is_empty();
+
+    // Find what applying the valid settings would touch, the same way thar-be-settings does.
+    let affected = get_metadata_for_data_keys(datastore, "affected-services", &valid_keys)?;
+    for services in affected.values() {
+        if let Some(services) = services.as_array() {
+            validation.affected_services.extend(
+                services
+                    .iter()
+                    .filter_map(|s| s.as_str())
+                    .map(|s| s.to_string()),
+            );
+        }
+    }
+    if !validation.affected_services.is_empty() {
+        for (name, service) in get_services(datastore)? {
+            if validation.affected_services.contains(&name) {
+                validation.configuration_files.extend(
+                    service
+                        .configuration_files
+                        .iter()
+                        .map(|file| file.to_string()),
+                );
+            }
+        }
+    }
+
+    Ok(validation)
+}
+
+/// Turns the given JSON settings into data store keys and serialized values.  We don't use the
+/// data store serializer here because the input isn't typed yet; for example, JSON numbers would
+/// all be u64, which the data store doesn't accept.  Nulls are skipped, like unset Options.
+fn flatten_settings(
+    value: &serde_json::Value,
+    path: &mut Vec<String>,
+    pairs: &mut HashMap<Key, String>,
+) -> Result<()> {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (name, inner) in map {
+                path.push(name.clone());
+                flatten_settings(inner, path, pairs)?;
+                path.pop();
+            }
+        }
+        serde_json::Value::Null => {}
+        scalar => {
+            let key = Key::from_segments(KeyType::Data, &path[..]).context(error::NewKey {
+                key_type: "data",
+                name: path.join("."),
+            })?;
+            let value = serde_json::to_string(scalar).context(error::ResponseSerialization)?;
+            pairs.insert(key, value);
+        }
+    }
+    Ok(())
+}
+
 // This is not as nice as get_settings, which uses Serializer/Deserializer to properly use the
 // data model and check types.
 /// Gets the value of a metadata key for the requested list of data keys.
@@ -314,8 +568,9 @@ pub(crate) fn get_metadata_for_all_data_keys<D: DataStore, S: AsRef<str>>(
     Ok(result)
 }
 
-/// Makes live any pending settings in the datastore, returning the changed keys.
-pub(crate) fn commit_transaction<D>(datastore: &mut D, transaction: &str) -> Result<HashSet<Key>>
+/// Makes live any pending settings in the datastore, returning the committed keys and the new
+/// generation, if any.
+pub(crate) fn commit_transaction<D>(datastore: &mut D, transaction: &str) -> Result<Commit>
 where
     D: DataStore,
 {
@@ -324,6 +579,53 @@ where
         .context(error::DataStore { op: "commit" })
 }
 
+/// Returns the live values of the given keys, after a commit, for sending to clients watching
+/// settings.  Only settings are included; settings that were removed have a null value.
+pub(crate) fn get_committed_settings<D: DataStore>(
+    datastore: &D,
+    keys: &HashSet<Key>,
+) -> Result<BTreeMap<String, Value>> {
+    let mut result = BTreeMap::new();
+    for key in keys.iter().filter(|k| k.name().starts_with("settings.")) {
+        let value = match datastore
+            .get_key(key, &Committed::Live)
+            .context(error::DataStore { op: "get_key" })?
+        {
+            Some(value_str) => deserialize_scalar::<_, ScalarError>(&value_str)
+                .context(error::InvalidValue { key: key.name() })?,
+            None => Value::Null,
+        };
+        result.insert(key.name().clone(), value);
+    }
+    Ok(result)
+}
+
+/// Returns the records of recently committed transactions, oldest first.
+pub(crate) fn get_transaction_history<D>(datastore: &D) -> Result<Vec<TransactionRecord>>
+where
+    D: DataStore,
+{
+    datastore
+        .list_history()
+        .context(error::DataStore { op: "list_history" })
+}
+
+/// Restores live settings to the given generation of the datastore by reverting the changes made
+/// in later transactions, returning the changed keys and the new generation, if any.
+pub(crate) fn rollback_transactions<D>(datastore: &mut D, generation: u64) -> Result<Commit>
+where
+    D: DataStore,
+{
+    match datastore.rollback_to_generation(generation) {
+        Ok(commit) => Ok(commit),
+        // Let the user know they asked for a generation we can't restore.
+        Err(e @ datastore::Error::HistoryNotFound { .. }) => {
+            Err(e).context(error::RollbackGeneration { generation })
+        }
+        Err(e) => Err(e).context(error::DataStore { op: "rollback" }),
+    }
+}
+
 /// Launches the config applier to make appropriate changes to the system based on any settings
 /// that have been committed.  Can be called after a commit, with the keys that changed in that
 /// commit, or called on its own to reset configuration state with all known keys.
@@ -623,4 +925,127 @@ mod test {
         let settings = get_settings(&ds, &Committed::Live).unwrap();
         assert_eq!(settings.motd, Some("json string".try_into().unwrap()));
     }
+
+    #[test]
+    fn rollback_works() {
+        let mut ds = MemoryDataStore::new();
+        let tx = "test transaction";
+        let pending = Committed::Pending { tx: tx.into() };
+        let motd = Key::new(KeyType::Data, "settings.motd").unwrap();
+
+        ds.set_key(&motd, "\"first\"", &pending).unwrap();
+        commit_transaction(&mut ds, tx).unwrap();
+        ds.set_key(&motd, "\"second\"", &pending).unwrap();
+        commit_transaction(&mut ds, tx).unwrap();
+
+        let history = get_transaction_history(&ds).unwrap();
+        assert_eq!(history.len(), 2);
+
+        // Restore the first generation
+        let commit = rollback_transactions(&mut ds, history[0].id).unwrap();
+        assert_eq!(commit.keys, hashset!(motd));
+        assert_eq!(commit.generation, Some(3));
+        let settings = get_settings(&ds, &Committed::Live).unwrap();
+        assert_eq!(settings.motd, Some("first".try_into().unwrap()));
+
+        // Generations that were never created can't be restored
+        match rollback_transactions(&mut ds, 42) {
+            Err(error::Error::RollbackGeneration { .. }) => {}
+            other => panic!("Expected RollbackGeneration error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn generation_check_works() {
+        let mut ds = MemoryDataStore::new();
+        let tx = "test transaction";
+        let pending = Committed::Pending { tx: tx.into() };
+        let motd = Key::new(KeyType::Data, "settings.motd").unwrap();
+
+        ds.set_key(&motd, "\"first\"", &pending).unwrap();
+        commit_transaction(&mut ds, tx).unwrap();
+        let generation = get_settings_prefix_generation(&ds, "motd").unwrap();
+        assert_eq!(generation, 1);
+        assert_eq!(
+            get_settings_keys_generation(&ds, &hashset!("settings.motd")).unwrap(),
+            1
+        );
+
+        // Nothing has changed since the generation we read
+        let settings = Settings {
+            motd: Some("second".try_into().unwrap()),
+            ..Default::default()
+        };
+        check_settings_generation(&ds, &settings, generation).unwrap();
+        set_settings(&mut ds, &settings, tx).unwrap();
+        check_transaction_generation(&ds, tx, generation).unwrap();
+
+        // Someone else changes the setting underneath us
+        ds.set_key(
+            &motd,
+            "\"other\"",
+            &Committed::Pending { tx: "other".into() },
+        )
+        .unwrap();
+        commit_transaction(&mut ds, "other").unwrap();
+        match check_transaction_generation(&ds, tx, generation) {
+            Err(error::Error::GenerationMismatch { expected, actual }) => {
+                assert_eq!(expected, 1);
+                assert_eq!(actual, 2);
+            }
+            other => panic!("Expected GenerationMismatch error, got {:?}", other),
+        }
+        check_settings_generation(&ds, &settings, generation).unwrap_err();
+    }
+
+    #[test]
+    fn validate_settings_works() {
+        let mut ds = MemoryDataStore::new();
+        ds.set_metadata(
+            &Key::new(KeyType::Meta, "affected-services").unwrap(),
+            &Key::new(KeyType::Data, "settings.motd").unwrap(),
+            "[\"motd\"]",
+        )
+        .unwrap();
+        ds.set_key(
+            &Key::new(KeyType::Data, "services.motd.configuration-files").unwrap(),
+            "[\"motd-file\"]",
+            &Committed::Live,
+        )
+        .unwrap();
+        ds.set_key(
+            &Key::new(KeyType::Data, "services.motd.restart-commands").unwrap(),
+            "[]",
+            &Committed::Live,
+        )
+        .unwrap();
+
+        let input = serde_json::json!({
+            "motd": "hi",
+            "ntp": {"time-servers": ["not a url"]},
+            "bogus": 1,
+        });
+        let validation = validate_settings(&ds, &input).unwrap();
+        assert!(!validation.valid);
+        let invalid: Vec<_> = validation.errors.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(invalid, vec!["settings.bogus", "settings.ntp.time-servers"]);
+        assert_eq!(
+            validation.affected_services.into_iter().collect::<Vec<_>>(),
+            vec!["motd"]
+        );
+        assert_eq!(
+            validation
+                .configuration_files
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec!["motd-file"]
+        );
+
+        // Nothing was written
+        assert!(ds.list_transactions().unwrap().is_empty());
+
+        let validation = validate_settings(&ds, &serde_json::json!({"motd": "hi"})).unwrap();
+        assert!(validation.valid);
+        assert!(validation.errors.is_empty());
+    }
 }
@@ -34,6 +34,45 @@ pub enum Error {
     // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
     // Server errors
+    #[snafu(display("Access denied: {}", reason))]
+    AccessDenied { reason: String },
+
+    #[snafu(display("Failed to read access policy '{}': {}", path.display(), source))]
+    ReadPolicy { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Invalid access policy '{}': {}", path.display(), source))]
+    ParsePolicy {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[snafu(display("Failed to open audit log '{}': {}", path.display(), source))]
+    AuditLogOpen { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to read audit log '{}': {}", path.display(), source))]
+    AuditLogRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Invalid record in audit log '{}': {}", path.display(), source))]
+    AuditRecordParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Invalid time '{}', expected RFC 3339: {}", input, source))]
+    InvalidAuditTime {
+        input: String,
+        source: chrono::ParseError,
+    },
+
+    #[snafu(display("Invalid number '{}' for '{}'", input, name))]
+    InvalidAuditNumber { name: String, input: String },
+
+    #[snafu(display("Server is not keeping an audit log"))]
+    NoAuditLog,
+
+    #[snafu(display("No certificate report; certdog hasn't checked certificates yet"))]
+    NoCertificateReport,
+
     #[snafu(display("Missing required input '{}'", input))]
     MissingInput { input: String },
 
@@ -55,6 +94,22 @@ pub enum Error {
     #[snafu(display("Tried to commit with no pending changes"))]
     CommitWithNoPending,
 
+    #[snafu(display(
+        "Settings changed since generation {}; now at generation {}",
+        expected,
+        actual
+    ))]
+    GenerationMismatch { expected: u64, actual: u64 },
+
+    #[snafu(display("Invalid If-Match header '{}', expected a quoted generation", input))]
+    InvalidIfMatch { input: String },
+
+    #[snafu(display("Invalid generation '{}': {}", input, source))]
+    InvalidGeneration {
+        input: String,
+        source: std::num::ParseIntError,
+    },
+
     #[snafu(display("Unable to get OS release data: {}", source))]
     ReleaseData { source: bottlerocket_release::Error },
 
@@ -94,6 +149,12 @@ pub enum Error {
         source: serde_json::Error,
     },
 
+    #[snafu(display("Unable to roll back to generation {}: {}", generation, source))]
+    RollbackGeneration {
+        generation: u64,
+        source: datastore::Error,
+    },
+
     #[snafu(display("Unable to make {} key '{}': {}", key_type, name, source))]
     NewKey {
         key_type: String,
@@ -107,6 +168,12 @@ pub enum Error {
         source: serde_json::Error,
     },
 
+    #[snafu(display("Value of '{}' is not valid JSON: {}", key, source))]
+    InvalidValue {
+        key: String,
+        source: serde_json::Error,
+    },
+
     #[snafu(display("Config applier was unable to fork child, returned {}", code))]
     ConfigApplierFork { code: String },
 
@@ -164,6 +231,12 @@ pub enum Error {
     #[snafu(display("Failed to parse update status: {} ", source))]
     UpdateStatusParse { source: serde_json::Error },
 
+    #[snafu(display("Unable to read drift reports: {}", source))]
+    DriftReports { source: thar_be_settings::Error },
+
+    #[snafu(display("Unable to read certificate report: {}", source))]
+    CertificateReport { source: certdog::Error },
+
     #[snafu(display(
         "Failed to parse update information from '{}': {} ",
         String::from_utf8_lossy(stdout),
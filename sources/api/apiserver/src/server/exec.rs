@@ -10,6 +10,8 @@
 // the WebSocket actors and the child, it's not async either - it uses standard threads and
 // channels.  See its docs for more detail.
 
+use crate::server::access::PeerCredentials;
+use crate::server::audit::{AuditDetails, AuditLog};
 use actix::prelude::{Actor, ActorContext, AsyncContext, Handler, StreamHandler};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws::{self, Message};
@@ -67,7 +69,17 @@ pub(crate) async fn ws_exec(
         r.path()
     );
 
-    ws::start(WsExec::new(data.exec_socket_path.clone()), &r, stream)
+    // The audit log records the start of the session; we also record the command once we get it.
+    let audit = data.audit_log.clone().map(|log| {
+        let peer = r.extensions().get::<PeerCredentials>().copied();
+        (log, peer)
+    });
+
+    ws::start(
+        WsExec::new(data.exec_socket_path.clone(), audit),
+        &r,
+        stream,
+    )
 }
 
 /// WsExec is an actor that represents the WebSocket connection to the client.  All messages to and
@@ -89,14 +101,19 @@ pub(crate) struct WsExec {
     /// This represents the path to the containerd socket that we use to spawn the requested
     /// process in a container namespace.
     exec_socket_path: PathBuf,
+
+    /// If the server keeps an audit log, the log and the client's credentials, so we can record
+    /// the requested command.
+    audit: Option<(AuditLog, Option<PeerCredentials>)>,
 }
 
 impl WsExec {
-    fn new(exec_socket_path: PathBuf) -> Self {
+    fn new(exec_socket_path: PathBuf, audit: Option<(AuditLog, Option<PeerCredentials>)>) -> Self {
         Self {
             heartbeat: Instant::now(),
             child_handles: None,
             exec_socket_path,
+            audit,
         }
     }
 
@@ -228,6 +245,17 @@ impl StreamHandler<Result<Message, ws::ProtocolError>> for WsExec {
                                init.target,
                                init.command,
                                init.tty.is_some());
+                        if let Some((log, peer)) = &self.audit {
+                            let details = AuditDetails {
+                                details: Some(format!(
+                                    "target '{}' command {:?}",
+                                    init.target, init.command
+                                )),
+                                ..Default::default()
+                            };
+                            // 101 Switching Protocols, like the request that started the session.
+                            actix::spawn(log.append(*peer, "GET", "/exec", details, 101, None));
+                        }
                         // Spawn the process, getting back handles that let us interact with it.
                         let child_handles = ok_or_stop!(
                             ChildHandles::new(init, &self.exec_socket_path, ctx.address()),
@@ -1,32 +1,40 @@
 //! The server module owns the API surface.  It interfaces with the datastore through the
 //! server::controller module.
 
+mod access;
+mod audit;
 mod controller;
 mod error;
 mod exec;
+mod watch;
 
 pub use error::Error;
 
 use actix_web::{
-    body::Body, error::ResponseError, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+    body::Body, dev::Service, error::ResponseError, http::header, web, App, HttpRequest,
+    HttpResponse, HttpServer, Responder,
 };
 use bottlerocket_release::BottlerocketRelease;
-use datastore::{Committed, FilesystemDataStore, Key, Value};
+use certdog::{CertificateReport, CERTIFICATE_REPORT_PATH};
+use datastore::{Commit, Committed, DataStore, FilesystemDataStore, Key, TransactionRecord, Value};
 use error::Result;
 use fs2::FileExt;
 use http::StatusCode;
-use log::info;
+use log::{error, info};
 use model::{ConfigurationFiles, Model, Services, Settings};
 use nix::unistd::{chown, Gid};
 use snafu::{ensure, OptionExt, ResultExt};
 use std::collections::{HashMap, HashSet};
 use std::env;
-use std::fs::{set_permissions, File, Permissions};
+use std::fs::{self, set_permissions, File, Permissions};
+use std::io;
 use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
 use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync;
+use thar_be_settings::drift::{DriftReport, DRIFT_REPORT_DIR};
 use thar_be_updates::status::{UpdateStatus, UPDATE_LOCKFILE};
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
@@ -55,24 +63,37 @@ fn notify_unix_socket_ready() -> Result<()> {
 /// This is the primary interface of the module.  It defines the server and application that actix
 /// spawns for requests.  It creates a shared datastore handle that can be used by handler methods
 /// to interface with the controller.
+/// If an access policy path is given, callers are limited to the access it grants them; see the
+/// `access` module for the format.  If an audit log path is given, requests that change the system
+/// are recorded there; see the `audit` module.
 pub async fn serve<P1, P2, P3>(
     socket_path: P1,
     datastore_path: P2,
     threads: usize,
     socket_gid: Option<Gid>,
     exec_socket_path: P3,
+    access_policy_path: Option<&Path>,
+    audit_log_path: Option<&Path>,
 ) -> Result<()>
 where
     P1: AsRef<Path>,
     P2: AsRef<Path>,
     P3: Into<PathBuf>,
 {
+    let access_policy = access_policy_path
+        .map(access::AccessPolicy::from_path)
+        .transpose()?;
+    let audit_log = audit_log_path.map(audit::AuditLog::open).transpose()?;
+
     // SharedData gives us a convenient way to make data available to handler methods when it
     // doesn't come from the request itself.  It's easier than the ownership tricks required to
     // pass parameters to the handler methods.
     let shared_data = web::Data::new(SharedData {
         ds: sync::RwLock::new(FilesystemDataStore::new(datastore_path)),
         exec_socket_path: exec_socket_path.into(),
+        watchers: watch::Watchers::new(),
+        access_policy,
+        audit_log,
     });
 
     let http_server = HttpServer::new(move || {
@@ -80,17 +101,39 @@ where
             // This makes the data store available to API methods merely by having a Data
             // parameter.
             .app_data(shared_data.clone())
+            // Check that the caller is allowed to use the requested route.
+            .wrap_fn(|req, srv| {
+                let call = access::authorize_request(&req).map(|_| srv.call(req));
+                async move { call?.await }
+            })
+            // Record requests that change the system.  This is registered after the access check
+            // so it runs first, and also records denied requests.
+            .wrap_fn(|req, srv| {
+                let record = audit::PendingRecord::start(&req);
+                let call = srv.call(req);
+                async move {
+                    let result = call.await;
+                    if let Some(record) = record {
+                        record.finish(&result).await;
+                    }
+                    result
+                }
+            })
             // Retrieve the full API model; not all data is writable, so we only support GET.
             .route("/", web::get().to(get_model))
             .service(
                 web::scope("/settings")
                     .route("", web::get().to(get_settings))
-                    .route("", web::patch().to(patch_settings)),
+                    .route("", web::patch().to(patch_settings))
+                    .route("/validate", web::post().to(validate_settings))
+                    .route("/watch", web::get().to(watch::watch_settings)),
             )
             .service(
                 // Transaction support
                 web::scope("/tx")
                     .route("/list", web::get().to(get_transaction_list))
+                    .route("/history", web::get().to(get_transaction_history))
+                    .route("/rollback", web::post().to(rollback_transactions))
                     .route("", web::get().to(get_transaction))
                     .route("", web::delete().to(delete_transaction))
                     .route("/commit", web::post().to(commit_transaction))
@@ -116,15 +159,21 @@ where
                 web::scope("/actions")
                     .route("/reboot", web::post().to(reboot))
                     .route("/refresh-updates", web::post().to(refresh_updates))
+                    .route("/download-update", web::post().to(download_update))
                     .route("/prepare-update", web::post().to(prepare_update))
                     .route("/activate-update", web::post().to(activate_update))
                     .route("/deactivate-update", web::post().to(deactivate_update)),
             )
             .service(web::scope("/updates").route("/status", web::get().to(get_update_status)))
             .service(web::resource("/exec").route(web::get().to(exec::ws_exec)))
+            .service(web::scope("/audit").route("", web::get().to(get_audit_records)))
+            .service(web::scope("/drift").route("", web::get().to(get_drift_reports)))
+            .service(web::scope("/certificates").route("", web::get().to(get_certificate_report)))
     })
     .workers(threads)
-    .bind_uds(socket_path.as_ref())
+    // Save the credentials of each caller so we can check their access.
+    .on_connect(access::save_peer_credentials)
+    .listen_uds(bind_socket(socket_path.as_ref())?)
     .context(error::BindSocket {
         path: socket_path.as_ref(),
     })?;
@@ -145,6 +194,19 @@ where
     http_server.run().await.context(error::ServerStart)
 }
 
+/// Binds the Unix-domain socket for the server.  actix-web can do this itself with bind_uds, but
+/// then it doesn't call our on_connect function, which we need to get caller credentials.
+fn bind_socket(socket_path: &Path) -> Result<UnixListener> {
+    // Like actix-web, remove any old socket so we can bind.
+    match fs::remove_file(socket_path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            return Err(e).context(error::BindSocket { path: socket_path })
+        }
+        _ => {}
+    }
+    UnixListener::bind(socket_path).context(error::BindSocket { path: socket_path })
+}
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 // Handler methods called by the router
@@ -170,47 +232,127 @@ async fn get_model(data: web::Data<SharedData>) -> Result<ModelResponse> {
 // actix-web doesn't support Query for enums, so we use a HashMap and check for the expected keys
 // ourselves.
 /// Return the live settings from the data store; if 'keys' or 'prefix' are specified in query
-/// parameters, return the subset of matching settings.
+/// parameters, return the subset of matching settings.  The ETag of the response is the latest
+/// generation in which any of the requested settings changed, and can be given in If-Match when
+/// changing settings.
 async fn get_settings(
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
-) -> Result<SettingsResponse> {
+) -> Result<GenerationSettingsResponse> {
     let datastore = data.ds.read().ok().context(error::DataStoreLock)?;
 
-    let settings = if let Some(keys_str) = query.get("keys") {
+    let (settings, generation) = if let Some(keys_str) = query.get("keys") {
         let keys = comma_separated("keys", keys_str)?;
-        controller::get_settings_keys(&*datastore, &keys, &Committed::Live)
+        (
+            controller::get_settings_keys(&*datastore, &keys, &Committed::Live)?,
+            controller::get_settings_keys_generation(&*datastore, &keys)?,
+        )
     } else if let Some(prefix_str) = query.get("prefix") {
         if prefix_str.is_empty() {
             return error::EmptyInput { input: "prefix" }.fail();
         }
         // Note: the prefix should not include "settings."
-        controller::get_settings_prefix(&*datastore, prefix_str, &Committed::Live)
+        (
+            controller::get_settings_prefix(&*datastore, prefix_str, &Committed::Live)?,
+            controller::get_settings_prefix_generation(&*datastore, prefix_str)?,
+        )
     } else {
-        controller::get_settings(&*datastore, &Committed::Live)
-    }?;
+        (
+            controller::get_settings(&*datastore, &Committed::Live)?,
+            controller::get_settings_prefix_generation(&*datastore, "")?,
+        )
+    };
 
-    Ok(SettingsResponse(settings))
+    Ok(GenerationSettingsResponse {
+        settings,
+        generation,
+    })
 }
 
-/// Apply the requested settings to the pending data store
+/// Apply the requested settings to the pending data store.  If an If-Match header is given, the
+/// request fails unless none of the requested settings have changed since that generation.
 async fn patch_settings(
+    req: HttpRequest,
     settings: web::Json<Settings>,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
 ) -> Result<HttpResponse> {
     let transaction = transaction_name(&query);
+    let expected_generation = if_match_generation(&req)?;
+    access::authorize_settings(&req, &data, Some(&controller::settings_keys(&settings)?))?;
+    audit::add_details(
+        &req,
+        audit::AuditDetails {
+            transaction: Some(transaction.to_string()),
+            changes: controller::settings_values(&settings)?,
+            ..Default::default()
+        },
+    );
     let mut datastore = data.ds.write().ok().context(error::DataStoreLock)?;
+    if let Some(expected) = expected_generation {
+        controller::check_settings_generation(&*datastore, &settings, expected)?;
+    }
     controller::set_settings(&mut *datastore, &settings, transaction)?;
     Ok(HttpResponse::NoContent().finish()) // 204
 }
 
+/// Check the given settings against the model without changing the data store.  Reports each
+/// invalid setting, and the services and configuration files that applying the valid settings
+/// would affect.
+async fn validate_settings(
+    settings: web::Json<serde_json::Value>,
+    data: web::Data<SharedData>,
+) -> Result<SettingsValidationResponse> {
+    let datastore = data.ds.read().ok().context(error::DataStoreLock)?;
+    let validation = controller::validate_settings(&*datastore, &settings)?;
+    Ok(SettingsValidationResponse(validation))
+}
+
 async fn get_transaction_list(data: web::Data<SharedData>) -> Result<TransactionListResponse> {
     let datastore = data.ds.read().ok().context(error::DataStoreLock)?;
     let data = controller::list_transactions(&*datastore)?;
     Ok(TransactionListResponse(data))
 }
 
+/// Get the records of recently committed transactions, oldest first.
+async fn get_transaction_history(
+    data: web::Data<SharedData>,
+) -> Result<TransactionHistoryResponse> {
+    let datastore = data.ds.read().ok().context(error::DataStoreLock)?;
+    let data = controller::get_transaction_history(&*datastore)?;
+    Ok(TransactionHistoryResponse(data))
+}
+
+/// Restores live settings to the generation given by 'id', reverting any transactions committed
+/// after it, then applies the changes.  Returns the list of changed keys.
+async fn rollback_transactions(
+    req: HttpRequest,
+    query: web::Query<HashMap<String, String>>,
+    data: web::Data<SharedData>,
+) -> Result<ChangedKeysResponse> {
+    // A rollback could change any setting.
+    access::authorize_settings(&req, &data, None::<&HashSet<Key>>)?;
+    let id_str = query
+        .get("id")
+        .context(error::MissingInput { input: "id" })?;
+    let generation = id_str
+        .parse::<u64>()
+        .context(error::InvalidGeneration { input: id_str })?;
+    let mut datastore = data.ds.write().ok().context(error::DataStoreLock)?;
+
+    let commit = controller::rollback_transactions(&mut *datastore, generation)?;
+
+    if !commit.keys.is_empty() {
+        let transaction = format!("rollback-to-{}", generation);
+        audit_changes(&req, &*datastore, &transaction, &commit.keys);
+        notify_watchers(&data, &*datastore, &transaction, &commit);
+        let key_names = commit.keys.iter().map(|k| k.name()).collect();
+        controller::apply_changes(Some(&key_names))?;
+    }
+
+    Ok(ChangedKeysResponse(commit.keys))
+}
+
 /// Get any pending settings in the given transaction, or the "default" transaction if unspecified.
 async fn get_transaction(
     query: web::Query<HashMap<String, String>>,
@@ -224,38 +366,131 @@ async fn get_transaction(
 
 /// Delete the given transaction, or the "default" transaction if unspecified.
 async fn delete_transaction(
+    req: HttpRequest,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
 ) -> Result<ChangedKeysResponse> {
     let transaction = transaction_name(&query);
     let mut datastore = data.ds.write().ok().context(error::DataStoreLock)?;
     let deleted = controller::delete_transaction(&mut *datastore, transaction)?;
+    audit::add_details(
+        &req,
+        audit::AuditDetails {
+            transaction: Some(transaction.to_string()),
+            changes: deleted
+                .iter()
+                .map(|k| (k.name().clone(), Value::Null))
+                .collect(),
+            ..Default::default()
+        },
+    );
     Ok(ChangedKeysResponse(deleted))
 }
 
 /// Save settings changes from the given transaction, or the "default" transaction if unspecified,
-/// to the live data store.  Returns the list of changed keys.
+/// to the live data store.  Returns the list of changed keys.  If an If-Match header is given, the
+/// commit fails unless none of the pending settings have changed since that generation.
 async fn commit_transaction(
+    req: HttpRequest,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
 ) -> Result<ChangedKeysResponse> {
     let transaction = transaction_name(&query);
+    let expected_generation = if_match_generation(&req)?;
     let mut datastore = data.ds.write().ok().context(error::DataStoreLock)?;
+    let pending_keys = controller::get_transaction_keys(&*datastore, transaction)?;
+    access::authorize_settings(&req, &data, Some(&pending_keys))?;
+    if let Some(expected) = expected_generation {
+        controller::check_transaction_generation(&*datastore, transaction, expected)?;
+    }
 
-    let changes = controller::commit_transaction(&mut *datastore, transaction)?;
+    let commit = controller::commit_transaction(&mut *datastore, transaction)?;
 
-    if changes.is_empty() {
+    if commit.keys.is_empty() {
         return error::CommitWithNoPending.fail();
     }
+    audit_changes(&req, &*datastore, transaction, &commit.keys);
+    notify_watchers(&data, &*datastore, transaction, &commit);
+
+    Ok(ChangedKeysResponse(commit.keys))
+}
+
+/// Adds committed settings changes to the audit record for the request.  The commit has already
+/// succeeded by the time we get here, so if the new values can't be read, we log the failure and
+/// record only the changed keys.
+fn audit_changes<D: DataStore>(
+    req: &HttpRequest,
+    datastore: &D,
+    transaction: &str,
+    changes: &HashSet<Key>,
+) {
+    let changes = match controller::get_committed_settings(datastore, changes) {
+        Ok(settings) => settings,
+        Err(e) => {
+            error!(
+                "Unable to audit the values committed by transaction '{}': {}",
+                transaction, e
+            );
+            changes
+                .iter()
+                .map(|k| (k.name().clone(), Value::Null))
+                .collect()
+        }
+    };
+    audit::add_details(
+        req,
+        audit::AuditDetails {
+            transaction: Some(transaction.to_string()),
+            changes,
+            ..Default::default()
+        },
+    );
+}
 
-    Ok(ChangedKeysResponse(changes))
+/// Sends committed settings changes to any clients watching settings.  The commit has already
+/// succeeded by the time we get here, so failures are logged rather than returned to the client.
+fn notify_watchers<D: DataStore>(
+    data: &SharedData,
+    datastore: &D,
+    transaction: &str,
+    commit: &Commit,
+) {
+    // Nothing to send if no live value changed.
+    let generation = match commit.generation {
+        Some(generation) => generation,
+        None => return,
+    };
+    match controller::get_committed_settings(datastore, &commit.keys) {
+        Ok(settings) if !settings.is_empty() => {
+            data.watchers.notify(watch::SettingsChange {
+                generation,
+                transaction: transaction.to_string(),
+                settings,
+            });
+        }
+        Ok(_) => {}
+        Err(e) => error!(
+            "Unable to notify watchers of transaction '{}': {}",
+            transaction, e
+        ),
+    }
 }
 
 /// Starts settings appliers for any changes that have been committed to the data store.  This
 /// updates config files, runs restart commands, etc.
-async fn apply_changes(query: web::Query<HashMap<String, String>>) -> Result<HttpResponse> {
+async fn apply_changes(
+    req: HttpRequest,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
     if let Some(keys_str) = query.get("keys") {
         let keys = comma_separated("keys", keys_str)?;
+        audit::add_details(
+            &req,
+            audit::AuditDetails {
+                changes: keys.iter().map(|k| (k.to_string(), Value::Null)).collect(),
+                ..Default::default()
+            },
+        );
         controller::apply_changes(Some(&keys))?;
     } else {
         controller::apply_changes(None as Option<&HashSet<&str>>)?;
@@ -266,24 +501,33 @@ async fn apply_changes(query: web::Query<HashMap<String, String>>) -> Result<Htt
 
 /// Usually you want to apply settings changes you've committed, so this is a convenience method to
 /// perform both a commit and an apply.  Commits the given transaction, or the "default"
-/// transaction if unspecified.
+/// transaction if unspecified.  Accepts an If-Match header like commit_transaction.
 async fn commit_transaction_and_apply(
+    req: HttpRequest,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
 ) -> Result<ChangedKeysResponse> {
     let transaction = transaction_name(&query);
+    let expected_generation = if_match_generation(&req)?;
     let mut datastore = data.ds.write().ok().context(error::DataStoreLock)?;
+    let pending_keys = controller::get_transaction_keys(&*datastore, transaction)?;
+    access::authorize_settings(&req, &data, Some(&pending_keys))?;
+    if let Some(expected) = expected_generation {
+        controller::check_transaction_generation(&*datastore, transaction, expected)?;
+    }
 
-    let changes = controller::commit_transaction(&mut *datastore, transaction)?;
+    let commit = controller::commit_transaction(&mut *datastore, transaction)?;
 
-    if changes.is_empty() {
+    if commit.keys.is_empty() {
         return error::CommitWithNoPending.fail();
     }
+    audit_changes(&req, &*datastore, transaction, &commit.keys);
+    notify_watchers(&data, &*datastore, transaction, &commit);
 
-    let key_names = changes.iter().map(|k| k.name()).collect();
+    let key_names = commit.keys.iter().map(|k| k.name()).collect();
     controller::apply_changes(Some(&key_names))?;
 
-    Ok(ChangedKeysResponse(changes))
+    Ok(ChangedKeysResponse(commit.keys))
 }
 
 async fn get_os_info() -> Result<BottlerocketReleaseResponse> {
@@ -367,7 +611,14 @@ async fn get_configuration_files(
 /// Get the update status from 'thar-be-updates'
 async fn get_update_status() -> Result<UpdateStatusResponse> {
     let lockfile = File::create(UPDATE_LOCKFILE).context(error::UpdateLockOpen)?;
-    lockfile.try_lock_shared().context(error::UpdateShareLock)?;
+    // While an update command holds the write lock, we still report the status from before it
+    // started, along with its progress.  thar-be-updates replaces the status file atomically, so
+    // it's always whole.
+    if let Err(e) = FileExt::try_lock_shared(&lockfile) {
+        if e.raw_os_error() != fs2::lock_contended_error().raw_os_error() {
+            return Err(e).context(error::UpdateShareLock);
+        }
+    }
     let result = thar_be_updates::status::get_update_status(&lockfile);
     match result {
         Ok(update_status) => Ok(UpdateStatusResponse(update_status)),
@@ -380,11 +631,46 @@ async fn get_update_status() -> Result<UpdateStatusResponse> {
     }
 }
 
+/// Get records from the audit log, oldest first, along with any breaks in the chain of hashes
+/// between records.  The records can be limited with the query parameters 'since' and 'until'
+/// (RFC 3339 times), 'uid', 'path' (a prefix of the request path), and 'limit' (the number of most
+/// recent matching records); the whole chain is checked either way.
+async fn get_audit_records(
+    query: web::Query<HashMap<String, String>>,
+    data: web::Data<SharedData>,
+) -> Result<AuditReportResponse> {
+    let log = data.audit_log.as_ref().context(error::NoAuditLog)?;
+    let filter = audit::AuditFilter::from_query(&query)?;
+    Ok(AuditReportResponse(log.read(&filter)?))
+}
+
+/// Get the latest report from each program that checks whether the system has drifted from
+/// settings.  The list is empty until the first drift check runs.
+async fn get_drift_reports() -> Result<DriftReportsResponse> {
+    let reports =
+        thar_be_settings::drift::read_reports(DRIFT_REPORT_DIR).context(error::DriftReports)?;
+    Ok(DriftReportsResponse(reports))
+}
+
+/// Get the latest report from `certdog check` of when the certificates in `settings.pki` expire.
+async fn get_certificate_report() -> Result<CertificateReportResponse> {
+    let report = CertificateReport::read(CERTIFICATE_REPORT_PATH)
+        .context(error::CertificateReport)?
+        .context(error::NoCertificateReport)?;
+    Ok(CertificateReportResponse(report))
+}
+
 /// Refreshes the list of updates and checks if an update is available matching the configured version lock
 async fn refresh_updates() -> Result<HttpResponse> {
     controller::dispatch_update_command(&["refresh"])
 }
 
+/// Downloads the chosen update without writing it to disk, so it can be prepared later without
+/// network access
+async fn download_update() -> Result<HttpResponse> {
+    controller::dispatch_update_command(&["download"])
+}
+
 /// Prepares update by downloading the images to the staging partition set
 async fn prepare_update() -> Result<HttpResponse> {
     controller::dispatch_update_command(&["prepare"])
@@ -432,6 +718,35 @@ fn comma_separated<'a>(key_name: &'static str, input: &'a str) -> Result<HashSet
     Ok(input.split(',').collect())
 }
 
+/// Parses the If-Match header, if given, into the generation the client expects data to be at.
+/// Returns None if there's no header or it's "*", since then any generation is acceptable.
+fn if_match_generation(req: &HttpRequest) -> Result<Option<u64>> {
+    let value = match req.headers().get(header::IF_MATCH) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let value_str = value.to_str().ok().context(error::InvalidIfMatch {
+        input: String::from_utf8_lossy(value.as_bytes()),
+    })?;
+
+    let tag = value_str.trim();
+    if tag == "*" {
+        return Ok(None);
+    }
+    // We only give out strong ETags, but accept weak ones, since they name the same generation.
+    let tag = tag.strip_prefix("W/").unwrap_or(tag);
+    tag.strip_prefix('"')
+        .and_then(|tag| tag.strip_suffix('"'))
+        .and_then(|tag| tag.parse::<u64>().ok())
+        .map(Some)
+        .context(error::InvalidIfMatch { input: value_str })
+}
+
+/// Formats a data store generation as an ETag header value.
+fn generation_etag(generation: u64) -> String {
+    format!("\"{}\"", generation)
+}
+
 fn transaction_name(query: &web::Query<HashMap<String, String>>) -> &str {
     if let Some(name_str) = query.get("tx") {
         name_str
@@ -450,6 +765,13 @@ impl ResponseError for error::Error {
             MissingInput { .. } => StatusCode::BAD_REQUEST,
             EmptyInput { .. } => StatusCode::BAD_REQUEST,
             NewKey { .. } => StatusCode::BAD_REQUEST,
+            InvalidGeneration { .. } => StatusCode::BAD_REQUEST,
+            InvalidIfMatch { .. } => StatusCode::BAD_REQUEST,
+            InvalidAuditTime { .. } => StatusCode::BAD_REQUEST,
+            InvalidAuditNumber { .. } => StatusCode::BAD_REQUEST,
+
+            // 403 Forbidden
+            AccessDenied { .. } => StatusCode::FORBIDDEN,
 
             // 404 Not Found
             MissingData { .. } => StatusCode::NOT_FOUND,
@@ -457,6 +779,9 @@ impl ResponseError for error::Error {
             UpdateDoesNotExist { .. } => StatusCode::NOT_FOUND,
             NoStagedImage { .. } => StatusCode::NOT_FOUND,
             UninitializedUpdateStatus { .. } => StatusCode::NOT_FOUND,
+            RollbackGeneration { .. } => StatusCode::NOT_FOUND,
+            NoAuditLog => StatusCode::NOT_FOUND,
+            NoCertificateReport => StatusCode::NOT_FOUND,
 
             // 422 Unprocessable Entity
             CommitWithNoPending => StatusCode::UNPROCESSABLE_ENTITY,
@@ -467,11 +792,17 @@ impl ResponseError for error::Error {
 
             // 409 Conflict
             DisallowCommand { .. } => StatusCode::CONFLICT,
+            GenerationMismatch { .. } => StatusCode::CONFLICT,
 
             // 500 Internal Server Error
             DataStoreLock => StatusCode::INTERNAL_SERVER_ERROR,
             ResponseSerialization { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             BindSocket { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ReadPolicy { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ParsePolicy { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AuditLogOpen { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AuditLogRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AuditRecordParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ServerStart { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ListedKeyNotPresent { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             DataStore { .. } => StatusCode::INTERNAL_SERVER_ERROR,
@@ -479,6 +810,7 @@ impl ResponseError for error::Error {
             DataStoreSerialization { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             CommandSerialization { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             InvalidMetadata { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            InvalidValue { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierFork { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierStart { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierStdin {} => StatusCode::INTERNAL_SERVER_ERROR,
@@ -494,6 +826,8 @@ impl ResponseError for error::Error {
             UpdateDispatcher { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateStatusParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            DriftReports { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            CertificateReport { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateInfoParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateLockOpen { .. } => StatusCode::INTERNAL_SERVER_ERROR,
         };
@@ -507,6 +841,9 @@ impl ResponseError for error::Error {
 pub(crate) struct SharedData {
     ds: sync::RwLock<FilesystemDataStore>,
     exec_socket_path: PathBuf,
+    watchers: watch::Watchers,
+    access_policy: Option<access::AccessPolicy>,
+    audit_log: Option<audit::AuditLog>,
 }
 
 /// Helper macro for implementing the actix-web Responder trait for a type.
@@ -538,6 +875,26 @@ impl_responder_for!(ModelResponse, self, self.0);
 struct SettingsResponse(Settings);
 impl_responder_for!(SettingsResponse, self, self.0);
 
+/// This lets us respond from our handler methods with a Settings and an ETag representing the
+/// generation of the data store the settings came from.
+struct GenerationSettingsResponse {
+    settings: Settings,
+    generation: u64,
+}
+
+impl Responder for GenerationSettingsResponse {
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse {
+        let body = match serde_json::to_string(&self.settings) {
+            Ok(s) => s,
+            Err(e) => return Error::ResponseSerialization { source: e }.into(),
+        };
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .insert_header((header::ETAG, generation_etag(self.generation)))
+            .body(body)
+    }
+}
+
 /// This lets us respond from our handler methods with a BottlerocketRelease (or Result<BottlerocketRelease>)
 struct BottlerocketReleaseResponse(BottlerocketRelease);
 impl_responder_for!(BottlerocketReleaseResponse, self, self.0);
@@ -564,3 +921,18 @@ impl_responder_for!(ChangedKeysResponse, self, self.0);
 
 struct TransactionListResponse(HashSet<String>);
 impl_responder_for!(TransactionListResponse, self, self.0);
+
+struct TransactionHistoryResponse(Vec<TransactionRecord>);
+impl_responder_for!(TransactionHistoryResponse, self, self.0);
+
+struct SettingsValidationResponse(controller::SettingsValidation);
+impl_responder_for!(SettingsValidationResponse, self, self.0);
+
+struct AuditReportResponse(audit::AuditReport);
+impl_responder_for!(AuditReportResponse, self, self.0);
+
+struct DriftReportsResponse(Vec<DriftReport>);
+impl_responder_for!(DriftReportsResponse, self, self.0);
+
+struct CertificateReportResponse(CertificateReport);
+impl_responder_for!(CertificateReportResponse, self, self.0);
@@ -0,0 +1,149 @@
+//! The 'watch' module lets clients follow changes to settings as they're committed, rather than
+//! polling for them.  Clients make a long-lived request and we send a Server-Sent Event for each
+//! commit that changes settings they're interested in.
+//!
+//! Each event has a single data line, a JSON object with the generation created by the commit,
+//! the name of the committed transaction, and a map of changed setting names to their new values.
+//! Settings that were removed have a null value.
+
+use crate::server::error::{self, Result};
+use crate::server::SharedData;
+use actix_web::{web, HttpResponse};
+use bytes::Bytes;
+use datastore::Value;
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// The number of changes we hold for each client before it's considered too slow.  Commits are
+/// infrequent, so a client that falls this far behind has probably stopped reading; we disconnect
+/// it, and it should re-read settings when it reconnects.
+const MAX_QUEUED_CHANGES: usize = 64;
+
+/// A commit that changed settings, as sent to watching clients.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct SettingsChange {
+    /// The generation of the data store created by the commit.
+    pub(crate) generation: u64,
+    /// The name of the committed transaction.
+    pub(crate) transaction: String,
+    /// The changed settings, by full dotted name, and their new values.
+    pub(crate) settings: BTreeMap<String, Value>,
+}
+
+/// Watchers lets request handlers send committed changes to any clients currently watching.
+#[derive(Debug)]
+pub(crate) struct Watchers(broadcast::Sender<Arc<SettingsChange>>);
+
+impl Watchers {
+    pub(crate) fn new() -> Self {
+        let (sender, _receiver) = broadcast::channel(MAX_QUEUED_CHANGES);
+        Self(sender)
+    }
+
+    /// Sends the given change to all watching clients.
+    pub(crate) fn notify(&self, change: SettingsChange) {
+        // The only error is that nobody is watching, which is fine.
+        if self.0.send(Arc::new(change)).is_err() {
+            debug!("No clients watching settings changes");
+        }
+    }
+}
+
+/// Starts an event stream of settings changes for the client.  If 'prefix' is given in the query,
+/// only changes to settings starting with that prefix are sent; like the settings GET, the prefix
+/// should not include "settings."
+pub(crate) async fn watch_settings(
+    query: web::Query<HashMap<String, String>>,
+    data: web::Data<SharedData>,
+) -> Result<HttpResponse> {
+    let prefix = match query.get("prefix") {
+        Some(prefix) if prefix.is_empty() => {
+            return error::EmptyInput { input: "prefix" }.fail();
+        }
+        Some(prefix) => format!("settings.{}", prefix),
+        None => "settings.".to_string(),
+    };
+
+    let receiver = data.watchers.0.subscribe();
+    let stream = futures::stream::unfold((receiver, prefix), |(mut receiver, prefix)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(change) => {
+                    if let Some(event) = event_for(&change, &prefix) {
+                        return Some((Ok::<_, Infallible>(event), (receiver, prefix)));
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(
+                        "Settings watch client missed {} changes, disconnecting",
+                        missed
+                    );
+                    return None;
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(Box::pin(stream)))
+}
+
+/// Builds the Server-Sent Event for the given change, including only settings that start with the
+/// given prefix.  Returns None if no changed settings match.
+fn event_for(change: &SettingsChange, prefix: &str) -> Option<Bytes> {
+    let settings: BTreeMap<_, _> = change
+        .settings
+        .iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    if settings.is_empty() {
+        return None;
+    }
+
+    let filtered = SettingsChange {
+        generation: change.generation,
+        transaction: change.transaction.clone(),
+        settings,
+    };
+    match serde_json::to_string(&filtered) {
+        Ok(json) => Some(Bytes::from(format!("data: {}\n\n", json))),
+        Err(e) => {
+            warn!("Failed to serialize settings change: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::btreemap;
+
+    #[test]
+    fn event_filters_by_prefix() {
+        let change = SettingsChange {
+            generation: 3,
+            transaction: "default".to_string(),
+            settings: btreemap!(
+                "settings.motd".to_string() => "hi".into(),
+                "settings.ntp.time-servers".to_string() => Value::Null,
+            ),
+        };
+
+        let event = event_for(&change, "settings.motd").unwrap();
+        assert_eq!(
+            event,
+            Bytes::from(
+                "data: {\"generation\":3,\"transaction\":\"default\",\"settings\":{\"settings.motd\":\"hi\"}}\n\n"
+            )
+        );
+        assert!(event_for(&change, "settings.kubernetes").is_none());
+    }
+}
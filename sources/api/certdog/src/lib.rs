@@ -0,0 +1,247 @@
+/*!
+The certdog library describes the report `certdog check` writes about when the certificates in
+`settings.pki` expire.  apiserver serves the latest report from `GET /certificates`, and metricdog
+reports the certificates that are expiring or expired.
+*/
+
+#![deny(rust_2018_idioms)]
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Where `certdog check` writes its latest report.
+pub const CERTIFICATE_REPORT_PATH: &str = "/run/cache/certdog/certificates.json";
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// The result of checking every certificate in use from `settings.pki`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CertificateReport {
+    pub checked_at: DateTime<Utc>,
+    /// Certificates that expire within this many days are reported as expiring.
+    pub threshold_days: u32,
+    pub certificates: Vec<CertificateExpiry>,
+}
+
+/// When a single certificate expires.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CertificateExpiry {
+    /// The name of the `settings.pki` entry holding the certificate.
+    pub name: String,
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: DateTime<Utc>,
+    /// Whole days until the certificate expires; negative once it has expired.
+    pub days_remaining: i64,
+    pub status: ExpiryStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExpiryStatus {
+    Valid,
+    Expiring,
+    Expired,
+}
+
+impl CertificateExpiry {
+    /// Describes the certificate in `pem`, from the `settings.pki` entry `name`, as of `now`.
+    pub fn new<S>(
+        name: S,
+        pem: &x509_parser::pem::Pem,
+        threshold_days: u32,
+        now: DateTime<Utc>,
+    ) -> Result<Self>
+    where
+        S: Into<String>,
+    {
+        let cert = pem.parse_x509().context(error::ParseX509Certificate)?;
+        let not_after = Utc.timestamp(cert.validity().not_after.timestamp(), 0);
+        let remaining = not_after.timestamp() - now.timestamp();
+        let status = if remaining <= 0 {
+            ExpiryStatus::Expired
+        } else if remaining <= i64::from(threshold_days) * SECONDS_PER_DAY {
+            ExpiryStatus::Expiring
+        } else {
+            ExpiryStatus::Valid
+        };
+
+        Ok(Self {
+            name: name.into(),
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            not_after,
+            days_remaining: remaining.div_euclid(SECONDS_PER_DAY),
+            status,
+        })
+    }
+}
+
+impl CertificateReport {
+    pub fn new(threshold_days: u32, certificates: Vec<CertificateExpiry>) -> Self {
+        Self {
+            checked_at: Utc::now(),
+            threshold_days,
+            certificates,
+        }
+    }
+
+    /// Returns the sorted names of the `settings.pki` entries with certificates that are expiring
+    /// or expired.
+    pub fn unhealthy_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .certificates
+            .iter()
+            .filter(|cert| cert.status != ExpiryStatus::Valid)
+            .map(|cert| cert.name.as_str())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Writes the report to `path`, replacing the last one.  The file is replaced atomically so
+    /// readers never see a partial report.
+    pub fn write<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let report = serde_json::to_string_pretty(self).context(error::ReportSerialize)?;
+
+        fs::create_dir_all(dir).context(error::ReportWrite { path: dir })?;
+        let mut temp_file =
+            tempfile::NamedTempFile::new_in(dir).context(error::ReportWrite { path: dir })?;
+        temp_file
+            .write_all(report.as_bytes())
+            .context(error::ReportWrite {
+                path: temp_file.path(),
+            })?;
+        temp_file
+            .persist(path)
+            .context(error::ReportPersist { path })?;
+        Ok(())
+    }
+
+    /// Reads the report at `path`.  If `certdog check` hasn't run, there isn't one.
+    pub fn read<P>(path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let report = match fs::read_to_string(path) {
+            Ok(report) => report,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(error::ReportRead { path }),
+        };
+        let report = serde_json::from_str(&report).context(error::ReportParse { path })?;
+        Ok(Some(report))
+    }
+}
+
+pub mod error {
+    use snafu::Snafu;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility = "pub(super)")]
+    pub enum Error {
+        #[snafu(display("Failed to parse cert: {}", source))]
+        ParseX509Certificate {
+            source: x509_parser::nom::Err<x509_parser::error::X509Error>,
+        },
+
+        #[snafu(display("Failed to parse certificate report '{}': {}", path.display(), source))]
+        ReportParse {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to replace certificate report '{}': {}", path.display(), source))]
+        ReportPersist {
+            path: PathBuf,
+            source: tempfile::PersistError,
+        },
+
+        #[snafu(display("Failed to read certificate report '{}': {}", path.display(), source))]
+        ReportRead { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to serialize certificate report: {}", source))]
+        ReportSerialize { source: serde_json::Error },
+
+        #[snafu(display("Failed to write certificate report to '{}': {}", path.display(), source))]
+        ReportWrite { path: PathBuf, source: io::Error },
+    }
+}
+
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static TEST_PEM: &str = include_str!("../../../models/tests/data/test-pem");
+
+    fn test_pem() -> x509_parser::pem::Pem {
+        let (_, pem) =
+            x509_parser::pem::parse_x509_pem(&base64::decode(TEST_PEM.as_bytes()).unwrap())
+                .unwrap();
+        pem
+    }
+
+    #[test]
+    fn expiry_status() {
+        // The test certificate expires at 2026-07-06T21:01:30Z
+        let pem = test_pem();
+        let check = |now: &str| {
+            let now = DateTime::parse_from_rfc3339(now)
+                .unwrap()
+                .with_timezone(&Utc);
+            let expiry = CertificateExpiry::new("test", &pem, 30, now).unwrap();
+            (expiry.status, expiry.days_remaining)
+        };
+        assert_eq!(check("2026-01-01T00:00:00Z"), (ExpiryStatus::Valid, 186));
+        assert_eq!(check("2026-06-06T21:01:30Z"), (ExpiryStatus::Expiring, 30));
+        assert_eq!(check("2026-07-06T21:00:00Z"), (ExpiryStatus::Expiring, 0));
+        assert_eq!(check("2026-07-06T21:01:30Z"), (ExpiryStatus::Expired, 0));
+        assert_eq!(check("2026-07-08T00:00:00Z"), (ExpiryStatus::Expired, -2));
+
+        let expiry = CertificateExpiry::new("test", &pem, 30, Utc::now()).unwrap();
+        assert_eq!(expiry.subject, "CN=bottlerocket, C=US, L=WASHINGTON");
+        assert_eq!(expiry.issuer, expiry.subject);
+    }
+
+    #[test]
+    fn write_and_read() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("certdog/certificates.json");
+        assert_eq!(CertificateReport::read(&path).unwrap(), None);
+
+        let pem = test_pem();
+        let now = DateTime::parse_from_rfc3339("2026-07-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let report = CertificateReport::new(
+            30,
+            vec![
+                CertificateExpiry::new("second", &pem, 30, now).unwrap(),
+                CertificateExpiry::new("first", &pem, 30, now).unwrap(),
+                CertificateExpiry::new("first", &pem, 30, now).unwrap(),
+                CertificateExpiry::new("valid", &pem, 1, now).unwrap(),
+            ],
+        );
+        assert_eq!(report.unhealthy_names(), vec!["first", "second"]);
+
+        report.write(&path).unwrap();
+        assert_eq!(CertificateReport::read(&path).unwrap(), Some(report));
+    }
+}
@@ -2,6 +2,24 @@
   certdog is a tool to manage the trusted certificates store. It adds and removes
   certificates from the final certificates bundle based on the configurations
   in the API.
+
+  Certificates with `consumers` are left out of the system bundle and given only
+  to the programs named.  For each container registry consumer, certdog writes
+  `ca-bundle.crt`, the system bundle plus the certificates for that registry, to
+  `/etc/pki/consumers/registries/<host>/`; a certificate with a `key` is written
+  there as the client certificate, `client.crt`, with its key in `client.key`,
+  readable only by root.  The `ecs` consumer gets the same files in
+  `/etc/pki/consumers/ecs/`.  Certificates for the `kubelet` consumer are added
+  to the certificate authorities the kubelet trusts for the API server when
+  that file is rendered; they're never trusted for client certificates.
+
+  `certdog check` lists the certificates in use, with their subject, issuer, and
+  how many days remain until they expire.  Certificates that expire within
+  `settings.certificate-expiry.threshold-days` are marked as expiring.  The list
+  is also written to `/run/cache/certdog/certificates.json`, which apiserver
+  serves from `GET /certificates`, and metricdog reports the system as unhealthy
+  while any certificate is expiring or expired.  Distrusted certificates aren't
+  checked, since nothing uses them.
 */
 
 #![deny(rust_2018_idioms)]
@@ -10,14 +28,17 @@
 extern crate log;
 
 use argh::FromArgs;
+use certdog::{CertificateExpiry, CertificateReport, ExpiryStatus};
+use chrono::Utc;
 use constants;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::ResultExt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Write;
 use std::fs;
 use std::io::BufReader;
-use std::io::{BufRead, Seek};
+use std::io::{BufRead, Seek, Write as _};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
 use std::path::Path;
 use std::process;
 use x509_parser;
@@ -29,6 +50,9 @@ const DEFAULT_SOURCE_BUNDLE: &str = "/usr/share/factory/etc/pki/tls/certs/ca-bun
 // This file is first created with tmpfilesd configurations
 const DEFAULT_TRUSTED_STORE: &str = "/etc/pki/tls/certs/ca-bundle.crt";
 
+// Used when settings.certificate-expiry.threshold-days isn't set
+const DEFAULT_THRESHOLD_DAYS: u32 = 30;
+
 // PEM delimiters
 const PEM_HEADER: &str = "-----BEGIN";
 const PEM_FOOTER: &str = "-----END";
@@ -49,16 +73,53 @@ struct Args {
     #[argh(option, default = "DEFAULT_SOURCE_BUNDLE.to_string()", short = 'b')]
     /// source-bundle path to source bundle
     source_bundle: String,
+    #[argh(
+        option,
+        default = "constants::PKI_CONSUMERS_DIR.to_string()",
+        short = 'c'
+    )]
+    /// consumers-dir path to the directory of certificates for specific programs
+    consumers_dir: String,
+    #[argh(subcommand)]
+    subcommand: Option<Subcommand>,
+}
+
+#[derive(FromArgs, Debug)]
+#[argh(subcommand)]
+enum Subcommand {
+    Check(CheckArgs),
+}
+
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "check")]
+/// List when the certificates in use expire, instead of updating the trusted store
+struct CheckArgs {
+    #[argh(
+        option,
+        default = "certdog::CERTIFICATE_REPORT_PATH.to_string()",
+        short = 'r'
+    )]
+    /// report-path path to write the report to
+    report_path: String,
 }
 
 struct CertBundle {
     trusted_certs: Vec<x509_parser::pem::Pem>,
     distrusted_certs: Vec<x509_parser::pem::Pem>,
+    /// Certificates for programs that don't use the system trust store, keyed by the directory
+    /// their files are written to
+    consumers: BTreeMap<String, ConsumerCerts>,
 }
 
-/// Query the API for the certificate bundles, returns a tuple with trusted
-/// and distrusted PEM certificates
-async fn get_certificate_bundles<P>(socket_path: P) -> Result<CertBundle>
+#[derive(Default)]
+struct ConsumerCerts {
+    trusted_certs: Vec<x509_parser::pem::Pem>,
+    /// The client certificate, and its private key as given in the API
+    client: Option<(Vec<x509_parser::pem::Pem>, Vec<u8>)>,
+}
+
+/// Query the API for the settings
+async fn get_settings<P>(socket_path: P) -> Result<model::Settings>
 where
     P: AsRef<Path>,
 {
@@ -75,15 +136,102 @@ where
     let settings: model::Settings =
         serde_json::from_str(&response_body).context(error::ResponseJson { uri })?;
 
+    Ok(settings)
+}
+
+/// Query the API for the certificate bundles, returns a tuple with trusted
+/// and distrusted PEM certificates
+async fn get_certificate_bundles<P>(socket_path: P) -> Result<CertBundle>
+where
+    P: AsRef<Path>,
+{
+    let settings = get_settings(socket_path).await?;
     split_bundles(settings.pki.unwrap_or_default())
 }
 
-/// Returns a tuple with two lists, for trusted and distrusted certificates
+/// Returns when each certificate in use expires, sorted by the name of its API record
+fn check_certificates(
+    certificates_bundle: HashMap<Identifier, model::PemCertificate>,
+    threshold_days: u32,
+) -> Result<Vec<CertificateExpiry>> {
+    let now = Utc::now();
+    let mut certificates_bundle: Vec<_> = certificates_bundle.into_iter().collect();
+    certificates_bundle.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut certificates = Vec::new();
+    for (name, bundle) in certificates_bundle.iter() {
+        let data = bundle.data.clone().unwrap_or_default();
+        let has_key = !bundle.key.clone().unwrap_or_default().trim().is_empty();
+        // Distrusted certificates aren't used, so it doesn't matter when they expire
+        if data.trim() == "" || !(bundle.trusted.unwrap_or(false) || has_key) {
+            continue;
+        }
+
+        let name = name.as_ref();
+        let decoded = base64::decode(data.as_bytes()).context(error::Base64Decode { name })?;
+        for pem in pems_from_iter(x509_parser::pem::Pem::iter_from_buffer(&decoded))? {
+            certificates.push(
+                CertificateExpiry::new(name, &pem, threshold_days, now)
+                    .context(error::CertificateReport)?,
+            );
+        }
+    }
+    Ok(certificates)
+}
+
+/// Prints when each certificate in use expires, and writes the report for the API and metricdog
+async fn check(args: &Args, check_args: &CheckArgs) -> Result<()> {
+    let settings = get_settings(&args.socket_path).await?;
+    let threshold_days = settings
+        .certificate_expiry
+        .and_then(|expiry| expiry.threshold_days)
+        .unwrap_or(DEFAULT_THRESHOLD_DAYS);
+    let certificates = check_certificates(settings.pki.unwrap_or_default(), threshold_days)?;
+
+    for cert in &certificates {
+        let status = match cert.status {
+            ExpiryStatus::Valid => "valid",
+            ExpiryStatus::Expiring => "expiring",
+            ExpiryStatus::Expired => "expired",
+        };
+        println!(
+            "{} ({}): {} days remaining, not after {}",
+            cert.name,
+            status,
+            cert.days_remaining,
+            cert.not_after.to_rfc3339()
+        );
+        println!("  subject: {}", cert.subject);
+        println!("  issuer:  {}", cert.issuer);
+    }
+
+    let report = CertificateReport::new(threshold_days, certificates);
+    for name in report.unhealthy_names() {
+        warn!(
+            "Certificate '{}' expires within {} days",
+            name, threshold_days
+        );
+    }
+    report
+        .write(&check_args.report_path)
+        .context(error::CertificateReport)?;
+    info!("Wrote certificate report to {}", check_args.report_path);
+
+    Ok(())
+}
+
+/// Returns the trusted and distrusted certificates, and the certificates for specific consumers
 fn split_bundles(
     certificates_bundle: HashMap<Identifier, model::PemCertificate>,
 ) -> Result<CertBundle> {
     let mut trusted_certs: Vec<x509_parser::pem::Pem> = Vec::new();
     let mut distrusted_certs: Vec<x509_parser::pem::Pem> = Vec::new();
+    let mut consumers: BTreeMap<String, ConsumerCerts> = BTreeMap::new();
+
+    // Sort by name so that it's clear which client certificate is used if there are several for
+    // the same consumer
+    let mut certificates_bundle: Vec<_> = certificates_bundle.into_iter().collect();
+    certificates_bundle.sort_by(|(a, _), (b, _)| a.cmp(b));
 
     for (name, bundle) in certificates_bundle.iter() {
         let data = bundle.data.clone().unwrap_or_default();
@@ -99,6 +247,56 @@ fn split_bundles(
         // Each record in the API could include one or more certificates
         let mut pems = pems_from_iter(x509_parser::pem::Pem::iter_from_buffer(&decoded))?;
 
+        let key = bundle.key.clone().unwrap_or_default();
+        let key = if key.trim() == "" {
+            None
+        } else {
+            Some(base64::decode(key.as_bytes()).context(error::Base64Decode { name })?)
+        };
+
+        let bundle_consumers = bundle.consumers.clone().unwrap_or_default();
+        if !bundle_consumers.is_empty() {
+            for consumer in bundle_consumers {
+                if key.is_some() && consumer.registry_host().is_none() {
+                    warn!(
+                        "Ignoring client certificate '{}' for '{}', which isn't a registry",
+                        name, consumer
+                    );
+                    continue;
+                }
+                // The kubelet's certificates are added to the certificate authorities it trusts
+                // for the API server by their template
+                if consumer.as_ref() == "kubelet" {
+                    continue;
+                }
+
+                let consumer_certs = consumers.entry(consumer.directory()).or_default();
+                if let Some(key) = &key {
+                    if consumer_certs.client.is_some() {
+                        warn!(
+                            "Ignoring client certificate '{}' for '{}'; it already has one",
+                            name, consumer
+                        );
+                        continue;
+                    }
+                    consumer_certs.client = Some((clone_pems(&pems), key.clone()));
+                } else if bundle.trusted.unwrap_or(false) {
+                    consumer_certs.trusted_certs.extend(clone_pems(&pems));
+                } else {
+                    // Distrusted certificates are removed from every bundle
+                    distrusted_certs.extend(clone_pems(&pems));
+                }
+            }
+            continue;
+        }
+
+        if key.is_some() {
+            warn!(
+                "Ignoring key for certificate '{}', which has no consumers to use it",
+                name
+            );
+        }
+
         // `trusted` defaults to false if not set in the API record
         if bundle.trusted.unwrap_or(false) {
             trusted_certs.append(&mut pems);
@@ -110,16 +308,17 @@ fn split_bundles(
     Ok(CertBundle {
         trusted_certs,
         distrusted_certs,
+        consumers,
     })
 }
 
 /// Updates the trusted certificates store, removing the distrusted certificates
-/// from the final bundle
+/// from the final bundle.  Returns the final bundle.
 fn update_trusted_store<P>(
-    mut cert_bundle: CertBundle,
+    cert_bundle: &mut CertBundle,
     trusted_store: P,
     source_bundle: P,
-) -> Result<()>
+) -> Result<Vec<x509_parser::pem::Pem>>
 where
     P: AsRef<Path>,
 {
@@ -143,9 +342,89 @@ where
     fs::write(&trusted_store, pems_to_string(&trusted_bundle)?)
         .context(error::UpdateTrustedStore)?;
 
+    Ok(trusted_bundle)
+}
+
+/// Writes the files for each consumer to its own directory in `consumers_dir`.  The files are
+/// written to a new directory that replaces the old one, so consumers that were removed from the
+/// API don't keep their certificates.
+fn write_consumer_files<P>(
+    cert_bundle: &CertBundle,
+    trusted_bundle: &[x509_parser::pem::Pem],
+    consumers_dir: P,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let consumers_dir = consumers_dir.as_ref();
+    let new_dir = consumers_dir.with_extension("new");
+    let old_dir = consumers_dir.with_extension("old");
+    for dir in &[&new_dir, &old_dir] {
+        if dir.exists() {
+            fs::remove_dir_all(dir).context(error::WriteConsumerFiles { path: *dir })?;
+        }
+    }
+
+    let mut dir_builder = fs::DirBuilder::new();
+    dir_builder.recursive(true).mode(0o755);
+    dir_builder
+        .create(&new_dir)
+        .context(error::WriteConsumerFiles { path: &new_dir })?;
+    for (directory, consumer_certs) in &cert_bundle.consumers {
+        let dir = new_dir.join(directory);
+        dir_builder
+            .create(&dir)
+            .context(error::WriteConsumerFiles { path: &dir })?;
+
+        let mut ca_bundle = clone_pems(trusted_bundle);
+        ca_bundle.extend(clone_pems(&consumer_certs.trusted_certs));
+        ca_bundle.retain(|pem| !cert_bundle.distrusted_certs.contains(pem));
+        write_file(
+            dir.join(constants::PKI_CA_BUNDLE_FILE),
+            pems_to_string(&ca_bundle)?.as_bytes(),
+            0o644,
+        )?;
+
+        if let Some((client_certs, key)) = &consumer_certs.client {
+            write_file(
+                dir.join(constants::PKI_CLIENT_CERT_FILE),
+                pems_to_string(client_certs)?.as_bytes(),
+                0o644,
+            )?;
+            write_file(dir.join(constants::PKI_CLIENT_KEY_FILE), key, 0o600)?;
+        }
+    }
+
+    if consumers_dir.exists() {
+        fs::rename(consumers_dir, &old_dir).context(error::WriteConsumerFiles {
+            path: consumers_dir,
+        })?;
+    }
+    fs::rename(&new_dir, consumers_dir).context(error::WriteConsumerFiles {
+        path: consumers_dir,
+    })?;
+    if old_dir.exists() {
+        fs::remove_dir_all(&old_dir).context(error::WriteConsumerFiles { path: &old_dir })?;
+    }
+
     Ok(())
 }
 
+/// Writes a file that's created with the given mode, so private keys are never readable by others
+fn write_file<P>(path: P, contents: &[u8], mode: u32) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(path)
+        .and_then(|mut f| f.write_all(contents))
+        .context(error::WriteConsumerFiles { path })
+}
+
 /// Returns a list with Pem objects from a PemIterator
 fn pems_from_iter<R>(iter: x509_parser::pem::PemIterator<R>) -> Result<Vec<x509_parser::pem::Pem>>
 where
@@ -159,6 +438,16 @@ where
     Ok(vec)
 }
 
+/// Returns copies of the PEM objects, which don't implement Clone
+fn clone_pems(pems: &[x509_parser::pem::Pem]) -> Vec<x509_parser::pem::Pem> {
+    pems.iter()
+        .map(|pem| x509_parser::pem::Pem {
+            label: pem.label.clone(),
+            contents: pem.contents.clone(),
+        })
+        .collect()
+}
+
 /// Concatenates all the PEM objects as a single PEM bundle
 fn pems_to_string(pems: &Vec<x509_parser::pem::Pem>) -> Result<String> {
     let mut out = String::new();
@@ -214,10 +503,20 @@ async fn run() -> Result<()> {
     SimpleLogger::init(args.log_level, LogConfig::default()).context(error::Logger)?;
 
     info!("certdog started");
-    let certificate_bundles = get_certificate_bundles(&args.socket_path).await?;
+    if let Some(Subcommand::Check(check_args)) = &args.subcommand {
+        return check(&args, check_args).await;
+    }
+
+    let mut certificate_bundles = get_certificate_bundles(&args.socket_path).await?;
     info!("Got certificate bundles from API");
-    update_trusted_store(certificate_bundles, args.trusted_store, args.source_bundle)?;
+    let trusted_bundle = update_trusted_store(
+        &mut certificate_bundles,
+        args.trusted_store,
+        args.source_bundle,
+    )?;
     info!("Updated trusted store");
+    write_consumer_files(&certificate_bundles, &trusted_bundle, args.consumers_dir)?;
+    info!("Updated certificates for consumers");
 
     Ok(())
 }
@@ -249,6 +548,9 @@ mod error {
             source: apiclient::Error,
         },
 
+        #[snafu(display("Unable to check certificates: {}", source))]
+        CertificateReport { source: certdog::Error },
+
         #[snafu(display("Unable to decode base64 from certificate '{}': {}", name, source))]
         Base64Decode {
             name: String,
@@ -286,6 +588,9 @@ mod error {
         #[snafu(display("Failed to update trust store: {}", source))]
         UpdateTrustedStore { source: io::Error },
 
+        #[snafu(display("Failed to write certificates to '{}': {}", path.display(), source))]
+        WriteConsumerFiles { path: PathBuf, source: io::Error },
+
         #[snafu(display("Failed to write to pem string: {}", source))]
         WritePemString { source: std::fmt::Error },
     }
@@ -297,12 +602,16 @@ type Result<T> = std::result::Result<T, error::Error>;
 mod test_certdog {
     use super::*;
     use model;
-    use model::modeled_types::{Identifier, PemCertificateString};
+    use model::modeled_types::{
+        Identifier, PemCertificateString, PemPrivateKeyString, PkiConsumer,
+    };
     use std::collections::HashMap;
     use std::convert::TryFrom;
     use std::fs::File;
+    use std::os::unix::fs::PermissionsExt;
 
     static TEST_PEM: &str = include_str!("../../../models/tests/data/test-pem");
+    static TEST_KEY: &str = include_str!("../../../models/tests/data/test-key");
 
     #[test]
     fn bundles_splitted() {
@@ -312,6 +621,8 @@ mod test_certdog {
             model::PemCertificate {
                 data: Some(PemCertificateString::try_from(TEST_PEM).unwrap()),
                 trusted: Some(true),
+                key: None,
+                consumers: None,
             },
         );
         bundle.insert(
@@ -319,6 +630,8 @@ mod test_certdog {
             model::PemCertificate {
                 data: Some(PemCertificateString::try_from(TEST_PEM).unwrap()),
                 trusted: Some(false),
+                key: None,
+                consumers: None,
             },
         );
         bundle.insert(
@@ -326,6 +639,8 @@ mod test_certdog {
             model::PemCertificate {
                 data: Some(PemCertificateString::try_from(TEST_PEM).unwrap()),
                 trusted: None,
+                key: None,
+                consumers: None,
             },
         );
 
@@ -335,6 +650,27 @@ mod test_certdog {
         assert!(splitted.distrusted_certs.len() == 4);
     }
 
+    #[test]
+    fn certificates_checked() {
+        let mut bundle = HashMap::new();
+        bundle.insert(
+            Identifier::try_from("client").unwrap(),
+            consumer_certificate(Some(TEST_KEY), &["registry:registry.example.com"]),
+        );
+        bundle.insert(
+            Identifier::try_from("trusted").unwrap(),
+            consumer_certificate(None, &[]),
+        );
+        let mut distrusted = consumer_certificate(None, &[]);
+        distrusted.trusted = Some(false);
+        bundle.insert(Identifier::try_from("distrusted").unwrap(), distrusted);
+
+        // Each record holds two certificates; distrusted ones aren't checked
+        let certificates = check_certificates(bundle, 30).unwrap();
+        let names: Vec<&str> = certificates.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["client", "client", "trusted", "trusted"]);
+    }
+
     #[test]
     fn trusted_store_updated() {
         let trusted_store = tempfile::NamedTempFile::new().unwrap();
@@ -344,11 +680,94 @@ mod test_certdog {
                 .unwrap();
         let mut trusted_certs: Vec<x509_parser::pem::Pem> = Vec::new();
         trusted_certs.push(pem);
-        let certs_bundle = CertBundle {
+        let mut certs_bundle = CertBundle {
             trusted_certs,
             distrusted_certs: Vec::new(),
+            consumers: BTreeMap::new(),
         };
-        assert!(update_trusted_store(certs_bundle, &trusted_store, &source_bundle).is_ok());
+        assert!(update_trusted_store(&mut certs_bundle, &trusted_store, &source_bundle).is_ok());
         assert!(File::open(trusted_store).unwrap().metadata().unwrap().len() != 0);
     }
+
+    fn consumer_certificate(key: Option<&str>, consumers: &[&str]) -> model::PemCertificate {
+        model::PemCertificate {
+            data: Some(PemCertificateString::try_from(TEST_PEM).unwrap()),
+            trusted: Some(true),
+            key: key.map(|key| PemPrivateKeyString::try_from(key).unwrap()),
+            consumers: Some(
+                consumers
+                    .iter()
+                    .map(|c| PkiConsumer::try_from(*c).unwrap())
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn consumers_splitted() {
+        let mut bundle = HashMap::new();
+        bundle.insert(
+            Identifier::try_from("registry-ca").unwrap(),
+            consumer_certificate(None, &["registry:registry.example.com", "ecs", "kubelet"]),
+        );
+        bundle.insert(
+            Identifier::try_from("registry-client").unwrap(),
+            consumer_certificate(Some(TEST_KEY), &["registry:registry.example.com", "ecs"]),
+        );
+        bundle.insert(
+            Identifier::try_from("second-client").unwrap(),
+            consumer_certificate(Some(TEST_KEY), &["registry:registry.example.com"]),
+        );
+
+        let splitted = split_bundles(bundle).unwrap();
+        // Certificates with consumers aren't added to the system bundle, and the kubelet's are
+        // handled by its template
+        assert!(splitted.trusted_certs.is_empty());
+        assert_eq!(
+            splitted.consumers.keys().collect::<Vec<_>>(),
+            vec!["ecs", "registries/registry.example.com"]
+        );
+        // Only registries get client certificates, and only the first by name
+        let ecs = &splitted.consumers["ecs"];
+        assert_eq!(ecs.trusted_certs.len(), 2);
+        assert!(ecs.client.is_none());
+        let registry = &splitted.consumers["registries/registry.example.com"];
+        assert_eq!(registry.trusted_certs.len(), 2);
+        let (client_certs, key) = registry.client.as_ref().unwrap();
+        assert_eq!(client_certs.len(), 2);
+        assert_eq!(key, &base64::decode(TEST_KEY).unwrap());
+    }
+
+    #[test]
+    fn consumer_files_written() {
+        let consumers_dir = tempfile::TempDir::new().unwrap();
+        let consumers_dir = consumers_dir.path().join("consumers");
+        let mut bundle = HashMap::new();
+        bundle.insert(
+            Identifier::try_from("registry-client").unwrap(),
+            consumer_certificate(Some(TEST_KEY), &["registry:registry.example.com:5000"]),
+        );
+        let splitted = split_bundles(bundle).unwrap();
+
+        // Files from the last run are removed
+        fs::create_dir_all(consumers_dir.join("ecs")).unwrap();
+        write_consumer_files(&splitted, &[], &consumers_dir).unwrap();
+        assert!(!consumers_dir.join("ecs").exists());
+
+        let registry_dir = consumers_dir.join("registries/registry.example.com:5000");
+        let mode = |file| {
+            fs::metadata(registry_dir.join(file))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode(constants::PKI_CA_BUNDLE_FILE), 0o644);
+        assert_eq!(mode(constants::PKI_CLIENT_CERT_FILE), 0o644);
+        assert_eq!(mode(constants::PKI_CLIENT_KEY_FILE), 0o600);
+        assert_eq!(
+            fs::read(registry_dir.join(constants::PKI_CLIENT_KEY_FILE)).unwrap(),
+            base64::decode(TEST_KEY).unwrap()
+        );
+    }
 }
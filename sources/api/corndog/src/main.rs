@@ -3,23 +3,55 @@ corndog is a delicious way to get at the meat inside the kernels.
 It sets kernel-related settings, for example:
 * sysctl values, based on key/value pairs in `settings.kernel.sysctl`
 * lockdown mode, based on the value of `settings.kernel.lockdown`
+* kernel modules, based on `settings.kernel.modules.<name>.autoload`
+* kernel command line parameters for the next boot, based on `settings.boot.kernel-parameters`
+
+Modules with `settings.kernel.modules.<name>.allowed` set to false are blocked by a modprobe.d
+file rendered from settings; the `modules` subcommand loads modules marked for autoload, and warns
+about blocked modules that were already loaded, since they stay loaded until reboot.
+
+The `kernel-parameters` subcommand saves the requested parameters in a GRUB environment block on
+the private partition, which the bootloader adds to the kernel command line on the next boot.
+Parameters already set by the variant, listed in `/usr/share/bottlerocket/kernel-parameters`,
+can't be overridden; if any are requested, or that list can't be read, nothing is saved.
+
+The `drift` subcommand compares the sysctls in settings with their values in `/proc/sys`, to
+notice values changed by something other than corndog.  It writes the differences to a report in
+`/run/cache/drift`, which the API serves from `GET /drift`.  If `settings.drift.reapply` is true,
+it also sets the changed sysctls back to the values in settings.  It holds thar-be-settings' lock
+while it runs, so it doesn't race changes from the API being applied.
 */
 
 #![deny(rust_2018_idioms)]
 
 use constants;
 use log::{debug, error, info, trace, warn};
+use model::modeled_types::{KernelParameter, KmodKey};
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
-use snafu::ResultExt;
-use std::collections::HashMap;
+use snafu::{ensure, ResultExt};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::str::FromStr;
 use std::string::String;
 use std::{env, process};
+use thar_be_settings::drift::{Difference, DriftReport, DRIFT_REPORT_DIR};
 
 const SYSCTL_PATH_PREFIX: &str = "/proc/sys";
 const LOCKDOWN_PATH: &str = "/sys/kernel/security/lockdown";
+const MODPROBE_BIN: &str = "/sbin/modprobe";
+const SYS_MODULE_PATH_PREFIX: &str = "/sys/module";
+
+/// The kernel parameters the variant sets on its own, which users can't override.
+const VARIANT_KERNEL_PARAMETERS_PATH: &str = "/usr/share/bottlerocket/kernel-parameters";
+/// The GRUB environment block, on the private partition, that GRUB loads user parameters from.
+const GRUBENV_PATH: &str = "/var/lib/bottlerocket/grubenv";
+const GRUBENV_HEADER: &str = "# GRUB Environment Block";
+const GRUBENV_VARIABLE: &str = "user_kernel_parameters";
+/// GRUB requires the environment block to be exactly this size, padded with '#'.
+const GRUBENV_SIZE: usize = 1024;
 
 /// Store the args we receive on the command line.
 struct Args {
@@ -35,25 +67,52 @@ async fn run() -> Result<()> {
     // SimpleLogger will send errors to stderr and anything less to stdout.
     SimpleLogger::init(args.log_level, LogConfig::default()).context(error::Logger)?;
 
+    // A drift check that re-applies sysctls mustn't race changes from the API being applied.
+    let _apply_lock = if args.subcommand == "drift" {
+        Some(thar_be_settings::lock_apply().context(error::ApplyLock)?)
+    } else {
+        None
+    };
+
     // If the user has kernel settings, apply them.
     let model = get_model(args.socket_path).await?;
     if let Some(settings) = model.settings {
-        if let Some(kernel) = settings.kernel {
-            match args.subcommand.as_ref() {
-                "sysctl" => {
-                    if let Some(sysctls) = kernel.sysctl {
-                        debug!("Applying sysctls: {:#?}", sysctls);
-                        set_sysctls(sysctls);
-                    }
+        match args.subcommand.as_ref() {
+            "sysctl" => {
+                if let Some(sysctls) = settings.kernel.and_then(|k| k.sysctl) {
+                    debug!("Applying sysctls: {:#?}", sysctls);
+                    set_sysctls(sysctls);
                 }
-                "lockdown" => {
-                    if let Some(lockdown) = kernel.lockdown {
-                        debug!("Setting lockdown: {:#?}", lockdown);
-                        set_lockdown(&lockdown)?;
-                    }
+            }
+            "lockdown" => {
+                if let Some(lockdown) = settings.kernel.and_then(|k| k.lockdown) {
+                    debug!("Setting lockdown: {:#?}", lockdown);
+                    set_lockdown(&lockdown)?;
                 }
-                _ => usage_msg(format!("Unknown subcommand '{}'", args.subcommand)), // should be unreachable
             }
+            "modules" => {
+                if let Some(modules) = settings.kernel.and_then(|k| k.modules) {
+                    debug!("Applying kernel modules: {:#?}", modules);
+                    apply_modules(modules);
+                }
+            }
+            // We always save the parameters, even if there are none, so that removing the last
+            // parameter takes effect too.
+            "kernel-parameters" => {
+                let parameters = settings
+                    .boot
+                    .and_then(|b| b.kernel_parameters)
+                    .unwrap_or_default();
+                debug!("Saving kernel parameters: {:#?}", parameters);
+                save_kernel_parameters(&parameters)?;
+            }
+            "drift" => {
+                let sysctls = settings.kernel.and_then(|k| k.sysctl).unwrap_or_default();
+                let reapply = settings.drift.and_then(|d| d.reapply).unwrap_or(false);
+                check_sysctl_drift(sysctls, reapply)?;
+            }
+            // should be unreachable
+            _ => usage_msg(format!("Unknown subcommand '{}'", args.subcommand)),
         }
     }
 
@@ -118,6 +177,62 @@ where
     }
 }
 
+/// Compares the requested sysctls with their current values and writes a drift report.  If
+/// `reapply` is true, sysctls that changed are set back to the requested values.
+///
+/// Like set_sysctls, we skip keys we can't read, because they vary between kernel versions.
+fn check_sysctl_drift<K>(sysctls: HashMap<K, String>, reapply: bool) -> Result<()>
+where
+    K: AsRef<str>,
+{
+    // Check keys in order so the report is stable for readers.
+    let mut sysctls: Vec<(K, String)> = sysctls.into_iter().collect();
+    sysctls.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+
+    let mut drifted = HashMap::new();
+    let mut differences = Vec::new();
+    for (key, expected) in sysctls {
+        let key = key.as_ref();
+        let actual = match fs::read_to_string(sysctl_path(key)) {
+            Ok(actual) => actual,
+            Err(e) => {
+                debug!("Unable to read sysctl value '{}': {}", key, e);
+                continue;
+            }
+        };
+        if !sysctl_matches(&expected, &actual) {
+            warn!(
+                "Sysctl '{}' is '{}' rather than '{}'",
+                key,
+                actual.trim(),
+                expected
+            );
+            differences.push(Difference::Sysctl {
+                key: key.to_string(),
+                expected: expected.clone(),
+                actual: actual.trim().to_string(),
+            });
+            drifted.insert(key.to_string(), expected);
+        }
+    }
+
+    let reapplied = reapply && !drifted.is_empty();
+    if reapplied {
+        info!("Setting {} drifted sysctl(s) back", drifted.len());
+        set_sysctls(drifted);
+    }
+
+    DriftReport::new("corndog", differences, reapplied)
+        .write(DRIFT_REPORT_DIR)
+        .context(error::DriftReport)
+}
+
+/// Returns whether a sysctl value read from /proc/sys matches the requested value.  The kernel
+/// separates multiple values with tabs and ends with a newline, so we compare word by word.
+fn sysctl_matches(requested: &str, actual: &str) -> bool {
+    requested.split_whitespace().eq(actual.split_whitespace())
+}
+
 /// Sets the requested lockdown mode in the kernel.
 ///
 /// The Linux kernel won't allow lowering the lockdown setting, but we want to allow users to
@@ -144,6 +259,124 @@ fn set_lockdown(lockdown: &str) -> Result<()> {
     fs::write(LOCKDOWN_PATH, lockdown).context(error::Lockdown { current, lockdown })
 }
 
+/// Loads the modules marked for autoload, and warns about blocked modules that are already loaded.
+///
+/// Like sysctls, we don't fail if a module can't be loaded, because the available modules vary
+/// between kernel versions.
+fn apply_modules(modules: HashMap<KmodKey, model::KernelModule>) {
+    for (name, module) in modules {
+        if module.allowed == Some(false) {
+            if module_loaded(&name) {
+                warn!(
+                    "Kernel module '{}' is blocked but already loaded; reboot to unload it",
+                    name
+                );
+            }
+            if module.autoload == Some(true) {
+                warn!("Not loading kernel module '{}' because it's blocked", name);
+            }
+            continue;
+        }
+
+        if module.autoload == Some(true) {
+            info!("Loading kernel module '{}'", name);
+            match Command::new(MODPROBE_BIN).arg(&*name).output() {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => error!(
+                    "Failed to load kernel module '{}': {}",
+                    name,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(e) => error!("Failed to run {} for '{}': {}", MODPROBE_BIN, name, e),
+            }
+        }
+    }
+}
+
+/// Returns whether the named module is loaded; modules appear in sysfs with '-' replaced by '_'.
+fn module_loaded(name: &str) -> bool {
+    Path::new(SYS_MODULE_PATH_PREFIX)
+        .join(name.replace('-', "_"))
+        .exists()
+}
+
+/// Saves the requested kernel parameters where the bootloader will find them on the next boot,
+/// after making sure they don't override the variant's own parameters.
+fn save_kernel_parameters(parameters: &[KernelParameter]) -> Result<()> {
+    // Every image has this file, so if we can't read it, we can't safely change anything.
+    let variant_parameters = fs::read_to_string(VARIANT_KERNEL_PARAMETERS_PATH).context(
+        error::KernelParametersRead {
+            path: VARIANT_KERNEL_PARAMETERS_PATH,
+        },
+    )?;
+    check_kernel_parameters(parameters, &variant_parameters)?;
+
+    let grubenv = grubenv(parameters)?;
+    if fs::read_to_string(GRUBENV_PATH).ok().as_deref() == Some(grubenv.as_str()) {
+        info!("Requested kernel parameters are already saved.");
+        return Ok(());
+    }
+
+    // Write the new environment block next to the old one and rename it into place, so the
+    // bootloader never sees a partial block.
+    let grubenv_dir = Path::new(GRUBENV_PATH)
+        .parent()
+        .unwrap_or_else(|| Path::new("/"));
+    let mut temp_file = tempfile::NamedTempFile::new_in(grubenv_dir)
+        .context(error::KernelParametersWrite { path: grubenv_dir })?;
+    temp_file
+        .write_all(grubenv.as_bytes())
+        .context(error::KernelParametersWrite {
+            path: temp_file.path(),
+        })?;
+    temp_file
+        .persist(GRUBENV_PATH)
+        .context(error::KernelParametersPersist { path: GRUBENV_PATH })?;
+    info!("Saved kernel parameters; they will take effect on the next boot.");
+    Ok(())
+}
+
+/// Makes sure none of the requested kernel parameters set something the variant already sets.
+/// `variant_parameters` is a whitespace-separated list of the variant's parameters.
+fn check_kernel_parameters(parameters: &[KernelParameter], variant_parameters: &str) -> Result<()> {
+    let variant_names: HashSet<String> = variant_parameters
+        .split_whitespace()
+        .map(|p| p.split('=').next().unwrap_or_default().replace('-', "_"))
+        .collect();
+    let conflicts: Vec<&str> = parameters
+        .iter()
+        .filter(|p| variant_names.contains(&p.normalized_name()))
+        .map(|p| p.as_ref())
+        .collect();
+    ensure!(
+        conflicts.is_empty(),
+        error::KernelParametersConflict {
+            parameters: conflicts.join(" "),
+        }
+    );
+    Ok(())
+}
+
+/// Builds a GRUB environment block that sets the user kernel parameters variable.
+fn grubenv(parameters: &[KernelParameter]) -> Result<String> {
+    let parameters: Vec<&str> = parameters.iter().map(|p| p.as_ref()).collect();
+    let mut grubenv = format!(
+        "{}\n{}={}\n",
+        GRUBENV_HEADER,
+        GRUBENV_VARIABLE,
+        parameters.join(" ")
+    );
+    ensure!(
+        grubenv.len() <= GRUBENV_SIZE,
+        error::KernelParametersTooLong {
+            size: grubenv.len(),
+            max: GRUBENV_SIZE,
+        }
+    );
+    grubenv.push_str(&"#".repeat(GRUBENV_SIZE - grubenv.len()));
+    Ok(grubenv)
+}
+
 /// The Linux kernel provides human-readable output like `[none] integrity confidentiality` when
 /// you read settings from virtual files like /sys/kernel/security/lockdown.  This parses out the
 /// current value of the setting from that human-readable output.
@@ -176,6 +409,9 @@ fn usage() -> ! {
     Subcommands:
         sysctl
         lockdown
+        modules
+        kernel-parameters
+        drift
 
     Global arguments:
         --socket-path PATH
@@ -219,7 +455,9 @@ fn parse_args(args: env::Args) -> Args {
                 )
             }
 
-            "sysctl" | "lockdown" => subcommand = Some(arg),
+            "sysctl" | "lockdown" | "modules" | "kernel-parameters" | "drift" => {
+                subcommand = Some(arg)
+            }
 
             _ => usage(),
         }
@@ -249,6 +487,7 @@ mod error {
     use http::StatusCode;
     use snafu::Snafu;
     use std::io;
+    use std::path::PathBuf;
 
     #[derive(Debug, Snafu)]
     #[snafu(visibility = "pub(super)")]
@@ -268,6 +507,41 @@ mod error {
             response_body: String,
         },
 
+        #[snafu(display("Failed to wait for settings to be applied: {}", source))]
+        ApplyLock { source: thar_be_settings::Error },
+
+        #[snafu(display("Failed to write drift report: {}", source))]
+        DriftReport { source: thar_be_settings::Error },
+
+        #[snafu(display(
+            "Kernel parameters '{}' are already set by this variant and can't be changed",
+            parameters
+        ))]
+        KernelParametersConflict { parameters: String },
+
+        #[snafu(display("Failed to save kernel parameters to '{}': {}", path.display(), source))]
+        KernelParametersPersist {
+            path: PathBuf,
+            source: tempfile::PersistError,
+        },
+
+        #[snafu(display(
+            "Failed to read this variant's kernel parameters from '{}': {}",
+            path.display(),
+            source
+        ))]
+        KernelParametersRead { path: PathBuf, source: io::Error },
+
+        #[snafu(display(
+            "Kernel parameters need {} bytes of boot configuration, but only {} are available",
+            size,
+            max
+        ))]
+        KernelParametersTooLong { size: usize, max: usize },
+
+        #[snafu(display("Failed to write kernel parameters to '{}': {}", path.display(), source))]
+        KernelParametersWrite { path: PathBuf, source: io::Error },
+
         #[snafu(display(
             "Failed to change lockdown from '{}' to '{}': {}",
             current,
@@ -301,6 +575,7 @@ type Result<T> = std::result::Result<T, error::Error>;
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::convert::TryFrom;
 
     #[test]
     fn no_traversal() {
@@ -326,6 +601,51 @@ mod test {
         );
     }
 
+    fn kernel_parameters(parameters: &[&str]) -> Vec<KernelParameter> {
+        parameters
+            .iter()
+            .map(|p| KernelParameter::try_from(*p).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn variant_kernel_parameters() {
+        let variant = "console=tty0\nconsole=ttyS0,115200n8\nnvme_core.io-timeout=30\n";
+        check_kernel_parameters(&kernel_parameters(&["nosmt", "mitigations=off"]), variant)
+            .unwrap();
+        check_kernel_parameters(&kernel_parameters(&["nosmt"]), "").unwrap();
+        for conflict in &["console=ttyS1", "nvme_core.io_timeout=4294967295"] {
+            check_kernel_parameters(&kernel_parameters(&["nosmt", conflict]), variant).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn grubenv_block() {
+        let block = grubenv(&kernel_parameters(&["nosmt", "console=ttyS1,115200n8"])).unwrap();
+        assert_eq!(block.len(), GRUBENV_SIZE);
+        assert!(block.starts_with(
+            "# GRUB Environment Block\nuser_kernel_parameters=nosmt console=ttyS1,115200n8\n#"
+        ));
+        assert!(block.trim_end_matches('#').ends_with('\n'));
+
+        let empty = grubenv(&[]).unwrap();
+        assert!(empty.starts_with("# GRUB Environment Block\nuser_kernel_parameters=\n#"));
+
+        let long = "a".repeat(200);
+        grubenv(&kernel_parameters(&[long.as_str(); 5])).unwrap_err();
+    }
+
+    #[test]
+    fn sysctl_values() {
+        assert!(sysctl_matches("1", "1\n"));
+        assert!(sysctl_matches(
+            "4096 87380 6291456",
+            "4096\t87380\t6291456\n"
+        ));
+        assert!(!sysctl_matches("1", "0\n"));
+        assert!(!sysctl_matches("4096 87380", "4096\t87380\t6291456\n"));
+    }
+
     #[test]
     fn no_brackets() {
         assert_eq!("none", parse_kernel_setting("none"));
@@ -331,7 +331,7 @@ where
                         .filter(|new_key| new_key.starts_with_segments(&[&struct_name]))
                         // Remove the prefix - should always work, but log and skip the key otherwise
                         .filter_map(|new_key| new_key
-                                    .strip_prefix(&struct_name)
+                                    .strip_prefix_segments(&[&struct_name])
                                     .map_err(|e| error!("Key starting with segment '{}' couldn't remove it as prefix: {}", &struct_name, e)).ok())
                         .collect();
 
@@ -508,6 +508,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn map_of_structs_with_dotted_keys_works() {
+        let map = &hashmap! {
+            key!("x.\"a.b\".boolean") => "true".to_string(),
+            key!("x.c.boolean") => "false".to_string(),
+        };
+        let x: HashMap<String, C> = from_map_with_prefix(Some("x".to_string()), map).unwrap();
+        assert_eq!(
+            x,
+            hashmap! {
+                "a.b".to_string() => C { boolean: true },
+                "c".to_string() => C { boolean: false },
+            }
+        );
+    }
+
     #[derive(Debug, Deserialize, PartialEq)]
     struct Bad {
         id: u64,
@@ -59,6 +59,26 @@ pub enum Error {
 
     #[snafu(display("Key name beyond maximum length {}: {}", name, max))]
     KeyTooLong { name: String, max: usize },
+
+    #[snafu(display(
+        "Generation {} is not in history; can roll back to generations {} through {}",
+        id,
+        oldest,
+        current
+    ))]
+    HistoryNotFound { id: u64, oldest: u64, current: u64 },
+
+    #[snafu(display("Invalid key generations file at {}: {}", path.display(), source))]
+    GenerationsFile {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Invalid history record at {}: {}", path.display(), source))]
+    HistoryRecord {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
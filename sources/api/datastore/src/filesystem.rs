@@ -3,18 +3,28 @@
 //!
 //! Data is kept in files with paths resembling the keys, e.g. a/b/c for a.b.c, and metadata is
 //! kept in a suffixed file next to the data, e.g. a/b/c.meta for metadata "meta" about a.b.c
+//!
+//! Records of committed transactions are kept as JSON files named by generation in the history
+//! directory, e.g. history/42.json, and the generation in which each data key was last changed is
+//! kept in generations.json.  A commit's record is written to history/pending.json before live data
+//! is changed, and moved into place once live data and generations are updated, so an interrupted
+//! commit can be finished before the next one.
+//!
+//! Files are written to a temporary file next to their final path and renamed into place, so
+//! readers never see partial values.
 
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::path::{self, Path, PathBuf};
 use walkdir::{DirEntry, WalkDir};
 
+use super::history::{self, TransactionRecord, MAX_HISTORY_RECORDS};
 use super::key::{Key, KeyType};
-use super::{error, Committed, DataStore, Result};
+use super::{error, Commit, Committed, DataStore, Result};
 
 const METADATA_KEY_PREFIX: &str = ".";
 
@@ -29,6 +39,8 @@ const ENCODE_CHARACTERS: &AsciiSet = &NON_ALPHANUMERIC.remove(b'_').remove(b'-')
 pub struct FilesystemDataStore {
     live_path: PathBuf,
     pending_base_path: PathBuf,
+    history_path: PathBuf,
+    generations_path: PathBuf,
 }
 
 impl FilesystemDataStore {
@@ -36,9 +48,148 @@ impl FilesystemDataStore {
         FilesystemDataStore {
             live_path: base_path.as_ref().join("live"),
             pending_base_path: base_path.as_ref().join("pending"),
+            history_path: base_path.as_ref().join("history"),
+            generations_path: base_path.as_ref().join("generations.json"),
+        }
+    }
+
+    /// Reads the map of data key name to the generation in which it was last changed.
+    fn read_generations(&self) -> Result<BTreeMap<String, u64>> {
+        let path = &self.generations_path;
+        match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data).context(error::GenerationsFile { path }),
+            // No commits have changed data yet.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e).context(error::Io { path }),
         }
     }
 
+    /// Returns the path of the history record for the given generation.
+    fn history_record_path(&self, id: u64) -> PathBuf {
+        self.history_path.join(format!("{}.json", id))
+    }
+
+    /// Returns the path of the record of a commit that hasn't been fully applied yet.
+    fn pending_record_path(&self) -> PathBuf {
+        self.history_path.join("pending.json")
+    }
+
+    /// Returns the generations that have history records on disk, in order.
+    fn history_ids(&self) -> Result<Vec<u64>> {
+        let entries = match fs::read_dir(&self.history_path) {
+            Ok(entries) => entries,
+            // No commits have been recorded yet.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).context(error::Io {
+                    path: &self.history_path,
+                })
+            }
+        };
+
+        let mut ids = Vec::new();
+        for entry in entries {
+            let entry = entry.context(error::Io {
+                path: &self.history_path,
+            })?;
+            let path = entry.path();
+            match path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+            {
+                Some(id) => ids.push(id),
+                None => trace!("Skipping non-record file in history: {}", path.display()),
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Applies the given values to live data, unsetting keys with None values, and records the
+    /// change in history if anything changed.  The oldest history records are removed to keep
+    /// at most MAX_HISTORY_RECORDS.  Returns the new generation, or None if nothing changed.
+    fn commit_values(
+        &mut self,
+        transaction: &str,
+        values: &HashMap<Key, Option<String>>,
+    ) -> Result<Option<u64>> {
+        // Live data must match history before we record changes against it.
+        self.finish_interrupted_commit()?;
+
+        let old = history::live_values(self, values.keys())?;
+        let ids = self.history_ids()?;
+        let id = ids.last().copied().unwrap_or(0) + 1;
+        let record = TransactionRecord::new(id, transaction, &old, values);
+        if record.changes.is_empty() {
+            trace!(
+                "No changes to record in history for transaction '{}'",
+                transaction
+            );
+            return Ok(None);
+        }
+
+        // Write the record before touching live data, so we can finish the commit if we're
+        // interrupted.
+        let path = self.pending_record_path();
+        debug!("Recording generation {} at {}", id, path.display());
+        let data =
+            serde_json::to_string_pretty(&record).context(error::HistoryRecord { path: &path })?;
+        write_file_mkdir(path, data)?;
+        self.apply_record(&record)?;
+
+        let excess = (ids.len() + 1).saturating_sub(MAX_HISTORY_RECORDS);
+        for old_id in ids.into_iter().take(excess) {
+            let path = self.history_record_path(old_id);
+            debug!("Removing old history record {}", path.display());
+            fs::remove_file(&path).context(error::Io { path })?;
+        }
+
+        Ok(Some(id))
+    }
+
+    /// Applies the new values from the pending record to live data and generations, and then
+    /// moves the record into history.  Each step can safely be repeated.
+    fn apply_record(&mut self, record: &TransactionRecord) -> Result<()> {
+        for (name, change) in &record.changes {
+            let key = Key::new(KeyType::Data, name)?;
+            match &change.new {
+                Some(value) => self.set_key(&key, value, &Committed::Live)?,
+                None => self.unset_key(&key, &Committed::Live)?,
+            }
+        }
+
+        let mut generations = self.read_generations()?;
+        for name in record.changes.keys() {
+            generations.insert(name.clone(), record.id);
+        }
+        let path = &self.generations_path;
+        let data =
+            serde_json::to_string_pretty(&generations).context(error::GenerationsFile { path })?;
+        write_file_mkdir(path.clone(), data)?;
+
+        let path = self.history_record_path(record.id);
+        debug!("Moving generation {} into history", record.id);
+        fs::rename(self.pending_record_path(), &path).context(error::Io { path })
+    }
+
+    /// If a commit was interrupted after its record was written, finishes applying it.
+    fn finish_interrupted_commit(&mut self) -> Result<()> {
+        let path = self.pending_record_path();
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).context(error::Io { path }),
+        };
+        let record: TransactionRecord =
+            serde_json::from_str(&data).context(error::HistoryRecord { path: &path })?;
+        warn!(
+            "Finishing interrupted commit of transaction '{}' as generation {}",
+            record.transaction, record.id
+        );
+        self.apply_record(&record)
+    }
+
     /// Returns the appropriate filesystem path for pending or live data.
     fn base_path(&self, committed: &Committed) -> PathBuf {
         match committed {
@@ -214,7 +365,8 @@ fn read_file_for_key(key: &Key, path: &Path) -> Result<Option<String>> {
 }
 
 /// Helper for writing a file that makes the directory tree beforehand, so we can handle
-/// arbitrarily dotted keys without needing to create fixed structure first.
+/// arbitrarily dotted keys without needing to create fixed structure first.  The data is written
+/// to a temporary file and renamed into place, so the file is never partially written.
 fn write_file_mkdir<S: AsRef<str>>(path: PathBuf, data: S) -> Result<()> {
     // create key prefix directory if necessary
     let dirname = path.parent().with_context(|| error::Internal {
@@ -225,7 +377,18 @@ fn write_file_mkdir<S: AsRef<str>>(path: PathBuf, data: S) -> Result<()> {
     })?;
     fs::create_dir_all(dirname).context(error::Io { path: dirname })?;
 
-    fs::write(&path, data.as_ref().as_bytes()).context(error::Io { path: &path })
+    // The leading dot gives the temporary file an empty key segment, so it's never mistaken for a
+    // key if we're interrupted before renaming it.
+    let file_name = path.file_name().with_context(|| error::Internal {
+        msg: format!("Given path to write without file name: {}", path.display()),
+    })?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".tmp");
+    let temp_path = dirname.join(temp_name);
+
+    fs::write(&temp_path, data.as_ref().as_bytes()).context(error::Io { path: &temp_path })?;
+    fs::rename(&temp_path, &path).context(error::Io { path: &path })
 }
 
 /// KeyPath represents the filesystem path to a data or metadata key, relative to the base path of
@@ -479,34 +642,42 @@ impl DataStore for FilesystemDataStore {
 
     /// We commit by copying pending keys to live, then removing pending.  Something smarter (lock,
     /// atomic flip, etc.) will be required to make the server concurrent.
-    fn commit_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
+    fn commit_transaction<S>(&mut self, transaction: S) -> Result<Commit>
     where
         S: Into<String> + AsRef<str>,
     {
+        let transaction = transaction.into();
         let pending = Committed::Pending {
-            tx: transaction.into(),
+            tx: transaction.clone(),
         };
         // Get data for changed keys
         let pending_data = self.get_prefix("settings.", &pending)?;
 
         // Nothing to do if no keys are present in pending
         if pending_data.is_empty() {
-            return Ok(Default::default());
+            return Ok(Commit::default());
         }
 
         // Save Keys for return value
         let pending_keys: HashSet<Key> = pending_data.keys().cloned().collect();
 
-        // Apply changes to live
+        // Apply changes to live, recording them in history
         debug!("Writing pending keys to live");
-        self.set_keys(&pending_data, &Committed::Live)?;
+        let values = pending_data
+            .into_iter()
+            .map(|(key, value)| (key, Some(value)))
+            .collect();
+        let generation = self.commit_values(&transaction, &values)?;
 
         // Remove pending
         debug!("Removing old pending keys");
         let path = self.base_path(&pending);
         fs::remove_dir_all(&path).context(error::Io { path })?;
 
-        Ok(pending_keys)
+        Ok(Commit {
+            keys: pending_keys,
+            generation,
+        })
     }
 
     fn delete_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
@@ -578,11 +749,42 @@ impl DataStore for FilesystemDataStore {
 
         Ok(transactions)
     }
+
+    fn list_history(&self) -> Result<Vec<TransactionRecord>> {
+        let mut records = Vec::new();
+        for id in self.history_ids()? {
+            let path = self.history_record_path(id);
+            let data = fs::read_to_string(&path).context(error::Io { path: &path })?;
+            let record = serde_json::from_str(&data).context(error::HistoryRecord { path })?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn rollback_to_generation(&mut self, generation: u64) -> Result<Commit> {
+        let values = history::rollback_values(&self.list_history()?, generation)?;
+        let generation = self.commit_values(&format!("rollback-to-{}", generation), &values)?;
+        Ok(Commit {
+            keys: values.into_keys().collect(),
+            generation,
+        })
+    }
+
+    fn key_generations<S: AsRef<str>>(&self, prefix: S) -> Result<HashMap<Key, u64>> {
+        let mut result = HashMap::new();
+        for (name, generation) in self.read_generations()? {
+            if name.starts_with(prefix.as_ref()) {
+                result.insert(Key::new(KeyType::Data, name)?, generation);
+            }
+        }
+        Ok(result)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use maplit::hashmap;
 
     #[test]
     fn data_path() {
@@ -623,6 +825,104 @@ mod test {
         assert_eq!(live.into_os_string(), "/base/live/a/b/c.my-metadata");
     }
 
+    #[test]
+    fn history_is_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut f = FilesystemDataStore::new(dir.path());
+        let key = Key::new(KeyType::Data, "settings.a").unwrap();
+        let tx = "test transaction";
+        let pending = Committed::Pending { tx: tx.into() };
+
+        for i in 0..MAX_HISTORY_RECORDS + 2 {
+            f.set_key(&key, i.to_string(), &pending).unwrap();
+            f.commit_transaction(tx).unwrap();
+        }
+
+        let history = f.list_history().unwrap();
+        assert_eq!(history.len(), MAX_HISTORY_RECORDS);
+        assert_eq!(history[0].id, 3);
+        assert_eq!(history.last().unwrap().id, (MAX_HISTORY_RECORDS + 2) as u64);
+
+        // Generation 2 is the oldest we can still restore.
+        f.rollback_to_generation(1).unwrap_err();
+        f.rollback_to_generation(2).unwrap();
+        assert_eq!(
+            f.get_key(&key, &Committed::Live).unwrap(),
+            Some("1".to_string())
+        );
+    }
+
+    #[test]
+    fn interrupted_commit_finished() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut f = FilesystemDataStore::new(dir.path());
+        let a = Key::new(KeyType::Data, "settings.a").unwrap();
+        let b = Key::new(KeyType::Data, "settings.b").unwrap();
+        let tx = "test transaction";
+        let pending = Committed::Pending { tx: tx.into() };
+        f.set_key(&a, "1", &pending).unwrap();
+        f.commit_transaction(tx).unwrap();
+
+        // Simulate a commit interrupted after writing its record, before changing live data.
+        let new = hashmap!(a.clone() => Some("2".to_string()));
+        let old = hashmap!(a.clone() => Some("1".to_string()));
+        let record = TransactionRecord::new(2, "interrupted", &old, &new);
+        write_file_mkdir(
+            f.pending_record_path(),
+            serde_json::to_string(&record).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(f.list_history().unwrap().len(), 1);
+
+        // The next commit finishes it first.
+        f.set_key(&b, "1", &pending).unwrap();
+        f.commit_transaction(tx).unwrap();
+        assert_eq!(
+            f.get_key(&a, &Committed::Live).unwrap(),
+            Some("2".to_string())
+        );
+        let history = f.list_history().unwrap();
+        assert_eq!(
+            history.iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(history[1].transaction, "interrupted");
+        assert!(!f.pending_record_path().exists());
+        assert_eq!(f.key_generations("settings.").unwrap().get(&a), Some(&2));
+    }
+
+    #[test]
+    fn key_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut f = FilesystemDataStore::new(dir.path());
+        let a = Key::new(KeyType::Data, "settings.x.a").unwrap();
+        let b = Key::new(KeyType::Data, "settings.y.b").unwrap();
+        let tx = "test transaction";
+        let pending = Committed::Pending { tx: tx.into() };
+
+        assert_eq!(f.prefix_generation("settings.").unwrap(), 0);
+
+        f.set_key(&a, "1", &pending).unwrap();
+        f.set_key(&b, "1", &pending).unwrap();
+        f.commit_transaction(tx).unwrap();
+        f.set_key(&b, "2", &pending).unwrap();
+        f.commit_transaction(tx).unwrap();
+        // Writing the same value again isn't a change.
+        f.set_key(&a, "1", &pending).unwrap();
+        assert_eq!(f.commit_transaction(tx).unwrap().generation, None);
+
+        assert_eq!(
+            f.key_generations("settings.").unwrap(),
+            hashmap!(a.clone() => 1, b.clone() => 2)
+        );
+        assert_eq!(f.prefix_generation("settings.x").unwrap(), 1);
+        assert_eq!(f.prefix_generation("settings.y").unwrap(), 2);
+
+        // Removing a key in a rollback counts as a change.
+        assert_eq!(f.rollback_to_generation(0).unwrap().generation, Some(3));
+        assert_eq!(f.prefix_generation("settings.x").unwrap(), 3);
+    }
+
     #[test]
     fn encode_path_component_works() {
         assert_eq!(encode_path_component("a-b_42"), "a-b_42");
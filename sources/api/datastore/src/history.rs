@@ -0,0 +1,205 @@
+//! The history module defines the records a data store keeps about committed transactions, so
+//! that changes can be reviewed and rolled back.
+//!
+//! Each commit that changes live data is given a generation number, one higher than the last.
+//! The record of the commit stores the old and new value of each changed key, so the changes can
+//! be reverted.  Only a bounded number of records is kept; see `MAX_HISTORY_RECORDS`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::ensure;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{error, Committed, DataStore, Key, KeyType, Result};
+
+/// The number of transaction records a data store keeps; older records are removed as new
+/// transactions are committed.
+pub const MAX_HISTORY_RECORDS: usize = 50;
+
+/// The old and new values of a data key that was changed in a transaction.  Values are in the
+/// data store's serialized scalar format, and are None if the key wasn't populated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyChange {
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// A record of a committed transaction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    /// The generation of the data store created by this commit.
+    pub id: u64,
+    /// The name of the transaction that was committed.
+    pub transaction: String,
+    /// The time the transaction was committed.
+    pub timestamp: DateTime<Utc>,
+    /// The changed data keys, by name, and their old and new values.
+    pub changes: BTreeMap<String, KeyChange>,
+}
+
+impl TransactionRecord {
+    /// Builds a record for the given commit, given the live data before and after the commit.
+    /// Keys whose values didn't change aren't included.
+    pub(crate) fn new<S>(
+        id: u64,
+        transaction: S,
+        old: &HashMap<Key, Option<String>>,
+        new: &HashMap<Key, Option<String>>,
+    ) -> Self
+    where
+        S: Into<String>,
+    {
+        let mut changes = BTreeMap::new();
+        for (key, new_value) in new {
+            let old_value = old.get(key).cloned().flatten();
+            if old_value != *new_value {
+                changes.insert(
+                    key.name().clone(),
+                    KeyChange {
+                        old: old_value,
+                        new: new_value.clone(),
+                    },
+                );
+            }
+        }
+
+        Self {
+            id,
+            transaction: transaction.into(),
+            timestamp: Utc::now(),
+            changes,
+        }
+    }
+
+    /// Returns the changed keys as Keys.
+    pub fn keys(&self) -> Result<HashSet<Key>> {
+        self.changes
+            .keys()
+            .map(|name| Key::new(KeyType::Data, name))
+            .collect()
+    }
+}
+
+/// Given the history of a data store, oldest first, returns the values each key must be given to
+/// restore live data to the given generation.  None means the key must be unset.
+///
+/// Fails if the history no longer covers every transaction committed after the given generation.
+pub(crate) fn rollback_values(
+    history: &[TransactionRecord],
+    generation: u64,
+) -> Result<HashMap<Key, Option<String>>> {
+    let current = history.last().map(|record| record.id).unwrap_or(0);
+    // We need every record after the requested generation to revert it.
+    let oldest_revertible = history
+        .first()
+        .map(|record| record.id - 1)
+        .unwrap_or(current);
+    ensure!(
+        generation >= oldest_revertible && generation <= current,
+        error::HistoryNotFound {
+            id: generation,
+            oldest: oldest_revertible,
+            current,
+        }
+    );
+
+    // Walk backward through the newer records; the oldest record that touched a key has the
+    // value it had at the requested generation.
+    let mut values = HashMap::new();
+    for record in history.iter().rev().take_while(|r| r.id > generation) {
+        for (name, change) in &record.changes {
+            let key = Key::new(KeyType::Data, name)?;
+            values.insert(key, change.old.clone());
+        }
+    }
+
+    Ok(values)
+}
+
+/// Reads the current live values of the given keys, for building a TransactionRecord.
+pub(crate) fn live_values<'a, D, I>(datastore: &D, keys: I) -> Result<HashMap<Key, Option<String>>>
+where
+    D: DataStore + ?Sized,
+    I: IntoIterator<Item = &'a Key>,
+{
+    let mut values = HashMap::new();
+    for key in keys {
+        values.insert(key.clone(), datastore.get_key(key, &Committed::Live)?);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::hashmap;
+
+    fn key(name: &str) -> Key {
+        Key::new(KeyType::Data, name).unwrap()
+    }
+
+    fn record(id: u64, changes: &[(&str, Option<&str>, Option<&str>)]) -> TransactionRecord {
+        TransactionRecord {
+            id,
+            transaction: "test".to_string(),
+            timestamp: Utc::now(),
+            changes: changes
+                .iter()
+                .map(|(name, old, new)| {
+                    (
+                        name.to_string(),
+                        KeyChange {
+                            old: old.map(String::from),
+                            new: new.map(String::from),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn new_record_skips_unchanged() {
+        let old = hashmap!(key("a") => Some("1".to_string()), key("b") => Some("2".to_string()));
+        let new = hashmap!(key("a") => Some("1".to_string()), key("b") => Some("3".to_string()));
+        let record = TransactionRecord::new(1, "tx", &old, &new);
+        assert_eq!(record.changes.len(), 1);
+        assert_eq!(
+            record.changes["b"],
+            KeyChange {
+                old: Some("2".to_string()),
+                new: Some("3".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn rollback_uses_oldest_reverted_value() {
+        let history = vec![
+            record(4, &[("a", None, Some("1"))]),
+            record(
+                5,
+                &[("a", Some("1"), Some("2")), ("b", Some("x"), Some("y"))],
+            ),
+            record(6, &[("a", Some("2"), Some("3"))]),
+        ];
+
+        assert_eq!(
+            rollback_values(&history, 4).unwrap(),
+            hashmap!(key("a") => Some("1".to_string()), key("b") => Some("x".to_string()))
+        );
+        assert_eq!(
+            rollback_values(&history, 3).unwrap(),
+            hashmap!(key("a") => None, key("b") => Some("x".to_string()))
+        );
+        assert!(rollback_values(&history, 6).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rollback_out_of_range() {
+        let history = vec![record(4, &[("a", None, Some("1"))])];
+        rollback_values(&history, 2).unwrap_err();
+        rollback_values(&history, 5).unwrap_err();
+        rollback_values(&[], 1).unwrap_err();
+    }
+}
@@ -100,6 +100,7 @@ impl Key {
     /// identical.
     ///
     /// Fails if the new key would be invalid, e.g. if the prefix is the entire key.
+    #[allow(dead_code)]
     pub(super) fn strip_prefix<S>(&self, prefix: S) -> Result<Self>
     where
         S: AsRef<str>,
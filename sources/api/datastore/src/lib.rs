@@ -14,6 +14,15 @@ There's also a common error type and some methods that implementations of DataSt
 We represent scalars -- the actual values stored under a datastore key -- using JSON, just to have a convenient human-readable form.
 (TOML doesn't allow raw scalars.  The JSON spec doesn't seem to either, but this works, and the format is so simple for scalars that it could be easily swapped out if needed.)
 
+# History
+
+Data stores keep a bounded history of committed transactions, including the old and new values of the changed keys.
+Each commit that changes data creates a new generation of the data store, and live data can be rolled back to a recent generation.
+See the `history` module.
+
+Data stores also track the generation in which each data key was last changed, even after the history record of that change is gone.
+This lets callers detect whether data has changed since they read it, for optimistic concurrency.
+
 # Serialization and deserialization
 
 The `serialization` module provides code to serialize Rust types into a mapping of datastore-acceptable keys (a.b.c) and values.
@@ -23,19 +32,21 @@ The `deserialization` module provides code to deserialize datastore-acceptable k
 # Current limitations
 
 * The user (e.g. apiserver) needs to handle locking.
-* There's no support for rolling back transactions.
+* Rollback only covers the most recent transactions, and only data keys; metadata isn't tracked.
 * The `serialization` module can't handle complex types under lists; it assumes lists can be serialized as scalars.
 */
 
 pub mod deserialization;
 pub mod error;
 pub mod filesystem;
+pub mod history;
 pub mod key;
 pub mod memory;
 pub mod serialization;
 
 pub use error::{Error, Result};
 pub use filesystem::FilesystemDataStore;
+pub use history::TransactionRecord;
 pub use key::{Key, KeyType, KEY_SEPARATOR, KEY_SEPARATOR_STR};
 
 use log::trace;
@@ -54,6 +65,15 @@ pub enum Committed {
     },
 }
 
+/// The result of committing changes to live data.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Commit {
+    /// The keys that were committed, whether or not their values changed.
+    pub keys: HashSet<Key>,
+    /// The generation created by the commit, or None if no live value changed.
+    pub generation: Option<u64>,
+}
+
 pub trait DataStore {
     /// Returns whether a key is present (has a value) in the datastore.
     fn key_populated(&self, key: &Key, committed: &Committed) -> Result<bool>;
@@ -124,8 +144,8 @@ pub trait DataStore {
     fn unset_metadata(&mut self, metadata_key: &Key, data_key: &Key) -> Result<()>;
 
     /// Applies pending changes from the given transaction to the live datastore.  Returns the
-    /// list of changed keys.
-    fn commit_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
+    /// list of committed keys and the generation the commit created, if any.
+    fn commit_transaction<S>(&mut self, transaction: S) -> Result<Commit>
     where
         S: Into<String> + AsRef<str>;
 
@@ -138,6 +158,26 @@ pub trait DataStore {
     /// Returns a list of the names of any pending transactions in the data store.
     fn list_transactions(&self) -> Result<HashSet<String>>;
 
+    /// Returns the records of recently committed transactions, oldest first.
+    fn list_history(&self) -> Result<Vec<TransactionRecord>>;
+
+    /// Restores live data to the state it had at the given generation, by reverting the changes
+    /// made by any transactions committed after it.  The rollback is recorded in history as a
+    /// new generation, so it can be reverted too.  Returns the list of changed keys and the
+    /// generation the rollback created, if any.
+    fn rollback_to_generation(&mut self, generation: u64) -> Result<Commit>;
+
+    /// Returns the generation in which each live data key starting with the given prefix was
+    /// last changed.  Keys that have been removed are included, since their removal is a change.
+    fn key_generations<S: AsRef<str>>(&self, prefix: S) -> Result<HashMap<Key, u64>>;
+
+    /// Returns the latest generation in which any live data key starting with the given prefix
+    /// was changed, or 0 if none have been changed since the data store was created.
+    fn prefix_generation<S: AsRef<str>>(&self, prefix: S) -> Result<u64> {
+        let generations = self.key_generations(prefix)?;
+        Ok(generations.values().copied().max().unwrap_or(0))
+    }
+
     /// Set multiple data keys at once in the data store.
     ///
     /// Implementers can replace the default implementation if there's a faster way than setting
@@ -5,7 +5,8 @@
 
 use std::collections::{HashMap, HashSet};
 
-use super::{Committed, DataStore, Key, Result};
+use super::history::{self, TransactionRecord, MAX_HISTORY_RECORDS};
+use super::{Commit, Committed, DataStore, Key, Result};
 
 #[derive(Debug)]
 pub struct MemoryDataStore {
@@ -16,6 +17,10 @@ pub struct MemoryDataStore {
     // Map of data keys to their metadata, which in turn is a mapping of metadata keys to
     // arbitrary (string/serialized) values.
     metadata: HashMap<Key, HashMap<Key, String>>,
+    // Records of committed transactions, oldest first.
+    history: Vec<TransactionRecord>,
+    // Generation in which each data key was last changed.
+    generations: HashMap<Key, u64>,
 }
 
 impl MemoryDataStore {
@@ -24,6 +29,8 @@ impl MemoryDataStore {
             pending: HashMap::new(),
             live: HashMap::new(),
             metadata: HashMap::new(),
+            history: Vec::new(),
+            generations: HashMap::new(),
         }
     }
 
@@ -40,6 +47,36 @@ impl MemoryDataStore {
             Committed::Pending { tx } => self.pending.entry(tx.clone()).or_default(),
         }
     }
+
+    /// Applies the given values to live data, unsetting keys with None values, and records the
+    /// change in history if anything changed.
+    fn commit_values(
+        &mut self,
+        transaction: &str,
+        values: &HashMap<Key, Option<String>>,
+    ) -> Result<Option<u64>> {
+        let old = history::live_values(self, values.keys())?;
+        for (key, value) in values {
+            match value {
+                Some(value) => self.set_key(key, value, &Committed::Live)?,
+                None => self.unset_key(key, &Committed::Live)?,
+            }
+        }
+
+        let id = self.history.last().map(|r| r.id).unwrap_or(0) + 1;
+        let record = TransactionRecord::new(id, transaction, &old, values);
+        if record.changes.is_empty() {
+            return Ok(None);
+        }
+        for key in record.keys()? {
+            self.generations.insert(key, id);
+        }
+        self.history.push(record);
+        if self.history.len() > MAX_HISTORY_RECORDS {
+            self.history.remove(0);
+        }
+        Ok(Some(id))
+    }
 }
 
 impl DataStore for MemoryDataStore {
@@ -150,18 +187,25 @@ impl DataStore for MemoryDataStore {
         Ok(())
     }
 
-    fn commit_transaction<S>(&mut self, transaction: S) -> Result<HashSet<Key>>
+    fn commit_transaction<S>(&mut self, transaction: S) -> Result<Commit>
     where
         S: Into<String> + AsRef<str>,
     {
         // Remove anything pending for this transaction
         if let Some(pending) = self.pending.remove(transaction.as_ref()) {
             // Apply pending changes to live
-            self.set_keys(&pending, &Committed::Live)?;
+            let values = pending
+                .iter()
+                .map(|(key, value)| (key.clone(), Some(value.clone())))
+                .collect();
+            let generation = self.commit_values(transaction.as_ref(), &values)?;
             // Return keys that were committed
-            Ok(pending.keys().cloned().collect())
+            Ok(Commit {
+                keys: pending.keys().cloned().collect(),
+                generation,
+            })
         } else {
-            Ok(HashSet::new())
+            Ok(Commit::default())
         }
     }
 
@@ -181,11 +225,33 @@ impl DataStore for MemoryDataStore {
     fn list_transactions(&self) -> Result<HashSet<String>> {
         Ok(self.pending.keys().cloned().collect())
     }
+
+    fn list_history(&self) -> Result<Vec<TransactionRecord>> {
+        Ok(self.history.clone())
+    }
+
+    fn rollback_to_generation(&mut self, generation: u64) -> Result<Commit> {
+        let values = history::rollback_values(&self.history, generation)?;
+        let generation = self.commit_values(&format!("rollback-to-{}", generation), &values)?;
+        Ok(Commit {
+            keys: values.into_keys().collect(),
+            generation,
+        })
+    }
+
+    fn key_generations<S: AsRef<str>>(&self, prefix: S) -> Result<HashMap<Key, u64>> {
+        Ok(self
+            .generations
+            .iter()
+            .filter(|(k, _)| k.name().starts_with(prefix.as_ref()))
+            .map(|(k, generation)| (k.clone(), *generation))
+            .collect())
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::super::{Committed, DataStore, Key, KeyType};
+    use super::super::{Commit, Committed, DataStore, Key, KeyType};
     use super::MemoryDataStore;
     use maplit::hashset;
 
@@ -251,6 +317,45 @@ mod test {
         assert!(m.key_populated(&k, &Committed::Live).unwrap());
     }
 
+    #[test]
+    fn rollback() {
+        let mut m = MemoryDataStore::new();
+        let k = Key::new(KeyType::Data, "settings.a").unwrap();
+        let k2 = Key::new(KeyType::Data, "settings.b").unwrap();
+        let tx = "test transaction";
+        let pending = Committed::Pending { tx: tx.into() };
+
+        m.set_key(&k, "1", &pending).unwrap();
+        m.commit_transaction(tx).unwrap();
+        m.set_key(&k, "2", &pending).unwrap();
+        m.set_key(&k2, "3", &pending).unwrap();
+        m.commit_transaction(tx).unwrap();
+
+        let history = m.list_history().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].id, 2);
+        assert_eq!(history[1].transaction, tx);
+
+        // Roll back the second commit; settings.b didn't exist before, so it's removed.
+        assert_eq!(
+            m.rollback_to_generation(1).unwrap(),
+            Commit {
+                keys: hashset!(k.clone(), k2.clone()),
+                generation: Some(3),
+            }
+        );
+        assert_eq!(
+            m.get_key(&k, &Committed::Live).unwrap(),
+            Some("1".to_string())
+        );
+        assert!(!m.key_populated(&k2, &Committed::Live).unwrap());
+
+        // The rollback is recorded as a new generation.
+        let history = m.list_history().unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[2].transaction, "rollback-to-1");
+    }
+
     #[test]
     fn delete_transaction() {
         let mut m = MemoryDataStore::new();
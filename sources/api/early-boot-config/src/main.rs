@@ -8,6 +8,13 @@ something like an instance metadata service.
 
 Currently, Amazon EC2 is supported through the IMDSv1 HTTP API.  Data will be taken from files in
 /etc/early-boot-config instead, if available, for testing purposes.
+
+On bare metal and local virtual machines, like KVM/libvirt labs, user data and metadata are read
+from a cloud-init NoCloud volume (labeled `cidata`) or an OpenStack config drive (labeled
+`config-2`), mounted at /media/nocloud.  User data is taken from `user-data` or
+`openstack/latest/user_data`, and may be gzip-compressed.  From the metadata, the hostname
+(`local-hostname`, or `hostname` on a config drive) is sent as `settings.network.hostname`, and the
+instance ID (`instance-id`, or `uuid` on a config drive) as `settings.metal.instance-id`.
 */
 
 #![deny(rust_2018_idioms)]
@@ -16,6 +16,11 @@ mod vmware;
 #[cfg(bottlerocket_platform = "vmware")]
 pub(crate) use vmware::VmwareDataProvider as Platform;
 
+#[cfg(bottlerocket_platform = "metal")]
+mod nocloud;
+#[cfg(bottlerocket_platform = "metal")]
+pub(crate) use nocloud::NoCloudDataProvider as Platform;
+
 /// Support for new platforms can be added by implementing this trait.
 #[async_trait]
 pub(crate) trait PlatformDataProvider {
@@ -0,0 +1,305 @@
+//! The nocloud module implements the `PlatformDataProvider` trait for gathering user data and
+//! metadata from a cloud-init NoCloud volume or an OpenStack config drive
+
+use super::{PlatformDataProvider, SettingsJson};
+use crate::compression::expand_slice_maybe;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::fs;
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+
+pub(crate) struct NoCloudDataProvider;
+
+impl NoCloudDataProvider {
+    // This program expects that the volume is already mounted.  Mounting happens elsewhere in a
+    // systemd unit file, for a volume labeled `cidata` (NoCloud) or `config-2` (config drive).
+    const MOUNT: &'static str = "/media/nocloud";
+
+    // A NoCloud volume has `user-data` and `meta-data` files at the top level
+    const NOCLOUD_USER_DATA: &'static str = "user-data";
+    const NOCLOUD_META_DATA: &'static str = "meta-data";
+
+    // A config drive has a directory for each version of the OpenStack metadata format
+    const CONFIG_DRIVE_USER_DATA: &'static str = "openstack/latest/user_data";
+    const CONFIG_DRIVE_META_DATA: &'static str = "openstack/latest/meta_data.json";
+
+    /// Read and decode user data from the volume mounted at `mount`, from a NoCloud `user-data`
+    /// file or, failing that, a config drive's `user_data` file
+    fn user_data(mount: &Path) -> Result<Option<SettingsJson>> {
+        info!("Attempting to retrieve user data from {}", mount.display());
+        let user_data_file =
+            match Self::find_file(mount, Self::NOCLOUD_USER_DATA, Self::CONFIG_DRIVE_USER_DATA) {
+                Some(file) => file,
+                None => return Ok(None),
+            };
+        info!("'{}' exists, using it", user_data_file.display());
+
+        // Read the file, decompressing it if compressed.
+        let user_data_raw = fs::read(&user_data_file).context(error::InputFileRead {
+            path: &user_data_file,
+        })?;
+        let user_data_str = expand_slice_maybe(&user_data_raw)
+            .context(error::Decompression { what: "user data" })?;
+
+        if user_data_str.is_empty() {
+            return Ok(None);
+        }
+
+        // User data could be 700MB compressed!  Eek!  :)
+        if user_data_str.len() <= 2048 {
+            trace!("Received user data: {}", user_data_str);
+        } else {
+            trace!(
+                "Received long user data, starts with: {}",
+                // (this isn't perfect because chars aren't grapheme clusters, but will error
+                // toward printing the whole input, which is fine)
+                String::from_iter(user_data_str.chars().take(2048))
+            );
+        }
+
+        let json = SettingsJson::from_toml_str(&user_data_str, "user data").context(
+            error::SettingsToJSON {
+                from: user_data_file.display().to_string(),
+            },
+        )?;
+        Ok(Some(json))
+    }
+
+    /// Read the metadata from the volume mounted at `mount`, returning a SettingsJson
+    /// representing the values we'd like to send to the API - currently the hostname and the
+    /// instance ID.
+    fn meta_data(mount: &Path) -> Result<Option<SettingsJson>> {
+        info!("Attempting to retrieve metadata from {}", mount.display());
+        let meta_data_file =
+            match Self::find_file(mount, Self::NOCLOUD_META_DATA, Self::CONFIG_DRIVE_META_DATA) {
+                Some(file) => file,
+                None => return Ok(None),
+            };
+        info!("'{}' exists, using it", meta_data_file.display());
+
+        let data = fs::read_to_string(&meta_data_file).context(error::InputFileRead {
+            path: &meta_data_file,
+        })?;
+        let meta_data = if meta_data_file.ends_with(Self::CONFIG_DRIVE_META_DATA) {
+            let meta_data: ConfigDriveMetaData =
+                serde_json::from_str(&data).context(error::DeserializeJson {
+                    path: &meta_data_file,
+                })?;
+            MetaData {
+                hostname: meta_data.hostname,
+                instance_id: meta_data.uuid,
+            }
+        } else {
+            Self::parse_nocloud_meta_data(&data)
+        };
+        trace!("Retrieved metadata: {:?}", meta_data);
+
+        let mut val = serde_json::Map::new();
+        if let Some(hostname) = meta_data.hostname {
+            val.insert("network".to_string(), json!({ "hostname": hostname }));
+        }
+        if let Some(instance_id) = meta_data.instance_id {
+            val.insert("metal".to_string(), json!({ "instance-id": instance_id }));
+        }
+        if val.is_empty() {
+            return Ok(None);
+        }
+
+        let json = SettingsJson::from_val(&val, "metadata").context(error::SettingsToJSON {
+            from: meta_data_file.display().to_string(),
+        })?;
+        Ok(Some(json))
+    }
+
+    /// Parse NoCloud metadata.  It's YAML, but in practice it's a flat list of `key: value` lines,
+    /// so we read those rather than bring in a YAML parser; nested values are ignored.
+    fn parse_nocloud_meta_data(data: &str) -> MetaData {
+        let mut values = HashMap::new();
+        for line in data.lines() {
+            // Nested values are indented; comments and blank lines have nothing for us
+            if line.starts_with(char::is_whitespace) || line.trim_start().starts_with('#') {
+                continue;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                    .unwrap_or(value);
+                if !value.is_empty() {
+                    values.insert(key.trim(), value.to_string());
+                }
+            }
+        }
+
+        // cloud-init prefers `local-hostname`, but some tools write `hostname`
+        MetaData {
+            hostname: values
+                .remove("local-hostname")
+                .or_else(|| values.remove("hostname")),
+            instance_id: values.remove("instance-id"),
+        }
+    }
+
+    /// Returns the path to the NoCloud file if it exists, otherwise the path to the config drive
+    /// file if that exists
+    fn find_file(mount: &Path, nocloud: &str, config_drive: &str) -> Option<PathBuf> {
+        [nocloud, config_drive]
+            .iter()
+            .map(|file| mount.join(file))
+            .find(|path| path.exists())
+    }
+}
+
+/// The metadata values we send to the API, whichever kind of volume they came from
+#[derive(Debug, Default, PartialEq)]
+struct MetaData {
+    hostname: Option<String>,
+    instance_id: Option<String>,
+}
+
+/// The parts of a config drive's `meta_data.json` we care about
+#[derive(Debug, Deserialize)]
+struct ConfigDriveMetaData {
+    hostname: Option<String>,
+    uuid: Option<String>,
+}
+
+#[async_trait]
+impl PlatformDataProvider for NoCloudDataProvider {
+    /// Return settings changes from the metadata and user data.
+    async fn platform_data(
+        &self,
+    ) -> std::result::Result<Vec<SettingsJson>, Box<dyn std::error::Error>> {
+        let mut output = Vec::new();
+        let mount = Path::new(Self::MOUNT);
+
+        // Metadata first, so the user has a chance to override
+        match Self::meta_data(mount)? {
+            Some(s) => output.push(s),
+            None => warn!("No metadata found in {}", Self::MOUNT),
+        }
+
+        // Optional user-specified configuration / overrides
+        match Self::user_data(mount)? {
+            Some(s) => output.push(s),
+            None => warn!("No user data found in {}", Self::MOUNT),
+        }
+
+        Ok(output)
+    }
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility = "pub(super)")]
+    pub(crate) enum Error {
+        #[snafu(display("Failed to decompress {}: {}", what, source))]
+        Decompression { what: String, source: io::Error },
+
+        #[snafu(display("Unable to deserialize JSON from '{}': {}", path.display(), source))]
+        DeserializeJson {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Unable to read input file '{}': {}", path.display(), source))]
+        InputFileRead { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Unable to serialize settings from {}: {}", from, source))]
+        SettingsToJSON {
+            from: String,
+            source: crate::settings::Error,
+        },
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_data() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_data")
+    }
+
+    fn parse(settings: &SettingsJson) -> serde_json::Value {
+        serde_json::from_str(&settings.json).unwrap()
+    }
+
+    #[test]
+    fn test_nocloud() {
+        let mount = test_data().join("nocloud");
+
+        let meta_data = NoCloudDataProvider::meta_data(&mount).unwrap().unwrap();
+        assert_eq!(
+            parse(&meta_data),
+            json!({
+                "metal": {"instance-id": "iid-local01"},
+                "network": {"hostname": "nocloud-1"},
+            })
+        );
+
+        let user_data = NoCloudDataProvider::user_data(&mount).unwrap().unwrap();
+        assert_eq!(parse(&user_data), json!({"motd": "hello"}));
+    }
+
+    #[test]
+    fn test_config_drive() {
+        // The config drive's user data is gzipped
+        let mount = test_data().join("config-drive");
+
+        let meta_data = NoCloudDataProvider::meta_data(&mount).unwrap().unwrap();
+        assert_eq!(
+            parse(&meta_data),
+            json!({
+                "metal": {"instance-id": "83679162-1378-4288-a2d4-70e13ec132aa"},
+                "network": {"hostname": "test.novalocal"},
+            })
+        );
+
+        let user_data = NoCloudDataProvider::user_data(&mount).unwrap().unwrap();
+        assert_eq!(parse(&user_data), json!({"motd": "hello"}));
+    }
+
+    #[test]
+    fn test_missing() {
+        let mount = test_data().join("missing");
+        assert!(NoCloudDataProvider::meta_data(&mount).unwrap().is_none());
+        assert!(NoCloudDataProvider::user_data(&mount).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_nocloud_meta_data() {
+        let data = r#"# written by hand
+instance-id: 'iid-abc'
+hostname: "fallback"
+public-keys:
+  - ssh-rsa AAAA
+"#;
+        assert_eq!(
+            NoCloudDataProvider::parse_nocloud_meta_data(data),
+            MetaData {
+                hostname: Some("fallback".to_string()),
+                instance_id: Some("iid-abc".to_string()),
+            }
+        );
+        assert_eq!(
+            NoCloudDataProvider::parse_nocloud_meta_data("local-hostname: a\nhostname: b\n"),
+            MetaData {
+                hostname: Some("a".to_string()),
+                instance_id: None,
+            }
+        );
+    }
+}
@@ -1145,3 +1145,311 @@ mod test_replace_metadata_list {
         .unwrap_err();
     }
 }
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// Returns whether `key` is `name`, or if `prefix` is set, whether it's under `name`.
+fn key_matches(key: &str, name: &str, prefix: bool) -> bool {
+    key == name || (prefix && key.starts_with(name) && key[name.len()..].starts_with('.'))
+}
+
+/// Moves the data and metadata of keys matching `from` (see `key_matches`) to the same place under
+/// `to`.  If any of the destination keys already exist, nothing is moved, so we don't overwrite
+/// data and can always move it back.
+fn move_keys(input: &mut MigrationData, from: &str, to: &str, prefix: bool) {
+    let destination = |key: &String| format!("{}{}", to, &key[from.len()..]);
+    let data_keys: Vec<String> = input
+        .data
+        .keys()
+        .filter(|k| key_matches(k, from, prefix))
+        .cloned()
+        .collect();
+    let metadata_keys: Vec<String> = input
+        .metadata
+        .keys()
+        .filter(|k| key_matches(k, from, prefix))
+        .cloned()
+        .collect();
+    if data_keys.is_empty() && metadata_keys.is_empty() {
+        println!("Found no '{}' to move", from);
+        return;
+    }
+
+    let existing = data_keys
+        .iter()
+        .map(destination)
+        .find(|k| input.data.contains_key(k))
+        .or_else(|| {
+            metadata_keys
+                .iter()
+                .map(destination)
+                .find(|k| input.metadata.contains_key(k))
+        });
+    if let Some(existing) = existing {
+        println!(
+            "'{}' already exists, leaving '{}' alone rather than replacing it",
+            existing, from
+        );
+        return;
+    }
+
+    for key in data_keys {
+        if let Some(data) = input.data.remove(&key) {
+            let new_key = destination(&key);
+            println!(
+                "Moved '{}' to '{}', which is set to '{}'",
+                key, new_key, data
+            );
+            input.data.insert(new_key, data);
+        }
+    }
+    for key in metadata_keys {
+        if let Some(metadata) = input.metadata.remove(&key) {
+            let new_key = destination(&key);
+            println!(
+                "Moved metadata of '{}' to '{}': {:?}",
+                key,
+                new_key,
+                metadata.keys().collect::<Vec<_>>()
+            );
+            input.metadata.insert(new_key, metadata);
+        }
+    }
+}
+
+/// We use this migration when we rename settings, keeping their values and metadata, such as
+/// 'affected-services' and 'setting-generator'.
+#[derive(Debug)]
+pub struct SettingRename {
+    pub old_name: &'static str,
+    pub new_name: &'static str,
+}
+
+pub struct RenameSettingsMigration(pub &'static [SettingRename]);
+
+impl Migration for RenameSettingsMigration {
+    fn forward(&mut self, mut input: MigrationData) -> Result<MigrationData> {
+        for rename in self.0 {
+            move_keys(&mut input, rename.old_name, rename.new_name, false);
+        }
+        Ok(input)
+    }
+
+    fn backward(&mut self, mut input: MigrationData) -> Result<MigrationData> {
+        for rename in self.0.iter().rev() {
+            move_keys(&mut input, rename.new_name, rename.old_name, false);
+        }
+        Ok(input)
+    }
+}
+
+#[cfg(test)]
+mod test_rename_settings {
+    use super::{RenameSettingsMigration, SettingRename};
+    use crate::{Migration, MigrationData};
+    use maplit::hashmap;
+    use std::collections::HashMap;
+
+    const RENAMES: &[SettingRename] = &[
+        SettingRename {
+            old_name: "settings.hi",
+            new_name: "settings.hello",
+        },
+        SettingRename {
+            old_name: "settings.bye",
+            new_name: "settings.goodbye",
+        },
+    ];
+
+    #[test]
+    fn forward() {
+        let data = MigrationData {
+            data: hashmap! {
+                "settings.hi".into() => "there".into(),
+                "settings.hi-there".into() => "friend".into(),
+                "settings.bye".into() => "now".into(),
+            },
+            metadata: hashmap! {
+                "settings.hi".into() => hashmap!{"affected-services".into() => vec!["hi"].into()},
+            },
+        };
+        let result = RenameSettingsMigration(RENAMES).forward(data).unwrap();
+        assert_eq!(
+            result.data,
+            hashmap! {
+                "settings.hello".into() => "there".into(),
+                "settings.hi-there".into() => "friend".into(),
+                "settings.goodbye".into() => "now".into(),
+            }
+        );
+        assert_eq!(
+            result.metadata,
+            hashmap! {
+                "settings.hello".into() => hashmap!{"affected-services".into() => vec!["hi"].into()},
+            }
+        );
+    }
+
+    #[test]
+    fn backward() {
+        let data = MigrationData {
+            data: hashmap! {
+                "settings.hello".into() => "there".into(),
+                "settings.goodbye".into() => "now".into(),
+            },
+            metadata: hashmap! {
+                "settings.hello".into() => hashmap!{"setting-generator".into() => "hi-gen".into()},
+            },
+        };
+        let result = RenameSettingsMigration(RENAMES).backward(data).unwrap();
+        assert_eq!(
+            result.data,
+            hashmap! {
+                "settings.hi".into() => "there".into(),
+                "settings.bye".into() => "now".into(),
+            }
+        );
+        assert_eq!(
+            result.metadata,
+            hashmap! {
+                "settings.hi".into() => hashmap!{"setting-generator".into() => "hi-gen".into()},
+            }
+        );
+    }
+
+    #[test]
+    fn existing() {
+        let data = MigrationData {
+            data: hashmap! {
+                "settings.hi".into() => "there".into(),
+                "settings.hello".into() => "already".into(),
+            },
+            metadata: HashMap::new(),
+        };
+        let result = RenameSettingsMigration(RENAMES).forward(data).unwrap();
+        // No change
+        assert_eq!(
+            result.data,
+            hashmap! {
+                "settings.hi".into() => "there".into(),
+                "settings.hello".into() => "already".into(),
+            }
+        );
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// We use this migration when we move a whole subtree of settings to a new prefix, keeping the
+/// values and metadata of the prefix and everything under it.
+#[derive(Debug)]
+pub struct MovePrefixMigration {
+    pub old_prefix: &'static str,
+    pub new_prefix: &'static str,
+}
+
+impl Migration for MovePrefixMigration {
+    fn forward(&mut self, mut input: MigrationData) -> Result<MigrationData> {
+        move_keys(&mut input, self.old_prefix, self.new_prefix, true);
+        Ok(input)
+    }
+
+    fn backward(&mut self, mut input: MigrationData) -> Result<MigrationData> {
+        move_keys(&mut input, self.new_prefix, self.old_prefix, true);
+        Ok(input)
+    }
+}
+
+#[cfg(test)]
+mod test_move_prefix {
+    use super::MovePrefixMigration;
+    use crate::{Migration, MigrationData};
+    use maplit::hashmap;
+    use std::collections::HashMap;
+
+    fn migration() -> MovePrefixMigration {
+        MovePrefixMigration {
+            old_prefix: "settings.old",
+            new_prefix: "settings.new.place",
+        }
+    }
+
+    fn old_data() -> MigrationData {
+        MigrationData {
+            data: hashmap! {
+                "settings.old.a".into() => "a".into(),
+                "settings.old.b.c".into() => vec!["c"].into(),
+                "settings.older.d".into() => "d".into(),
+            },
+            metadata: hashmap! {
+                "settings.old".into() => hashmap!{"affected-services".into() => vec!["old"].into()},
+                "settings.old.a".into() => hashmap!{"setting-generator".into() => "a-gen".into()},
+                "settings.older".into() => hashmap!{"affected-services".into() => vec!["older"].into()},
+            },
+        }
+    }
+
+    fn new_data() -> MigrationData {
+        MigrationData {
+            data: hashmap! {
+                "settings.new.place.a".into() => "a".into(),
+                "settings.new.place.b.c".into() => vec!["c"].into(),
+                "settings.older.d".into() => "d".into(),
+            },
+            metadata: hashmap! {
+                "settings.new.place".into() => hashmap!{"affected-services".into() => vec!["old"].into()},
+                "settings.new.place.a".into() => hashmap!{"setting-generator".into() => "a-gen".into()},
+                "settings.older".into() => hashmap!{"affected-services".into() => vec!["older"].into()},
+            },
+        }
+    }
+
+    #[test]
+    fn forward() {
+        let result = migration().forward(old_data()).unwrap();
+        let expected = new_data();
+        assert_eq!(result.data, expected.data);
+        assert_eq!(result.metadata, expected.metadata);
+    }
+
+    #[test]
+    fn backward() {
+        let result = migration().backward(new_data()).unwrap();
+        let expected = old_data();
+        assert_eq!(result.data, expected.data);
+        assert_eq!(result.metadata, expected.metadata);
+    }
+
+    #[test]
+    fn existing() {
+        let mut data = old_data();
+        data.data
+            .insert("settings.new.place.b.c".into(), "already".into());
+        let result = migration().forward(data).unwrap();
+        // No change
+        let mut expected = old_data();
+        expected
+            .data
+            .insert("settings.new.place.b.c".into(), "already".into());
+        assert_eq!(result.data, expected.data);
+        assert_eq!(result.metadata, expected.metadata);
+    }
+
+    #[test]
+    fn no_match() {
+        let data = MigrationData {
+            data: hashmap! {
+                "settings.older.d".into() => "d".into(),
+            },
+            metadata: HashMap::new(),
+        };
+        let result = migration().forward(data).unwrap();
+        // No change
+        assert_eq!(
+            result.data,
+            hashmap! {
+                "settings.older.d".into() => "d".into(),
+            }
+        );
+    }
+}
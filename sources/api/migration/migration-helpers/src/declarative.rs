@@ -0,0 +1,266 @@
+//! Declarative migrations are TOML files listing steps that map onto the common migrations, so
+//! simple changes don't need their own migration crate.  For example:
+//!
+//! ```toml
+//! [[step]]
+//! type = "add-settings"
+//! settings = ["settings.motd"]
+//!
+//! [[step]]
+//! type = "replace-list"
+//! setting = "services.motd.configuration-files"
+//! old = ["motd"]
+//! new = ["motd", "issue"]
+//! ```
+//!
+//! Steps run in order when migrating forward.  When migrating backward, each step is undone in
+//! reverse order, using the backward direction of its common migration.
+
+use crate::common_migrations::{
+    AddMetadataMigration, AddPrefixesMigration, AddSettingsMigration, ListReplacement,
+    MovePrefixMigration, RemoveSettingsMigration, RenameSettingsMigration, ReplaceListsMigration,
+    ReplaceStringMigration, ReplaceTemplateMigration, SettingMetadata, SettingRename,
+};
+use crate::{error, Migration, MigrationData, Result};
+use serde::Deserialize;
+use snafu::ResultExt;
+
+/// The file name suffix that marks a migration as declarative, rather than a binary.
+pub const DECLARATIVE_SUFFIX: &str = ".toml";
+
+/// The steps of a declarative migration, as written in its TOML file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MigrationFile {
+    #[serde(default)]
+    step: Vec<Step>,
+}
+
+/// A single step of a declarative migration, named for the common migration it uses.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+enum Step {
+    /// See `AddSettingsMigration`.
+    AddSettings { settings: Vec<String> },
+    /// See `AddPrefixesMigration`.
+    AddPrefixes { prefixes: Vec<String> },
+    /// See `RemoveSettingsMigration`.
+    RemoveSettings { settings: Vec<String> },
+    /// See `ReplaceStringMigration`.
+    ReplaceString {
+        setting: String,
+        old: String,
+        new: String,
+    },
+    /// See `ReplaceListsMigration`.
+    ReplaceList {
+        setting: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    /// See `ReplaceTemplateMigration`.
+    ReplaceTemplate {
+        setting: String,
+        old: String,
+        new: String,
+    },
+    /// See `AddMetadataMigration`.
+    AddMetadata {
+        setting: String,
+        metadata: Vec<String>,
+    },
+    /// See `RenameSettingsMigration`.
+    RenameSetting { old: String, new: String },
+    /// See `MovePrefixMigration`.
+    MovePrefix { old: String, new: String },
+}
+
+impl Step {
+    /// Returns the common migration that implements this step.
+    fn into_migration(self) -> Box<dyn Migration> {
+        match self {
+            Step::AddSettings { settings } => Box::new(AddSettingsMigration(leak_list(settings))),
+            Step::AddPrefixes { prefixes } => Box::new(AddPrefixesMigration(
+                prefixes.into_iter().map(leak).collect(),
+            )),
+            Step::RemoveSettings { settings } => {
+                Box::new(RemoveSettingsMigration(leak_list(settings)))
+            }
+            Step::ReplaceString { setting, old, new } => Box::new(ReplaceStringMigration {
+                setting: leak(setting),
+                old_val: leak(old),
+                new_val: leak(new),
+            }),
+            Step::ReplaceList { setting, old, new } => {
+                Box::new(ReplaceListsMigration(vec![ListReplacement {
+                    setting: leak(setting),
+                    old_vals: leak_list(old),
+                    new_vals: leak_list(new),
+                }]))
+            }
+            Step::ReplaceTemplate { setting, old, new } => Box::new(ReplaceTemplateMigration {
+                setting: leak(setting),
+                old_template: leak(old),
+                new_template: leak(new),
+            }),
+            Step::AddMetadata { setting, metadata } => {
+                let setting_metadata = SettingMetadata {
+                    setting: leak(setting),
+                    metadata: leak_list(metadata),
+                };
+                Box::new(AddMetadataMigration(Box::leak(Box::new([
+                    setting_metadata,
+                ]))))
+            }
+            Step::RenameSetting { old, new } => {
+                let rename = SettingRename {
+                    old_name: leak(old),
+                    new_name: leak(new),
+                };
+                Box::new(RenameSettingsMigration(Box::leak(Box::new([rename]))))
+            }
+            Step::MovePrefix { old, new } => Box::new(MovePrefixMigration {
+                old_prefix: leak(old),
+                new_prefix: leak(new),
+            }),
+        }
+    }
+}
+
+// The common migrations take 'static data because they're normally built from constants in a
+// migration binary.  A declarative migration is loaded once and used until the process exits, so
+// we leak its strings to give them the same lifetime.
+
+fn leak(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+fn leak_list(list: Vec<String>) -> &'static [&'static str] {
+    Box::leak(
+        list.into_iter()
+            .map(leak)
+            .collect::<Vec<_>>()
+            .into_boxed_slice(),
+    )
+}
+
+/// A migration made of common migrations, loaded from a TOML file.
+pub struct DeclarativeMigration {
+    steps: Vec<Box<dyn Migration>>,
+}
+
+impl DeclarativeMigration {
+    /// Parses a declarative migration from the contents of its TOML file.
+    pub fn from_toml(input: &str) -> Result<Self> {
+        let file: MigrationFile = toml::from_str(input).context(error::DeclarativeParse)?;
+        Ok(Self {
+            steps: file.step.into_iter().map(Step::into_migration).collect(),
+        })
+    }
+}
+
+impl Migration for DeclarativeMigration {
+    fn forward(&mut self, mut input: MigrationData) -> Result<MigrationData> {
+        for step in self.steps.iter_mut() {
+            input = step.forward(input)?;
+        }
+        Ok(input)
+    }
+
+    fn backward(&mut self, mut input: MigrationData) -> Result<MigrationData> {
+        for step in self.steps.iter_mut().rev() {
+            input = step.backward(input)?;
+        }
+        Ok(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DeclarativeMigration;
+    use crate::{Migration, MigrationData};
+    use maplit::hashmap;
+
+    const MIGRATION: &str = r#"
+[[step]]
+type = "add-settings"
+settings = ["settings.motd"]
+
+[[step]]
+type = "replace-string"
+setting = "settings.updates.metadata-base-url"
+old = "https://old.example.com/"
+new = "https://new.example.com/"
+
+[[step]]
+type = "replace-list"
+setting = "services.motd.configuration-files"
+old = ["motd"]
+new = ["motd", "issue"]
+
+[[step]]
+type = "add-metadata"
+setting = "settings.motd"
+metadata = ["setting-generator"]
+
+[[step]]
+type = "move-prefix"
+old = "settings.greetings"
+new = "settings.motd-parts"
+"#;
+
+    fn old_data() -> MigrationData {
+        MigrationData {
+            data: hashmap! {
+                "settings.updates.metadata-base-url".into() => "https://old.example.com/".into(),
+                "services.motd.configuration-files".into() => vec!["motd"].into(),
+                "settings.greetings.hi".into() => "hi".into(),
+            },
+            metadata: hashmap! {
+                "settings.motd".into() => hashmap!{"affected-services".into() => vec!["motd"].into()},
+            },
+        }
+    }
+
+    #[test]
+    fn forward() {
+        let mut migration = DeclarativeMigration::from_toml(MIGRATION).unwrap();
+        let result = migration.forward(old_data()).unwrap();
+        assert_eq!(
+            result.data,
+            hashmap! {
+                "settings.updates.metadata-base-url".into() => "https://new.example.com/".into(),
+                "services.motd.configuration-files".into() => vec!["motd", "issue"].into(),
+                "settings.motd-parts.hi".into() => "hi".into(),
+            }
+        );
+        assert_eq!(result.metadata, old_data().metadata);
+    }
+
+    #[test]
+    fn backward() {
+        let mut migration = DeclarativeMigration::from_toml(MIGRATION).unwrap();
+        // The new version has the added setting and metadata, which we expect to be removed.
+        let mut new_data = migration.forward(old_data()).unwrap();
+        new_data.data.insert("settings.motd".into(), "hi".into());
+        new_data
+            .metadata
+            .get_mut("settings.motd")
+            .unwrap()
+            .insert("setting-generator".into(), "motd-generator".into());
+
+        let result = migration.backward(new_data).unwrap();
+        assert_eq!(result.data, old_data().data);
+        assert_eq!(result.metadata, old_data().metadata);
+    }
+
+    #[test]
+    fn invalid() {
+        // Unknown step types and fields are rejected rather than ignored.
+        assert!(DeclarativeMigration::from_toml("[[step]]\ntype = \"rename\"\n").is_err());
+        assert!(DeclarativeMigration::from_toml(
+            "[[step]]\ntype = \"add-settings\"\nsettings = []\nprefixes = []\n"
+        )
+        .is_err());
+    }
+}
@@ -101,6 +101,9 @@ pub enum Error {
         data: Vec<serde_json::Value>,
     },
 
+    #[snafu(display("Unable to parse declarative migration: {}", source))]
+    DeclarativeParse { source: toml::de::Error },
+
     #[snafu(display("Failed to delete file '{}': '{}'", path.display(), source))]
     RemoveFile {
         path: PathBuf,
@@ -14,6 +14,7 @@
 mod args;
 pub mod common_migrations;
 mod datastore_helper;
+pub mod declarative;
 pub mod error;
 
 use snafu::ResultExt;
@@ -24,7 +25,8 @@ use std::fmt;
 use datastore::{Committed, Value};
 pub use datastore::{DataStore, FilesystemDataStore};
 
-use args::{parse_args, Args};
+use args::parse_args;
+pub use args::Args;
 use datastore_helper::{get_input_data, set_output_data};
 pub use error::Result;
 
@@ -9,19 +9,53 @@ use std::path::{Path, PathBuf};
 use std::process;
 use std::str::FromStr;
 
+// These are the paths migrator.service uses at boot.  The list and rollback subcommands default to
+// them, so operators don't have to reconstruct migrator's arguments by hand.
+const DEFAULT_DATASTORE_PATH: &str = "/var/lib/bottlerocket/datastore/current";
+const DEFAULT_MIGRATION_DIRECTORY: &str = "/var/lib/bottlerocket-migrations";
+const DEFAULT_ROOT_PATH: &str = "/usr/share/updog/root.json";
+const DEFAULT_METADATA_DIRECTORY: &str = "/var/cache/bottlerocket-metadata";
+
 /// Informs the user about proper usage of the program and exits.
 fn usage() -> ! {
     let program_name = env::args().next().unwrap_or_else(|| "program".to_string());
     eprintln!(
-        r"Usage: {}
+        r"Usage: {0}
             --datastore-path PATH
             --migration-directory PATH
             --root-path PATH
             --metadata-directory PATH
             (--migrate-to-version x.y | --migrate-to-version-from-os-release)
+            [ --dry-run ]
             [ --no-color ]
-            [ --log-level trace|debug|info|warn|error ]",
-        program_name
+            [ --log-level trace|debug|info|warn|error ]
+
+       {0} list
+            [ --datastore-path PATH ]
+            [ --migration-directory PATH ]
+            [ --root-path PATH ]
+            [ --metadata-directory PATH ]
+            [ --log-level trace|debug|info|warn|error ]
+
+       {0} rollback
+            (--migrate-to-version x.y | --migrate-to-version-from-os-release)
+            [ --datastore-path PATH ]
+            [ --migration-directory PATH ]
+            [ --root-path PATH ]
+            [ --metadata-directory PATH ]
+            [ --dry-run ]
+            [ --log-level trace|debug|info|warn|error ]
+
+The list and rollback subcommands default to the paths used at boot:
+    --datastore-path {1}
+    --migration-directory {2}
+    --root-path {3}
+    --metadata-directory {4}",
+        program_name,
+        DEFAULT_DATASTORE_PATH,
+        DEFAULT_MIGRATION_DIRECTORY,
+        DEFAULT_ROOT_PATH,
+        DEFAULT_METADATA_DIRECTORY,
     );
     process::exit(2);
 }
@@ -32,9 +66,38 @@ fn usage_msg<S: AsRef<str>>(msg: S) -> ! {
     usage();
 }
 
-/// Stores user-supplied arguments.
+/// The subcommand the user asked for, with its arguments.
+pub(crate) enum Command {
+    /// Migrate the data store to the given version; this is what runs at boot.
+    Migrate(Args),
+    /// List the data store versions and the cached update metadata.
+    List(ListArgs),
+    /// Migrate the data store back to an older version.
+    Rollback(Args),
+}
+
+impl Command {
+    pub(crate) fn log_level(&self) -> LevelFilter {
+        match self {
+            Command::Migrate(args) | Command::Rollback(args) => args.log_level,
+            Command::List(args) => args.log_level,
+        }
+    }
+}
+
+/// Stores user-supplied arguments for listing data store versions and update metadata.
+pub(crate) struct ListArgs {
+    pub(crate) datastore_path: PathBuf,
+    pub(crate) log_level: LevelFilter,
+    pub(crate) migration_directory: PathBuf,
+    pub(crate) root_path: PathBuf,
+    pub(crate) metadata_directory: PathBuf,
+}
+
+/// Stores user-supplied arguments for migrating.
 pub(crate) struct Args {
     pub(crate) datastore_path: PathBuf,
+    pub(crate) dry_run: bool,
     pub(crate) log_level: LevelFilter,
     pub(crate) migration_directory: PathBuf,
     pub(crate) migrate_to_version: Version,
@@ -42,18 +105,45 @@ pub(crate) struct Args {
     pub(crate) metadata_directory: PathBuf,
 }
 
-impl Args {
-    /// Parses user arguments into an Args structure.
+/// Canonicalizes the given data store path, or exits if it doesn't exist.
+fn datastore_path(path_str: &str) -> PathBuf {
+    // On first boot, the data store won't exist yet, because storewolf runs after.
+    if !Path::new(path_str).exists() {
+        eprintln!(
+            "Data store does not exist at given path, exiting ({})",
+            path_str
+        );
+        process::exit(0);
+    }
+
+    let canonical = fs::canonicalize(path_str).unwrap_or_else(|e| {
+        usage_msg(format!(
+            "Could not canonicalize given data store path: {}",
+            e
+        ))
+    });
+    trace!("Canonicalized data store path: {}", canonical.display());
+    canonical
+}
+
+impl Command {
+    /// Parses user arguments into a Command.
     pub(crate) fn from_env(args: env::Args) -> Self {
-        // Required parameters.
+        let mut iter = args.skip(1).peekable();
+        let subcommand = match iter.peek().map(String::as_str) {
+            Some("list") | Some("rollback") => iter.next(),
+            _ => None,
+        };
+
+        // Required parameters, unless the subcommand has a default.
         let mut datastore_path = None;
+        let mut dry_run = false;
         let mut log_level = None;
         let mut migration_directory = None;
         let mut migrate_to_version = None;
         let mut root_path = None;
         let mut metadata_path = None;
 
-        let mut iter = args.skip(1);
         while let Some(arg) = iter.next() {
             match arg.as_ref() {
                 "--datastore-path" => {
@@ -61,26 +151,11 @@ impl Args {
                         .next()
                         .unwrap_or_else(|| usage_msg("Did not give argument to --datastore-path"));
                     trace!("Given --datastore-path: {}", path_str);
-
-                    // On first boot, the data store won't exist yet, because storewolf runs after.
-                    if !Path::new(&path_str).exists() {
-                        eprintln!(
-                            "Data store does not exist at given path, exiting ({})",
-                            path_str
-                        );
-                        process::exit(0);
-                    }
-
-                    let canonical = fs::canonicalize(path_str).unwrap_or_else(|e| {
-                        usage_msg(format!(
-                            "Could not canonicalize given data store path: {}",
-                            e
-                        ))
-                    });
-                    trace!("Canonicalized data store path: {}", canonical.display());
-                    datastore_path = Some(canonical);
+                    datastore_path = Some(self::datastore_path(&path_str));
                 }
 
+                "--dry-run" => dry_run = true,
+
                 "--log-level" => {
                     let log_level_str = iter
                         .next()
@@ -135,21 +210,51 @@ impl Args {
             }
         }
 
-        Self {
-            datastore_path: datastore_path
-                .unwrap_or_else(|| usage_msg("--datastore-path must be specified")),
-            log_level: log_level.unwrap_or_else(|| LevelFilter::Info),
-            migration_directory: migration_directory
-                .unwrap_or_else(|| usage_msg("--migration-directory must be specified")),
+        let log_level = log_level.unwrap_or_else(|| LevelFilter::Info);
+        if subcommand.is_some() {
+            datastore_path =
+                datastore_path.or_else(|| Some(self::datastore_path(DEFAULT_DATASTORE_PATH)));
+            migration_directory =
+                migration_directory.or_else(|| Some(DEFAULT_MIGRATION_DIRECTORY.into()));
+            root_path = root_path.or_else(|| Some(DEFAULT_ROOT_PATH.into()));
+            metadata_path = metadata_path.or_else(|| Some(DEFAULT_METADATA_DIRECTORY.into()));
+        }
+        let datastore_path =
+            datastore_path.unwrap_or_else(|| usage_msg("--datastore-path must be specified"));
+        let migration_directory = migration_directory
+            .unwrap_or_else(|| usage_msg("--migration-directory must be specified"));
+        let root_path = root_path.unwrap_or_else(|| usage_msg("--root-path must be specified"));
+        let metadata_directory =
+            metadata_path.unwrap_or_else(|| usage_msg("--metadata-directory must be specified"));
+
+        if subcommand.as_deref() == Some("list") {
+            return Command::List(ListArgs {
+                datastore_path,
+                log_level,
+                migration_directory,
+                root_path,
+                metadata_directory,
+            });
+        }
+
+        let args = Args {
+            datastore_path,
+            dry_run,
+            log_level,
+            migration_directory,
             migrate_to_version: migrate_to_version.unwrap_or_else(|| {
                 usage_msg(
                     "Desired version could not be determined; pass --migrate-to-version or \
                     --migrate-to-version-from-os-release",
                 )
             }),
-            root_path: root_path.unwrap_or_else(|| usage_msg("--root-path must be specified")),
-            metadata_directory: metadata_path
-                .unwrap_or_else(|| usage_msg("--metadata-directory must be specified")),
+            root_path,
+            metadata_directory,
+        };
+        if subcommand.is_some() {
+            Command::Rollback(args)
+        } else {
+            Command::Migrate(args)
         }
     }
 }
@@ -1,6 +1,7 @@
 //! This module owns the Direction type used by the migrator to determine whether a migration
 //! is moving forward to a new version or rolling back to a previous version.
 
+use migration_helpers::MigrationType;
 use semver::Version;
 use std::cmp::{Ord, Ordering};
 use std::fmt;
@@ -32,6 +33,22 @@ impl Direction {
             Ordering::Equal => None,
         }
     }
+
+    /// Returns the migration type that declarative migrations should run in this direction.
+    pub(crate) fn migration_type(self) -> MigrationType {
+        match self {
+            Direction::Forward => MigrationType::Forward,
+            Direction::Backward => MigrationType::Backward,
+        }
+    }
+
+    /// Returns the direction that undoes this one.
+    pub(crate) fn reverse(self) -> Self {
+        match self {
+            Direction::Forward => Direction::Backward,
+            Direction::Backward => Direction::Forward,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,290 @@
+//! This module implements `--dry-run`, which runs a migration chain against a copy of a data store
+//! and reports what it would change, without touching the real data store or its links.
+//!
+//! After showing the changes, it runs the chain in the opposite direction against the migrated
+//! copy; the result must match the original data store, or we couldn't safely roll back.
+
+use crate::direction::Direction;
+use crate::error::{self, Result};
+use crate::run_migrations;
+use datastore::{Committed, DataStore, FilesystemDataStore};
+use semver::Version;
+use serde_json::Value;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Migrations are given the running OS's release information as "os.*" data keys, and write it
+/// back out, so it isn't a change worth reporting.
+const OS_PREFIX: &str = "os.";
+
+/// Runs the migration chain against a copy of `datastore_path`, prints the changes it makes, and
+/// checks that running the chain in the opposite direction restores the original data.
+pub(crate) fn dry_run<P, S>(
+    repository: &tough::Repository,
+    direction: Direction,
+    migrations: &[S],
+    datastore_path: P,
+    current_version: &Version,
+    new_version: &Version,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let datastore_path = datastore_path.as_ref();
+    let work_dir = TempDir::new().context(error::DryRunWorkDir)?;
+    let copy_path = work_dir.path().join(datastore_path.file_name().context(
+        error::DataStoreLinkToRoot {
+            path: datastore_path,
+        },
+    )?);
+    copy_dir(datastore_path, &copy_path)?;
+    let before = Contents::load(&copy_path)?;
+
+    println!(
+        "Dry run of {} migration(s) from {} to {}: {}",
+        migrations.len(),
+        current_version,
+        new_version,
+        migrations
+            .iter()
+            .map(|m| m.as_ref())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let migrated_path = run_migrations(repository, direction, migrations, &copy_path, new_version)?;
+    let after = Contents::load(&migrated_path)?;
+    print!("{}", before.diff(&after));
+
+    // Undo the chain to make sure we'd be able to roll back.
+    let reverse: Vec<&str> = migrations.iter().rev().map(|m| m.as_ref()).collect();
+    let restored_path = run_migrations(
+        repository,
+        direction.reverse(),
+        &reverse,
+        &migrated_path,
+        current_version,
+    )?;
+    let restored = Contents::load(&restored_path)?;
+    let round_trip = before.diff(&restored);
+    if !round_trip.is_empty() {
+        println!(
+            "Migrating back to {} doesn't restore the original data store:",
+            current_version
+        );
+        print!("{}", round_trip);
+    }
+    ensure!(
+        round_trip.is_empty(),
+        error::NotReversible {
+            from: current_version.clone(),
+            to: new_version.clone(),
+        }
+    );
+    println!(
+        "Migrating back to {} restores the original data store",
+        current_version
+    );
+    Ok(())
+}
+
+/// Recursively copies the directory at `from` to `to`, keeping symlinks as symlinks.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).context(error::DryRunCopy { path: to })?;
+    for entry in fs::read_dir(from).context(error::DryRunCopy { path: from })? {
+        let entry = entry.context(error::DryRunCopy { path: from })?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .context(error::DryRunCopy { path: &source })?;
+        if file_type.is_dir() {
+            copy_dir(&source, &target)?;
+        } else if file_type.is_symlink() {
+            let link = fs::read_link(&source).context(error::DryRunCopy { path: &source })?;
+            symlink(link, &target).context(error::DryRunCopy { path: &target })?;
+        } else {
+            fs::copy(&source, &target).context(error::DryRunCopy { path: &source })?;
+        }
+    }
+    Ok(())
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// The contents of a data store, grouped into sections: live data, each pending transaction, and
+/// metadata.  Within a section, values are keyed by data key name, or for metadata, by data key
+/// and metadata key names.
+#[derive(Debug, Default)]
+struct Contents {
+    sections: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl Contents {
+    fn load(path: &Path) -> Result<Self> {
+        let datastore = FilesystemDataStore::new(path);
+        let mut contents = Self::default();
+
+        let mut committeds = vec![("live data".to_string(), Committed::Live)];
+        let mut transactions: Vec<String> = datastore
+            .list_transactions()
+            .context(error::DryRunRead { path })?
+            .into_iter()
+            .collect();
+        transactions.sort();
+        committeds.extend(transactions.into_iter().map(|tx| {
+            (
+                format!("pending transaction '{}'", tx),
+                Committed::Pending { tx },
+            )
+        }));
+        for (name, committed) in committeds {
+            let data = datastore
+                .get_prefix("", &committed)
+                .context(error::DryRunRead { path })?;
+            let section = contents.sections.entry(name).or_default();
+            for (key, value) in data {
+                if !key.name().starts_with(OS_PREFIX) {
+                    section.insert(key.name().to_string(), parse_value(&value));
+                }
+            }
+        }
+
+        let metadata = datastore
+            .get_metadata_prefix("", &None as &Option<&str>)
+            .context(error::DryRunRead { path })?;
+        let section = contents.sections.entry("metadata".to_string()).or_default();
+        for (data_key, meta_map) in metadata {
+            for (metadata_key, value) in meta_map {
+                section.insert(
+                    format!("{}: {}", data_key.name(), metadata_key.name()),
+                    parse_value(&value),
+                );
+            }
+        }
+
+        Ok(contents)
+    }
+
+    /// Returns the changes needed to get from this data store's contents to `other`'s.
+    fn diff(&self, other: &Contents) -> Diff {
+        let empty = BTreeMap::new();
+        let names: BTreeSet<&String> = self.sections.keys().chain(other.sections.keys()).collect();
+        let mut diff = Diff::default();
+        for name in names {
+            let before = self.sections.get(name).unwrap_or(&empty);
+            let after = other.sections.get(name).unwrap_or(&empty);
+            let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+            let changes: BTreeMap<String, Change> = keys
+                .into_iter()
+                .filter_map(|key| {
+                    let change = match (before.get(key), after.get(key)) {
+                        (None, Some(new)) => Change::Added(new.clone()),
+                        (Some(old), None) => Change::Removed(old.clone()),
+                        (Some(old), Some(new)) if old != new => {
+                            Change::Changed(old.clone(), new.clone())
+                        }
+                        _ => return None,
+                    };
+                    Some((key.clone(), change))
+                })
+                .collect();
+            if !changes.is_empty() {
+                diff.sections.insert(name.clone(), changes);
+            }
+        }
+        diff
+    }
+}
+
+/// Data store values are serialized JSON; we compare them parsed so formatting doesn't matter.
+fn parse_value(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    Added(Value),
+    Removed(Value),
+    Changed(Value, Value),
+}
+
+/// The changes between two data stores, by section and key.
+#[derive(Debug, Default)]
+struct Diff {
+    sections: BTreeMap<String, BTreeMap<String, Change>>,
+}
+
+impl Diff {
+    fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for (name, changes) in &self.sections {
+            writeln!(f, "Changes to {}:", name)?;
+            for (key, change) in changes {
+                match change {
+                    Change::Added(new) => writeln!(f, "  + {} = {}", key, new)?,
+                    Change::Removed(old) => writeln!(f, "  - {} = {}", key, old)?,
+                    Change::Changed(old, new) => writeln!(f, "  ~ {} = {} -> {}", key, old, new)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maplit::btreemap;
+    use serde_json::json;
+
+    #[test]
+    fn diff() {
+        let before = Contents {
+            sections: btreemap! {
+                "live data".to_string() => btreemap! {
+                    "settings.a".to_string() => json!("a"),
+                    "settings.b".to_string() => json!(1),
+                    "settings.c".to_string() => json!(true),
+                },
+            },
+        };
+        let after = Contents {
+            sections: btreemap! {
+                "live data".to_string() => btreemap! {
+                    "settings.a".to_string() => json!("a"),
+                    "settings.b".to_string() => json!(2),
+                    "settings.d".to_string() => json!(["x"]),
+                },
+                "metadata".to_string() => btreemap! {
+                    "settings.d: affected-services".to_string() => json!(["d"]),
+                },
+            },
+        };
+
+        let diff = before.diff(&after);
+        assert_eq!(
+            diff.to_string(),
+            "Changes to live data:\n  \
+               ~ settings.b = 1 -> 2\n  \
+               - settings.c = true\n  \
+               + settings.d = [\"x\"]\n\
+             Changes to metadata:\n  \
+               + settings.d: affected-services = [\"d\"]\n"
+        );
+        assert!(before.diff(&before).is_empty());
+    }
+}
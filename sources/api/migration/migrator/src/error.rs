@@ -22,6 +22,18 @@ pub(crate) enum Error {
     #[snafu(display("Data store link '{}' points to /", path.display()))]
     DataStoreLinkToRoot { path: PathBuf },
 
+    #[snafu(display("Unable to copy data store for dry run at '{}': {}", path.display(), source))]
+    DryRunCopy { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Unable to read data store for dry run at '{}': {}", path.display(), source))]
+    DryRunRead {
+        path: PathBuf,
+        source: datastore::Error,
+    },
+
+    #[snafu(display("Unable to create work directory for dry run: {}", source))]
+    DryRunWorkDir { source: io::Error },
+
     #[snafu(display("Unable to create URL from path '{}'", path.display()))]
     DirectoryUrl { path: PathBuf },
 
@@ -45,6 +57,41 @@ pub(crate) enum Error {
     #[snafu(display("Data store for new version {} already exists at {}", version, path.display()))]
     NewVersionAlreadyExists { version: Version, path: PathBuf },
 
+    #[snafu(display(
+        "Migrations from {} to {} aren't reversible; migrating back doesn't restore the data store",
+        from,
+        to
+    ))]
+    NotReversible { from: Version, to: Version },
+
+    #[snafu(display("Failed to read declarative migration '{}': {}", migration, source))]
+    ReadDeclarative {
+        migration: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to load declarative migration '{}': {}", migration, source))]
+    LoadDeclarative {
+        migration: String,
+        source: migration_helpers::error::Error,
+    },
+
+    #[snafu(display("Declarative migration '{}' failed: {}", migration, source))]
+    DeclarativeFailure {
+        migration: String,
+        source: migration_helpers::error::Error,
+    },
+
+    #[snafu(display(
+        "Can only roll back to a version older than the data store's version {}, not {}",
+        current,
+        requested
+    ))]
+    RollbackNotOlder {
+        current: Version,
+        requested: Version,
+    },
+
     #[snafu(display("Unable to seal migration command: {}", source))]
     SealMigration { source: std::io::Error },
 
@@ -67,6 +114,9 @@ pub(crate) enum Error {
     #[snafu(display("Failed to read symlink at {} to find version: {}", link.display(), source))]
     LinkRead { link: PathBuf, source: io::Error },
 
+    #[snafu(display("Failed listing data store directory '{}': {}", path.display(), source))]
+    ListDataStores { path: PathBuf, source: io::Error },
+
     #[snafu(display("Failed listing migration directory '{}': {}", dir.display(), source))]
     ListMigrations { dir: PathBuf, source: io::Error },
 
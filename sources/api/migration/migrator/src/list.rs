@@ -0,0 +1,161 @@
+//! This module implements the `list` subcommand, which shows operators the data store versions on
+//! disk and the cached update metadata, and which older versions we could roll back to.
+
+use crate::args::ListArgs;
+use crate::error::{self, Result};
+use crate::{get_current_version, load_manifest, load_repository};
+use semver::Version;
+use snafu::{OptionExt, ResultExt};
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A data store directory, like `v1.2.0_0123456789abcdef`.
+struct DataStoreVersion {
+    version: Version,
+    path: PathBuf,
+}
+
+/// Finds the data store directories in `datastore_dir`, sorted by version.
+fn find_datastores(datastore_dir: &Path) -> Result<Vec<DataStoreVersion>> {
+    let mut datastores = Vec::new();
+    let entries = fs::read_dir(datastore_dir).context(error::ListDataStores {
+        path: datastore_dir,
+    })?;
+    for entry in entries {
+        let entry = entry.context(error::ListDataStores {
+            path: datastore_dir,
+        })?;
+        let path = entry.path();
+        // Version links are symlinks; the data stores they point to are directories.
+        if !path.is_dir()
+            || fs::symlink_metadata(&path).map_or(true, |m| m.file_type().is_symlink())
+        {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let version = name
+            .strip_prefix('v')
+            .and_then(|rest| rest.split('_').next())
+            .and_then(|version| Version::parse(version).ok());
+        match version {
+            Some(version) => datastores.push(DataStoreVersion { version, path }),
+            None => debug!("Skipping non-data-store directory {}", path.display()),
+        }
+    }
+    datastores.sort_by(|a, b| a.version.cmp(&b.version).then(a.path.cmp(&b.path)));
+    Ok(datastores)
+}
+
+/// Returns whether `link` resolves to `path`.
+fn points_to(link: &Path, path: &Path) -> bool {
+    fs::canonicalize(link).ok().as_deref() == Some(path)
+}
+
+/// Reads the named migration from the repository, which checks its signature and hash, and
+/// describes the result.
+fn verify_migration(repository: &tough::Repository, name: &str) -> String {
+    let target = match name.try_into() {
+        Ok(target) => target,
+        Err(e) => return format!("invalid name: {}", e),
+    };
+    match repository.read_target(&target) {
+        Ok(Some(mut reader)) => match io::copy(&mut reader, &mut io::sink()) {
+            Ok(_) => "verified".to_string(),
+            Err(e) => format!("failed verification: {}", e),
+        },
+        Ok(None) => "not in signed metadata".to_string(),
+        Err(e) => format!("unavailable: {}", e),
+    }
+}
+
+pub(crate) fn list(args: &ListArgs) -> Result<()> {
+    let datastore_dir = args
+        .datastore_path
+        .parent()
+        .context(error::DataStoreLinkToRoot {
+            path: &args.datastore_path,
+        })?;
+    let current_version = get_current_version(datastore_dir)?;
+
+    println!("Data store versions in {}:", datastore_dir.display());
+    let datastores = find_datastores(datastore_dir)?;
+    for datastore in &datastores {
+        let link = datastore_dir.join(format!("v{}", datastore.version));
+        let status = if points_to(&datastore_dir.join("current"), &datastore.path) {
+            " (current)"
+        } else if points_to(&link, &datastore.path) {
+            ""
+        } else {
+            " (not linked)"
+        };
+        println!(
+            "  {:<12} {}{}",
+            datastore.version.to_string(),
+            datastore.path.display(),
+            status
+        );
+    }
+
+    let repository = load_repository(
+        &args.root_path,
+        &args.metadata_directory,
+        &args.migration_directory,
+    )?;
+    println!(
+        "\nCached update metadata in {}:",
+        args.metadata_directory.display()
+    );
+    let root = &repository.root().signed;
+    let targets = &repository.targets().signed;
+    let snapshot = &repository.snapshot().signed;
+    let timestamp = &repository.timestamp().signed;
+    for (role, version, expires) in &[
+        ("root", root.version, root.expires),
+        ("targets", targets.version, targets.expires),
+        ("snapshot", snapshot.version, snapshot.expires),
+        ("timestamp", timestamp.version, timestamp.expires),
+    ] {
+        println!(
+            "  {:<10} version {}, expires {}",
+            role,
+            version,
+            expires.to_rfc3339()
+        );
+    }
+
+    // Offer the older versions we have data stores for, and the older versions the manifest
+    // knows how to migrate to.
+    let manifest = load_manifest(&repository)?;
+    let older: BTreeSet<&Version> = datastores
+        .iter()
+        .map(|datastore| &datastore.version)
+        .chain(manifest.migrations.keys().map(|(from, _to)| from))
+        .filter(|version| **version < current_version)
+        .collect();
+    println!("\nRolling back from {}:", current_version);
+    if older.is_empty() {
+        println!("  No older versions known");
+    }
+    for version in older.into_iter().rev() {
+        match update_metadata::find_migrations(&current_version, version, &manifest) {
+            Ok(migrations) if migrations.is_empty() => {
+                println!("  to {}: no migrations needed", version)
+            }
+            Ok(migrations) => {
+                println!("  to {}:", version);
+                for migration in migrations {
+                    println!(
+                        "    {} ({})",
+                        migration,
+                        verify_migration(&repository, &migration)
+                    );
+                }
+            }
+            Err(e) => println!("  to {}: no migration path: {}", version, e),
+        }
+    }
+    Ok(())
+}
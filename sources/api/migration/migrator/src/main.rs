@@ -17,15 +17,43 @@
 //!
 //! To understand motivation and more about the overall process, look at the migration system
 //! documentation, one level up.
+//!
+//! # Declarative migrations
+//!
+//! Migrations whose names end in `.toml` (before any `.lz4` extension) are declarative; rather than
+//! executing them, migrator parses their list of steps and runs the matching common migrations
+//! from migration-helpers itself.
+//!
+//! # Dry runs
+//!
+//! With `--dry-run`, migrator instead copies the data store to a temporary directory and runs the
+//! migrations against the copy, leaving the real data store and its links alone.
+//! It prints the data keys and metadata that the migrations would add, remove, or change, in the
+//! live data and in each pending transaction.
+//! It then runs the same migrations in the opposite direction against the result, to make sure we
+//! could roll back; if that doesn't give back the original data, or any migration fails, migrator
+//! exits with an error.
+//!
+//! # Listing and rolling back
+//!
+//! For offline recovery, `migrator list` shows the data store versions on disk, which one is
+//! current, the versions of the cached TUF metadata, and the migrations needed to roll back to each
+//! older version, checking each against the signed metadata.
+//!
+//! `migrator rollback --migrate-to-version <version>` runs those backward migrations with the same
+//! checks as a normal migration, but refuses to move to a version that isn't older than the current
+//! one.  Both subcommands default to the standard paths, so only the version is required.
 
 #![deny(rust_2018_idioms)]
 
 #[macro_use]
 extern crate log;
 
-use args::Args;
+use args::{Args, Command};
 use direction::Direction;
 use error::Result;
+use migration_helpers::declarative::DeclarativeMigration;
+use migration_helpers::Args as MigrationArgs;
 use nix::{dir::Dir, fcntl::OFlag, sys::stat::Mode, unistd::fsync};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use semver::Version;
@@ -35,6 +63,7 @@ use std::collections::HashSet;
 use std::convert::TryInto;
 use std::env;
 use std::fs::{self, File};
+use std::io::Read;
 use std::os::unix::fs::symlink;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
@@ -45,7 +74,9 @@ use url::Url;
 
 mod args;
 mod direction;
+mod dry_run;
 mod error;
+mod list;
 #[cfg(test)]
 mod test;
 
@@ -53,13 +84,18 @@ mod test;
 // we have nice Display representations of the error, so we wrap "main" (run) and print any error.
 // https://github.com/shepmaster/snafu/issues/110
 fn main() {
-    let args = Args::from_env(env::args());
+    let command = Command::from_env(env::args());
     // SimpleLogger will send errors to stderr and anything less to stdout.
-    if let Err(e) = SimpleLogger::init(args.log_level, LogConfig::default()) {
+    if let Err(e) = SimpleLogger::init(command.log_level(), LogConfig::default()) {
         eprintln!("{}", e);
         process::exit(1);
     }
-    if let Err(e) = run(&args) {
+    let result = match &command {
+        Command::Migrate(args) => run(args),
+        Command::List(args) => list::list(args),
+        Command::Rollback(args) => rollback(args),
+    };
+    if let Err(e) = result {
         eprintln!("{}", e);
         process::exit(1);
     }
@@ -114,53 +150,34 @@ pub(crate) fn run(args: &Args) -> Result<()> {
             process::exit(0);
         });
 
-    // create URLs from the metadata and targets directory paths
-    let metadata_base_url = Url::from_directory_path(&args.metadata_directory).map_err(|_| {
-        error::Error::DirectoryUrl {
-            path: args.metadata_directory.clone(),
-        }
-    })?;
-    let targets_base_url =
-        url::Url::from_directory_path(&args.migration_directory).map_err(|_| {
-            error::Error::DirectoryUrl {
-                path: args.migration_directory.clone(),
-            }
-        })?;
-
-    // open a reader to the root.json file
-    let root_file = File::open(&args.root_path).with_context(|| error::OpenRoot {
-        path: args.root_path.clone(),
-    })?;
-
-    // We will load the locally cached TUF repository to obtain the manifest. The Repository is
-    // loaded using a `TempDir` for its internal Datastore (this is the default). Part of using a
-    // `TempDir` is disabling timestamp checking, because we want an instance to still come up and
-    // run migrations regardless of the how the system time relates to what we have cached (for
-    // example if someone runs an update, then shuts down the instance for several weeks, beyond the
-    // expiration of at least the cached timestamp.json before booting it back up again). We also
-    // use a `TempDir` because see no value in keeping a datastore around. The latest  known
-    // versions of the repository metadata will always be the versions of repository metadata we
-    // have cached on the disk. More info at `ExpirationEnforcement::Unsafe` below.
-
-    // Failure to load the TUF repo at the expected location is a serious issue because updog should
-    // always create a TUF repo that contains at least the manifest, even if there are no migrations.
-    let repo = RepositoryLoader::new(root_file, metadata_base_url, targets_base_url)
-        .transport(FilesystemTransport)
-        // The threats TUF mitigates are more than the threats we are attempting to mitigate
-        // here by caching signatures for migrations locally and using them after a reboot but
-        // prior to Internet connectivity. We are caching the TUF repo and use it while offline
-        // after a reboot to mitigate binaries being added or modified in the migrations
-        // directory; the TUF repo is simply a code signing method we already have in place,
-        // even if it's not one that initially makes sense for this use case. So, we don't care
-        // if the targets expired between updog downloading them and now.
-        .expiration_enforcement(ExpirationEnforcement::Unsafe)
-        .load()
-        .context(error::RepoLoad)?;
+    let repo = load_repository(
+        &args.root_path,
+        &args.metadata_directory,
+        &args.migration_directory,
+    )?;
     let manifest = load_manifest(&repo)?;
     let migrations =
         update_metadata::find_migrations(&current_version, &args.migrate_to_version, &manifest)
             .context(error::FindMigrations)?;
 
+    if args.dry_run {
+        if migrations.is_empty() {
+            println!(
+                "No migrations from {} to {}; the data store would be used as-is",
+                current_version, args.migrate_to_version
+            );
+            return Ok(());
+        }
+        return dry_run::dry_run(
+            &repo,
+            direction,
+            &migrations,
+            &args.datastore_path,
+            &current_version,
+            &args.migrate_to_version,
+        );
+    }
+
     if migrations.is_empty() {
         // Not all new OS versions need to change the data store format.  If there's been no
         // change, we can just link to the last version rather than making a copy.
@@ -180,6 +197,26 @@ pub(crate) fn run(args: &Args) -> Result<()> {
     Ok(())
 }
 
+/// Migrates the data store back to an older version, for operators recovering from a failed
+/// upgrade.  This is the same as a normal migration, except that we refuse to move forward.
+pub(crate) fn rollback(args: &Args) -> Result<()> {
+    let datastore_dir = args
+        .datastore_path
+        .parent()
+        .context(error::DataStoreLinkToRoot {
+            path: &args.datastore_path,
+        })?;
+    let current_version = get_current_version(datastore_dir)?;
+    ensure!(
+        args.migrate_to_version < current_version,
+        error::RollbackNotOlder {
+            current: current_version,
+            requested: args.migrate_to_version.clone(),
+        }
+    );
+    run(args)
+}
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 /// Generates a random ID, affectionately known as a 'rando', that can be used to avoid timing
@@ -234,10 +271,10 @@ where
 {
     // We start with the given source_datastore, updating this after each migration to point to the
     // output of the previous one.
-    let mut source_datastore = source_datastore.as_ref();
+    let mut source_datastore = source_datastore.as_ref().to_owned();
     // We create a new data store (below) to serve as the target of each migration.  (Start at
     // source just to have the right type; we know we have migrations at this point.)
-    let mut target_datastore = source_datastore.to_owned();
+    let mut target_datastore = source_datastore.clone();
     // Any data stores we create that aren't the final one, i.e. intermediate data stores, will be
     // removed at the end.  (If we fail and return early, they're left for debugging purposes.)
     let mut intermediate_datastores = HashSet::new();
@@ -263,48 +300,22 @@ where
             migration: migration.raw(),
         })?;
 
-        // Create a sealed command with pentacle, so we can run the verified bytes from memory
-        let mut command =
-            pentacle::SealedCommand::new(&mut reader).context(error::SealMigration)?;
-
-        // Point each migration in the right direction, and at the given data store.
-        command.arg(direction.to_string());
-        command.args(&[
-            "--source-datastore".to_string(),
-            source_datastore.display().to_string(),
-        ]);
-
         // Create a new output location for this migration.
         target_datastore = new_datastore_location(&source_datastore, &new_version)?;
         intermediate_datastores.insert(target_datastore.clone());
 
-        command.args(&[
-            "--target-datastore".to_string(),
-            target_datastore.display().to_string(),
-        ]);
-
-        info!("Running migration command: {:?}", command);
-
-        let output = command.output().context(error::StartMigration)?;
-
-        if !output.stdout.is_empty() {
-            debug!(
-                "Migration stdout: {}",
-                String::from_utf8_lossy(&output.stdout)
-            );
+        if is_declarative(migration.raw()) {
+            run_declarative_migration(
+                &mut reader,
+                migration.raw(),
+                direction,
+                &source_datastore,
+                &target_datastore,
+            )?;
         } else {
-            debug!("No migration stdout");
+            run_binary_migration(&mut reader, direction, &source_datastore, &target_datastore)?;
         }
-        if !output.stderr.is_empty() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            // We want to see migration stderr on the console, so log at error level.
-            error!("Migration stderr: {}", stderr);
-        } else {
-            debug!("No migration stderr");
-        }
-
-        ensure!(output.status.success(), error::MigrationFailure { output });
-        source_datastore = &target_datastore;
+        source_datastore = target_datastore.clone();
     }
 
     // Remove the intermediate data stores
@@ -329,6 +340,87 @@ where
     Ok(target_datastore)
 }
 
+/// Returns whether the named migration is declarative, meaning a TOML file listing common
+/// migrations, rather than a binary.  Migrations in the repository are LZ4-compressed, so the
+/// name may have a ".lz4" suffix.
+fn is_declarative(name: &str) -> bool {
+    name.trim_end_matches(".lz4")
+        .ends_with(migration_helpers::declarative::DECLARATIVE_SUFFIX)
+}
+
+/// Runs a migration binary read from `reader`, migrating `source_datastore` into
+/// `target_datastore`.
+fn run_binary_migration<R: Read>(
+    reader: &mut R,
+    direction: Direction,
+    source_datastore: &Path,
+    target_datastore: &Path,
+) -> Result<()> {
+    // Create a sealed command with pentacle, so we can run the verified bytes from memory
+    let mut command = pentacle::SealedCommand::new(reader).context(error::SealMigration)?;
+
+    // Point each migration in the right direction, and at the given data store.
+    command.arg(direction.to_string());
+    command.args(&[
+        "--source-datastore".to_string(),
+        source_datastore.display().to_string(),
+    ]);
+    command.args(&[
+        "--target-datastore".to_string(),
+        target_datastore.display().to_string(),
+    ]);
+
+    info!("Running migration command: {:?}", command);
+
+    let output = command.output().context(error::StartMigration)?;
+
+    if !output.stdout.is_empty() {
+        debug!(
+            "Migration stdout: {}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    } else {
+        debug!("No migration stdout");
+    }
+    if !output.stderr.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // We want to see migration stderr on the console, so log at error level.
+        error!("Migration stderr: {}", stderr);
+    } else {
+        debug!("No migration stderr");
+    }
+
+    ensure!(output.status.success(), error::MigrationFailure { output });
+    Ok(())
+}
+
+/// Runs a declarative migration read from `reader`, migrating `source_datastore` into
+/// `target_datastore`.  Declarative migrations run inside migrator using the common migrations
+/// from migration-helpers, so they have the same behavior as migration binaries that use them.
+fn run_declarative_migration<R: Read>(
+    reader: &mut R,
+    name: &str,
+    direction: Direction,
+    source_datastore: &Path,
+    target_datastore: &Path,
+) -> Result<()> {
+    let mut input = String::new();
+    reader
+        .read_to_string(&mut input)
+        .context(error::ReadDeclarative { migration: name })?;
+    let migration = DeclarativeMigration::from_toml(&input)
+        .context(error::LoadDeclarative { migration: name })?;
+
+    info!("Running declarative migration '{}' {}", name, direction);
+    let args = MigrationArgs {
+        source_datastore: source_datastore.display().to_string(),
+        target_datastore: target_datastore.display().to_string(),
+        migration_type: direction.migration_type(),
+    };
+    migration_helpers::run_migration(migration, &args)
+        .context(error::DeclarativeFailure { migration: name })
+}
+
 /// Atomically flips version symlinks to point to the given "to" datastore so that it becomes live.
 ///
 /// This includes:
@@ -489,6 +581,52 @@ where
     Ok(())
 }
 
+/// Loads the locally cached TUF repository that signs our migrations.
+pub(crate) fn load_repository(
+    root_path: &Path,
+    metadata_directory: &Path,
+    migration_directory: &Path,
+) -> Result<tough::Repository> {
+    // create URLs from the metadata and targets directory paths
+    let metadata_base_url =
+        Url::from_directory_path(metadata_directory).map_err(|_| error::Error::DirectoryUrl {
+            path: metadata_directory.to_path_buf(),
+        })?;
+    let targets_base_url = url::Url::from_directory_path(migration_directory).map_err(|_| {
+        error::Error::DirectoryUrl {
+            path: migration_directory.to_path_buf(),
+        }
+    })?;
+
+    // open a reader to the root.json file
+    let root_file = File::open(root_path).context(error::OpenRoot { path: root_path })?;
+
+    // We will load the locally cached TUF repository to obtain the manifest. The Repository is
+    // loaded using a `TempDir` for its internal Datastore (this is the default). Part of using a
+    // `TempDir` is disabling timestamp checking, because we want an instance to still come up and
+    // run migrations regardless of the how the system time relates to what we have cached (for
+    // example if someone runs an update, then shuts down the instance for several weeks, beyond the
+    // expiration of at least the cached timestamp.json before booting it back up again). We also
+    // use a `TempDir` because see no value in keeping a datastore around. The latest  known
+    // versions of the repository metadata will always be the versions of repository metadata we
+    // have cached on the disk. More info at `ExpirationEnforcement::Unsafe` below.
+
+    // Failure to load the TUF repo at the expected location is a serious issue because updog should
+    // always create a TUF repo that contains at least the manifest, even if there are no migrations.
+    RepositoryLoader::new(root_file, metadata_base_url, targets_base_url)
+        .transport(FilesystemTransport)
+        // The threats TUF mitigates are more than the threats we are attempting to mitigate
+        // here by caching signatures for migrations locally and using them after a reboot but
+        // prior to Internet connectivity. We are caching the TUF repo and use it while offline
+        // after a reboot to mitigate binaries being added or modified in the migrations
+        // directory; the TUF repo is simply a code signing method we already have in place,
+        // even if it's not one that initially makes sense for this use case. So, we don't care
+        // if the targets expired between updog downloading them and now.
+        .expiration_enforcement(ExpirationEnforcement::Unsafe)
+        .load()
+        .context(error::RepoLoad)
+}
+
 fn load_manifest(repository: &tough::Repository) -> Result<Manifest> {
     let target = "manifest.json";
     let target = target.try_into().context(error::TargetName { target })?;
@@ -1,7 +1,8 @@
 //! Provides an end-to-end test of `migrator` via the `run` function. This module is conditionally
 //! compiled for cfg(test) only.
-use crate::args::Args;
-use crate::run;
+use crate::args::{Args, ListArgs};
+use crate::error::Error;
+use crate::{get_current_version, is_declarative, list, rollback, run};
 use chrono::{DateTime, Utc};
 use semver::Version;
 use std::fs;
@@ -37,6 +38,12 @@ const FIRST_MIGRATION: &str = "b-first-migration";
 /// The name of a test migration. The prefix `a-` ensures we are not alphabetically sorting.
 const SECOND_MIGRATION: &str = "a-second-migration";
 
+/// The name of a test migration that adds a setting and removes it when migrating backward.
+const ADD_MOTD_MIGRATION: &str = "c-add-motd-migration";
+
+/// The name of a test migration that adds a setting but doesn't remove it when migrating backward.
+const ONE_WAY_MIGRATION: &str = "d-one-way-migration";
+
 /// Creates a script that will serve as a migration during testing. The script writes its migrations
 /// name to a file named `result.txt` in the parent directory of the datastore. `pentacle` does not
 /// retain the name of the executing binary or script, so we take the `migration_name` as input,
@@ -54,6 +61,26 @@ echo "${{migration_name}}:" "${{@}}" >> "${{outfile}}"
     )
 }
 
+/// Creates a script that will serve as a migration of data during testing. The script copies the
+/// source data store to the target data store, and when migrating forward, sets the given setting
+/// in live data.  When migrating backward, it removes the setting only if `reversible` is set.
+fn create_test_data_migration(setting: &str, reversible: bool) -> String {
+    format!(
+        r#"#!/usr/bin/env bash
+set -eo pipefail
+cp -a "${{3}}" "${{5}}"
+mkdir -p "${{5}}/live/settings"
+if [ "${{1}}" = "--forward" ]; then
+    echo -n '"hi"' > "${{5}}/live/settings/{setting}"
+elif [ "{reversible}" = "true" ]; then
+    rm -f "${{5}}/live/settings/{setting}"
+fi
+"#,
+        setting = setting,
+        reversible = reversible
+    )
+}
+
 /// Holds the lifetime of a `TempDir` inside which a datastore directory and links are held for
 /// testing.
 struct TestDatastore {
@@ -115,6 +142,15 @@ fn create_test_repo() -> TestRepo {
         (Version::new(0, 99, 0), Version::new(0, 99, 1)),
         vec![FIRST_MIGRATION.into(), SECOND_MIGRATION.into()],
     );
+    // These migrations change data, for testing dry runs.
+    manifest.migrations.insert(
+        (Version::new(0, 99, 1), Version::new(0, 99, 2)),
+        vec![ADD_MOTD_MIGRATION.into()],
+    );
+    manifest.migrations.insert(
+        (Version::new(0, 99, 2), Version::new(0, 99, 3)),
+        vec![ONE_WAY_MIGRATION.into()],
+    );
     update_metadata::write_file(tuf_indir.join("manifest.json").as_path(), &manifest).unwrap();
 
     // Create an script that we can use as the 'migration' that migrator will run. This script will
@@ -129,6 +165,14 @@ fn create_test_repo() -> TestRepo {
     // Save lz4 compressed copies of the migration script into the tuftool_indir.
     compress(migration_a.as_bytes(), &tuf_indir.join(FIRST_MIGRATION));
     compress(migration_b.as_bytes(), &tuf_indir.join(SECOND_MIGRATION));
+    compress(
+        create_test_data_migration("motd", true).as_bytes(),
+        &tuf_indir.join(ADD_MOTD_MIGRATION),
+    );
+    compress(
+        create_test_data_migration("one-way", false).as_bytes(),
+        &tuf_indir.join(ONE_WAY_MIGRATION),
+    );
 
     // Create and sign the TUF repository.
     let mut editor = tough::editor::RepositoryEditor::new(root()).unwrap();
@@ -204,6 +248,7 @@ fn migrate_forward() {
     let test_repo = create_test_repo();
     let args = Args {
         datastore_path: test_datastore.datastore.clone(),
+        dry_run: false,
         log_level: log::LevelFilter::Info,
         migration_directory: test_repo.targets_path.clone(),
         migrate_to_version: to_version,
@@ -236,6 +281,7 @@ fn migrate_backward() {
     let test_repo = create_test_repo();
     let args = Args {
         datastore_path: test_datastore.datastore.clone(),
+        dry_run: false,
         log_level: log::LevelFilter::Info,
         migration_directory: test_repo.targets_path.clone(),
         migrate_to_version: to_version,
@@ -256,3 +302,94 @@ fn migrate_backward() {
     let got: String = second_line.chars().take(want.len()).collect();
     assert_eq!(got, want);
 }
+
+/// Does a dry run of migrations that change data, using the `c-` and `d-` migrations from
+/// `create_test_repo`.  The `c-` migration is undone by migrating backward, so a dry run through it
+/// succeeds; the `d-` migration isn't, so a dry run through it fails.  Neither should change the
+/// real data store.
+#[test]
+fn dry_run() {
+    let from_version = Version::parse("0.99.1").unwrap();
+    let test_datastore = TestDatastore::new(from_version.clone());
+    let settings_dir = test_datastore.datastore.join("live/settings");
+    fs::create_dir_all(&settings_dir).unwrap();
+    fs::write(settings_dir.join("hostname"), r#""localhost""#).unwrap();
+    let test_repo = create_test_repo();
+    let dry_run_to = |version: &str| {
+        run(&Args {
+            datastore_path: test_datastore.datastore.clone(),
+            dry_run: true,
+            log_level: log::LevelFilter::Info,
+            migration_directory: test_repo.targets_path.clone(),
+            migrate_to_version: Version::parse(version).unwrap(),
+            root_path: root(),
+            metadata_directory: test_repo.metadata_path.clone(),
+        })
+    };
+
+    dry_run_to("0.99.2").unwrap();
+    match dry_run_to("0.99.3") {
+        Err(Error::NotReversible { .. }) => {}
+        other => panic!("Expected NotReversible, got {:?}", other),
+    }
+
+    assert_eq!(
+        get_current_version(test_datastore.tmp.path()).unwrap(),
+        from_version
+    );
+    assert!(!settings_dir.join("motd").exists());
+    assert!(!settings_dir.join("one-way").exists());
+}
+
+/// Lists the test data store and repo, then rolls back with the `rollback` subcommand, which must
+/// refuse versions that aren't older than the current one.
+#[test]
+fn list_and_rollback() {
+    let from_version = Version::parse("0.99.1").unwrap();
+    let test_datastore = TestDatastore::new(from_version.clone());
+    let test_repo = create_test_repo();
+    list::list(&ListArgs {
+        datastore_path: test_datastore.datastore.clone(),
+        log_level: log::LevelFilter::Info,
+        migration_directory: test_repo.targets_path.clone(),
+        root_path: root(),
+        metadata_directory: test_repo.metadata_path.clone(),
+    })
+    .unwrap();
+
+    let rollback_to = |version: &str| {
+        rollback(&Args {
+            datastore_path: test_datastore.datastore.clone(),
+            dry_run: false,
+            log_level: log::LevelFilter::Info,
+            migration_directory: test_repo.targets_path.clone(),
+            migrate_to_version: Version::parse(version).unwrap(),
+            root_path: root(),
+            metadata_directory: test_repo.metadata_path.clone(),
+        })
+    };
+    for version in &["0.99.1", "0.99.2"] {
+        match rollback_to(version) {
+            Err(Error::RollbackNotOlder { .. }) => {}
+            other => panic!("Expected RollbackNotOlder, got {:?}", other),
+        }
+    }
+    assert_eq!(
+        get_current_version(test_datastore.tmp.path()).unwrap(),
+        from_version
+    );
+
+    rollback_to("0.99.0").unwrap();
+    assert_eq!(
+        get_current_version(test_datastore.tmp.path()).unwrap(),
+        Version::parse("0.99.0").unwrap()
+    );
+}
+
+#[test]
+fn declarative_names() {
+    assert!(is_declarative("migrate_v1.5.0_add-motd.toml.lz4"));
+    assert!(is_declarative("migrate_v1.5.0_add-motd.toml"));
+    assert!(!is_declarative("migrate_v1.5.0_add-motd.lz4"));
+    assert!(!is_declarative("migrate_v1.5.0_toml-settings.lz4"));
+}
@@ -10,6 +10,24 @@ It contains two subcommands meant for use as settings generators:
 * `generate-hostname`: returns the node's hostname in JSON format. If the lookup is unsuccessful, the IP of the node is used.
 
 The subcommand `set-hostname` sets the hostname for the system.
+
+The subcommand `write-interfaces` renders wicked configuration for the interfaces in
+`settings.network.interfaces`; configuring eth0 replaces its default DHCP configuration.  Interfaces
+can use DHCPv4, DHCPv6, SLAAC, or static addresses and routes, and can be VLANs or bonds of other
+interfaces.  Files that netdog wrote for interfaces that are no longer configured are removed, and
+other files are left alone.  It also records the primary interface, whose address is used as the
+node's current IP; if only one interface is configured, it's the primary.  For a static primary
+interface, its addresses and resolver configuration are written immediately, with the first
+address used as the node's IP, and for DHCP, they're written when a lease is received.
+
+The subcommand `write-resolv-conf` re-renders `/etc/resolv.conf` using `settings.dns`.  Name
+servers and search domains from settings replace those from the primary interface, and resolver
+options like `ndots`, `timeout`, and `attempts` are added.  The settings are also saved so they're
+applied when a new lease is received.
+
+The primary interface, its DNS information from the lease or settings, and the saved DNS settings
+describe the current boot, so they're kept in `/run/netdog`.  The settings applier runs
+`write-interfaces` and `write-resolv-conf` again on each boot.
 */
 
 // TODO:
@@ -24,17 +42,24 @@ The subcommand `set-hostname` sets the hostname for the system.
 #[macro_use]
 extern crate serde_plain;
 
+mod wicked;
+
 use argh::FromArgs;
 use dns_lookup::lookup_addr;
 use envy;
 use ipnet::IpNet;
 use lazy_static::lazy_static;
+use log::{info, warn};
+use model::modeled_types::{InterfaceAddressing, InterfaceMode, InterfaceName};
+use model::{DnsSettings, NetworkInterface, ResolverOptions};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use snafu::ResultExt;
-use std::collections::BTreeSet;
+use simplelog::{ColorChoice, Config as LogConfig, LevelFilter, TermLogger, TerminalMode};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
 use std::fmt::Write;
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
@@ -46,6 +71,10 @@ use std::str::FromStr;
 static RESOLV_CONF: &str = "/etc/resolv.conf";
 static KERNEL_HOSTNAME: &str = "/proc/sys/kernel/hostname";
 static CURRENT_IP: &str = "/var/lib/netdog/current_ip";
+static PRIMARY_INTERFACE: &str = "/run/netdog/primary_interface";
+static CURRENT_DNS: &str = "/run/netdog/current_dns";
+static DNS_SETTINGS: &str = "/run/netdog/dns_settings";
+static WICKED_IFCONFIG_DIR: &str = "/etc/wicked/ifconfig";
 
 // Matches wicked's shell-like syntax for DHCP lease variables:
 //     FOO='BAR' -> key=FOO, val=BAR
@@ -66,19 +95,16 @@ struct LeaseInfo {
     dns_search: Option<Vec<String>>,
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-enum InterfaceName {
-    Eth0,
-}
-
 #[derive(Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 enum InterfaceType {
     Dhcp,
+    // wicked may also report addresses that aren't from DHCP; we don't need to handle them.
+    Static,
+    Auto,
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 enum InterfaceFamily {
     Ipv4,
@@ -86,13 +112,53 @@ enum InterfaceFamily {
 }
 
 // Implement `from_str()` so argh can attempt to deserialize args into their proper types
-derive_fromstr_from_deserialize!(InterfaceName);
 derive_fromstr_from_deserialize!(InterfaceType);
 derive_fromstr_from_deserialize!(InterfaceFamily);
 
+/// Lets argh parse interface names, validating them like the model does.
+fn parse_interface_name(input: &str) -> std::result::Result<InterfaceName, String> {
+    InterfaceName::try_from(input).map_err(|e| e.to_string())
+}
+
+/// The interface whose address is the node's current IP, as written by `write-interfaces`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct PrimaryInterface {
+    name: String,
+    /// The family of the DHCP lease that gives the interface's address.
+    family: InterfaceFamily,
+    /// Name servers to use instead of those from DHCP, if any.
+    #[serde(default)]
+    name_servers: Vec<IpAddr>,
+}
+
+impl Default for PrimaryInterface {
+    /// Before `write-interfaces` runs, or if no interfaces are configured, we use the default
+    /// DHCP configuration of eth0.
+    fn default() -> Self {
+        Self {
+            name: "eth0".to_string(),
+            family: InterfaceFamily::Ipv4,
+            name_servers: Vec::new(),
+        }
+    }
+}
+
+/// DNS information for the primary interface, from its lease or from its settings.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct InterfaceDns {
+    name_servers: Vec<IpAddr>,
+    search: Vec<String>,
+}
+
 /// Stores user-supplied arguments.
 #[derive(FromArgs, PartialEq, Debug)]
 struct Args {
+    #[argh(option, default = "LevelFilter::Info", short = 'l')]
+    /// log-level trace|debug|info|warn|error
+    log_level: LevelFilter,
+
     #[argh(subcommand)]
     subcommand: SubCommand,
 }
@@ -105,13 +171,15 @@ enum SubCommand {
     NodeIp(NodeIpArgs),
     GenerateHostname(GenerateHostnameArgs),
     SetHostname(SetHostnameArgs),
+    WriteInterfaces(WriteInterfacesArgs),
+    WriteResolvConf(WriteResolvConfArgs),
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
 #[argh(subcommand, name = "install")]
 /// Write resolv.conf and current IP to disk
 struct InstallArgs {
-    #[argh(option, short = 'i')]
+    #[argh(option, short = 'i', from_str_fn(parse_interface_name))]
     /// name of the network interface
     interface_name: InterfaceName,
 
@@ -140,7 +208,7 @@ struct InstallArgs {
 // `wicked`.
 /// Does nothing
 struct RemoveArgs {
-    #[argh(option, short = 'i')]
+    #[argh(option, short = 'i', from_str_fn(parse_interface_name))]
     /// name of the network interface
     interface_name: InterfaceName,
 
@@ -172,6 +240,24 @@ struct SetHostnameArgs {
     hostname: String,
 }
 
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "write-interfaces")]
+/// Write wicked configuration for the interfaces in settings
+struct WriteInterfacesArgs {
+    #[argh(option, default = "constants::API_SOCKET.to_string()")]
+    /// path to the API socket
+    socket_path: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "write-resolv-conf")]
+/// Write resolv.conf using the DNS settings
+struct WriteResolvConfArgs {
+    #[argh(option, default = "constants::API_SOCKET.to_string()")]
+    /// path to the API socket
+    socket_path: String,
+}
+
 /// Parse lease data file into a LeaseInfo structure.
 fn parse_lease_info<P>(lease_file: P) -> Result<LeaseInfo>
 where
@@ -202,43 +288,158 @@ where
         .context(error::LeaseParseFailed { path: lease_file })?)
 }
 
-/// Write resolver configuration for libc.
-fn write_resolv_conf(dns_servers: &[&IpAddr], dns_search: &Option<Vec<String>>) -> Result<()> {
+/// Build resolver configuration for libc, merging DNS settings with the primary interface's DNS
+/// information.  Name servers and search domains from settings replace the interface's.
+fn render_resolv_conf(
+    interface_dns: &InterfaceDns,
+    dns_settings: Option<&DnsSettings>,
+) -> Result<String> {
     let mut output = String::new();
 
-    if let Some(s) = dns_search {
-        writeln!(output, "search {}", s.join(" ")).context(error::ResolvConfBuildFailed)?;
+    let dns_search = match dns_settings.and_then(|s| s.search_list.as_ref()) {
+        Some(search_list) if !search_list.is_empty() => {
+            search_list.iter().map(|d| d.to_string()).collect()
+        }
+        _ => interface_dns.search.clone(),
+    };
+    if !dns_search.is_empty() {
+        writeln!(output, "search {}", dns_search.join(" "))
+            .context(error::ResolvConfBuildFailed)?;
     }
 
+    let dns_servers = match dns_settings.and_then(|s| s.name_servers.as_ref()) {
+        Some(name_servers) if !name_servers.is_empty() => name_servers,
+        _ => &interface_dns.name_servers,
+    };
     for n in dns_servers {
         writeln!(output, "nameserver {}", n).context(error::ResolvConfBuildFailed)?;
     }
 
+    let options = resolver_options(dns_settings.and_then(|s| s.options.as_ref()));
+    if !options.is_empty() {
+        writeln!(output, "options {}", options.join(" ")).context(error::ResolvConfBuildFailed)?;
+    }
+
+    Ok(output)
+}
+
+/// Returns the resolver options that are set, in resolv.conf syntax.
+fn resolver_options(options: Option<&ResolverOptions>) -> Vec<String> {
+    let options = match options {
+        Some(options) => options,
+        None => return Vec::new(),
+    };
+    [
+        ("ndots", options.ndots.map(u8::from)),
+        ("timeout", options.timeout.map(u8::from)),
+        ("attempts", options.attempts.map(u8::from)),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.map(|value| format!("{}:{}", name, value)))
+    .collect()
+}
+
+/// Read a JSON file written by netdog, or return the default if it doesn't exist yet.
+fn read_state<T, P>(path: P) -> Result<T>
+where
+    T: Default + serde::de::DeserializeOwned,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let state_str = fs::read_to_string(path).context(error::StateReadFailed { path })?;
+    serde_json::from_str(&state_str).context(error::StateParseFailed { path })
+}
+
+/// Write a JSON file for later netdog runs.
+fn write_state<T, P>(path: P, state: &T) -> Result<()>
+where
+    T: Serialize,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let state_str = serde_json::to_string(state).context(error::JsonSerialize {
+        output: path.display().to_string(),
+    })?;
+    fs::write(path, state_str).context(error::StateWriteFailed { path })
+}
+
+/// Record the primary interface's DNS information and rewrite resolver configuration.
+fn write_interface_dns(interface_dns: &InterfaceDns) -> Result<()> {
+    write_state(CURRENT_DNS, interface_dns)?;
+    write_resolv_conf()
+}
+
+/// Write resolver configuration from the primary interface's DNS information and the saved DNS
+/// settings.  Until there's any information to use, the existing configuration is kept.
+fn write_resolv_conf() -> Result<()> {
+    let interface_dns: InterfaceDns = read_state(CURRENT_DNS)?;
+    let dns_settings: Option<DnsSettings> = read_state(DNS_SETTINGS)?;
+    let output = render_resolv_conf(&interface_dns, dns_settings.as_ref())?;
+    if output.is_empty() {
+        info!("No DNS information available, keeping current resolver configuration");
+        return Ok(());
+    }
+
     fs::write(RESOLV_CONF, output).context(error::ResolvConfWriteFailed { path: RESOLV_CONF })?;
     Ok(())
 }
 
-/// Persist the current IP address to file
-fn write_current_ip(ip: &IpAddr) -> Result<()> {
-    fs::write(CURRENT_IP, ip.to_string()).context(error::CurrentIpWriteFailed { path: CURRENT_IP })
+/// Persist the current IP addresses to file, one per line; the first is the node's IP
+fn write_current_ips(ips: &[IpAddr]) -> Result<()> {
+    let ips: Vec<String> = ips.iter().map(|ip| ip.to_string()).collect();
+    fs::write(CURRENT_IP, ips.join("\n")).context(error::CurrentIpWriteFailed { path: CURRENT_IP })
+}
+
+/// Read the node's IP address, the first of the current IP addresses
+fn read_current_ip() -> Result<IpAddr> {
+    let ips_string =
+        fs::read_to_string(CURRENT_IP).context(error::CurrentIpReadFailed { path: CURRENT_IP })?;
+    let ip_string = ips_string.lines().next().unwrap_or_default();
+    IpAddr::from_str(ip_string).context(error::IpFromString { ip: ip_string })
+}
+
+/// Read the primary interface written by `write-interfaces`, or the default if there isn't one.
+fn read_primary_interface() -> Result<PrimaryInterface> {
+    if !Path::new(PRIMARY_INTERFACE).exists() {
+        return Ok(PrimaryInterface::default());
+    }
+    let primary_str =
+        fs::read_to_string(PRIMARY_INTERFACE).context(error::PrimaryInterfaceReadFailed {
+            path: PRIMARY_INTERFACE,
+        })?;
+    serde_json::from_str(&primary_str).context(error::PrimaryInterfaceParseFailed {
+        path: PRIMARY_INTERFACE,
+    })
 }
 
 fn install(args: InstallArgs) -> Result<()> {
-    match (
-        &args.interface_name,
-        &args.interface_type,
-        &args.interface_family,
-    ) {
-        (InterfaceName::Eth0, InterfaceType::Dhcp, InterfaceFamily::Ipv4) => {
-            let info = parse_lease_info(&args.data_file)?;
+    // We only use leases for the primary interface; others don't affect the node's IP or DNS.
+    let primary = read_primary_interface()?;
+    if args.interface_name.as_ref() == primary.name
+        && args.interface_type == InterfaceType::Dhcp
+        && args.interface_family == primary.family
+    {
+        let info = parse_lease_info(&args.data_file)?;
+        // Name servers from the interface's settings take precedence over those from DHCP.
+        let name_servers = if primary.name_servers.is_empty() {
+            let mut dns_servers: Vec<_> = info.dns_servers.into_iter().collect();
             // Randomize name server order, for libc implementations like musl that send
             // queries to the first N servers.
-            let mut dns_servers: Vec<_> = info.dns_servers.iter().collect();
             dns_servers.shuffle(&mut thread_rng());
-            write_resolv_conf(&dns_servers, &info.dns_search)?;
-            write_current_ip(&info.ip_address.addr())?;
-        }
-        _ => eprintln!("Unhandled 'install' command: {:?}", &args),
+            dns_servers
+        } else {
+            primary.name_servers
+        };
+        write_interface_dns(&InterfaceDns {
+            name_servers,
+            search: info.dns_search.unwrap_or_default(),
+        })?;
+        write_current_ips(&[info.ip_address.addr()])?;
+    } else {
+        info!("Unhandled 'install' command: {:?}", &args);
     }
     Ok(())
 }
@@ -249,34 +450,30 @@ fn remove(args: RemoveArgs) -> Result<()> {
         &args.interface_type,
         &args.interface_family,
     ) {
-        _ => eprintln!("The 'remove' command is not implemented."),
+        _ => warn!("The 'remove' command is not implemented."),
     }
     Ok(())
 }
 
 /// Return the current IP address as JSON (intended for use as a settings generator)
 fn node_ip() -> Result<()> {
-    let ip_string =
-        fs::read_to_string(CURRENT_IP).context(error::CurrentIpReadFailed { path: CURRENT_IP })?;
     // Validate that we read a proper IP address
-    let _ = IpAddr::from_str(&ip_string).context(error::IpFromString { ip: &ip_string })?;
+    let ip = read_current_ip()?;
 
     // sundog expects JSON-serialized output
-    Ok(print_json(ip_string)?)
+    Ok(print_json(ip.to_string())?)
 }
 
 /// Attempt to resolve assigned IP address, if unsuccessful use the IP as the hostname.
 ///
 /// The result is returned as JSON. (intended for use as a settings generator)
 fn generate_hostname() -> Result<()> {
-    let ip_string =
-        fs::read_to_string(CURRENT_IP).context(error::CurrentIpReadFailed { path: CURRENT_IP })?;
-    let ip = IpAddr::from_str(&ip_string).context(error::IpFromString { ip: &ip_string })?;
+    let ip = read_current_ip()?;
     let hostname = match lookup_addr(&ip) {
         Ok(hostname) => hostname,
         Err(e) => {
-            eprintln!("Reverse DNS lookup failed: {}", e);
-            ip_string
+            warn!("Reverse DNS lookup failed: {}", e);
+            ip.to_string()
         }
     };
 
@@ -295,6 +492,149 @@ where
     Ok(())
 }
 
+/// Retrieve the current settings from the API.
+async fn get_settings<P>(socket_path: P) -> Result<model::Settings>
+where
+    P: AsRef<Path>,
+{
+    let uri = constants::API_SETTINGS_URI;
+    let method = "GET";
+    let (code, response_body) = apiclient::raw_request(socket_path, uri, method, None)
+        .await
+        .context(error::APIRequest { method, uri })?;
+    ensure!(
+        code.is_success(),
+        error::APIResponse {
+            method,
+            uri,
+            code,
+            response_body,
+        }
+    );
+
+    serde_json::from_str(&response_body).context(error::ResponseJson { method, uri })
+}
+
+/// Choose the primary interface: the one marked primary, or the only one.
+fn choose_primary(
+    interfaces: &HashMap<InterfaceName, NetworkInterface>,
+) -> Result<(&InterfaceName, &NetworkInterface)> {
+    let marked: Vec<_> = interfaces
+        .iter()
+        .filter(|(_, interface)| interface.primary == Some(true))
+        .collect();
+    match (marked.as_slice(), interfaces.len()) {
+        ([primary], _) => Ok(*primary),
+        ([], 1) => Ok(interfaces
+            .iter()
+            .next()
+            .context(error::NoPrimaryInterface)?),
+        ([], _) => error::NoPrimaryInterface.fail(),
+        _ => error::MultiplePrimaryInterfaces.fail(),
+    }
+}
+
+/// Write wicked configuration for the configured interfaces, removing any we wrote for interfaces
+/// that are no longer configured, and record the primary interface.
+async fn write_interfaces(args: WriteInterfacesArgs) -> Result<()> {
+    let interfaces = get_settings(&args.socket_path)
+        .await?
+        .network
+        .and_then(|network| network.interfaces)
+        .unwrap_or_default();
+    if interfaces.is_empty() {
+        info!("No network interfaces configured, keeping default configuration");
+        return Ok(());
+    }
+    let (primary_name, primary) = choose_primary(&interfaces)?;
+
+    let configs = wicked::render_interfaces(&interfaces)?;
+    let ifconfig_dir = Path::new(WICKED_IFCONFIG_DIR);
+    fs::create_dir_all(ifconfig_dir)
+        .context(error::WickedConfigWriteFailed { path: ifconfig_dir })?;
+    for (name, config) in &configs {
+        let path = ifconfig_dir.join(format!("{}.xml", name));
+        fs::write(&path, config).context(error::WickedConfigWriteFailed { path })?;
+    }
+    // Remove configuration we wrote for interfaces that are no longer configured.  Files we
+    // didn't write, like the default eth0 configuration, are left alone.
+    for entry in
+        fs::read_dir(ifconfig_dir).context(error::WickedConfigWriteFailed { path: ifconfig_dir })?
+    {
+        let path = entry
+            .context(error::WickedConfigWriteFailed { path: ifconfig_dir })?
+            .path();
+        if path.extension() != Some("xml".as_ref()) {
+            continue;
+        }
+        let configured = matches!(path.file_stem().and_then(|stem| stem.to_str()),
+                                  Some(stem) if configs.contains_key(stem));
+        if !configured && written_by_netdog(&path)? {
+            fs::remove_file(&path).context(error::WickedConfigWriteFailed { path: &path })?;
+        }
+    }
+
+    let addressing = primary
+        .mode
+        .as_ref()
+        .map_or(InterfaceAddressing::Dhcp, InterfaceMode::addressing);
+    let name_servers = primary.name_servers.clone().unwrap_or_default();
+    let primary_interface = PrimaryInterface {
+        name: primary_name.to_string(),
+        // Only DHCP leases are installed, so the family only matters for DHCP modes.
+        family: match addressing {
+            InterfaceAddressing::Dhcp
+            | InterfaceAddressing::Dhcp4
+            | InterfaceAddressing::Static => InterfaceFamily::Ipv4,
+            InterfaceAddressing::Dhcp6 | InterfaceAddressing::Slaac => InterfaceFamily::Ipv6,
+        },
+        name_servers: name_servers.clone(),
+    };
+    let primary_str = serde_json::to_string(&primary_interface).context(error::JsonSerialize {
+        output: primary_name.to_string(),
+    })?;
+    fs::write(PRIMARY_INTERFACE, primary_str).context(error::PrimaryInterfaceWriteFailed {
+        path: PRIMARY_INTERFACE,
+    })?;
+
+    // There's no lease for static addresses, so write the current IPs and resolver configuration
+    // now.
+    if addressing == InterfaceAddressing::Static {
+        let addresses: Vec<IpAddr> = primary
+            .addresses
+            .iter()
+            .flatten()
+            .map(|address| address.addr())
+            .collect();
+        if !addresses.is_empty() {
+            write_current_ips(&addresses)?;
+        }
+        write_interface_dns(&InterfaceDns {
+            name_servers,
+            search: Vec::new(),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Returns whether the wicked configuration file at the given path was written by netdog.
+fn written_by_netdog(path: &Path) -> Result<bool> {
+    let file = File::open(path).context(error::WickedConfigReadFailed { path })?;
+    let mut first_line = String::new();
+    BufReader::new(file)
+        .read_line(&mut first_line)
+        .context(error::WickedConfigReadFailed { path })?;
+    Ok(first_line.trim_end() == wicked::GENERATED_HEADER)
+}
+
+/// Save the DNS settings for later lease changes, and rewrite resolver configuration.
+async fn write_dns_settings(args: WriteResolvConfArgs) -> Result<()> {
+    let dns_settings = get_settings(&args.socket_path).await?.dns;
+    write_state(DNS_SETTINGS, &dns_settings)?;
+    write_resolv_conf()
+}
+
 /// Sets the hostname for the system
 fn set_hostname(args: SetHostnameArgs) -> Result<()> {
     fs::write(KERNEL_HOSTNAME, args.hostname).context(error::HostnameWriteFailed {
@@ -303,14 +643,26 @@ fn set_hostname(args: SetHostnameArgs) -> Result<()> {
     Ok(())
 }
 
-fn run() -> Result<()> {
+async fn run() -> Result<()> {
     let args: Args = argh::from_env();
+
+    // Log to stderr, since some subcommands print their output to stdout.
+    TermLogger::init(
+        args.log_level,
+        LogConfig::default(),
+        TerminalMode::Stderr,
+        ColorChoice::Auto,
+    )
+    .context(error::Logger)?;
+
     match args.subcommand {
         SubCommand::Install(args) => install(args)?,
         SubCommand::Remove(args) => remove(args)?,
         SubCommand::NodeIp(_) => node_ip()?,
         SubCommand::GenerateHostname(_) => generate_hostname()?,
         SubCommand::SetHostname(args) => set_hostname(args)?,
+        SubCommand::WriteInterfaces(args) => write_interfaces(args).await?,
+        SubCommand::WriteResolvConf(args) => write_dns_settings(args).await?,
     }
     Ok(())
 }
@@ -318,24 +670,72 @@ fn run() -> Result<()> {
 // Returning a Result from main makes it print a Debug representation of the error, but with Snafu
 // we have nice Display representations of the error, so we wrap "main" (run) and print any error.
 // https://github.com/shepmaster/snafu/issues/110
-fn main() {
-    if let Err(e) = run() {
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
         eprintln!("{}", e);
         process::exit(1);
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolv_conf_from_interface() {
+        let interface_dns = InterfaceDns {
+            name_servers: vec!["10.0.0.2".parse().unwrap()],
+            search: vec!["ec2.internal".to_string()],
+        };
+        assert_eq!(
+            render_resolv_conf(&interface_dns, None).unwrap(),
+            "search ec2.internal\nnameserver 10.0.0.2\n"
+        );
+    }
+
+    #[test]
+    fn resolv_conf_with_settings() {
+        let interface_dns = InterfaceDns {
+            name_servers: vec!["10.0.0.2".parse().unwrap()],
+            search: vec!["ec2.internal".to_string()],
+        };
+        let dns_settings: DnsSettings = toml::from_str(
+            r#"
+            name-servers = ["192.168.0.53", "2001:db8::53"]
+            options = { ndots = 2, attempts = 3 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            render_resolv_conf(&interface_dns, Some(&dns_settings)).unwrap(),
+            "search ec2.internal\nnameserver 192.168.0.53\nnameserver 2001:db8::53\noptions ndots:2 attempts:3\n"
+        );
+
+        let dns_settings: DnsSettings =
+            toml::from_str(r#"search-list = ["example.com", "corp.example.com"]"#).unwrap();
+        assert_eq!(
+            render_resolv_conf(&InterfaceDns::default(), Some(&dns_settings)).unwrap(),
+            "search example.com corp.example.com\n"
+        );
+    }
+}
+
 /// Potential errors during netdog execution
 mod error {
     use envy;
     use snafu::Snafu;
     use std::io;
+    use std::net::IpAddr;
     use std::path::PathBuf;
 
     #[derive(Debug, Snafu)]
     #[snafu(visibility = "pub(super)")]
     #[allow(clippy::enum_variant_names)]
     pub(super) enum Error {
+        #[snafu(display("Logger setup error: {}", source))]
+        Logger { source: log::SetLoggerError },
+
         #[snafu(display("Failed to read lease data in '{}': {}", path.display(), source))]
         LeaseReadFailed { path: PathBuf, source: io::Error },
 
@@ -363,6 +763,98 @@ mod error {
         #[snafu(display("Failed to read current IP data in '{}': {}", path.display(), source))]
         CurrentIpReadFailed { path: PathBuf, source: io::Error },
 
+        #[snafu(display("Error {}ing '{}': {}", method, uri, source))]
+        APIRequest {
+            method: String,
+            uri: String,
+            source: apiclient::Error,
+        },
+
+        #[snafu(display("Error {} when {}ing '{}': {}", code, method, uri, response_body))]
+        APIResponse {
+            method: String,
+            uri: String,
+            code: http::StatusCode,
+            response_body: String,
+        },
+
+        #[snafu(display(
+            "Error deserializing response as JSON from {} to '{}': {}",
+            method,
+            uri,
+            source
+        ))]
+        ResponseJson {
+            method: &'static str,
+            uri: String,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Interface '{}' is missing setting '{}'", interface, setting))]
+        MissingInterfaceSetting { interface: String, setting: String },
+
+        #[snafu(display(
+            "Interface '{}' has invalid VLAN ID {}, must be 1 to 4094",
+            interface,
+            id
+        ))]
+        InvalidVlanId { interface: String, id: u16 },
+
+        #[snafu(display(
+            "Interface '{}' has route to '{}' via '{}' of a different address family",
+            interface,
+            to,
+            via
+        ))]
+        RouteFamilyMismatch {
+            interface: String,
+            to: String,
+            via: IpAddr,
+        },
+
+        #[snafu(display(
+            "Interface '{}' is a member of bond '{}' and can't be configured separately",
+            member,
+            bond
+        ))]
+        BondMemberConfigured { bond: String, member: String },
+
+        #[snafu(display("Multiple network interfaces are configured; mark one as primary"))]
+        NoPrimaryInterface,
+
+        #[snafu(display("More than one network interface is marked primary"))]
+        MultiplePrimaryInterfaces,
+
+        #[snafu(display("Failed to write wicked configuration to '{}': {}", path.display(), source))]
+        WickedConfigWriteFailed { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to read wicked configuration from '{}': {}", path.display(), source))]
+        WickedConfigReadFailed { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to write primary interface to '{}': {}", path.display(), source))]
+        PrimaryInterfaceWriteFailed { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to read primary interface from '{}': {}", path.display(), source))]
+        PrimaryInterfaceReadFailed { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to parse primary interface in '{}': {}", path.display(), source))]
+        PrimaryInterfaceParseFailed {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to write state to '{}': {}", path.display(), source))]
+        StateWriteFailed { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to read state from '{}': {}", path.display(), source))]
+        StateReadFailed { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to parse state in '{}': {}", path.display(), source))]
+        StateParseFailed {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
         #[snafu(display("Error serializing to JSON: '{}': {}", output, source))]
         JsonSerialize {
             output: String,
@@ -0,0 +1,333 @@
+//! The wicked module renders wicked interface configuration from `settings.network.interfaces`.
+//! Each configured interface gets its own file, as does each member of a bond, so that wicked
+//! enslaves the members to the bond rather than addressing them itself.
+
+use crate::error;
+use crate::Result;
+use model::modeled_types::{InterfaceAddressing, InterfaceMode, InterfaceName, IpCidr};
+use model::{NetworkInterface, StaticRoute};
+use snafu::{ensure, OptionExt};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+
+/// How often the bonding driver checks member link state, in milliseconds.
+const BOND_MIIMON_FREQUENCY: u32 = 100;
+
+/// The first line of each file we render, so we know which files we can remove when interfaces
+/// are no longer configured.
+pub(crate) const GENERATED_HEADER: &str = "<!-- Generated by netdog -->";
+
+/// Returns the wicked configuration for the given interfaces, as a map of interface name to the
+/// contents of its configuration file.
+pub(crate) fn render_interfaces(
+    interfaces: &HashMap<InterfaceName, NetworkInterface>,
+) -> Result<BTreeMap<String, String>> {
+    let mut configs = BTreeMap::new();
+    for (name, interface) in interfaces {
+        configs.insert(name.to_string(), interface_config(name, interface)?);
+    }
+
+    // Bond members are configured only as members of their bond.
+    for (name, interface) in interfaces {
+        let members = interface.bond.as_ref().and_then(|b| b.members.as_ref());
+        for member in members.into_iter().flatten() {
+            ensure!(
+                !configs.contains_key(member.as_ref()),
+                error::BondMemberConfigured {
+                    bond: name.as_ref(),
+                    member: member.as_ref(),
+                }
+            );
+            configs.insert(member.to_string(), member_config(member, name));
+        }
+    }
+
+    Ok(configs)
+}
+
+/// Renders the configuration of a single interface.
+fn interface_config(name: &InterfaceName, interface: &NetworkInterface) -> Result<String> {
+    let mut lines = vec![
+        GENERATED_HEADER.to_string(),
+        "<interface>".to_string(),
+        format!("  <name>{}</name>", name),
+        "  <control>".to_string(),
+        "    <mode>boot</mode>".to_string(),
+        "    <link-detection>".to_string(),
+        "      <require-link />".to_string(),
+        "    </link-detection>".to_string(),
+        "  </control>".to_string(),
+    ];
+
+    if let Some(vlan) = &interface.vlan {
+        let device = vlan
+            .device
+            .as_ref()
+            .context(error::MissingInterfaceSetting {
+                interface: name.as_ref(),
+                setting: "vlan.device",
+            })?;
+        let id = vlan.id.context(error::MissingInterfaceSetting {
+            interface: name.as_ref(),
+            setting: "vlan.id",
+        })?;
+        ensure!(
+            (1..=4094).contains(&id),
+            error::InvalidVlanId {
+                interface: name.as_ref(),
+                id
+            }
+        );
+        lines.push("  <vlan>".to_string());
+        lines.push(format!("    <device>{}</device>", device));
+        lines.push(format!("    <tag>{}</tag>", id));
+        lines.push("  </vlan>".to_string());
+    }
+
+    if let Some(bond) = &interface.bond {
+        let members = bond.members.as_deref().unwrap_or_default();
+        ensure!(
+            !members.is_empty(),
+            error::MissingInterfaceSetting {
+                interface: name.as_ref(),
+                setting: "bond.members",
+            }
+        );
+        let mode = bond.mode.as_deref().unwrap_or("active-backup");
+        lines.push("  <bond>".to_string());
+        lines.push(format!("    <mode>{}</mode>", mode));
+        lines.push("    <miimon>".to_string());
+        lines.push(format!(
+            "      <frequency>{}</frequency>",
+            BOND_MIIMON_FREQUENCY
+        ));
+        lines.push("    </miimon>".to_string());
+        lines.push("    <slaves>".to_string());
+        for member in members {
+            lines.push(format!("      <slave><device>{}</device></slave>", member));
+        }
+        lines.push("    </slaves>".to_string());
+        lines.push("  </bond>".to_string());
+    }
+
+    lines.push("  <ipv4>".to_string());
+    lines.push("    <arp-verify>false</arp-verify>".to_string());
+    lines.push("    <arp-notify>false</arp-notify>".to_string());
+    lines.push("  </ipv4>".to_string());
+
+    // Dynamic addressing, based on the mode; like the default eth0 configuration, "dhcp" treats
+    // DHCPv6 as optional.
+    let addressing = interface
+        .mode
+        .as_ref()
+        .map_or(InterfaceAddressing::Dhcp, InterfaceMode::addressing);
+    let (dhcp4, dhcp6, slaac) = match addressing {
+        InterfaceAddressing::Dhcp => (true, true, false),
+        InterfaceAddressing::Dhcp4 => (true, false, false),
+        InterfaceAddressing::Dhcp6 => (false, true, false),
+        InterfaceAddressing::Slaac => (false, false, true),
+        InterfaceAddressing::Static => (false, false, false),
+    };
+    if dhcp4 {
+        lines.push("  <ipv4:dhcp>".to_string());
+        lines.push("    <enabled>true</enabled>".to_string());
+        lines.push("  </ipv4:dhcp>".to_string());
+    }
+    if dhcp6 {
+        lines.push("  <ipv6:dhcp>".to_string());
+        lines.push("    <enabled>true</enabled>".to_string());
+        lines.push("    <defer-timeout>1</defer-timeout>".to_string());
+        if addressing == InterfaceAddressing::Dhcp {
+            lines.push("    <flags>".to_string());
+            lines.push("      <optional />".to_string());
+            lines.push("    </flags>".to_string());
+        }
+        lines.push("  </ipv6:dhcp>".to_string());
+    }
+    if slaac {
+        lines.push("  <ipv6:auto>".to_string());
+        lines.push("    <enabled>true</enabled>".to_string());
+        lines.push("  </ipv6:auto>".to_string());
+    }
+
+    // Static addresses and routes, which wicked groups by address family.
+    let addresses = interface.addresses.as_deref().unwrap_or_default();
+    let routes = interface.routes.as_deref().unwrap_or_default();
+    ensure!(
+        routes.iter().all(|r| r.to.is_some()),
+        error::MissingInterfaceSetting {
+            interface: name.as_ref(),
+            setting: "routes.to",
+        }
+    );
+    for (family, ipv4) in &[("ipv4", true), ("ipv6", false)] {
+        let addresses: Vec<&IpCidr> = addresses
+            .iter()
+            .filter(|a| a.addr().is_ipv4() == *ipv4)
+            .collect();
+        let gateway = interface.gateway.filter(|g| g.is_ipv4() == *ipv4);
+        let routes: Vec<&StaticRoute> = routes
+            .iter()
+            .filter(|r| r.to.as_ref().map(|to| to.addr().is_ipv4()) == Some(*ipv4))
+            .collect();
+        if addresses.is_empty() && gateway.is_none() && routes.is_empty() {
+            continue;
+        }
+
+        lines.push(format!("  <{}:static>", family));
+        for address in addresses {
+            lines.push("    <address>".to_string());
+            lines.push(format!("      <local>{}</local>", address));
+            lines.push("    </address>".to_string());
+        }
+        if let Some(gateway) = gateway {
+            // A route without a destination is the default route.
+            route_lines(&mut lines, None, Some(gateway), None);
+        }
+        for route in routes {
+            if let (Some(to), Some(via)) = (&route.to, route.via) {
+                ensure!(
+                    via.is_ipv4() == *ipv4,
+                    error::RouteFamilyMismatch {
+                        interface: name.as_ref(),
+                        to: to.as_ref(),
+                        via
+                    }
+                );
+            }
+            route_lines(&mut lines, route.to.as_ref(), route.via, route.metric);
+        }
+        lines.push(format!("  </{}:static>", family));
+    }
+
+    lines.push("</interface>".to_string());
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Adds the lines for a static route.
+fn route_lines(
+    lines: &mut Vec<String>,
+    to: Option<&IpCidr>,
+    via: Option<IpAddr>,
+    metric: Option<u32>,
+) {
+    lines.push("    <route>".to_string());
+    if let Some(to) = to {
+        lines.push(format!("      <destination>{}</destination>", to));
+    }
+    if let Some(via) = via {
+        lines.push("      <nexthop>".to_string());
+        lines.push(format!("        <gateway>{}</gateway>", via));
+        lines.push("      </nexthop>".to_string());
+    }
+    if let Some(metric) = metric {
+        lines.push(format!("      <priority>{}</priority>", metric));
+    }
+    lines.push("    </route>".to_string());
+}
+
+/// Renders the configuration of a bond member, which has no addresses of its own.
+fn member_config(name: &InterfaceName, bond: &InterfaceName) -> String {
+    let lines = vec![
+        GENERATED_HEADER.to_string(),
+        "<interface>".to_string(),
+        format!("  <name>{}</name>", name),
+        "  <control>".to_string(),
+        "    <mode>hotplug</mode>".to_string(),
+        "  </control>".to_string(),
+        "  <link>".to_string(),
+        format!("    <master>{}</master>", bond),
+        "  </link>".to_string(),
+        "  <ipv4>".to_string(),
+        "    <enabled>false</enabled>".to_string(),
+        "  </ipv4>".to_string(),
+        "  <ipv6>".to_string(),
+        "    <enabled>false</enabled>".to_string(),
+        "  </ipv6>".to_string(),
+        "</interface>".to_string(),
+    ];
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn interfaces(toml_str: &str) -> HashMap<InterfaceName, NetworkInterface> {
+        toml::from_str(toml_str).unwrap()
+    }
+
+    #[test]
+    fn static_and_vlan() {
+        let interfaces = interfaces(
+            r#"
+            [eth0]
+            mode = "static"
+            addresses = ["192.168.1.10/24", "2001:db8::10/64"]
+            gateway = "192.168.1.1"
+            routes = [{ to = "10.0.0.0/8", via = "192.168.1.254", metric = 100 }]
+
+            ["eth0.100"]
+            mode = "dhcp4"
+            vlan = { device = "eth0", id = 100 }
+            "#,
+        );
+        let configs = render_interfaces(&interfaces).unwrap();
+        assert_eq!(configs.len(), 2);
+        assert!(configs.values().all(|c| c.starts_with(GENERATED_HEADER)));
+
+        let eth0 = &configs["eth0"];
+        assert!(eth0.contains("<ipv4:static>\n    <address>\n      <local>192.168.1.10/24</local>"));
+        assert!(eth0.contains("<ipv6:static>\n    <address>\n      <local>2001:db8::10/64</local>"));
+        assert!(eth0.contains("<nexthop>\n        <gateway>192.168.1.1</gateway>"));
+        assert!(eth0.contains("<destination>10.0.0.0/8</destination>"));
+        assert!(!eth0.contains("dhcp"));
+
+        let vlan = &configs["eth0.100"];
+        assert!(vlan.contains("<vlan>\n    <device>eth0</device>\n    <tag>100</tag>"));
+        assert!(vlan.contains("<ipv4:dhcp>"));
+        assert!(!vlan.contains("<ipv6:dhcp>"));
+    }
+
+    #[test]
+    fn bond() {
+        let interfaces = interfaces(
+            r#"
+            [bond0]
+            bond = { mode = "802.3ad", members = ["eth0", "eth1"] }
+            "#,
+        );
+        let configs = render_interfaces(&interfaces).unwrap();
+        assert_eq!(configs.len(), 3);
+        assert!(configs["bond0"].contains("<slave><device>eth1</device></slave>"));
+        assert!(configs["bond0"].contains("<optional />"));
+        assert!(configs["eth0"].contains("<master>bond0</master>"));
+    }
+
+    #[test]
+    fn bad_configs() {
+        // Members can't be configured separately.
+        let conflict = interfaces(
+            r#"
+            [bond0]
+            bond = { members = ["eth0"] }
+            [eth0]
+            mode = "dhcp"
+            "#,
+        );
+        render_interfaces(&conflict).unwrap_err();
+
+        let bad_vlan = interfaces("[vlan0]\nvlan = { device = \"eth0\", id = 5000 }");
+        render_interfaces(&bad_vlan).unwrap_err();
+
+        let mut mismatch =
+            interfaces("[eth0]\nroutes = [{ to = \"10.0.0.0/8\", via = \"2001:db8::1\" }]");
+        render_interfaces(&mismatch).unwrap_err();
+
+        // Names are validated by the model.
+        let name = InterfaceName::try_from("eth0").unwrap();
+        mismatch.get_mut(&name).unwrap().routes = None;
+        render_interfaces(&mismatch).unwrap();
+    }
+}
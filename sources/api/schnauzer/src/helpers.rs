@@ -5,13 +5,15 @@
 use dns_lookup::lookup_host;
 use handlebars::{Context, Handlebars, Helper, Output, RenderContext, RenderError};
 use lazy_static::lazy_static;
+use model::modeled_types::PkiConsumer;
 use num_cpus;
 use serde_json::value::Value;
 use snafu::{OptionExt, ResultExt};
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
 use url::Url;
 
 lazy_static! {
@@ -947,6 +949,167 @@ pub fn add_unresolvable_hostname(
     Ok(())
 }
 
+/// `registry_tls_configs` writes containerd's TLS configuration for each container registry named
+/// in the consumers of a `settings.pki` entry, using the files certdog writes for the registry.
+/// A registry gets a client certificate if one of its entries has a key.
+///
+/// Example:
+///    {{ registry_tls_configs settings.pki }}
+///    ...where `settings.pki` has an entry with a key and `consumers = ["registry:example.com"]`
+///    ...will produce:
+///    [plugins."io.containerd.grpc.v1.cri".registry.configs."example.com".tls]
+///    ca_file = "/etc/pki/consumers/registries/example.com/ca-bundle.crt"
+///    cert_file = "/etc/pki/consumers/registries/example.com/client.crt"
+///    key_file = "/etc/pki/consumers/registries/example.com/client.key"
+pub fn registry_tls_configs(
+    helper: &Helper<'_, '_>,
+    _: &Handlebars,
+    _: &Context,
+    renderctx: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    trace!("Starting registry_tls_configs helper");
+    let template_name = template_name(renderctx);
+    trace!("Template name: {}", &template_name);
+
+    trace!("Number of params: {}", helper.params().len());
+    check_param_count(helper, template_name, 1)?;
+
+    // Registry host -> its directory, and whether it has a client certificate
+    let mut registries: BTreeMap<String, (String, bool)> = BTreeMap::new();
+    for (_name, entry) in pki_entries(get_param(helper, 0)?, template_name)? {
+        let has_key = !entry.key.unwrap_or_default().trim().is_empty();
+        for consumer in entry.consumers.unwrap_or_default() {
+            if let Some(host) = consumer.registry_host() {
+                let registry = registries
+                    .entry(host.to_string())
+                    .or_insert_with(|| (consumer.directory(), false));
+                registry.1 |= has_key;
+            }
+        }
+    }
+
+    let mut result = String::new();
+    for (host, (dir, has_client)) in registries {
+        let dir = Path::new(constants::PKI_CONSUMERS_DIR).join(dir);
+        result.push_str(&format!(
+            "[plugins.\"io.containerd.grpc.v1.cri\".registry.configs.\"{}\".tls]\n",
+            host
+        ));
+        result.push_str(&format!(
+            "ca_file = \"{}\"\n",
+            dir.join(constants::PKI_CA_BUNDLE_FILE).display()
+        ));
+        if has_client {
+            result.push_str(&format!(
+                "cert_file = \"{}\"\nkey_file = \"{}\"\n",
+                dir.join(constants::PKI_CLIENT_CERT_FILE).display(),
+                dir.join(constants::PKI_CLIENT_KEY_FILE).display()
+            ));
+        }
+    }
+
+    out.write(&result).context(error::TemplateWrite {
+        template: template_name.to_owned(),
+    })?;
+    Ok(())
+}
+
+/// `pki_trusted_certs` writes the trusted certificates from `settings.pki` that are only for the
+/// given consumer, for programs like the kubelet that read their certificate authorities from a
+/// rendered file rather than from certdog.
+///
+/// Example:
+///    {{ pki_trusted_certs "kubelet" settings.pki }}
+///    ...where `settings.pki` has a trusted entry with `consumers = ["kubelet"]`
+///    ...will produce the decoded PEM certificates of that entry.
+pub fn pki_trusted_certs(
+    helper: &Helper<'_, '_>,
+    _: &Handlebars,
+    _: &Context,
+    renderctx: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    trace!("Starting pki_trusted_certs helper");
+    let template_name = template_name(renderctx);
+    trace!("Template name: {}", &template_name);
+
+    trace!("Number of params: {}", helper.params().len());
+    check_param_count(helper, template_name, 2)?;
+
+    let consumer = pki_consumer_param(helper, template_name)?;
+    let mut result = String::new();
+    for (_name, entry) in pki_entries(get_param(helper, 1)?, template_name)? {
+        // Entries with keys are client certificates, not certificate authorities
+        if !entry.trusted.unwrap_or(false) || !entry.key.unwrap_or_default().trim().is_empty() {
+            continue;
+        }
+        if !entry.consumers.unwrap_or_default().contains(&consumer) {
+            continue;
+        }
+        let data = entry.data.unwrap_or_default();
+        let decoded = base64::decode(data.as_bytes()).context(error::Base64Decode {
+            template: template_name.to_owned(),
+        })?;
+        let decoded = std::str::from_utf8(&decoded).context(error::InvalidUTF8 {
+            base64_string: data.to_string(),
+            template: template_name.to_owned(),
+        })?;
+        result.push_str(decoded);
+        if !result.ends_with('\n') {
+            result.push('\n');
+        }
+    }
+
+    out.write(&result).context(error::TemplateWrite {
+        template: template_name.to_owned(),
+    })?;
+    Ok(())
+}
+
+/// `pki_ca_bundle` writes the path of the certificate bundle certdog writes for the given
+/// consumer, or nothing if `settings.pki` has no entries for it.  Unlike the system trust store,
+/// the file only exists when it's needed.
+///
+/// Example:
+///    SSL_CERT_FILE={{ pki_ca_bundle "ecs" settings.pki }}
+///    ...where `settings.pki` has an entry with `consumers = ["ecs"]`
+///    ...will produce: "SSL_CERT_FILE=/etc/pki/consumers/ecs/ca-bundle.crt"
+pub fn pki_ca_bundle(
+    helper: &Helper<'_, '_>,
+    _: &Handlebars,
+    _: &Context,
+    renderctx: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    trace!("Starting pki_ca_bundle helper");
+    let template_name = template_name(renderctx);
+    trace!("Template name: {}", &template_name);
+
+    trace!("Number of params: {}", helper.params().len());
+    check_param_count(helper, template_name, 2)?;
+
+    let consumer = pki_consumer_param(helper, template_name)?;
+    let entries = pki_entries(get_param(helper, 1)?, template_name)?;
+    // certdog skips client certificates for consumers other than registries
+    let has_files = entries.into_iter().any(|(_name, entry)| {
+        let has_key = !entry.key.unwrap_or_default().trim().is_empty();
+        (!has_key || consumer.registry_host().is_some())
+            && entry.consumers.unwrap_or_default().contains(&consumer)
+    });
+
+    if has_files {
+        let path = Path::new(constants::PKI_CONSUMERS_DIR)
+            .join(consumer.directory())
+            .join(constants::PKI_CA_BUNDLE_FILE);
+        out.write(&path.display().to_string())
+            .context(error::TemplateWrite {
+                template: template_name.to_owned(),
+            })?;
+    }
+    Ok(())
+}
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 // helpers to the helpers
 
@@ -990,6 +1153,47 @@ fn check_param_count<S: AsRef<str>>(
     Ok(())
 }
 
+/// Deserializes the entries of `settings.pki`, sorted by name.  Entries without certificates are
+/// skipped, as certdog skips them, and a missing `settings.pki` has no entries.
+fn pki_entries(
+    value: &Value,
+    template_name: &str,
+) -> Result<Vec<(String, model::PemCertificate)>, RenderError> {
+    if value.is_null() {
+        return Ok(Vec::new());
+    }
+    let entries: HashMap<String, model::PemCertificate> = serde_json::from_value(value.clone())
+        .ok()
+        .context(error::InvalidTemplateValue {
+            expected: "map of PEM certificates",
+            value: value.to_owned(),
+            template: template_name.to_owned(),
+        })?;
+    let mut entries: Vec<_> = entries
+        .into_iter()
+        .filter(|(_name, entry)| !entry.data.clone().unwrap_or_default().trim().is_empty())
+        .collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(entries)
+}
+
+/// Gets the consumer named by the first param of the `settings.pki` helpers.
+fn pki_consumer_param(
+    helper: &Helper<'_, '_>,
+    template_name: &str,
+) -> Result<PkiConsumer, RenderError> {
+    let consumer_val = get_param(helper, 0)?;
+    let consumer = consumer_val
+        .as_str()
+        .and_then(|consumer| PkiConsumer::try_from(consumer).ok())
+        .context(error::InvalidTemplateValue {
+            expected: "PKI consumer",
+            value: consumer_val.to_owned(),
+            template: template_name.to_owned(),
+        })?;
+    Ok(consumer)
+}
+
 /// Constructs the fully qualified domain name for the ECR registry for the
 /// given region. Returns a default ECR registry if the region is not mapped.
 fn ecr_registry<S: AsRef<str>>(region: S) -> String {
@@ -1737,3 +1941,118 @@ mod test_add_unresolvable_hostname {
         assert_eq!(result, "")
     }
 }
+
+#[cfg(test)]
+mod test_pki_helpers {
+    use super::*;
+    use handlebars::RenderError;
+    use serde::Serialize;
+    use serde_json::json;
+
+    static TEST_PEM: &str = include_str!("../../../models/tests/data/test-pem");
+    static TEST_KEY: &str = include_str!("../../../models/tests/data/test-key");
+
+    // A thin wrapper around the handlebars render_template method that includes
+    // setup and registration of helpers
+    fn setup_and_render_template<T>(tmpl: &str, data: &T) -> Result<String, RenderError>
+    where
+        T: Serialize,
+    {
+        let mut registry = Handlebars::new();
+        registry.register_helper("registry_tls_configs", Box::new(registry_tls_configs));
+        registry.register_helper("pki_trusted_certs", Box::new(pki_trusted_certs));
+        registry.register_helper("pki_ca_bundle", Box::new(pki_ca_bundle));
+
+        registry.render_template(tmpl, data)
+    }
+
+    fn pki() -> serde_json::Value {
+        json!({"pki": {
+            "registry-client": {
+                "data": TEST_PEM,
+                "key": TEST_KEY,
+                "consumers": ["registry:registry.example.com:5000", "ecs"],
+            },
+            "registry-ca": {
+                "data": TEST_PEM,
+                "trusted": true,
+                "consumers": ["registry:registry.example.com:5000", "registry:other.example.com"],
+            },
+            "kubelet-ca": {
+                "data": TEST_PEM,
+                "trusted": true,
+                "consumers": ["kubelet"],
+            },
+            "removed": {
+                "data": "",
+                "consumers": ["ecs"],
+            },
+        }})
+    }
+
+    #[test]
+    fn registry_tls_configs_rendered() {
+        let result = setup_and_render_template("{{registry_tls_configs pki}}", &pki()).unwrap();
+        assert_eq!(
+            result,
+            r#"[plugins."io.containerd.grpc.v1.cri".registry.configs."other.example.com".tls]
+ca_file = "/etc/pki/consumers/registries/other.example.com/ca-bundle.crt"
+[plugins."io.containerd.grpc.v1.cri".registry.configs."registry.example.com:5000".tls]
+ca_file = "/etc/pki/consumers/registries/registry.example.com:5000/ca-bundle.crt"
+cert_file = "/etc/pki/consumers/registries/registry.example.com:5000/client.crt"
+key_file = "/etc/pki/consumers/registries/registry.example.com:5000/client.key"
+"#
+        );
+    }
+
+    #[test]
+    fn pki_trusted_certs_rendered() {
+        let result =
+            setup_and_render_template("{{pki_trusted_certs \"kubelet\" pki}}", &pki()).unwrap();
+        let expected = String::from_utf8(base64::decode(TEST_PEM).unwrap()).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn pki_ca_bundle_rendered() {
+        // The ECS entry with a key is skipped, as is the entry without data
+        let result = setup_and_render_template("{{pki_ca_bundle \"ecs\" pki}}", &pki()).unwrap();
+        assert_eq!(result, "");
+
+        let result = setup_and_render_template(
+            "{{pki_ca_bundle \"registry:other.example.com\" pki}}",
+            &pki(),
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "/etc/pki/consumers/registries/other.example.com/ca-bundle.crt"
+        );
+    }
+
+    #[test]
+    fn pki_ca_bundle_condition() {
+        // Templates only name a bundle that certdog writes
+        let tmpl =
+            "{{#if (pki_ca_bundle \"ecs\" pki)}}SSL_CERT_FILE={{pki_ca_bundle \"ecs\" pki}}{{/if}}";
+        assert_eq!(setup_and_render_template(tmpl, &pki()).unwrap(), "");
+
+        let mut pki = pki();
+        pki["pki"]["ecs-ca"] = json!({"data": TEST_PEM, "trusted": true, "consumers": ["ecs"]});
+        assert_eq!(
+            setup_and_render_template(tmpl, &pki).unwrap(),
+            "SSL_CERT_FILE=/etc/pki/consumers/ecs/ca-bundle.crt"
+        );
+    }
+
+    #[test]
+    fn missing_pki_renders_nothing() {
+        for tmpl in &[
+            "{{registry_tls_configs pki}}",
+            "{{pki_trusted_certs \"kubelet\" pki}}",
+            "{{pki_ca_bundle \"ecs\" pki}}",
+        ] {
+            assert_eq!(setup_and_render_template(tmpl, &json!({})).unwrap(), "");
+        }
+    }
+}
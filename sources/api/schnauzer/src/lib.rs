@@ -136,6 +136,12 @@ pub fn build_template_registry() -> Result<handlebars::Handlebars<'static>> {
         "add_unresolvable_hostname",
         Box::new(helpers::add_unresolvable_hostname),
     );
+    template_registry.register_helper(
+        "registry_tls_configs",
+        Box::new(helpers::registry_tls_configs),
+    );
+    template_registry.register_helper("pki_trusted_certs", Box::new(helpers::pki_trusted_certs));
+    template_registry.register_helper("pki_ca_bundle", Box::new(helpers::pki_ca_bundle));
 
     Ok(template_registry)
 }
@@ -1,9 +1,11 @@
+use crate::drift::Difference;
 use crate::service::Services;
 use crate::{error, Result};
 use itertools::join;
 use snafu::ResultExt;
 use std::collections::HashSet;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
 /// Query the API for ConfigurationFile data
@@ -59,12 +61,14 @@ pub fn render_config_files(
 
         let try_rendered = registry.render(&name, &settings);
         if strict {
-            let rendered = try_rendered.context(error::TemplateRender { template: name })?;
-            rendered_configs.push(RenderedConfigFile::new(&metadata.path, rendered));
+            let rendered = try_rendered.context(error::TemplateRender {
+                template: name.as_str(),
+            })?;
+            rendered_configs.push(RenderedConfigFile::new(name, &metadata.path, rendered));
         } else {
             match try_rendered {
                 Ok(rendered) => {
-                    rendered_configs.push(RenderedConfigFile::new(&metadata.path, rendered))
+                    rendered_configs.push(RenderedConfigFile::new(name, &metadata.path, rendered))
                 }
                 Err(err) => warn!("Unable to render template '{}': {}", &name, err),
             }
@@ -83,22 +87,61 @@ pub fn write_config_files(rendered_config: Vec<RenderedConfigFile>) -> Result<()
     Ok(())
 }
 
-/// RenderedConfigFile contains both the path to the config file
-/// and the rendered data to write.
+/// Compare the rendered configuration files with the files on disk, returning
+/// the files that differ, along with how they differ
+pub fn find_drift(
+    rendered_config: Vec<RenderedConfigFile>,
+) -> Vec<(RenderedConfigFile, Difference)> {
+    rendered_config
+        .into_iter()
+        .filter_map(|cfg| {
+            debug!("Checking {:?} for drift", &cfg.path);
+            cfg.drift().map(|difference| (cfg, difference))
+        })
+        .collect()
+}
+
+/// RenderedConfigFile contains the name of the config file, the path to
+/// the config file, and the rendered data to write.
 #[derive(Debug)]
 pub struct RenderedConfigFile {
+    name: String,
     path: PathBuf,
     rendered: String,
 }
 
 impl RenderedConfigFile {
-    fn new(path: &str, rendered: String) -> RenderedConfigFile {
+    fn new(name: String, path: &str, rendered: String) -> RenderedConfigFile {
         RenderedConfigFile {
+            name,
             path: PathBuf::from(&path),
             rendered,
         }
     }
 
+    /// The name of the configuration file, as used in service metadata
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns how the file on disk differs from the rendered template, if it does
+    fn drift(&self) -> Option<Difference> {
+        let missing = match fs::read(&self.path) {
+            Ok(contents) if contents == self.rendered.as_bytes() => return None,
+            Ok(_) => false,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => true,
+            Err(e) => {
+                warn!("Unable to read {}: {}", self.path.display(), e);
+                false
+            }
+        };
+        Some(Difference::ConfigurationFile {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            missing,
+        })
+    }
+
     /// Writes the rendered template at the proper location
     fn write_to_disk(&self) -> Result<()> {
         if let Some(dirname) = self.path.parent() {
@@ -140,4 +183,40 @@ mod test {
 
         assert_eq!(get_config_file_names(&services), expected_output)
     }
+
+    #[test]
+    fn test_find_drift() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name).to_string_lossy().to_string();
+        fs::write(path("same"), "same").unwrap();
+        fs::write(path("edited"), "edited by hand").unwrap();
+        let rendered = vec![
+            RenderedConfigFile::new("same".to_string(), &path("same"), "same".to_string()),
+            RenderedConfigFile::new("edited".to_string(), &path("edited"), "edited".to_string()),
+            RenderedConfigFile::new("missing".to_string(), &path("missing"), "x".to_string()),
+        ];
+
+        let drifted = find_drift(rendered);
+        let differences: Vec<&Difference> = drifted.iter().map(|(_, d)| d).collect();
+        assert_eq!(
+            differences,
+            vec![
+                &Difference::ConfigurationFile {
+                    name: "edited".to_string(),
+                    path: PathBuf::from(path("edited")),
+                    missing: false,
+                },
+                &Difference::ConfigurationFile {
+                    name: "missing".to_string(),
+                    path: PathBuf::from(path("missing")),
+                    missing: true,
+                },
+            ]
+        );
+
+        // Rewriting the drifted files fixes them.
+        write_config_files(drifted.into_iter().map(|(cfg, _)| cfg).collect()).unwrap();
+        assert_eq!(fs::read_to_string(path("edited")).unwrap(), "edited");
+        assert_eq!(fs::read_to_string(path("missing")).unwrap(), "x");
+    }
 }
@@ -0,0 +1,152 @@
+//! The drift module describes differences between the system and what settings ask for, as found
+//! by the periodic drift check.  corndog checks sysctls and thar-be-settings checks rendered
+//! configuration files; each writes its own report to `DRIFT_REPORT_DIR`, and apiserver serves
+//! them all from `GET /drift`.
+
+use crate::{error, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The directory holding the latest drift report from each program.
+pub const DRIFT_REPORT_DIR: &str = "/run/cache/drift";
+
+/// The result of one program's drift check.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DriftReport {
+    /// The program that checked for drift, like "corndog"; the report file is named for it.
+    pub source: String,
+    pub checked_at: DateTime<Utc>,
+    pub differences: Vec<Difference>,
+    /// Whether settings were re-applied to fix the differences.
+    pub reapplied: bool,
+}
+
+/// Something on the system that doesn't match settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Difference {
+    /// A sysctl whose value was changed from the one in `settings.kernel.sysctl`.
+    Sysctl {
+        key: String,
+        expected: String,
+        actual: String,
+    },
+    /// A configuration file whose contents don't match its rendered template.
+    ConfigurationFile {
+        name: String,
+        path: PathBuf,
+        missing: bool,
+    },
+}
+
+impl DriftReport {
+    pub fn new<S>(source: S, differences: Vec<Difference>, reapplied: bool) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            source: source.into(),
+            checked_at: Utc::now(),
+            differences,
+            reapplied,
+        }
+    }
+
+    /// Writes the report to `<dir>/<source>.json`, replacing the last report from the same
+    /// program.  The file is replaced atomically so readers never see a partial report.
+    pub fn write<P>(&self, dir: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        let path = dir.join(format!("{}.json", self.source));
+        let report = serde_json::to_string_pretty(self).context(error::DriftReportSerialize)?;
+
+        fs::create_dir_all(dir).context(error::DriftReportWrite { path: dir })?;
+        let mut temp_file =
+            tempfile::NamedTempFile::new_in(dir).context(error::DriftReportWrite { path: dir })?;
+        temp_file
+            .write_all(report.as_bytes())
+            .context(error::DriftReportWrite {
+                path: temp_file.path(),
+            })?;
+        temp_file
+            .persist(&path)
+            .context(error::DriftReportPersist { path })?;
+        Ok(())
+    }
+}
+
+/// Reads the reports in `dir`, sorted by source.  If no drift check has run, there are none.
+pub fn read_reports<P>(dir: P) -> Result<Vec<DriftReport>>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(error::DriftReportRead { path: dir }),
+    };
+
+    let mut reports = Vec::new();
+    for entry in entries {
+        let path = entry.context(error::DriftReportRead { path: dir })?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let report = fs::read_to_string(&path).context(error::DriftReportRead { path: &path })?;
+        let report: DriftReport =
+            serde_json::from_str(&report).context(error::DriftReportParse { path: &path })?;
+        reports.push(report);
+    }
+    reports.sort_by(|a, b| a.source.cmp(&b.source));
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn write_and_read() {
+        let dir = TempDir::new().unwrap();
+        assert!(read_reports(dir.path().join("missing")).unwrap().is_empty());
+
+        let sysctls = DriftReport::new(
+            "corndog",
+            vec![Difference::Sysctl {
+                key: "net.ipv4.ip_forward".to_string(),
+                expected: "1".to_string(),
+                actual: "0".to_string(),
+            }],
+            false,
+        );
+        let files = DriftReport::new(
+            "thar-be-settings",
+            vec![Difference::ConfigurationFile {
+                name: "motd".to_string(),
+                path: PathBuf::from("/etc/motd"),
+                missing: true,
+            }],
+            true,
+        );
+        files.write(dir.path()).unwrap();
+        sysctls.write(dir.path()).unwrap();
+        assert_eq!(
+            read_reports(dir.path()).unwrap(),
+            vec![sysctls.clone(), files.clone()]
+        );
+
+        // A new report from the same program replaces the old one.
+        let fixed = DriftReport::new("corndog", Vec::new(), false);
+        fixed.write(dir.path()).unwrap();
+        assert_eq!(read_reports(dir.path()).unwrap(), vec![fixed, files]);
+    }
+}
@@ -7,6 +7,12 @@ use std::path::PathBuf;
 #[derive(Debug, Snafu)]
 #[snafu(visibility = "pub(crate)")]
 pub enum Error {
+    #[snafu(display("Failed to lock '{}': {}", path, source))]
+    ApplyLock {
+        path: &'static str,
+        source: io::Error,
+    },
+
     #[snafu(display("Failed to read changed settings from {}", location))]
     ReadInput {
         location: &'static str,
@@ -69,6 +75,27 @@ pub enum Error {
         source: serde_json::Error,
     },
 
+    #[snafu(display("Failed to parse drift report '{}': {}", path.display(), source))]
+    DriftReportParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Failed to save drift report to '{}': {}", path.display(), source))]
+    DriftReportPersist {
+        path: PathBuf,
+        source: tempfile::PersistError,
+    },
+
+    #[snafu(display("Failed to read drift reports from '{}': {}", path.display(), source))]
+    DriftReportRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to serialize drift report: {}", source))]
+    DriftReportSerialize { source: serde_json::Error },
+
+    #[snafu(display("Failed to write drift report to '{}': {}", path.display(), source))]
+    DriftReportWrite { path: PathBuf, source: io::Error },
+
     #[snafu(display("Error GETing JSON from '{}': {}", uri, source))]
     GetJson {
         uri: String,
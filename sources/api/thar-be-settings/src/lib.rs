@@ -12,6 +12,12 @@ It then renders the templates and rewrites the affected configuration files.
 Service data from the API includes any commands needed to restart services affected by configuration file changes, which are run here.
 
 In the standalone ("all keys") mode, it queries the API for all services and configuration files, then renders and rewrites all configuration files and restarts all services.
+
+In the drift-check mode, it renders all configuration files and compares them with the files on disk, to notice files that were edited or removed by something other than thar-be-settings.
+It writes the differences to a report in `/run/cache/drift`, which the API serves from `GET /drift`.
+If `settings.drift.reapply` is true, it also rewrites the files that differ and restarts the services that use them.
+
+Every mode holds an exclusive lock on `/run/lock/thar-be-settings.lock` while it runs, as does corndog's drift check, so a drift check can't re-apply settings while changes from the API are being applied.
 */
 
 #![deny(rust_2018_idioms)]
@@ -19,17 +25,39 @@ In the standalone ("all keys") mode, it queries the API for all services and con
 #[macro_use]
 extern crate log;
 
+use nix::fcntl::{flock, FlockArg};
 use snafu::ResultExt;
 use std::collections::HashSet;
+use std::fs::File;
 use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
 
 pub mod config;
+pub mod drift;
 pub mod error;
 pub mod service;
 
 pub use error::Error;
 type Result<T> = std::result::Result<T, Error>;
 
+/// The lock held while settings are applied or checked for drift.
+pub const APPLY_LOCKFILE: &str = "/run/lock/thar-be-settings.lock";
+
+/// Waits for the exclusive lock on `APPLY_LOCKFILE`, which is held until the returned file is
+/// dropped.
+pub fn lock_apply() -> Result<File> {
+    let lockfile = File::create(APPLY_LOCKFILE).context(error::ApplyLock {
+        path: APPLY_LOCKFILE,
+    })?;
+    debug!("Waiting for lock on {}", APPLY_LOCKFILE);
+    flock(lockfile.as_raw_fd(), FlockArg::LockExclusive)
+        .map_err(io::Error::from)
+        .context(error::ApplyLock {
+            path: APPLY_LOCKFILE,
+        })?;
+    Ok(lockfile)
+}
+
 /// Read stdin and parse into JSON
 pub fn get_changed_settings() -> Result<HashSet<String>> {
     let mut input = String::new();
@@ -11,7 +11,8 @@ use std::process;
 use std::str::FromStr;
 use tokio::runtime::Runtime;
 
-use thar_be_settings::{config, get_changed_settings, service};
+use thar_be_settings::drift::{DriftReport, DRIFT_REPORT_DIR};
+use thar_be_settings::{config, get_changed_settings, lock_apply, service};
 
 mod error {
     use snafu::Snafu;
@@ -33,12 +34,13 @@ mod error {
 }
 
 /// RunMode represents how thar-be-settings was requested to be run, either handling all
-/// configuration files and services, or handling configuration files and services based on
-/// specific keys given by the user.
+/// configuration files and services, handling configuration files and services based on
+/// specific keys given by the user, or checking all configuration files for drift.
 #[derive(Debug)]
 enum RunMode {
     All,
     SpecificKeys,
+    CheckDrift,
 }
 
 /// Store the args we receive on the command line
@@ -54,7 +56,7 @@ fn usage() -> ! {
     let program_name = env::args().next().unwrap_or_else(|| "program".to_string());
     eprintln!(
         r"Usage: {}
-            [ --all ]
+            [ --all | --check-drift ]
             [ --daemon ]
             [ --socket-path PATH ]
             [ --log-level trace|debug|info|warn|error ]
@@ -64,11 +66,17 @@ fn usage() -> ! {
     will be read from stdin; only files related to those keys will be written,
     and only services related to those keys will be restarted.
 
+    If --check-drift is given, all configuration files will be rendered and
+    compared with the files on disk, and the differences will be reported in
+    {}.  If settings.drift.reapply is true, files that differ
+    will be rewritten and the services that use them will be restarted.
+
     If --daemon is given, thar-be-settings will fork and do its work in a new
     process; this is useful to prevent blocking an API call.
 
     Socket path defaults to {}",
         program_name,
+        DRIFT_REPORT_DIR,
         constants::API_SOCKET,
     );
     process::exit(2);
@@ -92,6 +100,8 @@ fn parse_args(args: env::Args) -> Args {
         match arg.as_ref() {
             "--all" => mode = RunMode::All,
 
+            "--check-drift" => mode = RunMode::CheckDrift,
+
             "--daemon" => daemon = true,
 
             "--log-level" => {
@@ -122,12 +132,13 @@ fn parse_args(args: env::Args) -> Args {
     }
 }
 
-/// Render and write config files to disk.  If `files_limit` is Some, only
-/// write those files, otherwise write all known files.
-async fn write_config_files(
+/// Render config files.  If `files_limit` is Some, only render those files,
+/// otherwise render all known files.
+async fn render_config_files(
     args: &Args,
     files_limit: Option<HashSet<String>>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    settings: model::Model,
+) -> Result<Vec<config::RenderedConfigFile>, Box<dyn std::error::Error>> {
     // Create a vec of ConfigFile structs from the list of changed services
     info!("Requesting configuration file data for affected services");
     let config_files = config::get_affected_config_files(&args.socket_path, files_limit).await?;
@@ -149,17 +160,27 @@ async fn write_config_files(
             })?;
     }
 
-    // Get all settings values for config file templates
-    debug!("Requesting settings values");
-    let settings = schnauzer::get_settings(&args.socket_path).await?;
-
     // Ensure all files render properly
     info!("Rendering config files...");
     let strict = match &args.mode {
         RunMode::SpecificKeys => true,
-        RunMode::All => false,
+        RunMode::All | RunMode::CheckDrift => false,
     };
     let rendered = config::render_config_files(&template_registry, config_files, settings, strict)?;
+    Ok(rendered)
+}
+
+/// Render and write config files to disk.  If `files_limit` is Some, only
+/// write those files, otherwise write all known files.
+async fn write_config_files(
+    args: &Args,
+    files_limit: Option<HashSet<String>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Get all settings values for config file templates
+    debug!("Requesting settings values");
+    let settings = schnauzer::get_settings(&args.socket_path).await?;
+
+    let rendered = render_config_files(args, files_limit, settings).await?;
 
     // If all the config renders properly, write it to disk
     info!("Writing config files to disk...");
@@ -168,12 +189,60 @@ async fn write_config_files(
     Ok(())
 }
 
+/// Compare all config files on disk with their rendered templates, and report the
+/// differences.  If settings.drift.reapply is true, rewrite the files that differ and
+/// restart the services that use them.
+async fn check_drift(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    debug!("Requesting settings values");
+    let settings = schnauzer::get_settings(&args.socket_path).await?;
+    let reapply = settings
+        .settings
+        .as_ref()
+        .and_then(|s| s.drift.as_ref())
+        .and_then(|d| d.reapply)
+        .unwrap_or(false);
+
+    let rendered = render_config_files(args, None, settings).await?;
+    info!("Checking config files for drift...");
+    let drifted = config::find_drift(rendered);
+    let differences = drifted.iter().map(|(_, d)| d.clone()).collect();
+
+    let reapplied = reapply && !drifted.is_empty();
+    if reapplied {
+        let names: HashSet<String> = drifted
+            .iter()
+            .map(|(cfg, _)| cfg.name().to_string())
+            .collect();
+        info!("Rewriting config files that drifted: {:?}", names);
+        config::write_config_files(drifted.into_iter().map(|(cfg, _)| cfg).collect())?;
+
+        let mut services = service::get_affected_services(&args.socket_path, None).await?;
+        services.0.retain(|_, service| {
+            service
+                .model
+                .configuration_files
+                .iter()
+                .any(|file| names.contains(file.as_ref()))
+        });
+        info!("Restarting services that use them...");
+        service::restart_services(services)?;
+    } else if !drifted.is_empty() {
+        warn!("{} config file(s) drifted from settings", drifted.len());
+    }
+
+    DriftReport::new("thar-be-settings", differences, reapplied).write(DRIFT_REPORT_DIR)?;
+    Ok(())
+}
+
 async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
     // SimpleLogger will send errors to stderr and anything less to stdout.
     SimpleLogger::init(args.log_level, LogConfig::default()).context(error::Logger)?;
 
     info!("thar-be-settings started");
 
+    // Keep a drift check from re-applying settings while changes from the API are applied.
+    let _apply_lock = lock_apply()?;
+
     match args.mode {
         RunMode::SpecificKeys => {
             // Get the settings that changed via stdin
@@ -212,6 +281,7 @@ async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
             trace!("Found services: {:?}", services);
             service::restart_services(services)?;
         }
+        RunMode::CheckDrift => check_drift(&args).await?,
     }
 
     Ok(())
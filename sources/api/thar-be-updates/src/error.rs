@@ -101,6 +101,9 @@ pub enum Error {
     #[snafu(display("Failed to start updog: {}", source))]
     Updog { source: std::io::Error },
 
+    #[snafu(display("Failed to download the update with updog"))]
+    DownloadUpdate,
+
     #[snafu(display("Failed to prepare the update with updog"))]
     PrepareUpdate,
 
@@ -110,6 +113,12 @@ pub enum Error {
     #[snafu(display("Failed to deactivate the update with updog"))]
     DeactivateUpdate,
 
+    #[snafu(display("Failed to update automatically with updog"))]
+    AutoUpdate,
+
+    #[snafu(display("Failed to reboot: {}", source))]
+    Reboot { source: std::io::Error },
+
     #[snafu(display("Failed to start signpost: {}", source))]
     Signpost { source: std::io::Error },
 
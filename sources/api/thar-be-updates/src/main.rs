@@ -12,7 +12,13 @@ The parent process immediately returns back to the caller with an exit status of
 The output and status of the command will be written to the update status file.
 This allows the caller to synchronously call thar-be-updates without having to wait for a result to come back.
 
+The `auto-update` command is the exception: it runs in the foreground, and is run by `updog-auto-update.timer` when `settings.updates.mode` is `automatic`.
+It lets updog apply the update it chooses if the maintenance window is open, records the update in the status file as if it had been prepared and activated, and then reboots.
+It's allowed until an update has been activated, and taking the same lock as the other commands keeps it from running alongside an update requested through the API.
+
 thar-be-updates uses a lockfile to control read/write access to the disks and the update status file.
+While a command holds the lock, the update API still serves the status from before the command started.
+For commands that download or write update images, the status also includes their progress, which updog reports in `/run/cache/updog/progress.json`: the current phase (`metadata`, `migrations`, `root`, `boot`, or `hash`), the percent complete, and the throughput.
 
 */
 
@@ -33,7 +39,7 @@ use tempfile::NamedTempFile;
 use thar_be_updates::error;
 use thar_be_updates::error::{Error, Result, TbuErrorStatus};
 use thar_be_updates::status::{
-    get_update_status, UpdateCommand, UpdateState, UpdateStatus, UPDATE_LOCKFILE,
+    get_update_status, UpdateCommand, UpdateImage, UpdateState, UpdateStatus, UPDATE_LOCKFILE,
     UPDATE_STATUS_FILE,
 };
 
@@ -54,10 +60,13 @@ fn usage() -> ! {
             Subcommands:
                 refresh     Query update repository, store the list of available updates,
                             and check if chosen version is available
-                prepare     Download the chosen update and write the update image to the
-                            inactive partition
+                download    Download the chosen update without writing it to disk
+                prepare     Download the chosen update, if not already downloaded, and write
+                            the update image to the inactive partition
                 activate    Marks the inactive partition for boot
                 deactivate  Reverts update activation by marking current active partition for boot
+                auto-update Apply the update updog chooses, if updates are automatic and the
+                            maintenance window is open, and reboot into it
 
             Global options:
                     [ --socket-path PATH ]    Bottlerocket API socket path (default {})
@@ -199,7 +208,32 @@ fn refresh(status: &mut UpdateStatus, socket_path: &str) -> Result<bool> {
     })
 }
 
-/// Prepares the update by downloading and writing the update to the staging partition
+/// Downloads the update so it can be written to the staging partition later without network access
+fn download(status: &mut UpdateStatus) -> Result<()> {
+    fork_and_return!({
+        debug!("Spawning 'updog prepare'");
+        let chosen_update = status
+            .chosen_update()
+            .context(error::UpdateDoesNotExist)?
+            .clone();
+        let output = Command::new("updog")
+            .arg("prepare")
+            .arg("--image")
+            .arg(chosen_update.version().to_string())
+            .output()
+            .context(error::Updog)?;
+        status.set_recent_command_info(UpdateCommand::Download, &output);
+        if !output.status.success() {
+            warn!("Failed to download the update with updog");
+            return error::DownloadUpdate.fail();
+        }
+        status.set_downloaded_update(Some(chosen_update));
+        Ok(())
+    })
+}
+
+/// Prepares the update by downloading and writing the update to the staging partition.  updog
+/// writes a downloaded update from its cache.
 fn prepare(status: &mut UpdateStatus) -> Result<()> {
     fork_and_return!({
         debug!("Spawning 'updog update-image'");
@@ -207,8 +241,12 @@ fn prepare(status: &mut UpdateStatus) -> Result<()> {
             .chosen_update()
             .context(error::UpdateDoesNotExist)?
             .clone();
+        // Ask for the chosen version explicitly so updog only uses a downloaded update if it's
+        // the one we chose.
         let output = Command::new("updog")
             .arg("update-image")
+            .arg("--image")
+            .arg(chosen_update.version().to_string())
             .output()
             .context(error::Updog)?;
         status.set_recent_command_info(UpdateCommand::Prepare, &output);
@@ -216,6 +254,7 @@ fn prepare(status: &mut UpdateStatus) -> Result<()> {
             warn!("Failed to prepare the update with updog");
             return error::PrepareUpdate.fail();
         }
+        status.set_downloaded_update(None);
         status.set_staging_partition_image_info(chosen_update);
         Ok(())
     })
@@ -255,6 +294,46 @@ fn deactivate(status: &mut UpdateStatus) -> Result<()> {
     })
 }
 
+/// Lets updog apply an update if updates are automatic and the maintenance window is open.
+/// Unlike the other commands, this runs in the foreground so the caller can wait for the reboot.
+/// Returns true if an update was written and marked for boot.
+fn auto_update(status: &mut UpdateStatus) -> Result<bool> {
+    debug!("Spawning 'updog auto-update'");
+    let output = Command::new("updog")
+        .arg("auto-update")
+        .arg("--json")
+        .output()
+        .context(error::Updog)?;
+    status.set_recent_command_info(UpdateCommand::AutoUpdate, &output);
+    if !output.status.success() {
+        warn!("Failed to update automatically with updog");
+        return error::AutoUpdate.fail();
+    }
+    // updog only prints the update if it applied one.
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(false);
+    }
+    let update: update_metadata::Update =
+        serde_json::from_slice(&output.stdout).context(error::UpdateInfo)?;
+    status.set_downloaded_update(None);
+    status.set_staging_partition_image_info(UpdateImage::from(update));
+    status.mark_staging_partition_next_to_boot()?;
+    Ok(true)
+}
+
+/// Reboots into the update that `auto_update` applied.
+fn reboot() -> Result<()> {
+    debug!("Rebooting into the update");
+    let status = Command::new("shutdown")
+        .arg("-r")
+        .status()
+        .context(error::Reboot)?;
+    if !status.success() {
+        warn!("shutdown exited with {}", status);
+    }
+    Ok(())
+}
+
 /// Given the update command, this drives the update state machine.
 fn drive_state_machine(
     update_status: &mut UpdateStatus,
@@ -272,14 +351,38 @@ fn drive_state_machine(
                 UpdateState::Idle
             }
         }
+        (UpdateCommand::Refresh, UpdateState::Downloaded) => {
+            let available = refresh(update_status, socket_path)?;
+            if update_status.chosen_update() == update_status.downloaded_update() {
+                UpdateState::Downloaded
+            } else if available {
+                // The chosen update changed, so what we downloaded isn't what would be prepared
+                UpdateState::Available
+            } else {
+                UpdateState::Idle
+            }
+        }
         // Refreshing the list of updates is allowed under every update state
         (UpdateCommand::Refresh, _) => {
             refresh(update_status, socket_path)?;
             // No need to transition state here as we're already beyond `Available`
             update_status.update_state().to_owned()
         }
-        // Preparing the update is allowed when the state is either `Available` or `Staged`
+        // Downloading the update is allowed when the state is either `Available` or `Downloaded`
+        (UpdateCommand::Download, UpdateState::Available)
+        | (UpdateCommand::Download, UpdateState::Downloaded) => {
+            // Make sure the chosen update exists
+            ensure!(
+                update_status.chosen_update().is_some(),
+                error::UpdateDoesNotExist
+            );
+            download(update_status)?;
+            // If we succeed in downloading the update, we transition to `Downloaded`
+            UpdateState::Downloaded
+        }
+        // Preparing the update is allowed when the state is `Available`, `Downloaded`, or `Staged`
         (UpdateCommand::Prepare, UpdateState::Available)
+        | (UpdateCommand::Prepare, UpdateState::Downloaded)
         | (UpdateCommand::Prepare, UpdateState::Staged) => {
             // Make sure the chosen update exists
             ensure!(
@@ -312,6 +415,18 @@ fn drive_state_machine(
             // If we succeed in deactivating the update, we transition to `Staged`
             UpdateState::Staged
         }
+        // Automatic updates are allowed until an update has been activated
+        (UpdateCommand::AutoUpdate, UpdateState::Idle)
+        | (UpdateCommand::AutoUpdate, UpdateState::Available)
+        | (UpdateCommand::AutoUpdate, UpdateState::Downloaded)
+        | (UpdateCommand::AutoUpdate, UpdateState::Staged) => {
+            if auto_update(update_status)? {
+                // updog marked the update for boot, just like `activate`
+                UpdateState::Ready
+            } else {
+                update_status.update_state().to_owned()
+            }
+        }
         // Everything else is disallowed
         _ => {
             return error::DisallowCommand {
@@ -351,7 +466,15 @@ fn run() -> Result<()> {
     // success, so we store the result rather than returning early here.
     let result = drive_state_machine(&mut update_status, &args.subcommand, &args.socket_path);
     write_update_status(&update_status)?;
-    result
+    result?;
+
+    // Only reboot once the status file reflects the update.
+    if args.subcommand == UpdateCommand::AutoUpdate
+        && matches!(update_status.update_state(), UpdateState::Ready)
+    {
+        reboot()?;
+    }
+    Ok(())
 }
 
 fn match_error_to_exit_status(err: Error) -> i32 {
@@ -2,6 +2,8 @@ use crate::error;
 use crate::error::Result;
 use bottlerocket_release::BottlerocketRelease;
 use chrono::{DateTime, Utc};
+use healthdog::{BootHealth, BOOT_HEALTH_PATH};
+use log::warn;
 use model::modeled_types::FriendlyVersion;
 use serde::{Deserialize, Serialize};
 use signpost::State;
@@ -11,6 +13,7 @@ use std::fs::File;
 use std::os::unix::process::ExitStatusExt;
 use std::process::Output;
 use tokio::runtime::Runtime;
+use update_metadata::progress::{Phase, Progress, PROGRESS_PATH};
 
 pub const UPDATE_LOCKFILE: &str = "/run/lock/thar-be-updates.lock";
 pub const UPDATE_STATUS_FILE: &str = "/run/cache/thar-be-updates/status.json";
@@ -19,12 +22,13 @@ pub const UPDATE_STATUS_FILE: &str = "/run/cache/thar-be-updates/status.json";
 pub enum UpdateState {
     Idle,
     Available,
+    Downloaded,
     Staged,
     Ready,
 }
 
 /// UpdateImage represents a Bottlerocket update image
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct UpdateImage {
     arch: String,
     version: semver::Version,
@@ -37,6 +41,16 @@ impl UpdateImage {
     }
 }
 
+impl From<update_metadata::Update> for UpdateImage {
+    fn from(update: update_metadata::Update) -> Self {
+        Self {
+            arch: update.arch,
+            version: update.version,
+            variant: update.variant,
+        }
+    }
+}
+
 /// StagedImage represents a Bottlerocket image that is written to a partition set
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct StagedImage {
@@ -62,9 +76,11 @@ pub enum CommandStatus {
 #[serde(rename_all = "kebab-case")]
 pub enum UpdateCommand {
     Refresh,
+    Download,
     Prepare,
     Activate,
     Deactivate,
+    AutoUpdate,
 }
 
 /// CommandResult represents the result of an issued command
@@ -77,14 +93,46 @@ struct CommandResult {
     stderr: Option<String>,
 }
 
+/// UpdateProgress summarizes updog's progress on the update command that's running
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UpdateProgress {
+    phase: Phase,
+    /// How much of the update's images have been downloaded and written, from 0 to 100
+    percent_complete: f64,
+    /// The average rate of downloading and writing since the command started
+    bytes_per_second: u64,
+    updated: DateTime<Utc>,
+}
+
+impl From<Progress> for UpdateProgress {
+    fn from(progress: Progress) -> Self {
+        Self {
+            phase: progress.phase,
+            // One decimal place is plenty for a progress display.
+            percent_complete: (progress.percent_complete() * 10.0).round() / 10.0,
+            bytes_per_second: progress.bytes_per_second(),
+            updated: progress.updated,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UpdateStatus {
     update_state: UpdateState,
     available_updates: Vec<semver::Version>,
     chosen_update: Option<UpdateImage>,
+    /// The update whose images have been downloaded, ready to be written to the staging partition
+    #[serde(default)]
+    downloaded_update: Option<UpdateImage>,
     active_partition: Option<StagedImage>,
     staging_partition: Option<StagedImage>,
     most_recent_command: Option<CommandResult>,
+    /// healthdog's decision about the most recent boot of an update
+    #[serde(default)]
+    boot_health: Option<BootHealth>,
+    /// The progress of the update command that's running, if it downloads or writes images
+    #[serde(default)]
+    progress: Option<UpdateProgress>,
 }
 
 impl Default for UpdateStatus {
@@ -93,18 +141,34 @@ impl Default for UpdateStatus {
     }
 }
 
-/// Loads and returns the update status from disk.
+/// Loads and returns the update status from disk, along with the boot health record and the
+/// progress of any running update command.
 /// This takes the update lock file as an parameter to signal to caller that the update
-/// lock needs to be obtained before calling this.
+/// lock needs to be obtained before calling this, unless an update command holds it; the status
+/// file is replaced atomically, so it can be read while a command runs.
 pub fn get_update_status(_lockfile: &File) -> Result<UpdateStatus> {
     let status_file = File::open(UPDATE_STATUS_FILE).context(error::NoStatusFile {
         path: UPDATE_STATUS_FILE,
     })?;
-    Ok(
+    let mut status: UpdateStatus =
         serde_json::from_reader(status_file).context(error::StatusParse {
             path: UPDATE_STATUS_FILE,
-        })?,
-    )
+        })?;
+    // healthdog keeps its own record; a missing or unreadable one just means there's no decision
+    // to report.
+    status.boot_health = BootHealth::load(BOOT_HEALTH_PATH).unwrap_or_else(|e| {
+        warn!("Unable to read boot health record: {}", e);
+        None
+    });
+    // updog only keeps a progress file while it's working.
+    status.progress = match Progress::load(PROGRESS_PATH) {
+        Ok(progress) => progress.map(UpdateProgress::from),
+        Err(e) => {
+            warn!("Unable to read update progress: {}", e);
+            None
+        }
+    };
+    Ok(status)
 }
 
 /// Retrieves settings from the API.
@@ -133,9 +197,12 @@ impl UpdateStatus {
             update_state: UpdateState::Idle,
             available_updates: vec![],
             chosen_update: None,
+            downloaded_update: None,
             active_partition: None,
             staging_partition: None,
             most_recent_command: None,
+            boot_health: None,
+            progress: None,
         }
     }
 
@@ -183,6 +250,15 @@ impl UpdateStatus {
         Ok(())
     }
 
+    pub fn downloaded_update(&self) -> Option<&UpdateImage> {
+        self.downloaded_update.as_ref()
+    }
+
+    /// Sets the information of the update whose images have been downloaded
+    pub fn set_downloaded_update(&mut self, image: Option<UpdateImage>) {
+        self.downloaded_update = image;
+    }
+
     /// Sets the staging partition image information
     pub fn set_staging_partition_image_info(&mut self, image: UpdateImage) {
         self.staging_partition = Some(StagedImage {
@@ -281,11 +357,7 @@ impl UpdateStatus {
         if String::from(locked_version.to_owned()) == "latest" {
             // Set chosen_update to the latest version available
             if let Some(latest_update) = UpdateStatus::get_latest_update(updates)? {
-                self.chosen_update = Some(UpdateImage {
-                    arch: latest_update.arch,
-                    version: latest_update.version,
-                    variant: latest_update.variant,
-                });
+                self.chosen_update = Some(UpdateImage::from(latest_update));
                 return Ok(true);
             }
         } else {
@@ -7,6 +7,24 @@ pub const API_SOCKET: &str = "/run/api.sock";
 pub const API_SETTINGS_URI: &str = "/settings";
 pub const API_SETTINGS_GENERATORS_URI: &str = "/metadata/setting-generators";
 
+// Settings whose values shouldn't be shown in logs or audit records.  These follow the Unix shell
+// style pattern outlined here: https://docs.rs/glob/0.3.0/glob/struct.Pattern.html.
+pub const SENSITIVE_SETTINGS_PATTERNS: &[&str] = &[
+    "*.user-data",
+    "settings.kubernetes.bootstrap-token",
+    // Can contain a username:password component
+    "settings.network.https-proxy",
+    // Private keys for client certificates
+    "settings.pki.*.key",
+];
+
+// Where certdog writes the certificates for programs named in `settings.pki.*.consumers`, and the
+// names of the files in each program's directory
+pub const PKI_CONSUMERS_DIR: &str = "/etc/pki/consumers";
+pub const PKI_CA_BUNDLE_FILE: &str = "ca-bundle.crt";
+pub const PKI_CLIENT_CERT_FILE: &str = "client.crt";
+pub const PKI_CLIENT_KEY_FILE: &str = "client.key";
+
 // Shared transaction used by boot time services
 pub const LAUNCH_TRANSACTION: &str = "bottlerocket-launch";
 
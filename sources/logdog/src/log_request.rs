@@ -9,6 +9,7 @@
 //! these provide the list of log requests that `logdog` will run.
 
 use crate::error::{self, Result};
+use constants::SENSITIVE_SETTINGS_PATTERNS;
 use datastore::deserialization::from_map;
 use datastore::serialization::to_pairs;
 use glob::{glob, Pattern};
@@ -27,15 +28,6 @@ const COMMON_REQUESTS: &str = include_str!("../conf/logdog.common.conf");
 /// The `logdog` log requests that are specific to the current variant.
 const VARIANT_REQUESTS: &str = include_str!("../conf/current/logdog.conf");
 
-/// Patterns to filter from settings output.  These follow the Unix shell style pattern outlined
-/// here: https://docs.rs/glob/0.3.0/glob/struct.Pattern.html.
-const SENSITIVE_SETTINGS_PATTERNS: &[&str] = &[
-    "*.user-data",
-    "settings.kubernetes.bootstrap-token",
-    // Can contain a username:password component
-    "settings.network.https-proxy",
-];
-
 /// Returns the list of log requests to run by combining `VARIANT_REQUESTS` and `COMMON_REQUESTS`.
 /// These are read at compile time from files named `logdog.conf` and `logdog.common.conf`
 /// respectively.
@@ -5,6 +5,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 const DEFAULT_CONFIG_PATH: &str = "/etc/metricdog.toml";
+const DEFAULT_DRIFT_REPORT_DIR: &str = "/run/cache/drift";
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct Config {
@@ -15,6 +16,20 @@ pub(crate) struct Config {
     pub(crate) seed: u32,
     pub(crate) version_lock: String,
     pub(crate) ignore_waves: bool,
+    /// The directory where corndog and thar-be-settings report drift from settings.
+    #[serde(default = "default_drift_report_dir")]
+    pub(crate) drift_report_dir: PathBuf,
+    /// Where certdog reports when the certificates in settings expire.
+    #[serde(default = "default_certificate_report_path")]
+    pub(crate) certificate_report_path: PathBuf,
+}
+
+fn default_drift_report_dir() -> PathBuf {
+    PathBuf::from(DEFAULT_DRIFT_REPORT_DIR)
+}
+
+fn default_certificate_report_path() -> PathBuf {
+    PathBuf::from(certdog::CERTIFICATE_REPORT_PATH)
 }
 
 impl Config {
@@ -33,6 +48,7 @@ impl Config {
 #[cfg(test)]
 mod test {
     use crate::config::Config;
+    use std::path::PathBuf;
     use tempfile::TempDir;
 
     // This is what most configs will look like.
@@ -73,6 +89,11 @@ mod test {
         assert_eq!(1234, config.seed);
         assert_eq!("v0.1.2", config.version_lock);
         assert!(!config.ignore_waves);
+        assert_eq!(PathBuf::from("/run/cache/drift"), config.drift_report_dir);
+        assert_eq!(
+            PathBuf::from("/run/cache/certdog/certificates.json"),
+            config.certificate_report_path
+        );
     }
 
     #[test]
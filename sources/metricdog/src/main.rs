@@ -34,6 +34,10 @@ seed the environment with these variables manually.
 
 * `is_healthy`: true or false based on whether critical services are running.
 * `failed_services`: a list of critical services that have failed, if any.
+* `drift`: a list of the programs that found the system drifted from settings, if any, like `corndog` for sysctls or `thar-be-settings` for configuration files.
+  Drift also makes `is_healthy` false.
+* `expiring_certificates`: a list of the `settings.pki` entries whose certificates certdog found expiring or expired, if any.
+  These also make `is_healthy` false.
 
 # Configuration
 
@@ -2,10 +2,15 @@ use crate::config::Config;
 use crate::error::{self, Result};
 use crate::service_check::ServiceCheck;
 use bottlerocket_release::BottlerocketRelease;
-use log::debug;
+use certdog::CertificateReport;
+use log::{debug, warn};
 use reqwest::blocking::Client;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
 use snafu::ResultExt;
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::str::FromStr;
 use std::time::Duration;
 use url::Url;
@@ -114,6 +119,12 @@ impl Metricdog {
     /// notification to the metrics url reporting `is_healthy=true&failed_services=` if all services
     /// are healthy, or `is_healthy=false&failed_services=a:1,b:2` where `a` and `b` are the failed
     /// services, and `1` and `2` are exit codes of the failed services.
+    ///
+    /// Also checks the drift reports in `config.drift_report_dir`, and reports `is_healthy=false`
+    /// and `drift=corndog` if, for example, corndog found sysctls that drifted from settings.
+    /// Likewise, certificates that certdog found expiring or expired are reported as
+    /// `expiring_certificates=a,b`, where `a` and `b` are the names of their `settings.pki`
+    /// entries.
     pub(crate) fn send_health_ping(&self) -> Result<()> {
         let mut is_healthy = true;
         let mut failed_services = Vec::new();
@@ -129,11 +140,24 @@ impl Metricdog {
                 }
             }
         }
+        let drift = drifted_sources(&self.config.drift_report_dir);
+        if !drift.is_empty() {
+            is_healthy = false;
+        }
+        let expiring_certificates = expiring_certificates(&self.config.certificate_report_path);
+        if !expiring_certificates.is_empty() {
+            is_healthy = false;
+        }
         let mut values = HashMap::new();
         values.insert(String::from("is_healthy"), format!("{}", is_healthy));
         // consistent ordering of failed services could be helpful when viewing raw records.
         failed_services.sort();
         values.insert(String::from("failed_services"), failed_services.join(","));
+        values.insert(String::from("drift"), drift.join(","));
+        values.insert(
+            String::from("expiring_certificates"),
+            expiring_certificates.join(","),
+        );
         self.send("metricdog", "health_ping", Some(&values), None)?;
         Ok(())
     }
@@ -156,3 +180,63 @@ impl Metricdog {
         Ok(())
     }
 }
+
+/// The parts of a drift report, as written by corndog and thar-be-settings, that we report.
+#[derive(Deserialize)]
+struct DriftReport {
+    source: String,
+    differences: Vec<IgnoredAny>,
+}
+
+/// Returns the sorted names of the programs whose latest drift report, in `dir`, found differences
+/// from settings.  Reports that can't be read are skipped; no reports means no drift check has run.
+fn drifted_sources(dir: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Unable to read drift reports from {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut sources = Vec::new();
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        let report = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str::<DriftReport>(&s).map_err(|e| e.to_string()));
+        match report {
+            Ok(report) if !report.differences.is_empty() => sources.push(report.source),
+            Ok(_) => {}
+            Err(e) => warn!("Unable to read drift report {}: {}", path.display(), e),
+        }
+    }
+    sources.sort();
+    sources
+}
+
+/// Returns the sorted names of the `settings.pki` entries with certificates that certdog found
+/// expiring or expired.  If the report can't be read, or certdog hasn't checked yet, there are
+/// none.
+fn expiring_certificates(path: &Path) -> Vec<String> {
+    match CertificateReport::read(path) {
+        Ok(Some(report)) => report
+            .unhealthy_names()
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        Ok(None) => Vec::new(),
+        Err(e) => {
+            debug!(
+                "Unable to read certificate report {}: {}",
+                path.display(),
+                e
+            );
+            Vec::new()
+        }
+    }
+}
@@ -4,6 +4,8 @@ use crate::metricdog::Metricdog;
 use crate::service_check::{ServiceCheck, ServiceHealth};
 use bottlerocket_release::BottlerocketRelease;
 use httptest::{matchers::*, responders::*, Expectation, Server};
+use serde_json::json;
+use std::path::PathBuf;
 use tempfile::TempDir;
 
 const OS_RELEASE: &str = r#"NAME=Bottlerocket
@@ -74,6 +76,8 @@ fn send_healthy_ping() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            drift_report_dir: PathBuf::from("/nonexistent"),
+            certificate_report_path: PathBuf::from("/nonexistent"),
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -117,6 +121,114 @@ fn send_unhealthy_ping() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            drift_report_dir: PathBuf::from("/nonexistent"),
+            certificate_report_path: PathBuf::from("/nonexistent"),
+        },
+        os_release(),
+        Box::new(MockCheck {}),
+    )
+    .unwrap();
+    metricdog.send_health_ping().unwrap();
+}
+
+#[test]
+fn send_drifted_ping() {
+    let drift_dir = TempDir::new().unwrap();
+    std::fs::write(
+        drift_dir.path().join("corndog.json"),
+        r#"{"source": "corndog", "checked-at": "2021-08-01T00:00:00Z", "reapplied": false,
+            "differences": [{"type": "sysctl", "key": "a.b", "expected": "1", "actual": "0"}]}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        drift_dir.path().join("thar-be-settings.json"),
+        r#"{"source": "thar-be-settings", "differences": []}"#,
+    )
+    .unwrap();
+
+    let server = Server::run();
+    let matcher = all_of![
+        request::method_path("GET", "/metrics"),
+        request::query(url_decoded(contains(("failed_services", "")))),
+        request::query(url_decoded(contains(("drift", "corndog")))),
+        request::query(url_decoded(contains(("is_healthy", "false")))),
+    ];
+    server.expect(Expectation::matching(matcher).respond_with(status_code(200)));
+    let metrics_url = server.url_str("/metrics");
+    let metricdog = Metricdog::from_parts(
+        Config {
+            metrics_url,
+            send_metrics: true,
+            service_checks: vec![String::from("service_a")],
+            region: String::from("us-east-1"),
+            seed: 2041,
+            version_lock: String::from("latest"),
+            ignore_waves: false,
+            drift_report_dir: drift_dir.path().to_path_buf(),
+            certificate_report_path: PathBuf::from("/nonexistent"),
+        },
+        os_release(),
+        Box::new(MockCheck {}),
+    )
+    .unwrap();
+    metricdog.send_health_ping().unwrap();
+}
+
+/// An entry in certdog's certificate report.
+fn certificate(name: &str, status: &str, days_remaining: i64) -> serde_json::Value {
+    json!({
+        "name": name,
+        "subject": "CN=example",
+        "issuer": "CN=example",
+        "not-after": "2021-09-01T00:00:00Z",
+        "days-remaining": days_remaining,
+        "status": status,
+    })
+}
+
+#[test]
+fn send_expiring_certificates_ping() {
+    let report_dir = TempDir::new().unwrap();
+    let report_path = report_dir.path().join("certificates.json");
+    std::fs::write(
+        &report_path,
+        serde_json::to_string(&json!({
+            "checked-at": "2021-08-01T00:00:00Z",
+            "threshold-days": 30,
+            "certificates": [
+                certificate("registry", "expiring", 3),
+                certificate("registry", "valid", 300),
+                certificate("old-ca", "expired", -3),
+                certificate("proxy", "valid", 300),
+            ],
+        }))
+        .unwrap(),
+    )
+    .unwrap();
+
+    let server = Server::run();
+    let matcher = all_of![
+        request::method_path("GET", "/metrics"),
+        request::query(url_decoded(contains(("failed_services", "")))),
+        request::query(url_decoded(contains((
+            "expiring_certificates",
+            "old-ca,registry"
+        )))),
+        request::query(url_decoded(contains(("is_healthy", "false")))),
+    ];
+    server.expect(Expectation::matching(matcher).respond_with(status_code(200)));
+    let metrics_url = server.url_str("/metrics");
+    let metricdog = Metricdog::from_parts(
+        Config {
+            metrics_url,
+            send_metrics: true,
+            service_checks: vec![String::from("service_a")],
+            region: String::from("us-east-1"),
+            seed: 2041,
+            version_lock: String::from("latest"),
+            ignore_waves: false,
+            drift_report_dir: PathBuf::from("/nonexistent"),
+            certificate_report_path: report_path,
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -153,6 +265,8 @@ fn send_boot_success() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            drift_report_dir: PathBuf::from("/nonexistent"),
+            certificate_report_path: PathBuf::from("/nonexistent"),
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -4,8 +4,9 @@ use std::collections::HashMap;
 
 use crate::modeled_types::Identifier;
 use crate::{
-    AwsSettings, BootstrapContainer, ECSSettings, HostContainer, KernelSettings, MetricsSettings,
-    NetworkSettings, NtpSettings, PemCertificate, RegistrySettings, UpdatesSettings,
+    AwsSettings, BootSettings, BootstrapContainer, CertificateExpirySettings, DnsSettings,
+    DriftSettings, ECSSettings, HostContainer, KernelSettings, MetricsSettings, NetworkSettings,
+    NtpSettings, PemCertificate, RegistrySettings, UpdatesSettings,
 };
 
 // Note: we have to use 'rename' here because the top-level Settings structure is the only one
@@ -18,10 +19,14 @@ struct Settings {
     bootstrap_containers: HashMap<Identifier, BootstrapContainer>,
     ntp: NtpSettings,
     network: NetworkSettings,
+    dns: DnsSettings,
     kernel: KernelSettings,
+    boot: BootSettings,
+    drift: DriftSettings,
     aws: AwsSettings,
     ecs: ECSSettings,
     metrics: MetricsSettings,
     pki: HashMap<Identifier, PemCertificate>,
+    certificate_expiry: CertificateExpirySettings,
     container_registry: RegistrySettings,
 }
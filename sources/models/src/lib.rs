@@ -49,6 +49,11 @@ The `#[model]` attribute on Settings and its sub-structs reduces duplication and
 * [Model](src/aws-dev/mod.rs)
 * [Default settings](src/aws-dev/defaults.d/)
 
+## metal-dev: Bare metal development build
+
+* [Model](src/metal-dev/mod.rs)
+* [Default settings](src/metal-dev/defaults.d/)
+
 ## vmware-dev: VMware development build
 
 * [Model](src/vmware-dev/mod.rs)
@@ -110,13 +115,15 @@ use std::net::IpAddr;
 
 use crate::de::deserialize_mirrors;
 use crate::modeled_types::{
-    BootstrapContainerMode, CpuManagerPolicy, DNSDomain, ECSAgentLogLevel, ECSAttributeKey,
-    ECSAttributeValue, FriendlyVersion, Identifier, KubernetesAuthenticationMode,
-    KubernetesBootstrapToken, KubernetesCloudProvider, KubernetesClusterName,
-    KubernetesDurationValue, KubernetesEvictionHardKey, KubernetesLabelKey, KubernetesLabelValue,
-    KubernetesQuantityValue, KubernetesReservedResourceKey, KubernetesTaintValue,
-    KubernetesThresholdValue, Lockdown, PemCertificateString, SingleLineString, SysctlKey,
-    TopologyManagerPolicy, TopologyManagerScope, Url, ValidBase64, ValidLinuxHostname,
+    BondMode, BootstrapContainerMode, CpuManagerPolicy, CronSchedule, DNSDomain, ECSAgentLogLevel,
+    ECSAttributeKey, ECSAttributeValue, FriendlyVersion, Identifier, InterfaceMode, InterfaceName,
+    IpCidr, KernelParameter, KmodKey, KubernetesAuthenticationMode, KubernetesBootstrapToken,
+    KubernetesCloudProvider, KubernetesClusterName, KubernetesDurationValue,
+    KubernetesEvictionHardKey, KubernetesLabelKey, KubernetesLabelValue, KubernetesQuantityValue,
+    KubernetesReservedResourceKey, KubernetesTaintValue, KubernetesThresholdValue, Lockdown,
+    MaintenanceWindowMinutes, PemCertificateString, PemPrivateKeyString, PkiConsumer,
+    ResolverAttempts, ResolverNdots, ResolverTimeout, SingleLineString, SysctlKey,
+    TopologyManagerPolicy, TopologyManagerScope, UpdateMode, Url, ValidBase64, ValidLinuxHostname,
 };
 
 // Kubernetes static pod manifest settings
@@ -204,6 +211,35 @@ struct UpdatesSettings {
     // Version to update to when updating via the API.
     version_lock: FriendlyVersion,
     ignore_waves: bool,
+    // A local copy of the update repository, such as an offline bundle on mounted media, used
+    // instead of the base URLs.
+    local_repository: SingleLineString,
+    // Whether updates are applied automatically, only when requested, or not at all.
+    mode: UpdateMode,
+    maintenance_window: MaintenanceWindow,
+    // Versions that are never chosen as updates.
+    denylist: Vec<FriendlyVersion>,
+    boot_health: BootHealthSettings,
+}
+
+// Checks that the first boot of an update must pass before it's marked successful, rather than
+// rolled back.  The service checks default to the metrics service checks.
+#[model]
+struct BootHealthSettings {
+    enabled: bool,
+    service_checks: Vec<String>,
+    check_api: bool,
+    check_registration: bool,
+    deadline_seconds: u32,
+    max_boot_attempts: u32,
+}
+
+// The time during which automatic updates may start: a cron-style schedule for the start of the
+// window, in UTC, and its length.
+#[model]
+struct MaintenanceWindow {
+    start: CronSchedule,
+    duration_minutes: MaintenanceWindowMinutes,
 }
 
 #[model]
@@ -221,6 +257,60 @@ struct NetworkSettings {
     https_proxy: Url,
     // We allow some flexibility in NO_PROXY values because different services support different formats.
     no_proxy: Vec<SingleLineString>,
+    interfaces: HashMap<InterfaceName, NetworkInterface>,
+}
+
+// Configuration of a network interface; netdog renders wicked configuration from these.  Static
+// addresses and routes are added in any mode; the mode decides any dynamic addressing.
+#[model]
+struct NetworkInterface {
+    mode: InterfaceMode,
+    // The primary interface's address is used as the node IP.
+    primary: bool,
+    addresses: Vec<IpCidr>,
+    gateway: IpAddr,
+    routes: Vec<StaticRoute>,
+    name_servers: Vec<IpAddr>,
+    vlan: VlanSettings,
+    bond: BondSettings,
+}
+
+#[model]
+struct StaticRoute {
+    to: IpCidr,
+    via: IpAddr,
+    metric: u32,
+}
+
+// Makes the interface a VLAN on the given device.
+#[model]
+struct VlanSettings {
+    device: InterfaceName,
+    id: u16,
+}
+
+// Makes the interface a bond of the given member devices.
+#[model]
+struct BondSettings {
+    mode: BondMode,
+    members: Vec<InterfaceName>,
+}
+
+// DNS settings.  netdog merges these with DNS information from the primary interface when it
+// writes resolv.conf; name servers and search domains given here replace those from DHCP.
+#[model]
+struct DnsSettings {
+    name_servers: Vec<IpAddr>,
+    search_list: Vec<DNSDomain>,
+    options: ResolverOptions,
+}
+
+// Resolver options, as described in resolv.conf(5).
+#[model]
+struct ResolverOptions {
+    ndots: ResolverNdots,
+    timeout: ResolverTimeout,
+    attempts: ResolverAttempts,
 }
 
 // NTP settings
@@ -235,6 +325,28 @@ struct KernelSettings {
     lockdown: Lockdown,
     // Values are almost always a single line and often just an integer... but not always.
     sysctl: HashMap<SysctlKey, String>,
+    modules: HashMap<KmodKey, KernelModule>,
+}
+
+// Kernel module settings.  corndog loads modules marked for autoload, and modules that aren't
+// allowed are blocked through modprobe.d.
+#[model]
+struct KernelModule {
+    allowed: bool,
+    autoload: bool,
+}
+
+// Boot settings.  corndog saves these for the bootloader, so they take effect on the next boot.
+#[model]
+struct BootSettings {
+    kernel_parameters: Vec<KernelParameter>,
+}
+
+// Drift detection settings.  The periodic drift check always reports differences between settings
+// and the system; this decides whether it also re-applies settings to fix them.
+#[model]
+struct DriftSettings {
+    reapply: bool,
 }
 
 // Platform-specific settings
@@ -243,6 +355,12 @@ struct AwsSettings {
     region: SingleLineString,
 }
 
+// The instance ID from a NoCloud volume or config drive, for bare metal and local VMs.
+#[model]
+struct MetalSettings {
+    instance_id: SingleLineString,
+}
+
 // Metrics settings
 #[model]
 struct MetricsSettings {
@@ -296,8 +414,19 @@ struct BootstrapContainer {
 }
 
 ///// PEM Certificates
+// Entries with consumers are only given to those programs, not added to the system trust store.
+// An entry with a key is a client certificate for its consumers to present.
 #[model]
 struct PemCertificate {
     data: PemCertificateString,
     trusted: bool,
+    key: PemPrivateKeyString,
+    consumers: Vec<PkiConsumer>,
+}
+
+// Certificate expiry monitoring.  certdog reports certificates from `settings.pki` that expire
+// within this many days, and they make the health check fail.
+#[model]
+struct CertificateExpirySettings {
+    threshold_days: u32,
 }
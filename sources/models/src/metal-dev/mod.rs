@@ -0,0 +1,31 @@
+use model_derive::model;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::modeled_types::Identifier;
+use crate::{
+    BootSettings, BootstrapContainer, CertificateExpirySettings, DnsSettings, DriftSettings,
+    HostContainer, KernelSettings, MetalSettings, MetricsSettings, NetworkSettings, NtpSettings,
+    PemCertificate, RegistrySettings, UpdatesSettings,
+};
+
+// Note: we have to use 'rename' here because the top-level Settings structure is the only one
+// that uses its name in serialization; internal structures use the field name that points to it
+#[model(rename = "settings", impl_default = true)]
+struct Settings {
+    motd: String,
+    updates: UpdatesSettings,
+    host_containers: HashMap<Identifier, HostContainer>,
+    bootstrap_containers: HashMap<Identifier, BootstrapContainer>,
+    ntp: NtpSettings,
+    network: NetworkSettings,
+    dns: DnsSettings,
+    kernel: KernelSettings,
+    boot: BootSettings,
+    drift: DriftSettings,
+    metrics: MetricsSettings,
+    pki: HashMap<Identifier, PemCertificate>,
+    certificate_expiry: CertificateExpirySettings,
+    container_registry: RegistrySettings,
+    metal: MetalSettings,
+}
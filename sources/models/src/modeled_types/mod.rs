@@ -61,12 +61,47 @@ pub mod error {
         #[snafu(display("Invalid hostname '{}': {}", input, msg))]
         InvalidLinuxHostname { input: String, msg: String },
 
+        #[snafu(display("Invalid network interface name '{}': {}", input, msg))]
+        InvalidInterfaceName { input: String, msg: String },
+
+        #[snafu(display("Invalid network interface mode '{}'", input))]
+        InvalidInterfaceMode { input: String },
+
+        #[snafu(display("Invalid bond mode '{}'", input))]
+        InvalidBondMode { input: String },
+
+        #[snafu(display("Invalid CIDR '{}': {}", input, msg))]
+        InvalidCidr { input: String, msg: String },
+
+        #[snafu(display("Invalid resolver option {} '{}': maximum is {}", option, input, max))]
+        InvalidResolverOption { option: String, input: u8, max: u8 },
+
+        #[snafu(display("Invalid update mode '{}'", input))]
+        InvalidUpdateMode { input: String },
+
+        #[snafu(display("Invalid cron schedule '{}': {}", input, msg))]
+        InvalidCronSchedule { input: String, msg: String },
+
+        #[snafu(display(
+            "Invalid maintenance window length {} minutes: must be between {} and {}",
+            input,
+            min,
+            max
+        ))]
+        InvalidMaintenanceWindow { input: u32, min: u32, max: u32 },
+
         #[snafu(display("Invalid Linux lockdown mode '{}'", input))]
         InvalidLockdown { input: String },
 
         #[snafu(display("Invalid sysctl key '{}': {}", input, msg))]
         InvalidSysctlKey { input: String, msg: String },
 
+        #[snafu(display("Invalid kernel module name '{}': {}", input, msg))]
+        InvalidKmodKey { input: String, msg: String },
+
+        #[snafu(display("Invalid kernel parameter '{}': {}", input, msg))]
+        InvalidKernelParameter { input: String, msg: String },
+
         #[snafu(display("Invalid input for field {}: {}", field, source))]
         InvalidPlainValue {
             field: String,
@@ -102,6 +137,12 @@ pub mod error {
         #[snafu(display("No valid certificate found in bundle"))]
         NoCertificatesFound {},
 
+        #[snafu(display("Invalid private key: {}", msg))]
+        InvalidPrivateKey { msg: String },
+
+        #[snafu(display("Invalid PKI consumer '{}': {}", input, msg))]
+        InvalidPkiConsumer { input: String, msg: String },
+
         #[snafu(display("Invalid topology manager scope '{}'", input))]
         InvalidTopologyManagerScope {
             input: String,
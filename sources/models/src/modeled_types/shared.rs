@@ -5,10 +5,11 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use super::error;
 use semver::Version;
 use serde::de::Error as _;
-use snafu::{ensure, ResultExt};
+use snafu::{ensure, OptionExt, ResultExt};
 use std::borrow::Borrow;
 use std::convert::TryFrom;
 use std::fmt;
+use std::net::IpAddr;
 use std::ops::Deref;
 use std::str::FromStr;
 use url::Host;
@@ -466,6 +467,308 @@ mod test_version {
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
+/// UpdateMode represents a string that is a valid update policy:
+/// * "automatic": updates are applied and the host rebooted without a request, during the
+///   maintenance window if there is one
+/// * "managed": updates are only applied when requested
+/// * "disabled": updates aren't applied, and checks report them as skipped
+///
+/// It stores the original string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct UpdateMode {
+    inner: String,
+}
+
+impl TryFrom<&str> for UpdateMode {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        ensure!(
+            matches!(input, "automatic" | "managed" | "disabled"),
+            error::InvalidUpdateMode { input }
+        );
+        Ok(UpdateMode {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(UpdateMode, "UpdateMode");
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// CronSchedule represents a string that is a cron-style schedule with five fields: minute (0-59),
+/// hour (0-23), day of the month (1-31), month (1-12), and day of the week (0-7, where both 0 and
+/// 7 are Sunday).  Each field is '*' or a comma-separated list of values and ranges like '1-5',
+/// either of which can be followed by a step like '/15'.  Names of months and days aren't
+/// supported.
+///
+/// As in cron, if both the day of the month and the day of the week are restricted, a time
+/// matches if either of them matches.  CronSchedule stores the original string and makes it
+/// accessible through standard traits, and can check whether a given time matches it.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct CronSchedule {
+    inner: String,
+    // Bit N is set if the value N matches the field.
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// Returns whether the given time matches the schedule.  `weekday` counts from Sunday as 0.
+    pub fn matches(&self, minute: u32, hour: u32, day: u32, month: u32, weekday: u32) -> bool {
+        let has = |field: u64, value: u32| value < 64 && field & (1 << value) != 0;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, _) | (_, true) => has(self.days, day) && has(self.weekdays, weekday),
+            (false, false) => has(self.days, day) || has(self.weekdays, weekday),
+        };
+        has(self.minutes, minute) && has(self.hours, hour) && has(self.months, month) && day_matches
+    }
+
+    /// Parses one field into a bit set of the values it matches, given the range of valid values.
+    fn parse_field(input: &str, field: &str, min: u32, max: u32) -> Result<u64, error::Error> {
+        let invalid = |msg: String| error::InvalidCronSchedule { input, msg }.build();
+        let mut bits = 0;
+        for item in field.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (
+                    range,
+                    step.parse::<u32>()
+                        .ok()
+                        .filter(|step| *step > 0)
+                        .ok_or_else(|| invalid(format!("invalid step in '{}'", item)))?,
+                ),
+                None => (item, 1),
+            };
+            let value = |s: &str| {
+                s.parse::<u32>()
+                    .ok()
+                    .filter(|v| (min..=max).contains(v))
+                    .ok_or_else(|| {
+                        invalid(format!("'{}' must be a number from {} to {}", s, min, max))
+                    })
+            };
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((start, end)) = range.split_once('-') {
+                (value(start)?, value(end)?)
+            } else if step > 1 {
+                // As in cron, a single value with a step runs to the end of the range.
+                (value(range)?, max)
+            } else {
+                let v = value(range)?;
+                (v, v)
+            };
+            ensure!(
+                start <= end,
+                error::InvalidCronSchedule {
+                    input,
+                    msg: format!("range '{}' is backwards", range),
+                }
+            );
+            for v in (start..=end).step_by(step as usize) {
+                bits |= 1 << v;
+            }
+        }
+        Ok(bits)
+    }
+}
+
+impl TryFrom<&str> for CronSchedule {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        let fields: Vec<&str> = input.split_whitespace().collect();
+        ensure!(
+            fields.len() == 5,
+            error::InvalidCronSchedule {
+                input,
+                msg: "must have five fields: minute, hour, day of month, month, day of week",
+            }
+        );
+        let mut weekdays = Self::parse_field(input, fields[4], 0, 7)?;
+        // Both 0 and 7 mean Sunday.
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(CronSchedule {
+            inner: input.to_string(),
+            minutes: Self::parse_field(input, fields[0], 0, 59)?,
+            hours: Self::parse_field(input, fields[1], 0, 23)?,
+            days: Self::parse_field(input, fields[2], 1, 31)?,
+            months: Self::parse_field(input, fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+}
+
+string_impls_for!(CronSchedule, "CronSchedule");
+
+#[cfg(test)]
+mod test_cron_schedule {
+    use super::CronSchedule;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_cron_schedule() {
+        for ok in &[
+            "* * * * *",
+            "0 2 * * *",
+            "30 1 * * 0",
+            "*/15 0-6 1,15 * 1-5",
+            "0 22 * 1-12/3 7",
+            "5/10 * * * *",
+        ] {
+            CronSchedule::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_cron_schedule() {
+        for err in &[
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "5-1 * * * *",
+            "*/0 * * * *",
+            "a * * * *",
+            "1,,2 * * * *",
+            "* * * jan *",
+        ] {
+            CronSchedule::try_from(*err).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn cron_schedule_matches() {
+        // 02:30 on Saturdays and Sundays.
+        let weekend = CronSchedule::try_from("30 2 * * 6,7").unwrap();
+        assert!(weekend.matches(30, 2, 14, 3, 0));
+        assert!(weekend.matches(30, 2, 13, 3, 6));
+        assert!(!weekend.matches(30, 2, 12, 3, 5));
+        assert!(!weekend.matches(31, 2, 13, 3, 6));
+
+        // Every 20 minutes during the first hour of the first of the month or of Mondays.
+        let either = CronSchedule::try_from("*/20 0 1 * 1").unwrap();
+        assert!(either.matches(40, 0, 1, 6, 3));
+        assert!(either.matches(0, 0, 9, 6, 1));
+        assert!(!either.matches(0, 0, 9, 6, 2));
+        assert!(!either.matches(10, 0, 1, 6, 3));
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// MaintenanceWindowMinutes is the length of a maintenance window in minutes.  Automatic updates
+/// are checked for every 10 minutes, plus a randomized delay of up to 2, so a window must be at
+/// least 15 minutes long for a check to always fall inside it.  A window longer than a week would
+/// always be open.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct MaintenanceWindowMinutes {
+    inner: u32,
+}
+
+impl MaintenanceWindowMinutes {
+    pub const MIN: u32 = 15;
+    pub const MAX: u32 = 7 * 24 * 60;
+}
+
+impl TryFrom<u32> for MaintenanceWindowMinutes {
+    type Error = error::Error;
+
+    fn try_from(input: u32) -> Result<Self, error::Error> {
+        ensure!(
+            (Self::MIN..=Self::MAX).contains(&input),
+            error::InvalidMaintenanceWindow {
+                input,
+                min: Self::MIN,
+                max: Self::MAX,
+            }
+        );
+        Ok(MaintenanceWindowMinutes { inner: input })
+    }
+}
+
+impl<'de> Deserialize<'de> for MaintenanceWindowMinutes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let original = u32::deserialize(deserializer)?;
+        Self::try_from(original).map_err(|e| {
+            D::Error::custom(format!(
+                "Unable to deserialize into MaintenanceWindowMinutes: {}",
+                e
+            ))
+        })
+    }
+}
+
+impl Serialize for MaintenanceWindowMinutes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u32(self.inner)
+    }
+}
+
+impl Deref for MaintenanceWindowMinutes {
+    type Target = u32;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl fmt::Display for MaintenanceWindowMinutes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl From<MaintenanceWindowMinutes> for u32 {
+    fn from(x: MaintenanceWindowMinutes) -> Self {
+        x.inner
+    }
+}
+
+#[cfg(test)]
+mod test_maintenance_window_minutes {
+    use super::MaintenanceWindowMinutes;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn good_values() {
+        for ok in &[15, 60, 10080] {
+            MaintenanceWindowMinutes::try_from(*ok).unwrap();
+        }
+        let minutes: MaintenanceWindowMinutes = serde_plain::from_str("90").unwrap();
+        assert_eq!(*minutes, 90);
+    }
+
+    #[test]
+    fn bad_values() {
+        for err in &[0, 10, 14, 10081] {
+            MaintenanceWindowMinutes::try_from(*err).unwrap_err();
+        }
+        serde_plain::from_str::<MaintenanceWindowMinutes>("5").unwrap_err();
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
 /// DNSDomain represents a string that is a valid DNS domain. It stores the
 /// original string and makes it accessible through standard traits. Its purpose
 /// is input validation, for example validating the kubelet's "clusterDomain"
@@ -672,6 +975,228 @@ string_impls_for!(Lockdown, "Lockdown");
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
+/// KmodKey represents a string that is a valid Linux kernel module name, as given to modprobe.
+/// KmodKey stores the original string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct KmodKey {
+    inner: String,
+}
+
+lazy_static! {
+    /// Pattern matching the name of a kernel module.  The kernel limits names to a little under 64
+    /// characters; modprobe treats '-' and '_' the same.
+    pub(crate) static ref KMOD_KEY: Regex = Regex::new(r"^[a-zA-Z0-9_-]{1,64}$").unwrap();
+}
+
+impl TryFrom<&str> for KmodKey {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        ensure!(
+            KMOD_KEY.is_match(input),
+            error::InvalidKmodKey {
+                input,
+                msg: format!("must match pattern {}", *KMOD_KEY),
+            }
+        );
+        Ok(KmodKey {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(KmodKey, "KmodKey");
+
+#[cfg(test)]
+mod test_kmod_key {
+    use super::KmodKey;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_kmod_key() {
+        for ok in &[
+            "ip_vs",
+            "br_netfilter",
+            "sctp",
+            "nf-conntrack",
+            "a",
+            &"a".repeat(64),
+        ] {
+            KmodKey::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_kmod_key() {
+        for err in &[
+            "",
+            &"a".repeat(65),
+            "../sctp",
+            "ip vs",
+            "sctp\n",
+            "nf.conntrack",
+            "$",
+        ] {
+            KmodKey::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// KernelParameter represents a single parameter for the kernel command line, like "nosmt" or
+/// "console=ttyS1,115200n8".  It stores the original string and makes it accessible through
+/// standard traits.
+///
+/// Parameters are written to the boot configuration and read by the bootloader, so we only allow
+/// characters that can't be interpreted by it, and we refuse to let users override the parameters
+/// that every Bottlerocket image sets for verified boot and SELinux.  Variant-specific parameters
+/// are checked by corndog when it writes the boot configuration.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct KernelParameter {
+    inner: String,
+}
+
+lazy_static! {
+    /// Pattern matching a kernel parameter: a name, optionally followed by '=' and a value.
+    pub(crate) static ref KERNEL_PARAMETER: Regex =
+        Regex::new(r"^[a-zA-Z0-9][a-zA-Z0-9_.-]*(=[a-zA-Z0-9_.,:/=+@%-]*)?$").unwrap();
+}
+
+/// Parameters set by the bootloader configuration of every image, or that would weaken its
+/// security or change how it boots, which users may not set.
+/// The kernel treats '-' and '_' in parameter names the same, so these are listed with '_'.
+const RESERVED_KERNEL_PARAMETERS: &[&str] = &[
+    "biosdevname",
+    "enforcing",
+    "init",
+    "lockdown",
+    "lsm",
+    "module.sig_enforce",
+    "net.ifnames",
+    "raid",
+    "random.trust_cpu",
+    "rdinit",
+    "ro",
+    "root",
+    "rootwait",
+    "rw",
+    "security",
+    "selinux",
+    "single",
+];
+
+/// Families of parameters that users may not set: those for the dm-verity root filesystem, and
+/// those that configure systemd, which could skip units or start a debug shell.
+const RESERVED_KERNEL_PARAMETER_PREFIXES: &[&str] =
+    &["dm_mod.", "dm_verity.", "rd.systemd.", "systemd."];
+
+impl KernelParameter {
+    /// Returns the name of the parameter, without any value, with '-' normalized to '_' the way
+    /// the kernel compares parameter names.
+    pub fn normalized_name(&self) -> String {
+        self.inner
+            .split('=')
+            .next()
+            .unwrap_or_default()
+            .replace('-', "_")
+    }
+}
+
+impl TryFrom<&str> for KernelParameter {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        ensure!(
+            input.len() <= 256,
+            error::InvalidKernelParameter {
+                input,
+                msg: "must be no more than 256 characters",
+            }
+        );
+        ensure!(
+            KERNEL_PARAMETER.is_match(input),
+            error::InvalidKernelParameter {
+                input,
+                msg: format!("must match pattern {}", *KERNEL_PARAMETER),
+            }
+        );
+        let parameter = KernelParameter {
+            inner: input.to_string(),
+        };
+        let name = parameter.normalized_name();
+        ensure!(
+            !RESERVED_KERNEL_PARAMETERS.contains(&name.as_str())
+                && !RESERVED_KERNEL_PARAMETER_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix)),
+            error::InvalidKernelParameter {
+                input,
+                msg: format!("'{}' is set by Bottlerocket and can't be changed", name),
+            }
+        );
+        Ok(parameter)
+    }
+}
+
+string_impls_for!(KernelParameter, "KernelParameter");
+
+#[cfg(test)]
+mod test_kernel_parameter {
+    use super::KernelParameter;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_kernel_parameter() {
+        for ok in &[
+            "nosmt",
+            "console=ttyS1,115200n8",
+            "crashkernel=256M@16M",
+            "isolcpus=1-3,5",
+            "cgroup_no_v1=all",
+            "module_blacklist=sctp",
+            "nvme_core.io_timeout=4294967295",
+            "empty=",
+        ] {
+            KernelParameter::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_kernel_parameter() {
+        for err in &[
+            "",
+            "=value",
+            "two params",
+            "quote=\"x\"",
+            "var=${x}",
+            "semi;colon",
+            "--",
+            &"a".repeat(257),
+            // Reserved, including spellings the kernel treats the same
+            "root=/dev/sda1",
+            "selinux=0",
+            "enforcing=0",
+            "init=/bin/sh",
+            "dm-mod.create=x",
+            "dm_verity.error_behavior=0",
+            "rw",
+            "lsm=lockdown",
+            "security=none",
+            "module.sig_enforce=0",
+            "lockdown=none",
+            "systemd.unit=emergency.target",
+            "systemd.debug_shell",
+            "systemd.unified_cgroup_hierarchy=0",
+            "rd.systemd.unit=emergency.target",
+        ] {
+            KernelParameter::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct BootstrapContainerMode {
     inner: String,
@@ -798,3 +1323,639 @@ mod test_valid_pem_certificate_string {
         .is_err())
     }
 }
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// PemPrivateKeyString represents base64-encoded PEM containing a single private key, to be used
+/// with the client certificate in the same `settings.pki` entry.  Like PemCertificateString, an
+/// empty string is valid so that a key can be removed.  PemPrivateKeyString stores the original
+/// string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PemPrivateKeyString {
+    inner: String,
+}
+
+impl TryFrom<&str> for PemPrivateKeyString {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        if input.trim().is_empty() {
+            return Ok(PemPrivateKeyString {
+                inner: input.to_string(),
+            });
+        }
+        let decoded_bytes = base64::decode(input).context(error::InvalidBase64)?;
+        let mut keys_found = 0;
+        for pem in x509_parser::pem::Pem::iter_from_buffer(&decoded_bytes) {
+            let pem = pem.context(error::InvalidPEM)?;
+            // The parser only keeps the first word of the label, so this covers PKCS#8 "PRIVATE
+            // KEY" and the older "RSA PRIVATE KEY" and "EC PRIVATE KEY".  Encrypted keys can't be
+            // used without a passphrase, so we don't accept them.
+            ensure!(
+                ["PRIVATE", "RSA", "EC"].contains(&pem.label.as_str()),
+                error::InvalidPrivateKey {
+                    msg: format!("unexpected PEM object '{}'", pem.label),
+                }
+            );
+            keys_found += 1;
+        }
+        ensure!(
+            keys_found == 1,
+            error::InvalidPrivateKey {
+                msg: format!("expected one private key, found {}", keys_found),
+            }
+        );
+
+        Ok(PemPrivateKeyString {
+            inner: input.to_string(),
+        })
+    }
+}
+
+impl Default for PemPrivateKeyString {
+    fn default() -> Self {
+        PemPrivateKeyString {
+            inner: "".to_string(),
+        }
+    }
+}
+
+string_impls_for!(PemPrivateKeyString, "PemPrivateKeyString");
+
+#[cfg(test)]
+mod test_valid_pem_private_key_string {
+    use super::PemPrivateKeyString;
+    use std::convert::TryFrom;
+
+    static TEST_PEM: &str = include_str!("../../tests/data/test-pem");
+    static TEST_KEY: &str = include_str!("../../tests/data/test-key");
+
+    #[test]
+    fn valid_pem_private_key() {
+        assert!(PemPrivateKeyString::try_from(TEST_KEY).is_ok());
+        assert!(PemPrivateKeyString::try_from("").is_ok());
+    }
+
+    #[test]
+    fn invalid_pem_private_key() {
+        // Certificates aren't keys
+        assert!(PemPrivateKeyString::try_from(TEST_PEM).is_err());
+        // Two keys
+        let two_keys = base64::encode(
+            [
+                base64::decode(TEST_KEY).unwrap(),
+                base64::decode(TEST_KEY).unwrap(),
+            ]
+            .concat(),
+        );
+        assert!(PemPrivateKeyString::try_from(two_keys.as_str()).is_err());
+        assert!(PemPrivateKeyString::try_from("not base64!").is_err());
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// PkiConsumer names a program that uses a `settings.pki` entry on its own, rather than through
+/// the system trust store: "kubelet", "ecs", or "registry:" followed by the host of a container
+/// registry, with an optional port, like "registry:registry.example.com:5000".
+/// PkiConsumer stores the original string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PkiConsumer {
+    inner: String,
+}
+
+const REGISTRY_CONSUMER_PREFIX: &str = "registry:";
+
+impl TryFrom<&str> for PkiConsumer {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        if input != "kubelet" && input != "ecs" {
+            let registry = input.strip_prefix(REGISTRY_CONSUMER_PREFIX).context(
+                error::InvalidPkiConsumer {
+                    input,
+                    msg: "must be 'kubelet', 'ecs', or 'registry:<host>'",
+                },
+            )?;
+            // The host is used in file paths and containerd's configuration, so it can't be
+            // anything but a host and port.
+            let (host, port) = match registry.rsplit_once(':') {
+                // A bracketed IPv6 address without a port
+                Some(_) if registry.ends_with(']') => (registry, None),
+                Some((host, port)) => (host, Some(port)),
+                None => (registry, None),
+            };
+            ensure!(
+                Host::parse(host).is_ok() && !host.starts_with('.') && !host.contains('/'),
+                error::InvalidPkiConsumer {
+                    input,
+                    msg: format!("invalid registry host '{}'", host),
+                }
+            );
+            if let Some(port) = port {
+                ensure!(
+                    port.parse::<u16>().is_ok(),
+                    error::InvalidPkiConsumer {
+                        input,
+                        msg: format!("invalid registry port '{}'", port),
+                    }
+                );
+            }
+        }
+        Ok(PkiConsumer {
+            inner: input.to_string(),
+        })
+    }
+}
+
+impl PkiConsumer {
+    /// Returns the registry host, and port if given, for a registry consumer.
+    pub fn registry_host(&self) -> Option<&str> {
+        self.inner.strip_prefix(REGISTRY_CONSUMER_PREFIX)
+    }
+
+    /// Returns the directory, relative to the directory of all consumers, where certdog writes
+    /// the files for this consumer.
+    pub fn directory(&self) -> String {
+        match self.registry_host() {
+            Some(host) => format!("registries/{}", host),
+            None => self.inner.clone(),
+        }
+    }
+}
+
+string_impls_for!(PkiConsumer, "PkiConsumer");
+
+#[cfg(test)]
+mod test_pki_consumer {
+    use super::PkiConsumer;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_pki_consumer() {
+        for ok in &[
+            "kubelet",
+            "ecs",
+            "registry:registry.example.com",
+            "registry:registry.example.com:5000",
+            "registry:10.0.0.1:443",
+            "registry:[fd00::1]:5000",
+            "registry:[fd00::1]",
+        ] {
+            PkiConsumer::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_pki_consumer() {
+        for err in &[
+            "",
+            "docker",
+            "registry:",
+            "registry:example.com:port",
+            "registry:example.com:99999",
+            "registry:example.com/path",
+            "registry:../../etc",
+            "registry:..",
+            "registry:.",
+            "registry:exa mple.com",
+        ] {
+            PkiConsumer::try_from(*err).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn consumer_directory() {
+        let registry = PkiConsumer::try_from("registry:registry.example.com:5000").unwrap();
+        assert_eq!(registry.registry_host(), Some("registry.example.com:5000"));
+        assert_eq!(registry.directory(), "registries/registry.example.com:5000");
+        let kubelet = PkiConsumer::try_from("kubelet").unwrap();
+        assert_eq!(kubelet.registry_host(), None);
+        assert_eq!(kubelet.directory(), "kubelet");
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// InterfaceName represents a string that is a valid Linux network interface name, like "eth0",
+/// "bond0", or "eth0.100" for a VLAN.  Names are limited to 15 characters by the kernel, and we
+/// further limit them to characters that are safe to use in file names and configuration.
+/// InterfaceName stores the original string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct InterfaceName {
+    inner: String,
+}
+
+lazy_static! {
+    /// Pattern matching a network interface name; the kernel allows up to 15 bytes (IFNAMSIZ less
+    /// the terminating null) of anything but '/', ':', and whitespace.
+    pub(crate) static ref INTERFACE_NAME: Regex = Regex::new(r"^[a-zA-Z0-9_.-]{1,15}$").unwrap();
+}
+
+impl TryFrom<&str> for InterfaceName {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        ensure!(
+            input != "." && input != "..",
+            error::InvalidInterfaceName {
+                input,
+                msg: "must not be '.' or '..'",
+            }
+        );
+        ensure!(
+            INTERFACE_NAME.is_match(input),
+            error::InvalidInterfaceName {
+                input,
+                msg: format!("must match pattern {}", *INTERFACE_NAME),
+            }
+        );
+        Ok(InterfaceName {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(InterfaceName, "InterfaceName");
+
+#[cfg(test)]
+mod test_interface_name {
+    use super::InterfaceName;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_interface_name() {
+        for ok in &[
+            "eth0",
+            "ens192",
+            "bond0",
+            "eth0.100",
+            "br-lan_1",
+            &"a".repeat(15),
+        ] {
+            InterfaceName::try_from(*ok).unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_interface_name() {
+        for err in &[
+            "",
+            ".",
+            "..",
+            &"a".repeat(16),
+            "eth0:1",
+            "eth 0",
+            "eth/0",
+            "../eth0x",
+        ] {
+            InterfaceName::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// InterfaceMode represents a string that is a valid way to address a network interface:
+/// * "dhcp": DHCPv4, plus DHCPv6 if it's available
+/// * "dhcp4": DHCPv4 only
+/// * "dhcp6": DHCPv6 only
+/// * "slaac": IPv6 stateless address autoconfiguration only
+/// * "static": only the addresses and routes given in settings
+///
+/// It stores the original string and makes it accessible through standard traits, along with the
+/// parsed mode, so users can match on it.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct InterfaceMode {
+    inner: String,
+    addressing: InterfaceAddressing,
+}
+
+/// The ways an interface can be addressed, as parsed from an InterfaceMode.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum InterfaceAddressing {
+    Dhcp,
+    Dhcp4,
+    Dhcp6,
+    Slaac,
+    Static,
+}
+
+impl InterfaceMode {
+    pub fn addressing(&self) -> InterfaceAddressing {
+        self.addressing
+    }
+}
+
+impl TryFrom<&str> for InterfaceMode {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        let addressing = match input {
+            "dhcp" => InterfaceAddressing::Dhcp,
+            "dhcp4" => InterfaceAddressing::Dhcp4,
+            "dhcp6" => InterfaceAddressing::Dhcp6,
+            "slaac" => InterfaceAddressing::Slaac,
+            "static" => InterfaceAddressing::Static,
+            _ => return error::InvalidInterfaceMode { input }.fail(),
+        };
+        Ok(InterfaceMode {
+            inner: input.to_string(),
+            addressing,
+        })
+    }
+}
+
+string_impls_for!(InterfaceMode, "InterfaceMode");
+
+#[cfg(test)]
+mod test_interface_mode {
+    use super::{InterfaceAddressing, InterfaceMode};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn good_interface_modes() {
+        for (ok, addressing) in &[
+            ("dhcp", InterfaceAddressing::Dhcp),
+            ("dhcp4", InterfaceAddressing::Dhcp4),
+            ("dhcp6", InterfaceAddressing::Dhcp6),
+            ("slaac", InterfaceAddressing::Slaac),
+            ("static", InterfaceAddressing::Static),
+        ] {
+            assert_eq!(
+                InterfaceMode::try_from(*ok).unwrap().addressing(),
+                *addressing
+            );
+        }
+    }
+
+    #[test]
+    fn bad_interface_modes() {
+        for err in &["", "DHCP", "dhcp5", "manual"] {
+            InterfaceMode::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// BondMode represents a string that is a valid Linux bonding driver mode name.  It stores the
+/// original string and makes it accessible through standard traits.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct BondMode {
+    inner: String,
+}
+
+impl TryFrom<&str> for BondMode {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        ensure!(
+            matches!(
+                input,
+                "balance-rr"
+                    | "active-backup"
+                    | "balance-xor"
+                    | "broadcast"
+                    | "802.3ad"
+                    | "balance-tlb"
+                    | "balance-alb"
+            ),
+            error::InvalidBondMode { input }
+        );
+        Ok(BondMode {
+            inner: input.to_string(),
+        })
+    }
+}
+
+string_impls_for!(BondMode, "BondMode");
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// IpCidr represents a string that is an IPv4 or IPv6 address with a prefix length, like
+/// "192.168.1.10/24" or "2001:db8::/32".  The address doesn't have to be the start of the
+/// network, so it can describe both an interface address and the network it's on.  IpCidr stores
+/// the original string and makes it accessible through standard traits, along with the parsed
+/// address and prefix length.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct IpCidr {
+    inner: String,
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+}
+
+impl TryFrom<&str> for IpCidr {
+    type Error = error::Error;
+
+    fn try_from(input: &str) -> Result<Self, error::Error> {
+        let (addr_str, prefix_str) = input.split_once('/').context(error::InvalidCidr {
+            input,
+            msg: "must be an address and prefix length separated by '/'",
+        })?;
+        let addr = IpAddr::from_str(addr_str).map_err(|e| {
+            error::InvalidCidr {
+                input,
+                msg: e.to_string(),
+            }
+            .build()
+        })?;
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_str
+            .parse::<u8>()
+            .ok()
+            .filter(|len| *len <= max_prefix_len)
+            .context(error::InvalidCidr {
+                input,
+                msg: format!("prefix length must be 0 to {}", max_prefix_len),
+            })?;
+        Ok(IpCidr {
+            inner: input.to_string(),
+            addr,
+            prefix_len,
+        })
+    }
+}
+
+string_impls_for!(IpCidr, "IpCidr");
+
+#[cfg(test)]
+mod test_ip_cidr {
+    use super::IpCidr;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn valid_ip_cidr() {
+        for ok in &[
+            "192.168.1.10/24",
+            "0.0.0.0/0",
+            "10.0.0.1/32",
+            "2001:db8::1/64",
+            "::/0",
+            "fe80::1/128",
+        ] {
+            IpCidr::try_from(*ok).unwrap();
+        }
+        let cidr = IpCidr::try_from("192.168.1.10/24").unwrap();
+        assert_eq!(cidr.addr().to_string(), "192.168.1.10");
+        assert_eq!(cidr.prefix_len(), 24);
+    }
+
+    #[test]
+    fn invalid_ip_cidr() {
+        for err in &[
+            "",
+            "192.168.1.10",
+            "192.168.1.10/33",
+            "192.168.1.10/-1",
+            "192.168.1/24",
+            "2001:db8::1/129",
+            "/24",
+            "192.168.1.10/24/1",
+            "example.com/24",
+        ] {
+            IpCidr::try_from(*err).unwrap_err();
+        }
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+/// Helper macro for the resolver option types, which are numbers with a maximum given in
+/// resolv.conf(5).  The resolver silently caps larger values, so we reject them instead.
+macro_rules! resolver_option_for {
+    ($(#[$attr:meta])* $for:ident, $option:expr, $max:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+        pub struct $for {
+            inner: u8,
+        }
+
+        impl $for {
+            pub const MAX: u8 = $max;
+        }
+
+        impl TryFrom<u8> for $for {
+            type Error = error::Error;
+
+            fn try_from(input: u8) -> Result<Self, error::Error> {
+                ensure!(
+                    input <= Self::MAX,
+                    error::InvalidResolverOption {
+                        option: $option,
+                        input,
+                        max: Self::MAX,
+                    }
+                );
+                Ok($for { inner: input })
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $for {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                let original = u8::deserialize(deserializer)?;
+                Self::try_from(original).map_err(|e| {
+                    D::Error::custom(format!(
+                        "Unable to deserialize into {}: {}",
+                        stringify!($for),
+                        e
+                    ))
+                })
+            }
+        }
+
+        impl Serialize for $for {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                serializer.serialize_u8(self.inner)
+            }
+        }
+
+        impl Deref for $for {
+            type Target = u8;
+            fn deref(&self) -> &Self::Target {
+                &self.inner
+            }
+        }
+
+        impl fmt::Display for $for {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.inner)
+            }
+        }
+
+        impl From<$for> for u8 {
+            fn from(x: $for) -> Self {
+                x.inner
+            }
+        }
+    };
+}
+
+resolver_option_for!(
+    /// ResolverNdots is the number of dots a name needs before it's tried as an absolute name,
+    /// at most 15.
+    ResolverNdots,
+    "ndots",
+    15
+);
+
+resolver_option_for!(
+    /// ResolverTimeout is the number of seconds to wait for a name server, at most 30.
+    ResolverTimeout,
+    "timeout",
+    30
+);
+
+resolver_option_for!(
+    /// ResolverAttempts is the number of times to try the name servers, at most 5.
+    ResolverAttempts,
+    "attempts",
+    5
+);
+
+#[cfg(test)]
+mod test_resolver_options {
+    use super::{ResolverAttempts, ResolverNdots, ResolverTimeout};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn good_values() {
+        for ok in &[0, 1, 15] {
+            ResolverNdots::try_from(*ok).unwrap();
+        }
+        for ok in &[1, 5, 30] {
+            ResolverTimeout::try_from(*ok).unwrap();
+        }
+        for ok in &[1, 2, 5] {
+            ResolverAttempts::try_from(*ok).unwrap();
+        }
+        let ndots: ResolverNdots = serde_plain::from_str("2").unwrap();
+        assert_eq!(*ndots, 2);
+        assert_eq!(serde_plain::to_string(&ndots).unwrap(), "2");
+    }
+
+    #[test]
+    fn bad_values() {
+        ResolverNdots::try_from(16).unwrap_err();
+        ResolverTimeout::try_from(31).unwrap_err();
+        ResolverAttempts::try_from(6).unwrap_err();
+        serde_plain::from_str::<ResolverAttempts>("10").unwrap_err();
+        serde_plain::from_str::<ResolverAttempts>("-1").unwrap_err();
+    }
+}
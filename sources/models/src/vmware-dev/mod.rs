@@ -4,8 +4,9 @@ use std::collections::HashMap;
 
 use crate::modeled_types::Identifier;
 use crate::{
-    BootstrapContainer, HostContainer, KernelSettings, MetricsSettings, NetworkSettings,
-    NtpSettings, PemCertificate, RegistrySettings, UpdatesSettings,
+    BootSettings, BootstrapContainer, CertificateExpirySettings, DnsSettings, DriftSettings,
+    HostContainer, KernelSettings, MetricsSettings, NetworkSettings, NtpSettings, PemCertificate,
+    RegistrySettings, UpdatesSettings,
 };
 
 // Note: we have to use 'rename' here because the top-level Settings structure is the only one
@@ -18,8 +19,12 @@ struct Settings {
     bootstrap_containers: HashMap<Identifier, BootstrapContainer>,
     ntp: NtpSettings,
     network: NetworkSettings,
+    dns: DnsSettings,
     kernel: KernelSettings,
+    boot: BootSettings,
+    drift: DriftSettings,
     metrics: MetricsSettings,
     pki: HashMap<Identifier, PemCertificate>,
+    certificate_expiry: CertificateExpirySettings,
     container_registry: RegistrySettings,
 }
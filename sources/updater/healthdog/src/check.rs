@@ -0,0 +1,112 @@
+//! The checks that decide whether a boot is healthy.
+
+use log::trace;
+use std::fmt;
+use std::process::Command;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// The kubelet's health endpoint; it answers once the kubelet is running.
+const KUBELET_HEALTHZ_URL: &str = "http://127.0.0.1:10248/healthz";
+
+/// The ECS agent's introspection endpoint; it includes the container instance ARN once the agent
+/// has registered with the cluster.
+const ECS_AGENT_METADATA_URL: &str = "http://127.0.0.1:51678/v1/metadata";
+
+/// How long to wait for a response from a local endpoint.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Check {
+    /// The systemd unit is active.
+    Service(String),
+    /// The API server answers requests.
+    Api,
+    /// The kubelet's healthz endpoint reports it healthy; this doesn't check that its Node
+    /// object is registered.
+    Kubelet,
+    /// The ECS agent has registered with its cluster.
+    EcsAgent,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Check::Service(unit) => write!(f, "service {}", unit),
+            Check::Api => write!(f, "API"),
+            Check::Kubelet => write!(f, "kubelet healthz"),
+            Check::EcsAgent => write!(f, "ECS agent registration"),
+        }
+    }
+}
+
+impl Check {
+    /// Returns the check for the variant's orchestrator agent, if it has one.
+    pub(crate) fn registration(variant: &str) -> Option<Self> {
+        if variant.contains("-ecs-") {
+            Some(Check::EcsAgent)
+        } else if variant.contains("-k8s-") {
+            Some(Check::Kubelet)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the check passes right now.
+    pub(crate) fn passes(&self, runtime: &Runtime, socket_path: &str) -> bool {
+        let passes = match self {
+            Check::Service(unit) => Command::new("systemctl")
+                .arg("is-active")
+                .arg("--quiet")
+                .arg(unit)
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false),
+            Check::Api => runtime
+                .block_on(async {
+                    apiclient::raw_request(socket_path, constants::API_SETTINGS_URI, "GET", None)
+                        .await
+                })
+                .map(|(code, _)| code.is_success())
+                .unwrap_or(false),
+            Check::Kubelet => matches!(
+                get_local(KUBELET_HEALTHZ_URL).as_deref().map(str::trim),
+                Some("ok")
+            ),
+            Check::EcsAgent => get_local(ECS_AGENT_METADATA_URL)
+                .and_then(|body| serde_json::from_str::<serde_json::Value>(&body).ok())
+                .and_then(|metadata| {
+                    metadata["ContainerInstanceArn"]
+                        .as_str()
+                        .map(|arn| !arn.is_empty())
+                })
+                .unwrap_or(false),
+        };
+        trace!("Check '{}' passes: {}", self, passes);
+        passes
+    }
+}
+
+/// Returns the body of a successful response from a local endpoint.
+fn get_local(url: &str) -> Option<String> {
+    reqwest::blocking::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .and_then(|client| client.get(url).send())
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::Check;
+
+    #[test]
+    fn registration_check() {
+        assert_eq!(Check::registration("aws-k8s-1.21"), Some(Check::Kubelet));
+        assert_eq!(Check::registration("vmware-k8s-1.20"), Some(Check::Kubelet));
+        assert_eq!(Check::registration("aws-ecs-1"), Some(Check::EcsAgent));
+        assert_eq!(Check::registration("aws-dev"), None);
+    }
+}
@@ -0,0 +1,44 @@
+use snafu::Snafu;
+use std::path::PathBuf;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility = "pub(super)")]
+pub(super) enum Error {
+    #[snafu(display("Failed to record boot health: {}", source))]
+    BootHealth { source: healthdog::error::Error },
+
+    #[snafu(display("Failed to parse config file {}: {}", path.display(), source))]
+    ConfigParse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[snafu(display("Failed to read config file {}: {}", path.display(), source))]
+    ConfigRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Logger setup error: {}", source))]
+    Logger { source: log::SetLoggerError },
+
+    #[snafu(display("Failed to read partition table: {}", source))]
+    PartitionTableRead { source: signpost::Error },
+
+    #[snafu(display("Failed to write partition table: {}", source))]
+    PartitionTableWrite { source: signpost::Error },
+
+    #[snafu(display("Failed to reboot: {}", source))]
+    Reboot { source: std::io::Error },
+
+    #[snafu(display("Unable to load Bottlerocket release info: {}", source))]
+    ReleaseVersion { source: bottlerocket_release::Error },
+
+    #[snafu(display("Failed to roll back to the previous partition set: {}", source))]
+    Rollback { source: signpost::Error },
+
+    #[snafu(display("Failed to create async runtime: {}", source))]
+    Runtime { source: std::io::Error },
+}
+
+pub(super) type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,126 @@
+//! This library holds the record of healthdog's decision about the first boot of an update, so
+//! that other programs, like thar-be-updates, can report it.
+
+#![deny(rust_2018_idioms)]
+
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// This is where healthdog records its decision about the most recent update boot.  It's kept
+/// across reboots so the record survives a rollback.
+pub const BOOT_HEALTH_PATH: &str = "/var/lib/healthdog/boot-health.json";
+
+/// What healthdog decided about a boot.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// The checks are still running.
+    Pending,
+    /// The checks passed and the boot was marked successful.
+    Healthy,
+    /// The checks didn't pass in time, and the host rebooted to try the update again.
+    Retrying,
+    /// The checks didn't pass in time on the last allowed attempt, and the host rolled back to
+    /// the previous partition set.
+    RolledBack,
+}
+
+/// The record of healthdog's decision about the first boot of an update.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BootHealth {
+    /// The version that was booted.
+    pub version: Version,
+    pub outcome: Outcome,
+    /// The number of times the version has been booted while waiting for its checks to pass.
+    pub attempts: u32,
+    /// The checks that hadn't passed when the deadline was reached.
+    pub failed_checks: Vec<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl BootHealth {
+    /// Loads the record at the given path, if there is one.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(error::Read { path }),
+        };
+        serde_json::from_slice(&data)
+            .map(Some)
+            .context(error::Parse { path })
+    }
+
+    /// Writes the record to the given path, replacing any existing record.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(error::Write { path: dir })?;
+        }
+        let data = serde_json::to_vec_pretty(self).context(error::Serialize)?;
+        // Write a temporary file and rename it so readers never see a partial record.
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, data).context(error::Write { path: &temp_path })?;
+        fs::rename(&temp_path, path).context(error::Write { path })
+    }
+}
+
+pub mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility = "pub(crate)")]
+    pub enum Error {
+        #[snafu(display("Failed to read boot health record '{}': {}", path.display(), source))]
+        Read {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to parse boot health record '{}': {}", path.display(), source))]
+        Parse {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to serialize boot health record: {}", source))]
+        Serialize { source: serde_json::Error },
+
+        #[snafu(display("Failed to write boot health record '{}': {}", path.display(), source))]
+        Write {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+    }
+}
+
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("boot-health.json");
+        assert!(BootHealth::load(&path).unwrap().is_none());
+
+        let record = BootHealth {
+            version: Version::parse("1.2.0").unwrap(),
+            outcome: Outcome::RolledBack,
+            attempts: 2,
+            failed_checks: vec!["service kubelet".to_string()],
+            timestamp: Utc::now(),
+        };
+        record.save(&path).unwrap();
+        assert_eq!(BootHealth::load(&path).unwrap(), Some(record));
+    }
+}
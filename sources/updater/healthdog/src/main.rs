@@ -0,0 +1,407 @@
+/*!
+# Introduction
+
+healthdog decides whether the first boot of an update is healthy.
+
+After an update, the new partition set is booted with one try left and isn't yet marked as
+successfully booted, so if the host goes down before the boot is marked, GRUB falls back to the
+previous partition set.
+Normally, `healthdog mark-successful-boot` marks the boot early in startup.
+If boot health checks are enabled, it leaves the first boot of an update unmarked, and
+`healthdog check-boot` runs the checks once the host is up:
+
+* the configured systemd units, by default those in `settings.metrics.service-checks`, are active
+* the API server answers requests
+* the kubelet reports healthy on its healthz endpoint, or the ECS agent has registered with its
+  cluster, depending on the variant
+
+If every check passes before the deadline, the boot is marked successful.
+Otherwise, if the update has boot attempts left, healthdog gives it another try and reboots;
+on the last attempt, it rolls back to the previous partition set with `rollback_to_inactive` and
+reboots into it.
+
+Each decision is recorded in `/var/lib/healthdog/boot-health.json`, which thar-be-updates includes
+in the update status.
+
+# Configuration
+
+Configuration is read from a TOML file, which is generated from Bottlerocket settings:
+
+```toml
+# whether to check the first boot of an update before marking it successful
+enabled = true
+# the systemd units that must be active
+service_checks = ["apiserver", "containerd", "kubelet"]
+# whether the API server must answer requests
+check_api = true
+# whether the kubelet must be healthy, or the ECS agent registered
+check_registration = true
+# how long the checks have to pass after check-boot starts
+deadline_seconds = 600
+# how many times the update is booted before rolling back
+max_boot_attempts = 1
+```
+*/
+
+#![deny(rust_2018_idioms)]
+
+mod check;
+mod error;
+
+use crate::check::Check;
+use crate::error::Result;
+use bottlerocket_release::BottlerocketRelease;
+use chrono::Utc;
+use healthdog::{BootHealth, Outcome, BOOT_HEALTH_PATH};
+use log::{error, info, warn};
+use semver::Version;
+use serde::Deserialize;
+use signpost::State;
+use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
+use snafu::ResultExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{env, fs, process, thread};
+use tokio::runtime::Runtime;
+
+const DEFAULT_CONFIG_PATH: &str = "/etc/healthdog.toml";
+
+/// How long to wait between rounds of checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    enabled: bool,
+    #[serde(default)]
+    service_checks: Vec<String>,
+    check_api: bool,
+    check_registration: bool,
+    deadline_seconds: u64,
+    max_boot_attempts: u32,
+}
+
+impl Config {
+    fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let s = fs::read_to_string(path).context(error::ConfigRead { path })?;
+        toml::from_str(&s).context(error::ConfigParse { path })
+    }
+
+    /// Returns the checks to run on the given variant.
+    fn checks(&self, variant: &str) -> Vec<Check> {
+        let mut checks: Vec<Check> = self
+            .service_checks
+            .iter()
+            .map(|unit| Check::Service(unit.clone()))
+            .collect();
+        if self.check_api {
+            checks.push(Check::Api);
+        }
+        if self.check_registration {
+            checks.extend(Check::registration(variant));
+        }
+        checks
+    }
+}
+
+/// Store the args we receive on the command line.
+struct Args {
+    subcommand: String,
+    log_level: LevelFilter,
+    config_path: PathBuf,
+    socket_path: String,
+}
+
+/// Print a usage message in the event a bad arg is passed
+fn usage() -> ! {
+    let program_name = env::args().next().unwrap_or_else(|| "program".to_string());
+    eprintln!(
+        r"Usage: {}
+            [ mark-successful-boot | check-boot ]
+            [ --config PATH ]
+            [ --socket-path PATH ]
+            [ --log-level trace|debug|info|warn|error ]
+
+    mark-successful-boot: marks the boot successful, unless it's the first boot of an update
+                          and boot health checks are enabled
+    check-boot:           runs the boot health checks on the first boot of an update, and marks
+                          the boot successful, retries it, or rolls back
+
+    Config path defaults to {}
+    Socket path defaults to {}",
+        program_name,
+        DEFAULT_CONFIG_PATH,
+        constants::API_SOCKET,
+    );
+    process::exit(2);
+}
+
+/// Prints a more specific message before exiting through usage().
+fn usage_msg<S: AsRef<str>>(msg: S) -> ! {
+    eprintln!("{}\n", msg.as_ref());
+    usage();
+}
+
+/// Parses the arguments to the program and return a representation of them.
+fn parse_args(args: env::Args) -> Args {
+    let mut subcommand = None;
+    let mut log_level = None;
+    let mut config_path = None;
+    let mut socket_path = None;
+
+    let mut iter = args.skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "--log-level" => {
+                let log_level_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to --log-level"));
+                log_level = Some(LevelFilter::from_str(&log_level_str).unwrap_or_else(|_| {
+                    usage_msg(format!("Invalid log level '{}'", log_level_str))
+                }));
+            }
+
+            "--config" => {
+                config_path = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --config")),
+                )
+            }
+
+            "--socket-path" => {
+                socket_path = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --socket-path")),
+                )
+            }
+
+            "mark-successful-boot" | "check-boot" => subcommand = Some(arg),
+
+            _ => usage(),
+        }
+    }
+
+    Args {
+        subcommand: subcommand.unwrap_or_else(|| usage_msg("Must specify a subcommand.")),
+        log_level: log_level.unwrap_or(LevelFilter::Info),
+        config_path: config_path
+            .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string())
+            .into(),
+        socket_path: socket_path.unwrap_or_else(|| constants::API_SOCKET.to_string()),
+    }
+}
+
+/// Loads the config, treating a missing config as disabling the checks, so a host without one
+/// still marks its boots successful.
+fn load_config(path: &Path) -> Result<Option<Config>> {
+    if !path.exists() {
+        warn!(
+            "No config at {}, boot health checks are disabled",
+            path.display()
+        );
+        return Ok(None);
+    }
+    Ok(Some(Config::from_file(path)?).filter(|config| config.enabled))
+}
+
+/// Returns which boot attempt of `version` this is, given the previous record.
+fn attempt_number(previous: Option<&BootHealth>, version: &Version) -> u32 {
+    match previous {
+        Some(record) if record.version == *version && record.outcome != Outcome::Healthy => {
+            record.attempts + 1
+        }
+        _ => 1,
+    }
+}
+
+/// Returns what to do when the checks fail on the given attempt.
+fn failure_outcome(attempt: u32, max_boot_attempts: u32) -> Outcome {
+    if attempt < max_boot_attempts {
+        Outcome::Retrying
+    } else {
+        Outcome::RolledBack
+    }
+}
+
+fn mark_successful_boot(state: &mut State) -> Result<()> {
+    state.mark_successful_boot();
+    state.write().context(error::PartitionTableWrite)
+}
+
+fn reboot() -> Result<()> {
+    process::Command::new("shutdown")
+        .arg("-r")
+        .status()
+        .context(error::Reboot)?;
+    Ok(())
+}
+
+/// Runs the checks until they all pass or the deadline passes, and returns the checks that
+/// didn't pass in the last round.
+fn run_checks(checks: &[Check], deadline: Duration, socket_path: &str) -> Result<Vec<String>> {
+    let runtime = Runtime::new().context(error::Runtime)?;
+    let start = Instant::now();
+    loop {
+        let failed: Vec<String> = checks
+            .iter()
+            .filter(|check| !check.passes(&runtime, socket_path))
+            .map(|check| check.to_string())
+            .collect();
+        if failed.is_empty() || start.elapsed() >= deadline {
+            return Ok(failed);
+        }
+        info!("Waiting for boot health checks: {}", failed.join(", "));
+        thread::sleep(CHECK_INTERVAL);
+    }
+}
+
+fn check_boot(state: &mut State, config: &Config, socket_path: &str) -> Result<()> {
+    let release = BottlerocketRelease::new().context(error::ReleaseVersion)?;
+    let previous = BootHealth::load(BOOT_HEALTH_PATH).unwrap_or_else(|e| {
+        warn!("Ignoring unreadable boot health record: {}", e);
+        None
+    });
+    let mut record = BootHealth {
+        attempts: attempt_number(previous.as_ref(), &release.version_id),
+        version: release.version_id,
+        outcome: Outcome::Pending,
+        failed_checks: Vec::new(),
+        timestamp: Utc::now(),
+    };
+    record.save(BOOT_HEALTH_PATH).context(error::BootHealth)?;
+
+    let checks = config.checks(&release.variant_id);
+    info!(
+        "Checking boot attempt {} of {} {}",
+        record.attempts, record.version, release.variant_id
+    );
+    let failed = run_checks(
+        &checks,
+        Duration::from_secs(config.deadline_seconds),
+        socket_path,
+    )?;
+
+    record.timestamp = Utc::now();
+    if failed.is_empty() {
+        info!("Boot health checks passed, marking boot successful");
+        mark_successful_boot(state)?;
+        record.outcome = Outcome::Healthy;
+        return record.save(BOOT_HEALTH_PATH).context(error::BootHealth);
+    }
+
+    error!("Boot health checks failed: {}", failed.join(", "));
+    record.failed_checks = failed;
+    record.outcome = failure_outcome(record.attempts, config.max_boot_attempts);
+    match record.outcome {
+        Outcome::Retrying => {
+            info!("Rebooting to try the update again");
+            state.retry_active();
+        }
+        _ => {
+            info!("Rolling back to the previous partition set");
+            state.rollback_to_inactive().context(error::Rollback)?;
+        }
+    }
+    state.write().context(error::PartitionTableWrite)?;
+    record.save(BOOT_HEALTH_PATH).context(error::BootHealth)?;
+    reboot()
+}
+
+fn run() -> Result<()> {
+    let args = parse_args(env::args());
+
+    // SimpleLogger will send errors to stderr and anything less to stdout.
+    SimpleLogger::init(args.log_level, LogConfig::default()).context(error::Logger)?;
+
+    let mut state = State::load().context(error::PartitionTableRead)?;
+    // Only the first boots of an update need a decision.
+    if state.active_successful() {
+        info!("Boot was already marked successful");
+        return Ok(());
+    }
+
+    let config = load_config(&args.config_path)?;
+    match (args.subcommand.as_ref(), config) {
+        ("mark-successful-boot", Some(_)) => {
+            info!("Leaving the first boot of an update for the boot health checks");
+            Ok(())
+        }
+        ("check-boot", Some(config)) => check_boot(&mut state, &config, &args.socket_path),
+        // Without checks, both subcommands mark the boot; check-boot only gets here if the
+        // checks were disabled after startup.
+        (_, None) => mark_successful_boot(&mut state),
+        _ => unreachable!("parse_args only accepts known subcommands"),
+    }
+}
+
+// Returning a Result from main makes it print a Debug representation of the error, but with Snafu
+// we have nice Display representations of the error, so we wrap "main" (run) and print any error.
+// https://github.com/shepmaster/snafu/issues/110
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_checks() {
+        let config: Config = toml::from_str(
+            r#"
+            enabled = true
+            service_checks = ["apiserver", "kubelet"]
+            check_api = true
+            check_registration = true
+            deadline_seconds = 600
+            max_boot_attempts = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.checks("aws-k8s-1.21"),
+            vec![
+                Check::Service("apiserver".to_string()),
+                Check::Service("kubelet".to_string()),
+                Check::Api,
+                Check::Kubelet,
+            ]
+        );
+        assert_eq!(config.checks("aws-dev").len(), 3);
+    }
+
+    #[test]
+    fn attempts() {
+        let version = Version::parse("1.2.0").unwrap();
+        let record = |version: &str, outcome| BootHealth {
+            version: Version::parse(version).unwrap(),
+            outcome,
+            attempts: 1,
+            failed_checks: Vec::new(),
+            timestamp: Utc::now(),
+        };
+        assert_eq!(attempt_number(None, &version), 1);
+        assert_eq!(
+            attempt_number(Some(&record("1.2.0", Outcome::Retrying)), &version),
+            2
+        );
+        // A new version, or one that was healthy before, starts over.
+        assert_eq!(
+            attempt_number(Some(&record("1.1.0", Outcome::Retrying)), &version),
+            1
+        );
+        assert_eq!(
+            attempt_number(Some(&record("1.2.0", Outcome::Healthy)), &version),
+            1
+        );
+
+        assert_eq!(failure_outcome(1, 2), Outcome::Retrying);
+        assert_eq!(failure_outcome(2, 2), Outcome::RolledBack);
+        assert_eq!(failure_outcome(1, 1), Outcome::RolledBack);
+        assert_eq!(failure_outcome(1, 0), Outcome::RolledBack);
+    }
+}
@@ -193,6 +193,19 @@ impl State {
         self.set_gptprio(self.active(), flags);
     }
 
+    /// Returns whether the active partition set has been marked as successfully booted.
+    pub fn active_successful(&self) -> bool {
+        self.gptprio(self.active()).successful()
+    }
+
+    /// Gives the active partition set another boot attempt, for when it hasn't been marked as
+    /// successfully booted and GRUB has used up its tries, but **does not write to the disk**.
+    pub fn retry_active(&mut self) {
+        let mut flags = self.gptprio(self.active());
+        flags.set_tries_left(1);
+        self.set_gptprio(self.active(), flags);
+    }
+
     /// Clears priority bits of the inactive partition in preparation to write new images, but
     /// **does not write to the disk**.
     pub fn clear_inactive(&mut self) {
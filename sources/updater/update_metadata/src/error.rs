@@ -56,6 +56,13 @@ pub enum Error {
         source: parse_datetime::Error,
     },
 
+    #[snafu(display(
+        "Delta must be from an older version: {} is not older than {}",
+        from,
+        to
+    ))]
+    DeltaVersion { from: Version, to: Version },
+
     #[snafu(display("Duplicate key ID: {}", keyid))]
     DuplicateKeyId { backtrace: Backtrace, keyid: u32 },
 
@@ -131,6 +138,27 @@ pub enum Error {
         target: Version,
     },
 
+    #[snafu(display("No update found for {} {} {}", variant, arch, version))]
+    MissingUpdate {
+        backtrace: Backtrace,
+        variant: String,
+        arch: String,
+        version: Version,
+    },
+
+    #[snafu(display("Failed to parse update progress in '{}': {}", path.display(), source))]
+    ProgressParse {
+        path: PathBuf,
+        source: serde_json::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to serialize update progress: {}", source))]
+    ProgressSerialize {
+        source: serde_json::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to serialize update information: {}", source))]
     UpdateSerialize {
         source: serde_json::Error,
@@ -2,6 +2,7 @@
 
 mod de;
 pub mod error;
+pub mod progress;
 mod se;
 
 use crate::error::Result;
@@ -9,7 +10,7 @@ use chrono::{DateTime, Utc};
 use parse_datetime::parse_offset;
 use semver::Version;
 use serde::{Deserialize, Serialize};
-use snafu::{ensure, ResultExt};
+use snafu::{ensure, OptionExt, ResultExt};
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::fs;
@@ -80,14 +81,14 @@ pub struct UpdateWave {
     pub fleet_percentage: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Images {
     pub boot: String,
     pub root: String,
     pub hash: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Update {
     pub variant: String,
     pub arch: String,
@@ -96,6 +97,19 @@ pub struct Update {
     #[serde(deserialize_with = "de::deserialize_bound")]
     pub waves: BTreeMap<u32, DateTime<Utc>>,
     pub images: Images,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deltas: Vec<Delta>,
+}
+
+/// Delta images rebuild an update's images from the images of an older version, so hosts running
+/// that version can download less.  Each delta is applied to the corresponding partition of the
+/// running version; the result is checked against `sha256`, the hex-encoded digests of the
+/// uncompressed images the deltas produce.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delta {
+    pub from: Version,
+    pub images: Images,
+    pub sha256: Images,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -171,6 +185,7 @@ impl Manifest {
             max_version: max_version.clone(),
             images,
             waves: BTreeMap::new(),
+            deltas: Vec::new(),
         };
         self.update_max_version(
             &update.max_version,
@@ -181,6 +196,40 @@ impl Manifest {
         Ok(())
     }
 
+    // Adds delta images from `from` to the update matching the given variant, arch, and version,
+    // replacing any existing deltas from the same version.
+    pub fn add_delta(
+        &mut self,
+        variant: String,
+        arch: String,
+        image_version: Version,
+        from: Version,
+        images: Images,
+        sha256: Images,
+    ) -> Result<()> {
+        ensure!(
+            from < image_version,
+            error::DeltaVersion {
+                from,
+                to: image_version
+            }
+        );
+        let mut matching =
+            self.get_matching_updates(variant.clone(), arch.clone(), image_version.clone());
+        let update = matching.pop().context(error::MissingUpdate {
+            variant,
+            arch,
+            version: image_version,
+        })?;
+        update.deltas.retain(|delta| delta.from != from);
+        update.deltas.push(Delta {
+            from,
+            images,
+            sha256,
+        });
+        Ok(())
+    }
+
     /// Update the maximum version for all updates that optionally match the
     /// architecture and variant of some new update.
     pub fn update_max_version(
@@ -222,6 +271,18 @@ impl Manifest {
     }
 
     /// Returns Updates matching variant, arch, and version
+    /// Returns the update for the given variant, architecture, and version.
+    pub fn find_update(&self, variant: &str, arch: &str, version: &Version) -> Result<&Update> {
+        self.updates
+            .iter()
+            .find(|u| u.variant == variant && u.arch == arch && u.version == *version)
+            .context(error::MissingUpdate {
+                variant,
+                arch,
+                version: version.clone(),
+            })
+    }
+
     fn get_matching_updates(
         &mut self,
         variant: String,
@@ -475,6 +536,7 @@ mod tests {
                 root: String::from("root"),
                 hash: String::from("hash"),
             },
+            deltas: Vec::new(),
         }
     }
 
@@ -640,6 +702,7 @@ mod tests {
                 root: String::from("root"),
                 hash: String::from("hash"),
             },
+            deltas: Vec::new(),
         };
         let seed = 1024;
         // Construct a DateTime object for 1/1/2000 00:00:00
@@ -691,4 +754,77 @@ mod tests {
         assert!(i.next().unwrap() == "migration_1.1.0_b");
         assert!(i.next().unwrap() == "migration_1.1.0_a");
     }
+
+    #[test]
+    fn test_add_delta() {
+        let mut manifest = Manifest::default();
+        manifest.updates.push(test_update());
+        let images = |suffix: &str| Images {
+            boot: format!("boot.{}", suffix),
+            root: format!("root.{}", suffix),
+            hash: format!("hash.{}", suffix),
+        };
+        let version = Version::parse("1.1.1").unwrap();
+        let from = Version::parse("1.1.0").unwrap();
+
+        manifest
+            .add_delta(
+                "bottlerocket".to_string(),
+                "test".to_string(),
+                version.clone(),
+                from.clone(),
+                images("old"),
+                images("sha"),
+            )
+            .unwrap();
+        // A second delta from the same version replaces the first.
+        manifest
+            .add_delta(
+                "bottlerocket".to_string(),
+                "test".to_string(),
+                version.clone(),
+                from.clone(),
+                images("delta"),
+                images("sha"),
+            )
+            .unwrap();
+        assert_eq!(manifest.updates[0].deltas.len(), 1);
+        assert_eq!(manifest.updates[0].deltas[0].images.root, "root.delta");
+
+        // Deltas must be from an older version of an existing update.
+        assert!(manifest
+            .add_delta(
+                "bottlerocket".to_string(),
+                "test".to_string(),
+                version.clone(),
+                Version::parse("1.2.0").unwrap(),
+                images("delta"),
+                images("sha"),
+            )
+            .is_err());
+        assert!(manifest
+            .add_delta(
+                "bottlerocket".to_string(),
+                "other".to_string(),
+                version,
+                from,
+                images("delta"),
+                images("sha"),
+            )
+            .is_err());
+
+        // Deltas survive a round trip, and manifests without them still parse.
+        let json = serde_json::to_string(&manifest).unwrap();
+        let parsed = Manifest::from_json(json.as_bytes()).unwrap();
+        assert_eq!(
+            parsed.updates[0].deltas[0].from,
+            Version::parse("1.1.0").unwrap()
+        );
+        manifest.updates[0].deltas.clear();
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert!(!json.contains("deltas"));
+        assert!(Manifest::from_json(json.as_bytes()).unwrap().updates[0]
+            .deltas
+            .is_empty());
+    }
 }
@@ -0,0 +1,185 @@
+//! updog reports the progress of downloading and writing an update to a file while it works, so
+//! other tools, like thar-be-updates, can show it.
+
+use crate::error::{self, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Where updog keeps its progress while it's downloading or writing an update.  The file only
+/// exists while updog is working.
+pub const PROGRESS_PATH: &str = "/run/cache/updog/progress.json";
+
+/// The part of the update updog is working on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Loading the repository metadata and manifest
+    Metadata,
+    /// Fetching the migrations needed to move to the new version and back
+    Migrations,
+    /// Downloading or writing the root image
+    Root,
+    /// Downloading or writing the boot image
+    Boot,
+    /// Downloading or writing the dm-verity hash image
+    Hash,
+}
+
+/// The progress on one image of an update.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetProgress {
+    pub name: String,
+    pub phase: Phase,
+    /// The size of the image in the repository
+    pub total: u64,
+    pub downloaded: u64,
+    /// How much of the downloaded image has been written to disk, if it's being written.
+    pub written: Option<u64>,
+}
+
+impl TargetProgress {
+    /// Bytes of work done and expected; writing an image counts as much as downloading it.
+    fn work(&self) -> (u64, u64) {
+        match self.written {
+            Some(written) => (self.downloaded + written, self.total * 2),
+            None => (self.downloaded, self.total),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    pub phase: Phase,
+    pub targets: Vec<TargetProgress>,
+    pub started: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    /// Bytes downloaded or written since `started`; parts of images downloaded by an earlier run
+    /// aren't counted.
+    pub transferred: u64,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        let now = Utc::now();
+        Self {
+            phase: Phase::Metadata,
+            targets: Vec::new(),
+            started: now,
+            updated: now,
+            transferred: 0,
+        }
+    }
+}
+
+impl Progress {
+    /// Returns how much of the images' work is done, from 0 to 100.
+    #[must_use]
+    pub fn percent_complete(&self) -> f64 {
+        let (done, expected) = self
+            .targets
+            .iter()
+            .map(TargetProgress::work)
+            .fold((0, 0), |(done, expected), (d, e)| (done + d, expected + e));
+        if expected == 0 {
+            return 0.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let percent = done as f64 * 100.0 / expected as f64;
+        percent.min(100.0)
+    }
+
+    /// Returns the average number of bytes downloaded or written per second since `started`.
+    #[must_use]
+    pub fn bytes_per_second(&self) -> u64 {
+        let elapsed = self.updated - self.started;
+        match u64::try_from(elapsed.num_milliseconds()) {
+            Ok(millis) if millis > 0 => self.transferred * 1000 / millis,
+            _ => 0,
+        }
+    }
+
+    /// Loads the progress saved at `path`, if there is any.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file exists but can't be read or parsed.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(error::FileRead { path }),
+        };
+        serde_json::from_slice(&data)
+            .map(Some)
+            .context(error::ProgressParse { path })
+    }
+
+    /// Saves the progress to `path`.  A temporary file is renamed into place so readers never see
+    /// a partial file.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the progress can't be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(error::FileWrite { path: dir })?;
+        }
+        let data = serde_json::to_vec(self).context(error::ProgressSerialize)?;
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, data).context(error::FileWrite { path: &temp_path })?;
+        fs::rename(&temp_path, path).context(error::FileWrite { path })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Duration;
+
+    fn target(name: &str, downloaded: u64, written: Option<u64>) -> TargetProgress {
+        TargetProgress {
+            name: name.to_string(),
+            phase: Phase::Root,
+            total: 100,
+            downloaded,
+            written,
+        }
+    }
+
+    #[test]
+    fn percent_and_throughput() {
+        let mut progress = Progress::default();
+        assert!(progress.percent_complete() < f64::EPSILON);
+        assert_eq!(progress.bytes_per_second(), 0);
+
+        // Downloads only count once; images being written count twice.
+        progress.targets = vec![target("root", 100, Some(50)), target("boot", 50, None)];
+        assert!((progress.percent_complete() - 200.0 / 3.0).abs() < 0.001);
+
+        progress.transferred = 2000;
+        progress.updated = progress.started + Duration::seconds(4);
+        assert_eq!(progress.bytes_per_second(), 500);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("updog/progress.json");
+        assert_eq!(Progress::load(&path).unwrap(), None);
+
+        let progress = Progress {
+            phase: Phase::Boot,
+            targets: vec![target("boot", 10, Some(0))],
+            ..Progress::default()
+        };
+        progress.save(&path).unwrap();
+        assert_eq!(Progress::load(&path).unwrap(), Some(progress));
+    }
+}
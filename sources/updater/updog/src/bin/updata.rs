@@ -1,8 +1,13 @@
 #![deny(rust_2018_idioms)]
 #![warn(clippy::pedantic)]
 
+#[path = "../delta.rs"]
+mod delta;
 #[path = "../error.rs"]
 mod error;
+#[cfg(test)]
+#[path = "../local_repository.rs"]
+mod local_repository;
 
 #[macro_use]
 extern crate log;
@@ -12,10 +17,13 @@ use chrono::{DateTime, Utc};
 use semver::Version;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{ErrorCompat, OptionExt, ResultExt};
-use std::fs;
+use std::convert::TryInto;
+use std::fs::{self, File};
 use std::path::PathBuf;
 use structopt::StructOpt;
-use update_metadata::{Images, Manifest, Release, UpdateWaves};
+use tough::RepositoryLoader;
+use update_metadata::{find_migrations, Images, Manifest, Release, UpdateWaves};
+use url::Url;
 
 #[derive(Debug, StructOpt)]
 struct GeneralArgs {
@@ -231,6 +239,197 @@ impl MaxVersionArgs {
     }
 }
 
+#[derive(Debug, StructOpt)]
+struct AddDeltaArgs {
+    // metadata file to modify
+    file: PathBuf,
+
+    // image 'variant', eg. 'aws-ecs-1'
+    #[structopt(short = "l", long = "variant")]
+    variant: String,
+
+    // architecture image is built for
+    #[structopt(short = "a", long = "arch")]
+    arch: String,
+
+    // version of the update the deltas produce
+    #[structopt(short = "v", long = "version")]
+    image_version: Version,
+
+    // version the deltas apply to; it must also be in the manifest
+    #[structopt(long = "from")]
+    from: Version,
+
+    // directory containing the images of the 'from' version, named as in the manifest
+    #[structopt(long = "old-images")]
+    old_images: PathBuf,
+
+    // directory containing the images of the new version, named as in the manifest
+    #[structopt(long = "new-images")]
+    new_images: PathBuf,
+
+    // directory to write the delta targets to; they must be added to the repository
+    #[structopt(short = "o", long = "outdir")]
+    outdir: PathBuf,
+}
+
+impl AddDeltaArgs {
+    fn run(self) -> Result<()> {
+        let mut manifest: Manifest = update_metadata::load_file(&self.file)?;
+        let find_images = |version: &Version| {
+            manifest
+                .find_update(&self.variant, &self.arch, version)
+                .map(|u| &u.images)
+        };
+        let old = find_images(&self.from)?;
+        let new = find_images(&self.image_version)?;
+
+        fs::create_dir_all(&self.outdir).context(error::DirCreate { path: &self.outdir })?;
+        let generate = |old_name: &str, new_name: &str| -> Result<(String, String)> {
+            let name = format!("{}.from-{}.delta", new_name, self.from);
+            let old_path = self.old_images.join(old_name);
+            let new_path = self.new_images.join(new_name);
+            let out_path = self.outdir.join(&name);
+            let old = lz4::Decoder::new(
+                File::open(&old_path).context(error::OpenImage { path: &old_path })?,
+            )
+            .context(error::Lz4Decode { target: old_name })?;
+            let new = lz4::Decoder::new(
+                File::open(&new_path).context(error::OpenImage { path: &new_path })?,
+            )
+            .context(error::Lz4Decode { target: new_name })?;
+            let out = File::create(&out_path).context(error::WriteDelta { path: &out_path })?;
+            let mut encoder = lz4::EncoderBuilder::new()
+                .build(out)
+                .context(error::WriteDelta { path: &out_path })?;
+            let digest = delta::generate(old, new, &mut encoder)?;
+            let (_, result) = encoder.finish();
+            result.context(error::WriteDelta { path: &out_path })?;
+            info!("Wrote {}", out_path.display());
+            Ok((name, hex::encode(digest)))
+        };
+        let (root, root_sha256) = generate(&old.root, &new.root)?;
+        let (boot, boot_sha256) = generate(&old.boot, &new.boot)?;
+        let (hash, hash_sha256) = generate(&old.hash, &new.hash)?;
+
+        manifest.add_delta(
+            self.variant,
+            self.arch,
+            self.image_version,
+            self.from,
+            Images { boot, root, hash },
+            Images {
+                boot: boot_sha256,
+                root: root_sha256,
+                hash: hash_sha256,
+            },
+        )?;
+        update_metadata::write_file(&self.file, &manifest)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct ExportBundleArgs {
+    // directory to write the bundle to
+    outdir: PathBuf,
+
+    // trusted root metadata for the repository
+    #[structopt(short = "r", long = "root")]
+    root: PathBuf,
+
+    // repository metadata URL; may be a file:// URL
+    #[structopt(short = "m", long = "metadata-url")]
+    metadata_url: Url,
+
+    // repository targets URL; may be a file:// URL
+    #[structopt(short = "t", long = "targets-url")]
+    targets_url: Url,
+
+    // image 'variant', eg. 'aws-ecs-1'
+    #[structopt(short = "l", long = "variant")]
+    variant: String,
+
+    // architecture image is built for
+    #[structopt(short = "a", long = "arch")]
+    arch: String,
+
+    // oldest version that hosts will update from; migrations from this version are included
+    #[structopt(long = "from-version")]
+    from_version: Option<Version>,
+
+    // newest version to include
+    #[structopt(long = "to-version")]
+    to_version: Option<Version>,
+}
+
+impl ExportBundleArgs {
+    /// Writes the repository metadata and the targets needed to update to versions in the given
+    /// range to `metadata` and `targets` directories under `outdir`, in a layout that updog can
+    /// use as its `local_repository`.
+    fn run(self) -> Result<()> {
+        let repository = RepositoryLoader::new(
+            File::open(&self.root).context(error::OpenRoot { path: &self.root })?,
+            self.metadata_url.clone(),
+            self.targets_url.clone(),
+        )
+        .load()
+        .context(error::Metadata)?;
+
+        let target = "manifest.json";
+        let target = target.try_into().context(error::TargetName { target })?;
+        let manifest = Manifest::from_json(
+            repository
+                .read_target(&target)
+                .context(error::ManifestLoad)?
+                .context(error::ManifestNotFound)?,
+        )
+        .context(error::ManifestParse)?;
+
+        let in_range = |version: &Version| {
+            self.from_version.iter().all(|from| version >= from)
+                && self.to_version.iter().all(|to| version <= to)
+        };
+        let updates: Vec<_> = manifest
+            .updates
+            .iter()
+            .filter(|u| u.variant == self.variant && u.arch == self.arch && in_range(&u.version))
+            .collect();
+        let oldest = updates.iter().map(|u| &u.version).min();
+        let newest = updates.iter().map(|u| &u.version).max();
+        let (oldest, newest) = oldest.zip(newest).context(error::UpdateNotAvailable)?;
+
+        let mut targets = vec!["manifest.json".to_string()];
+        for update in &updates {
+            info!(
+                "Including {} {} {}",
+                update.variant, update.arch, update.version
+            );
+            targets.push(update.images.root.clone());
+            targets.push(update.images.boot.clone());
+            targets.push(update.images.hash.clone());
+        }
+        // Hosts may be running the starting version or any included version.
+        let start = self.from_version.as_ref().unwrap_or(oldest);
+        targets.extend(find_migrations(start, newest, &manifest)?);
+
+        repository
+            .cache(
+                self.outdir.join("metadata"),
+                self.outdir.join("targets"),
+                Some(&targets),
+                true,
+            )
+            .context(error::ExportBundle { path: &self.outdir })?;
+        info!(
+            "Wrote {} targets to {}",
+            targets.len(),
+            self.outdir.display()
+        );
+        Ok(())
+    }
+}
+
 #[derive(Debug, StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 enum Command {
@@ -238,6 +437,8 @@ enum Command {
     Init(GeneralArgs),
     /// Add a new update to the manifest, not including wave information
     AddUpdate(AddUpdateArgs),
+    /// Generate deltas from an older version's images and add them to an update
+    AddDelta(AddDeltaArgs),
     /// Set waves for an update
     SetWaves(WaveArgs),
     /// Set the global maximum image version
@@ -248,6 +449,8 @@ enum Command {
     SetMigrations(MigrationArgs),
     /// Validate a manifest file, but make no changes
     Validate(GeneralArgs),
+    /// Export the metadata and targets for a range of versions as an offline bundle
+    ExportBundle(ExportBundleArgs),
 }
 
 fn main_inner() -> Result<()> {
@@ -262,6 +465,7 @@ fn main_inner() -> Result<()> {
             }
         }
         Command::AddUpdate(args) => args.run(),
+        Command::AddDelta(args) => args.run(),
         Command::SetWaves(args) => args.set(),
         Command::SetMaxVersion(args) => args.run(),
         Command::RemoveUpdate(args) => args.run(),
@@ -270,6 +474,7 @@ fn main_inner() -> Result<()> {
             Ok(_) => Ok(()),
             Err(e) => Err(error::Error::UpdateMetadata { source: e }),
         },
+        Command::ExportBundle(args) => args.run(),
     }
 }
 
@@ -417,4 +622,161 @@ mod tests {
         }
         Ok(())
     }
+
+    fn data_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("data")
+            .join(name)
+    }
+
+    /// Writes a copy of the test root that doesn't expire, since the repository loader checks.
+    fn write_root(path: &Path, keys: &[Box<dyn tough::key_source::KeySource>]) {
+        let root: tough::schema::Signed<tough::schema::Root> =
+            serde_json::from_reader(File::open(data_path("expired-root.json")).unwrap()).unwrap();
+        let mut root = root.signed;
+        root.expires = DateTime::parse_from_rfc3339("2999-01-01T00:00:00Z")
+            .unwrap()
+            .into();
+        let signed = tough::editor::signed::SignedRole::new(
+            root.clone(),
+            &tough::schema::KeyHolder::Root(root),
+            keys,
+            &ring::rand::SystemRandom::new(),
+        )
+        .unwrap();
+        fs::write(path, signed.buffer()).unwrap();
+    }
+
+    /// Creates a signed repository under `dir` with three releases of one variant, and a migration
+    /// into each of the later two.  Returns the paths to its root, metadata, and targets.
+    fn create_repo(dir: &Path) -> (PathBuf, PathBuf, PathBuf) {
+        let indir = dir.join("in");
+        let metadata_path = dir.join("metadata");
+        let targets_path = dir.join("targets");
+        let root_path = dir.join("root.json");
+        fs::create_dir(&indir).unwrap();
+
+        let keys: Vec<Box<dyn tough::key_source::KeySource>> =
+            vec![Box::new(tough::key_source::LocalKeySource {
+                path: data_path("snakeoil.pem"),
+            })];
+        write_root(&root_path, &keys);
+
+        let mut manifest = Manifest::default();
+        for version in &["1.0.0", "1.1.0", "1.2.0"] {
+            let images = Images {
+                boot: format!("boot-{}", version),
+                root: format!("root-{}", version),
+                hash: format!("hash-{}", version),
+            };
+            for image in &[&images.boot, &images.root, &images.hash] {
+                fs::write(indir.join(image), image).unwrap();
+            }
+            manifest
+                .add_update(
+                    Version::parse(version).unwrap(),
+                    None,
+                    "x86_64".to_string(),
+                    "aws-k8s-1.21".to_string(),
+                    images,
+                )
+                .unwrap();
+        }
+        for (from, to, migration) in &[
+            ("1.0.0", "1.1.0", "migrate_v1.1.0_a"),
+            ("1.1.0", "1.2.0", "migrate_v1.2.0_b"),
+        ] {
+            fs::write(indir.join(migration), migration).unwrap();
+            manifest.migrations.insert(
+                (Version::parse(from).unwrap(), Version::parse(to).unwrap()),
+                vec![migration.to_string()],
+            );
+        }
+        update_metadata::write_file(&indir.join("manifest.json"), &manifest).unwrap();
+
+        let mut editor = tough::editor::RepositoryEditor::new(&root_path).unwrap();
+        let expires: DateTime<Utc> = DateTime::parse_from_rfc3339("2999-01-01T00:00:00Z")
+            .unwrap()
+            .into();
+        let one = std::num::NonZeroU64::new(1).unwrap();
+        editor
+            .targets_version(one)
+            .unwrap()
+            .targets_expires(expires)
+            .unwrap()
+            .snapshot_version(one)
+            .snapshot_expires(expires)
+            .timestamp_version(one)
+            .timestamp_expires(expires);
+        for entry in fs::read_dir(&indir).unwrap() {
+            let entry = entry.unwrap();
+            editor
+                .add_target(
+                    entry.file_name().to_str().unwrap(),
+                    tough::schema::Target::from_path(entry.path()).unwrap(),
+                )
+                .unwrap();
+        }
+        let signed_repo = editor.sign(&keys).unwrap();
+        signed_repo
+            .link_targets(
+                &indir,
+                &targets_path,
+                tough::editor::signed::PathExists::Fail,
+            )
+            .unwrap();
+        signed_repo.write(&metadata_path).unwrap();
+
+        (root_path, metadata_path, targets_path)
+    }
+
+    #[test]
+    // Ensure that an exported bundle can be read back as a local repository, like updog does
+    fn export_bundle_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (root_path, metadata_path, targets_path) = create_repo(tmp.path());
+        let bundle_path = tmp.path().join("bundle");
+
+        ExportBundleArgs {
+            outdir: bundle_path.clone(),
+            root: root_path.clone(),
+            metadata_url: Url::from_directory_path(&metadata_path).unwrap(),
+            targets_url: Url::from_directory_path(&targets_path).unwrap(),
+            variant: "aws-k8s-1.21".to_string(),
+            arch: "x86_64".to_string(),
+            from_version: Some(Version::parse("1.1.0").unwrap()),
+            to_version: None,
+        }
+        .run()
+        .unwrap();
+
+        // Load the bundle the way updog loads a local repository
+        let (metadata_url, targets_url) = local_repository::urls(&bundle_path).unwrap();
+        let repository =
+            RepositoryLoader::new(File::open(&root_path).unwrap(), metadata_url, targets_url)
+                .load()
+                .unwrap();
+        let read = |name: &str| {
+            let target = name.try_into().unwrap();
+            repository
+                .read_target(&target)
+                .ok()
+                .flatten()
+                .map(|mut reader| {
+                    let mut contents = String::new();
+                    std::io::Read::read_to_string(&mut reader, &mut contents).unwrap();
+                    contents
+                })
+        };
+
+        let exported = Manifest::from_json(read("manifest.json").unwrap().as_bytes()).unwrap();
+        assert_eq!(exported.updates.len(), 3);
+        for name in &["boot-1.1.0", "root-1.2.0", "hash-1.2.0", "migrate_v1.2.0_b"] {
+            assert_eq!(read(name).as_deref(), Some(*name));
+        }
+        for name in &["root-1.0.0", "migrate_v1.1.0_a"] {
+            assert_eq!(read(name), None);
+        }
+    }
 }
@@ -0,0 +1,262 @@
+//! The delta module generates and applies block-level deltas between two versions of an image.
+//!
+//! A delta describes the new image as a sequence of operations that either copy a run of blocks
+//! from the old image or provide a block's data literally.  Partition images change little
+//! between nearby releases, so most of the new image can be copied from the partitions of the
+//! running version, and the delta only needs to carry the blocks that changed.
+//!
+//! The format is a magic string and the block size as a little-endian u32, followed by operations
+//! until the end of the stream:
+//! * copy: the byte 0, the first source block as a little-endian u64, and the number of blocks as
+//!   a little-endian u32.
+//! * literal: the byte 1, the data length as a little-endian u32, and the data.
+//!
+//! Deltas are stored LZ4-compressed, like the images themselves.
+
+use crate::error::{self, Result};
+use sha2::{Digest, Sha256};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+/// The size of the blocks we compare between images.
+const BLOCK_SIZE: u32 = 4096;
+
+/// Identifies the delta format.
+const MAGIC: &[u8] = b"BRDELTA\x01";
+
+const OP_COPY: u8 = 0;
+const OP_LITERAL: u8 = 1;
+
+/// Writes a delta that rebuilds `new` from `old` to `out`, and returns the SHA-256 digest of
+/// `new`.  Both images are read uncompressed.
+// This module is shared with updata, which generates deltas; updog only generates them in tests.
+#[allow(dead_code)]
+pub(crate) fn generate<O, N, W>(mut old: O, mut new: N, mut out: W) -> Result<Vec<u8>>
+where
+    O: Read,
+    N: Read,
+    W: Write,
+{
+    // Index the full blocks of the old image by their hashes; the first occurrence of a block
+    // wins.
+    let mut index = HashMap::new();
+    let mut block = vec![0; BLOCK_SIZE as usize];
+    let mut block_num = 0u64;
+    loop {
+        let n = read_block(&mut old, &mut block).context(error::DeltaSource)?;
+        if n < block.len() {
+            break;
+        }
+        index.entry(Sha256::digest(&block)).or_insert(block_num);
+        block_num += 1;
+    }
+
+    out.write_all(MAGIC).context(error::DeltaWrite)?;
+    out.write_all(&BLOCK_SIZE.to_le_bytes())
+        .context(error::DeltaWrite)?;
+
+    let mut hasher = Sha256::new();
+    // The pending run of blocks to copy, as the first source block and the number of blocks.
+    let mut run: Option<(u64, u32)> = None;
+    loop {
+        let n = read_block(&mut new, &mut block).context(error::DeltaRead)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&block[..n]);
+        let source = if n == block.len() {
+            index.get(&Sha256::digest(&block)).copied()
+        } else {
+            None
+        };
+        match (run, source) {
+            (Some((start, count)), Some(source))
+                if source == start + u64::from(count) && count < u32::MAX =>
+            {
+                run = Some((start, count + 1));
+            }
+            (_, Some(source)) => {
+                write_copy(&mut out, run)?;
+                run = Some((source, 1));
+            }
+            (_, None) => {
+                write_copy(&mut out, run)?;
+                run = None;
+                out.write_all(&[OP_LITERAL]).context(error::DeltaWrite)?;
+                // n is at most BLOCK_SIZE, so this can't truncate.
+                #[allow(clippy::cast_possible_truncation)]
+                out.write_all(&(n as u32).to_le_bytes())
+                    .context(error::DeltaWrite)?;
+                out.write_all(&block[..n]).context(error::DeltaWrite)?;
+            }
+        }
+        if n < block.len() {
+            break;
+        }
+    }
+    write_copy(&mut out, run)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Writes a copy operation for the given run of blocks, if any.
+fn write_copy<W: Write>(out: &mut W, run: Option<(u64, u32)>) -> Result<()> {
+    if let Some((start, count)) = run {
+        out.write_all(&[OP_COPY]).context(error::DeltaWrite)?;
+        out.write_all(&start.to_le_bytes())
+            .context(error::DeltaWrite)?;
+        out.write_all(&count.to_le_bytes())
+            .context(error::DeltaWrite)?;
+    }
+    Ok(())
+}
+
+/// Applies the uncompressed delta read from `delta` to `source`, writing the new image to `out`.
+/// The caller is responsible for checking the result against a known digest; blocks copied from
+/// `source` are only as good as `source` is.
+// updata, which shares this module, only generates deltas.
+#[allow(dead_code)]
+pub(crate) fn apply<S, D, W>(source: &mut S, mut delta: D, out: &mut W) -> Result<()>
+where
+    S: Read + Seek,
+    D: Read,
+    W: Write,
+{
+    let mut magic = [0; MAGIC.len()];
+    delta.read_exact(&mut magic).context(error::DeltaRead)?;
+    ensure!(
+        magic == MAGIC,
+        error::DeltaFormat {
+            reason: "bad magic"
+        }
+    );
+    let block_size = u64::from(read_u32(&mut delta)?);
+    ensure!(
+        block_size > 0,
+        error::DeltaFormat {
+            reason: "block size is zero"
+        }
+    );
+
+    loop {
+        let mut op = [0; 1];
+        if delta.read(&mut op).context(error::DeltaRead)? == 0 {
+            break;
+        }
+        match op[0] {
+            OP_COPY => {
+                let mut start = [0; 8];
+                delta.read_exact(&mut start).context(error::DeltaRead)?;
+                let start = u64::from_le_bytes(start);
+                let count = u64::from(read_u32(&mut delta)?);
+                let (offset, len) = start
+                    .checked_mul(block_size)
+                    .zip(count.checked_mul(block_size))
+                    .context(error::DeltaFormat {
+                        reason: "copy source out of range",
+                    })?;
+                source
+                    .seek(SeekFrom::Start(offset))
+                    .context(error::DeltaSource)?;
+                let copied =
+                    io::copy(&mut (&mut *source).take(len), out).context(error::DeltaSource)?;
+                ensure!(
+                    copied == len,
+                    error::DeltaFormat {
+                        reason: "copy source past the end of the old image"
+                    }
+                );
+            }
+            OP_LITERAL => {
+                let len = u64::from(read_u32(&mut delta)?);
+                let copied =
+                    io::copy(&mut (&mut delta).take(len), out).context(error::DeltaWrite)?;
+                ensure!(
+                    copied == len,
+                    error::DeltaFormat {
+                        reason: "truncated literal"
+                    }
+                );
+            }
+            _ => {
+                return error::DeltaFormat {
+                    reason: "unknown operation",
+                }
+                .fail()
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf).context(error::DeltaRead)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Fills `buf` from `reader` unless it reaches the end first, and returns the number of bytes
+/// read.
+fn read_block<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn image(blocks: &[u8], tail: &[u8]) -> Vec<u8> {
+        let mut image = Vec::new();
+        for b in blocks {
+            image.extend_from_slice(&[*b; BLOCK_SIZE as usize]);
+        }
+        image.extend_from_slice(tail);
+        image
+    }
+
+    #[test]
+    fn roundtrip() {
+        let old = image(&[1, 2, 3, 4, 5], b"old tail");
+        // Blocks move around, repeat, and change, and the tail changes length.
+        let new = image(&[9, 1, 2, 3, 3, 5, 4], b"a longer new tail");
+        let mut delta = Vec::new();
+        let digest = generate(&old[..], &new[..], &mut delta).unwrap();
+        assert_eq!(digest, Sha256::digest(&new).to_vec());
+        // Only the changed block and the tail are carried literally.
+        assert!(delta.len() < 2 * BLOCK_SIZE as usize);
+
+        let mut out = Vec::new();
+        apply(&mut Cursor::new(&old), &delta[..], &mut out).unwrap();
+        assert_eq!(out, new);
+    }
+
+    #[test]
+    fn rejects_bad_deltas() {
+        let old = image(&[1], b"");
+        let new = image(&[1, 1], b"");
+        let mut delta = Vec::new();
+        generate(&old[..], &new[..], &mut delta).unwrap();
+
+        // Applying to a shorter source fails rather than producing a short image.
+        let mut out = Vec::new();
+        apply(&mut Cursor::new(&old[..100]), &delta[..], &mut out).unwrap_err();
+
+        let mut bad = delta.clone();
+        bad[0] = b'X';
+        apply(&mut Cursor::new(&old), &bad[..], &mut Vec::new()).unwrap_err();
+
+        let mut bad = delta;
+        bad.push(7);
+        apply(&mut Cursor::new(&old), &bad[..], &mut Vec::new()).unwrap_err();
+    }
+}
@@ -0,0 +1,811 @@
+//! The download module fetches update images into a cache under /var/lib before they're written
+//! to disk.  Images are fetched in chunks and each chunk is persisted as it arrives, so an
+//! interrupted download resumes where it left off instead of starting over.  Cached images are
+//! checked against the hashes in the signed TUF targets metadata, and after an image is written to
+//! disk, it's read back from the disk rather than the page cache and checked again.
+
+use crate::delta;
+use crate::error::{self, Result};
+use crate::progress::{Tracker, WriteProgress};
+use crate::transport::QueryParams;
+use log::{debug, info, warn};
+use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use tough::Repository;
+use update_metadata::Images;
+use url::Url;
+
+/// This is where we store partially and fully downloaded images.
+pub(crate) const DOWNLOAD_PATH: &str = "/var/lib/updog/downloads";
+
+/// How much of an image we fetch in one request; progress is saved after each chunk.
+const CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// How many times we try to fetch a chunk before giving up.  The download can be resumed later.
+const CHUNK_ATTEMPTS: u32 = 5;
+
+/// An image in the repository, described by its signed targets metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Image {
+    name: String,
+    /// The name of the image file in the repository, which includes its hash if the repository
+    /// uses consistent snapshots.
+    filename: String,
+    length: u64,
+    sha256: Vec<u8>,
+}
+
+impl Image {
+    pub(crate) fn new(repository: &Repository, name: &str) -> Result<Self> {
+        let target_name = name
+            .try_into()
+            .context(error::TargetName { target: name })?;
+        let target = repository
+            .targets()
+            .signed
+            .find_target(&target_name)
+            .ok()
+            .context(error::TargetNotFound { target: name })?;
+        let sha256 = target.hashes.sha256.to_vec();
+        let filename = if repository.root().signed.consistent_snapshot {
+            format!("{}.{}", hex::encode(&sha256), target_name.resolved())
+        } else {
+            target_name.resolved().to_string()
+        };
+        Ok(Self {
+            name: name.to_string(),
+            filename,
+            length: target.length,
+            sha256,
+        })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_parts(name: &str, length: u64, sha256: &[u8]) -> Self {
+        Self {
+            name: name.to_string(),
+            filename: name.to_string(),
+            length,
+            sha256: sha256.to_vec(),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// The name of the image in the download cache; the hash keeps partial downloads of different
+    /// images with the same name apart.
+    fn cache_name(&self) -> String {
+        format!("{}.{}", hex::encode(&self.sha256), self.name)
+    }
+}
+
+/// The root, boot, and dm-verity hash images of an update, or the deltas that produce them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ImageSet {
+    pub(crate) root: Image,
+    pub(crate) boot: Image,
+    pub(crate) hash: Image,
+}
+
+impl ImageSet {
+    pub(crate) fn new(repository: &Repository, images: &Images) -> Result<Self> {
+        Ok(Self {
+            root: Image::new(repository, &images.root)?,
+            boot: Image::new(repository, &images.boot)?,
+            hash: Image::new(repository, &images.hash)?,
+        })
+    }
+
+    pub(crate) fn images(&self) -> [&Image; 3] {
+        [&self.root, &self.boot, &self.hash]
+    }
+
+    /// Downloads each of the images, returning the paths to the root, boot, and hash images.
+    pub(crate) fn download(&self, downloader: &Downloader) -> Result<(PathBuf, PathBuf, PathBuf)> {
+        Ok((
+            downloader.download(&self.root)?,
+            downloader.download(&self.boot)?,
+            downloader.download(&self.hash)?,
+        ))
+    }
+}
+
+/// Downloads images to a cache directory, recording progress with the given tracker.
+#[derive(Debug)]
+pub(crate) struct Downloader {
+    client: reqwest::blocking::Client,
+    targets_url: Url,
+    query_params: QueryParams,
+    cache_dir: PathBuf,
+    chunk_size: u64,
+    progress: Tracker,
+}
+
+impl Downloader {
+    pub(crate) fn new<P>(
+        targets_url: Url,
+        query_params: QueryParams,
+        cache_dir: P,
+        progress: Tracker,
+    ) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            client: reqwest::blocking::Client::new(),
+            targets_url,
+            query_params,
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+            chunk_size: CHUNK_SIZE,
+            progress,
+        }
+    }
+
+    pub(crate) fn progress(&self) -> &Tracker {
+        &self.progress
+    }
+
+    /// Removes cached downloads of any images other than the given ones, so downloads for
+    /// updates we're no longer interested in don't take up space.
+    pub(crate) fn retain(&self, images: &[&Image]) -> Result<()> {
+        if !self.cache_dir.exists() {
+            return Ok(());
+        }
+        let keep: Vec<String> = images.iter().map(|i| i.cache_name()).collect();
+        for entry in fs::read_dir(&self.cache_dir).context(error::DownloadCache {
+            path: &self.cache_dir,
+        })? {
+            let path = entry
+                .context(error::DownloadCache {
+                    path: &self.cache_dir,
+                })?
+                .path();
+            let name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default();
+            let name = name.trim_end_matches(".partial");
+            if !keep.iter().any(|k| k == name) {
+                debug!("Removing stale download '{}'", path.display());
+                fs::remove_file(&path).context(error::DownloadCache { path: &path })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the cached download of the given image.
+    pub(crate) fn remove(&self, image: &Image) -> Result<()> {
+        let path = self.cache_dir.join(image.cache_name());
+        if path.exists() {
+            fs::remove_file(&path).context(error::DownloadCache { path: &path })?;
+        }
+        Ok(())
+    }
+
+    /// Downloads the given image to the cache, resuming any earlier partial download, and
+    /// returns the path to the verified download.
+    pub(crate) fn download(&self, image: &Image) -> Result<PathBuf> {
+        let path = self.cache_dir.join(image.cache_name());
+        if path.exists() {
+            debug!("Using cached download of '{}'", image.name);
+            self.progress.downloaded(image, image.length, 0);
+            return Ok(path);
+        }
+
+        fs::create_dir_all(&self.cache_dir).context(error::DownloadCache {
+            path: &self.cache_dir,
+        })?;
+        let partial_path = path.with_file_name(format!("{}.partial", image.cache_name()));
+        let mut partial = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&partial_path)
+            .context(error::DownloadCache {
+                path: &partial_path,
+            })?;
+        let mut offset = partial
+            .metadata()
+            .context(error::DownloadCache {
+                path: &partial_path,
+            })?
+            .len();
+        if offset > image.length {
+            // This can't be a prefix of the image; start over.
+            partial.set_len(0).context(error::DownloadCache {
+                path: &partial_path,
+            })?;
+            offset = 0;
+        }
+        self.progress.downloaded(image, offset, 0);
+        if offset > 0 {
+            info!(
+                "Resuming download of '{}' at {} of {} bytes",
+                image.name, offset, image.length
+            );
+        }
+
+        let url = self
+            .targets_url
+            .join(&image.filename)
+            .context(error::UrlParse {
+                url: &image.filename,
+            })?;
+        while offset < image.length {
+            let end = std::cmp::min(offset + self.chunk_size, image.length);
+            match self.fetch_chunk(&url, offset, end)? {
+                Fetched::Chunk(chunk) => {
+                    self.save_chunk(image, &mut partial, &partial_path, offset, &chunk)?;
+                    offset = end;
+                }
+                Fetched::Rest(mut reader) => {
+                    // The server ignored our range request and is sending the whole image, so
+                    // save the rest from this response rather than skipping through the image
+                    // again for every chunk.
+                    offset = self.save_rest(
+                        image,
+                        &url,
+                        &mut reader,
+                        &mut partial,
+                        &partial_path,
+                        offset,
+                    )?;
+                }
+            }
+        }
+
+        // Check the complete download against the signed metadata before using it.
+        let digest = sha256_file(&partial_path, image.length)?;
+        if digest != image.sha256 {
+            // A bad prefix would never succeed; start over next time.
+            fs::remove_file(&partial_path).context(error::DownloadCache {
+                path: &partial_path,
+            })?;
+            return error::DownloadHashMismatch {
+                target: &image.name,
+            }
+            .fail();
+        }
+        fs::rename(&partial_path, &path).context(error::DownloadCache { path: &path })?;
+        info!("Downloaded '{}'", image.name);
+        Ok(path)
+    }
+
+    /// Appends a chunk of the image that starts at `offset` to the partial download.
+    fn save_chunk(
+        &self,
+        image: &Image,
+        partial: &mut File,
+        partial_path: &Path,
+        offset: u64,
+        chunk: &[u8],
+    ) -> Result<()> {
+        partial
+            .write_all(chunk)
+            .context(error::DownloadCache { path: partial_path })?;
+        // Make sure the chunk is on disk before we count it as progress.
+        partial
+            .sync_data()
+            .context(error::DownloadCache { path: partial_path })?;
+        let end = offset + chunk.len() as u64;
+        self.progress.downloaded(image, end, end - offset);
+        debug!(
+            "Downloaded {} of {} bytes of '{}'",
+            end, image.length, image.name
+        );
+        Ok(())
+    }
+
+    /// Saves the rest of the image, a chunk at a time, from a reader positioned at `offset`.
+    /// Returns the offset reached; if the connection fails after some progress, the caller can
+    /// request the rest again.
+    fn save_rest(
+        &self,
+        image: &Image,
+        url: &Url,
+        reader: &mut dyn Read,
+        partial: &mut File,
+        partial_path: &Path,
+        start: u64,
+    ) -> Result<u64> {
+        let mut offset = start;
+        while offset < image.length {
+            let len = std::cmp::min(self.chunk_size, image.length - offset);
+            match read_chunk(reader, url.as_str(), len) {
+                Ok(chunk) => {
+                    self.save_chunk(image, partial, partial_path, offset, &chunk)?;
+                    offset += len;
+                }
+                Err(e) if offset > start => {
+                    warn!(
+                        "Failed to fetch '{}' after {} bytes: {}",
+                        image.name, offset, e
+                    );
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(offset)
+    }
+
+    /// Fetches bytes `start..end` of the file at the given URL, retrying on failure.
+    fn fetch_chunk(&self, url: &Url, start: u64, end: u64) -> Result<Fetched> {
+        let mut attempt = 1;
+        loop {
+            match self.fetch_range(url, start, end) {
+                Ok(chunk) => return Ok(chunk),
+                Err(e) if attempt < CHUNK_ATTEMPTS => {
+                    warn!(
+                        "Failed to fetch bytes {}-{} of '{}' (attempt {} of {}): {}",
+                        start, end, url, attempt, CHUNK_ATTEMPTS, e
+                    );
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Fetches bytes `start..end` of the file at the given URL.  Local files are read directly, and
+    /// other URLs are fetched with an HTTP range request.
+    fn fetch_range(&self, url: &Url, start: u64, end: u64) -> Result<Fetched> {
+        if url.scheme() == "file" {
+            let path = url
+                .to_file_path()
+                .ok()
+                .context(error::LocalRepositoryPath { path: url.path() })?;
+            let mut f = File::open(&path).context(error::FetchChunk { url: url.as_str() })?;
+            f.seek(SeekFrom::Start(start))
+                .context(error::FetchChunk { url: url.as_str() })?;
+            return Ok(Fetched::Chunk(read_chunk(
+                &mut f,
+                url.as_str(),
+                end - start,
+            )?));
+        }
+
+        let full_url = self.query_params.add_params_to_url(url.clone());
+        let mut response = self
+            .client
+            .get(full_url)
+            .header(RANGE, format!("bytes={}-{}", start, end - 1))
+            .send()
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .context(error::FetchRange { url: url.as_str() })?;
+        if response.status() == StatusCode::PARTIAL_CONTENT {
+            return Ok(Fetched::Chunk(read_chunk(
+                &mut response,
+                url.as_str(),
+                end - start,
+            )?));
+        }
+        // The server ignored the range and is sending the whole file; skip to the chunk.
+        io::copy(&mut (&mut response).take(start), &mut io::sink())
+            .context(error::FetchChunk { url: url.as_str() })?;
+        Ok(Fetched::Rest(Box::new(response)))
+    }
+}
+
+/// The data returned for a request for part of an image.
+enum Fetched {
+    /// The requested bytes.
+    Chunk(Vec<u8>),
+    /// The rest of the image, starting with the requested bytes.
+    Rest(Box<dyn Read>),
+}
+
+/// Reads exactly `len` bytes from `reader`, which is fetching the given URL.
+fn read_chunk(reader: &mut dyn Read, url: &str, len: u64) -> Result<Vec<u8>> {
+    let mut chunk = Vec::new();
+    reader
+        .take(len)
+        .read_to_end(&mut chunk)
+        .context(error::FetchChunk { url })?;
+    ensure!(
+        chunk.len() as u64 == len,
+        error::ShortChunk {
+            url,
+            expected: len,
+            actual: chunk.len() as u64,
+        }
+    );
+    Ok(chunk)
+}
+
+/// Opens the given file for reading and drops any of it that's in the page cache, so what we read
+/// comes from the disk.  The file's data must already be synced.
+pub(crate) fn open_uncached(path: &Path) -> io::Result<File> {
+    let f = File::open(path)?;
+    posix_fadvise(f.as_raw_fd(), 0, 0, PosixFadviseAdvice::POSIX_FADV_DONTNEED)
+        .map_err(io::Error::from)?;
+    Ok(f)
+}
+
+/// Returns the SHA-256 digest of the first `len` bytes of the given file.
+fn sha256_file(path: &Path, len: u64) -> Result<Vec<u8>> {
+    let f = File::open(path).context(error::DownloadCache { path })?;
+    let mut hasher = HashingWriter::new(io::sink());
+    io::copy(&mut f.take(len), &mut hasher).context(error::DownloadCache { path })?;
+    Ok(hasher.finish().0)
+}
+
+/// Writes the LZ4-compressed image at `cache_path` to `disk_path`, then reads the written data
+/// back to make sure it matches what we decompressed.  Returns the length of the written data.
+pub(crate) fn write_image_to_disk<P1, P2>(
+    image: &Image,
+    cache_path: P1,
+    disk_path: P2,
+    progress: &Tracker,
+) -> Result<u64>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let cache_path = cache_path.as_ref();
+    let disk_path = disk_path.as_ref();
+
+    // Check the compressed image against the signed metadata as we read it, in case the cache was
+    // changed since it was downloaded.
+    let cache_file = File::open(cache_path).context(error::DownloadCache { path: cache_path })?;
+    let mut compressed = HashingReader::new(WriteProgress::new(cache_file, image, progress));
+    // Note: the file extension for the compression type we're using should be removed in
+    // retrieve_migrations below.
+    let mut reader = lz4::Decoder::new(&mut compressed).context(error::Lz4Decode {
+        target: &image.name,
+    })?;
+    let f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(disk_path)
+        .context(error::OpenPartition { path: disk_path })?;
+    let mut writer = HashingWriter::new(f);
+    io::copy(&mut reader, &mut writer).context(error::WriteUpdate)?;
+    // Read any trailing bytes that the decoder didn't need so they're included in the hash.
+    io::copy(&mut compressed, &mut io::sink())
+        .context(error::DownloadCache { path: cache_path })?;
+    let (written_digest, written_len, f) = writer.finish();
+    f.sync_all().context(error::WriteUpdate)?;
+    if compressed.finish() != image.sha256 {
+        // Don't use the bad download again; it'll be fetched again next time.
+        fs::remove_file(cache_path).context(error::DownloadCache { path: cache_path })?;
+        return error::DownloadHashMismatch {
+            target: &image.name,
+        }
+        .fail();
+    }
+
+    verify_partition(&image.name, disk_path, &written_digest, written_len)?;
+    Ok(written_len)
+}
+
+/// Applies the LZ4-compressed delta at `cache_path` to the partition at `source_path`, writing the
+/// result to `disk_path`.  The result must match `sha256`, the digest of the uncompressed image
+/// from the signed manifest, and is read back like a full image.  Returns the length of the
+/// result.
+pub(crate) fn write_delta_to_disk<P1, P2, P3>(
+    delta: &Image,
+    cache_path: P1,
+    source_path: P2,
+    disk_path: P3,
+    sha256: &str,
+    progress: &Tracker,
+) -> Result<u64>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    P3: AsRef<Path>,
+{
+    let cache_path = cache_path.as_ref();
+    let source_path = source_path.as_ref();
+    let disk_path = disk_path.as_ref();
+    let expected = hex::decode(sha256).context(error::DeltaDigest {
+        target: &delta.name,
+    })?;
+
+    let cache_file = File::open(cache_path).context(error::DownloadCache { path: cache_path })?;
+    let mut compressed = HashingReader::new(WriteProgress::new(cache_file, delta, progress));
+    let reader = lz4::Decoder::new(&mut compressed).context(error::Lz4Decode {
+        target: &delta.name,
+    })?;
+    let mut source = File::open(source_path).context(error::OpenPartition { path: source_path })?;
+    let f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(disk_path)
+        .context(error::OpenPartition { path: disk_path })?;
+    let mut writer = HashingWriter::new(f);
+    delta::apply(&mut source, reader, &mut writer)?;
+    io::copy(&mut compressed, &mut io::sink())
+        .context(error::DownloadCache { path: cache_path })?;
+    let (written_digest, written_len, f) = writer.finish();
+    f.sync_all().context(error::WriteUpdate)?;
+    ensure!(
+        compressed.finish() == delta.sha256,
+        error::DownloadHashMismatch {
+            target: &delta.name
+        }
+    );
+    ensure!(
+        written_digest == expected,
+        error::DeltaHashMismatch {
+            target: &delta.name
+        }
+    );
+
+    verify_partition(&delta.name, disk_path, &written_digest, written_len)?;
+    Ok(written_len)
+}
+
+/// Reads back the first `len` bytes written to `disk_path` and makes sure they match `digest`.
+fn verify_partition(name: &str, disk_path: &Path, digest: &[u8], len: u64) -> Result<()> {
+    // Read back what we wrote.
+    let f = open_uncached(disk_path).context(error::OpenPartition { path: disk_path })?;
+    let mut readback = HashingWriter::new(io::sink());
+    io::copy(&mut f.take(len), &mut readback)
+        .context(error::VerifyPartition { path: disk_path })?;
+    let (readback_digest, readback_len, _) = readback.finish();
+    ensure!(
+        readback_len == len && readback_digest == digest,
+        error::PartitionMismatch {
+            target: name,
+            path: disk_path,
+        }
+    );
+    Ok(())
+}
+
+/// Wraps a reader, calculating the SHA-256 digest of everything read.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.hasher.finalize().to_vec()
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Wraps a writer, calculating the SHA-256 digest and length of everything written.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    len: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            len: 0,
+        }
+    }
+
+    fn finish(self) -> (Vec<u8>, u64, W) {
+        (self.hasher.finalize().to_vec(), self.len, self.inner)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::TempDir;
+
+    fn image(name: &str, data: &[u8]) -> Image {
+        Image::from_parts(name, data.len() as u64, &Sha256::digest(data))
+    }
+
+    fn downloader(repo_dir: &Path, cache_dir: &Path) -> Downloader {
+        let mut downloader = Downloader::new(
+            Url::from_directory_path(repo_dir).unwrap(),
+            QueryParams::default(),
+            cache_dir,
+            Tracker::default(),
+        );
+        downloader.chunk_size = 7;
+        downloader
+    }
+
+    #[test]
+    fn resumes_download() {
+        let repo_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let data = b"an image that takes a few chunks to download";
+        fs::write(repo_dir.path().join("image.lz4"), data).unwrap();
+        let image = image("image.lz4", data);
+
+        // Pretend an earlier download stopped partway through.
+        let partial = cache_dir
+            .path()
+            .join(format!("{}.partial", image.cache_name()));
+        fs::write(&partial, &data[..10]).unwrap();
+
+        let downloader = downloader(repo_dir.path(), cache_dir.path());
+        let path = downloader.download(&image).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), data.to_vec());
+        assert!(!partial.exists());
+
+        // Other downloads are cleaned up.
+        fs::write(cache_dir.path().join("stale.partial"), "stale").unwrap();
+        downloader.retain(&[&image]).unwrap();
+        assert_eq!(fs::read_dir(cache_dir.path()).unwrap().count(), 1);
+        downloader.remove(&image).unwrap();
+        assert!(!path.exists());
+    }
+
+    /// Serves `data` over HTTP in the background, ignoring range requests, and returns the
+    /// server's URL along with a count of the requests it receives.
+    fn serve_ignoring_ranges(data: &'static [u8]) -> (Url, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                    request.push(byte[0]);
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                let headers = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    data.len()
+                );
+                stream.write_all(headers.as_bytes()).unwrap();
+                stream.write_all(data).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn streams_when_range_ignored() {
+        let data = b"an image from a server that doesn't do range requests";
+        let (url, requests) = serve_ignoring_ranges(data);
+        let cache_dir = TempDir::new().unwrap();
+        let image = image("image.lz4", data);
+        let partial = cache_dir
+            .path()
+            .join(format!("{}.partial", image.cache_name()));
+        fs::write(&partial, &data[..10]).unwrap();
+
+        let mut downloader = downloader(cache_dir.path(), cache_dir.path());
+        downloader.targets_url = url;
+        let path = downloader.download(&image).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), data.to_vec());
+        // The rest of the image came from one response rather than one per chunk.
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn rejects_bad_download() {
+        let repo_dir = TempDir::new().unwrap();
+        let cache_dir = TempDir::new().unwrap();
+        let data = b"the real image";
+        fs::write(repo_dir.path().join("image.lz4"), b"a fake image!!").unwrap();
+        let image = image("image.lz4", data);
+
+        let downloader = downloader(repo_dir.path(), cache_dir.path());
+        downloader.download(&image).unwrap_err();
+        // The bad download isn't kept.
+        assert_eq!(fs::read_dir(cache_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn writes_and_verifies_image() {
+        let dir = TempDir::new().unwrap();
+        let data = [1; 4096];
+        let mut encoder = lz4::EncoderBuilder::new().build(Vec::new()).unwrap();
+        encoder.write_all(&data).unwrap();
+        let (compressed, result) = encoder.finish();
+        result.unwrap();
+
+        let cache_path = dir.path().join("root.lz4");
+        fs::write(&cache_path, &compressed).unwrap();
+        let image = image("root.lz4", &compressed);
+        let disk_path = dir.path().join("disk");
+        let len =
+            write_image_to_disk(&image, &cache_path, &disk_path, &Tracker::default()).unwrap();
+        assert_eq!(len, data.len() as u64);
+        assert_eq!(fs::read(&disk_path).unwrap(), data.to_vec());
+
+        // Images that don't match the signed hash aren't accepted.
+        let mut bad_image = image;
+        bad_image.sha256 = Sha256::digest(b"something else").to_vec();
+        write_image_to_disk(&bad_image, &cache_path, &disk_path, &Tracker::default()).unwrap_err();
+        // ...and the download is removed so it's fetched again.
+        assert!(!cache_path.exists());
+    }
+
+    #[test]
+    fn writes_delta_and_checks_result() {
+        let dir = TempDir::new().unwrap();
+        let old = [[1; 4096], [2; 4096]].concat();
+        let new = [[2; 4096], [3; 4096]].concat();
+        let mut encoder = lz4::EncoderBuilder::new().build(Vec::new()).unwrap();
+        let digest = delta::generate(&old[..], &new[..], &mut encoder).unwrap();
+        let (compressed, result) = encoder.finish();
+        result.unwrap();
+
+        let cache_path = dir.path().join("root.delta");
+        fs::write(&cache_path, &compressed).unwrap();
+        let source_path = dir.path().join("active");
+        fs::write(&source_path, &old).unwrap();
+        let disk_path = dir.path().join("inactive");
+        let image = image("root.delta", &compressed);
+        write_delta_to_disk(
+            &image,
+            &cache_path,
+            &source_path,
+            &disk_path,
+            &hex::encode(&digest),
+            &Tracker::default(),
+        )
+        .unwrap();
+        assert_eq!(fs::read(&disk_path).unwrap(), new);
+
+        // If the active partition isn't what the delta expects, the result doesn't match.
+        fs::write(&source_path, [[1; 4096], [4; 4096]].concat()).unwrap();
+        write_delta_to_disk(
+            &image,
+            &cache_path,
+            &source_path,
+            &disk_path,
+            &hex::encode(&digest),
+            &Tracker::default(),
+        )
+        .unwrap_err();
+    }
+}
@@ -46,6 +46,42 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Invalid output digest for delta '{}': {}", target, source))]
+    DeltaDigest {
+        target: String,
+        source: hex::FromHexError,
+    },
+
+    #[snafu(display("Invalid delta: {}", reason))]
+    DeltaFormat {
+        reason: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Applying delta '{}' didn't produce the expected image", target))]
+    DeltaHashMismatch {
+        target: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to read delta: {}", source))]
+    DeltaRead {
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to read delta source image: {}", source))]
+    DeltaSource {
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to write delta output: {}", source))]
+    DeltaWrite {
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to create directory: {:?}", path))]
     DirCreate {
         backtrace: Backtrace,
@@ -53,6 +89,28 @@ pub(crate) enum Error {
         path: PathBuf,
     },
 
+    #[snafu(display("Failed to access download cache '{}': {}", path.display(), source))]
+    DownloadCache {
+        path: PathBuf,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Downloaded target '{}' doesn't match its signed hash", target))]
+    DownloadHashMismatch {
+        target: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to export bundle to '{}': {}", path.display(), source))]
+    ExportBundle {
+        path: PathBuf,
+        source: tough::error::Error,
+    },
+
+    #[snafu(display("Local repository path '{}' must be absolute", path.display()))]
+    LocalRepositoryPath { path: PathBuf },
+
     #[snafu(display("Logger setup error: {}", source))]
     Logger { source: log::SetLoggerError },
 
@@ -72,6 +130,20 @@ pub(crate) enum Error {
         source: tough::error::Error,
     },
 
+    #[snafu(display("Failed to read from '{}': {}", url, source))]
+    FetchChunk {
+        url: String,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to fetch '{}': {}", url, source))]
+    FetchRange {
+        url: String,
+        source: reqwest::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Manifest load error: {}", source))]
     ManifestLoad {
         source: tough::error::Error,
@@ -100,6 +172,13 @@ pub(crate) enum Error {
         name: String,
     },
 
+    #[snafu(display("Failed to open image {}: {}", path.display(), source))]
+    OpenImage {
+        path: PathBuf,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to open partition {}: {}", path.display(), source))]
     OpenPartition {
         path: PathBuf,
@@ -114,6 +193,17 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display(
+        "Data read back from '{}' doesn't match target '{}' written to it",
+        path.display(),
+        target
+    ))]
+    PartitionMismatch {
+        target: String,
+        path: PathBuf,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed to read OS disk partition table: {}", source))]
     PartitionTableRead {
         // signpost::Error triggers clippy::large_enum_variant
@@ -130,6 +220,13 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Failed to access prepared update '{}': {}", path, source))]
+    PreparedUpdate {
+        path: &'static str,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Unable to parse proxy setting '{}': {}", proxy, source))]
     Proxy {
         proxy: String,
@@ -159,12 +256,22 @@ pub(crate) enum Error {
         backtrace: Backtrace,
     },
 
+    #[snafu(display("Expected {} bytes from '{}' but received {}", expected, url, actual))]
+    ShortChunk {
+        url: String,
+        expected: u64,
+        actual: u64,
+    },
+
     #[snafu(display("Failed to create tmpfile for root mount"))]
     TmpFileCreate {
         backtrace: Backtrace,
         source: std::io::Error,
     },
 
+    #[snafu(display("Updates are disabled by the update mode setting"))]
+    UpdatesDisabled { backtrace: Backtrace },
+
     #[snafu(display("No update available"))]
     UpdateNotAvailable { backtrace: Backtrace },
 
@@ -177,6 +284,48 @@ pub(crate) enum Error {
     #[snafu(display("--wave-file <path> required to add waves to update"))]
     WaveFileArg { backtrace: Backtrace },
 
+    #[snafu(display("Failed to read back update data from '{}': {}", path.display(), source))]
+    VerifyPartition {
+        path: PathBuf,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("'{}' doesn't contain a dm-verity superblock", path.display()))]
+    VerityHeader { path: PathBuf, backtrace: Backtrace },
+
+    #[snafu(display(
+        "dm-verity hash tree in '{}' doesn't match '{}'",
+        hash_path.display(),
+        root_path.display()
+    ))]
+    VerityHashTree {
+        hash_path: PathBuf,
+        root_path: PathBuf,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Boot image in '{}' doesn't use dm-verity root hash {}", path.display(), root_hash))]
+    VerityRootHash {
+        path: PathBuf,
+        root_hash: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Unsupported dm-verity superblock in '{}': {}", path.display(), reason))]
+    VerityUnsupported {
+        path: PathBuf,
+        reason: String,
+        backtrace: Backtrace,
+    },
+
+    #[snafu(display("Failed to write delta {}: {}", path.display(), source))]
+    WriteDelta {
+        path: PathBuf,
+        source: std::io::Error,
+        backtrace: Backtrace,
+    },
+
     #[snafu(display("Failed writing update data to disk: {}", source))]
     WriteUpdate {
         source: std::io::Error,
@@ -0,0 +1,20 @@
+//! The `local_repository` module locates a repository on local storage, like a bundle written by
+//! `updata export-bundle`.  It's shared with updata, whose tests read exported bundles the same
+//! way updog does.
+
+use crate::error::{self, Result};
+use snafu::OptionExt;
+use std::path::Path;
+use url::Url;
+
+/// Returns file:// URLs for the `metadata` and `targets` directories of the repository at the
+/// given path, which must be absolute.
+pub(crate) fn urls(path: &Path) -> Result<(Url, Url)> {
+    let directory_url = |name| {
+        let path = path.join(name);
+        Url::from_directory_path(&path)
+            .ok()
+            .context(error::LocalRepositoryPath { path })
+    };
+    Ok((directory_url("metadata")?, directory_url("targets")?))
+}
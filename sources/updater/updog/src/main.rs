@@ -1,15 +1,24 @@
 #![deny(rust_2018_idioms)]
 #![warn(clippy::pedantic)]
 
+mod delta;
+mod download;
 mod error;
+mod local_repository;
+mod progress;
 mod transport;
+mod verity;
 
+use crate::download::{
+    write_delta_to_disk, write_image_to_disk, Downloader, Image, ImageSet, DOWNLOAD_PATH,
+};
 use crate::error::Result;
+use crate::progress::Tracker;
 use crate::transport::{HttpQueryTransport, QueryParams};
 use bottlerocket_release::BottlerocketRelease;
-use chrono::Utc;
-use log::debug;
-use model::modeled_types::FriendlyVersion;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use log::{debug, warn};
+use model::modeled_types::{CronSchedule, FriendlyVersion, MaintenanceWindowMinutes};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use signal_hook::consts::SIGTERM;
@@ -18,14 +27,15 @@ use signpost::State;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{ErrorCompat, OptionExt, ResultExt};
 use std::convert::{TryFrom, TryInto};
-use std::fs::{self, File, OpenOptions};
-use std::io;
-use std::path::Path;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 use std::process;
 use std::str::FromStr;
 use std::thread;
 use tough::{Repository, RepositoryLoader};
-use update_metadata::{find_migrations, Manifest, Update};
+use update_metadata::progress::{Phase, PROGRESS_PATH};
+use update_metadata::{find_migrations, Images, Manifest, Update};
 use url::Url;
 
 #[cfg(target_arch = "x86_64")]
@@ -42,6 +52,9 @@ const MIGRATION_PATH: &str = "/var/lib/bottlerocket-migrations";
 /// This is where we store the TUF metadata used by migrator after reboot.
 const METADATA_PATH: &str = "/var/cache/bottlerocket-metadata";
 
+/// This is where `updog prepare` records the update it downloaded.
+const PREPARED_PATH: &str = "/var/lib/updog/prepared.json";
+
 #[derive(Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 enum Command {
@@ -52,6 +65,28 @@ enum Command {
     UpdateImage,
     UpdateApply,
     UpdateRevert,
+    AutoUpdate,
+}
+
+/// How updates are applied, from the `mode` setting.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum UpdateMode {
+    /// `auto-update` applies updates and reboots, during the maintenance window if there is one.
+    Automatic,
+    /// Updates are only applied when requested.
+    #[default]
+    Managed,
+    /// Updates are only checked for, and reported as skipped.
+    Disabled,
+}
+
+/// The time during which `auto-update` may start an update: the window opens at times matching
+/// `start`, in UTC, and stays open for `duration_minutes`.
+#[derive(Debug, Deserialize)]
+struct MaintenanceWindow {
+    start: CronSchedule,
+    duration_minutes: MaintenanceWindowMinutes,
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,9 +98,37 @@ struct Config {
     ignore_waves: bool,
     https_proxy: Option<String>,
     no_proxy: Option<Vec<String>>,
-    // TODO API sourced configuration, eg.
-    // blacklist: Option<Vec<Version>>,
-    // mode: Option<{Automatic, Managed, Disabled}>
+    // A local copy of the repository, e.g. an offline bundle on mounted media, to use instead of
+    // the base URLs.
+    local_repository: Option<PathBuf>,
+    #[serde(default)]
+    mode: UpdateMode,
+    maintenance_window: Option<MaintenanceWindow>,
+    // Versions that are never chosen as updates.
+    #[serde(default)]
+    denylist: Vec<FriendlyVersion>,
+}
+
+/// Why an update newer than the running version wasn't chosen.
+#[derive(Debug, PartialEq)]
+enum SkipReason {
+    Denylisted,
+    WaveNotReached,
+    VersionLock(Version),
+    OutsideWindow,
+    Disabled,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::Denylisted => write!(f, "the version is in the denylist"),
+            SkipReason::WaveNotReached => write!(f, "this host's update wave hasn't been reached"),
+            SkipReason::VersionLock(lock) => write!(f, "version-lock is set to {}", lock),
+            SkipReason::OutsideWindow => write!(f, "outside the maintenance window"),
+            SkipReason::Disabled => write!(f, "updates are disabled by the update mode setting"),
+        }
+    }
 }
 
 /// Prints a more specific message before exiting through usage().
@@ -86,7 +149,10 @@ SUBCOMMANDS:
         [ --ignore-waves ]            Ignore release schedule when checking
                                       for a new update
 
-    prepare                 Download update files and migration targets
+    prepare                 Download update files and migration targets without writing them;
+                            a later 'update' or 'update-image' writes them from the cache
+        [ -i | --image version ]      Prepare a specfic image version
+        [ -n | --now ]                Prepare immediately, ignoring wave limits
 
     update                  Perform an update if available
         [ -i | --image version ]      Update to a specfic image version
@@ -103,6 +169,10 @@ SUBCOMMANDS:
 
     update-revert           Revert actions done by 'update-apply'
 
+    auto-update             If the update mode is 'automatic' and the maintenance window is open,
+                            perform an update if available; run by 'thar-be-updates auto-update'
+        [ -r | --reboot ]             Reboot into new update on success
+
 GLOBAL OPTIONS:
     [ -j | --json ]               JSON-formatted output
     [ --log-level trace|debug|info|warn|error ]  Set logging verbosity");
@@ -116,20 +186,34 @@ fn load_config() -> Result<Config> {
     Ok(config)
 }
 
+/// Returns the metadata and targets URLs of the repository.  A local repository has `metadata`
+/// and `targets` directories, as exported by `updata export-bundle`.
+fn repository_urls(config: &Config) -> Result<(Url, Url)> {
+    if let Some(local_repository) = &config.local_repository {
+        return local_repository::urls(local_repository);
+    }
+
+    Ok((
+        Url::parse(&config.metadata_base_url).context(error::UrlParse {
+            url: &config.metadata_base_url,
+        })?,
+        Url::parse(&config.targets_base_url).context(error::UrlParse {
+            url: &config.targets_base_url,
+        })?,
+    ))
+}
+
 fn load_repository(transport: HttpQueryTransport, config: &Config) -> Result<Repository> {
     fs::create_dir_all(METADATA_PATH).context(error::CreateMetadataCache {
         path: METADATA_PATH,
     })?;
+    let (metadata_url, targets_url) = repository_urls(config)?;
     RepositoryLoader::new(
         File::open(TRUSTED_ROOT_PATH).context(error::OpenRoot {
             path: TRUSTED_ROOT_PATH,
         })?,
-        Url::parse(&config.metadata_base_url).context(error::UrlParse {
-            url: &config.metadata_base_url,
-        })?,
-        Url::parse(&config.targets_base_url).context(error::UrlParse {
-            url: &config.targets_base_url,
-        })?,
+        metadata_url,
+        targets_url,
     )
     .transport(transport)
     .load()
@@ -141,6 +225,7 @@ fn applicable_updates<'a>(
     variant: &str,
     ignore_waves: bool,
     seed: u32,
+    denylist: &[Version],
 ) -> Vec<&'a Update> {
     let mut updates: Vec<&Update> = manifest
         .updates
@@ -149,6 +234,7 @@ fn applicable_updates<'a>(
             u.variant == *variant
                 && u.arch == TARGET_ARCH
                 && u.version <= u.max_version
+                && !denylist.contains(&u.version)
                 && (ignore_waves || u.update_ready(seed, Utc::now()))
         })
         .collect();
@@ -157,39 +243,66 @@ fn applicable_updates<'a>(
     updates
 }
 
-// TODO use config if there is api-sourced configuration that could affect this
-// TODO updog.toml may include settings that cause us to ignore/delay
-// certain/any updates;
-//  Ignore Specific Target Version
-//  Ignore Any Target
-//  ...
+/// Returns the version that `version_lock` names, or None for "latest".
+fn parse_version_lock(version_lock: &str) -> Result<Option<Version>> {
+    if version_lock == "latest" {
+        return Ok(None);
+    }
+    // Make sure the version string from the config is a valid version string that might be prefixed with 'v'
+    let friendly_version_lock =
+        FriendlyVersion::try_from(version_lock).context(error::BadVersionConfig {
+            version_str: version_lock,
+        })?;
+    // Convert back to semver::Version
+    friendly_version_lock
+        .try_into()
+        .map(Some)
+        .context(error::BadVersion {
+            version_str: version_lock,
+        })
+}
+
+/// Returns the versions in the configured denylist.
+fn denied_versions(config: &Config) -> Result<Vec<Version>> {
+    config
+        .denylist
+        .iter()
+        .map(|v| {
+            v.clone().try_into().context(error::BadVersion {
+                version_str: v.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The settings that decide which update this host takes.
+struct UpdatePolicy<'a> {
+    variant: &'a str,
+    ignore_waves: bool,
+    seed: u32,
+    version_lock: &'a str,
+    denylist: &'a [Version],
+}
+
 fn update_required<'a>(
     manifest: &'a Manifest,
     version: &Version,
-    variant: &str,
-    ignore_waves: bool,
-    seed: u32,
-    version_lock: &str,
+    policy: &UpdatePolicy<'_>,
     force_version: Option<Version>,
 ) -> Result<Option<&'a Update>> {
-    let updates = applicable_updates(manifest, variant, ignore_waves, seed);
+    let updates = applicable_updates(
+        manifest,
+        policy.variant,
+        policy.ignore_waves,
+        policy.seed,
+        policy.denylist,
+    );
 
     if let Some(forced_version) = force_version {
         return Ok(updates.into_iter().find(|u| u.version == forced_version));
     }
 
-    if version_lock != "latest" {
-        // Make sure the version string from the config is a valid version string that might be prefixed with 'v'
-        let friendly_version_lock =
-            FriendlyVersion::try_from(version_lock).context(error::BadVersionConfig {
-                version_str: version_lock,
-            })?;
-        // Convert back to semver::Version
-        let semver_version_lock = friendly_version_lock
-            .try_into()
-            .context(error::BadVersion {
-                version_str: version_lock,
-            })?;
+    if let Some(semver_version_lock) = parse_version_lock(policy.version_lock)? {
         // If the configured version-lock matches our current version, we won't update to the same version
         return if semver_version_lock == *version {
             Ok(None)
@@ -210,32 +323,61 @@ fn update_required<'a>(
     Ok(None)
 }
 
-fn write_target_to_disk<P: AsRef<Path>>(
-    repository: &Repository,
-    target: &str,
-    disk_path: P,
-) -> Result<()> {
-    let target = target.try_into().context(error::TargetName { target })?;
-    let reader = repository
-        .read_target(&target)
-        .context(error::Metadata)?
-        .context(error::TargetNotFound {
-            target: target.raw(),
-        })?;
-    // Note: the file extension for the compression type we're using should be removed in
-    // retrieve_migrations below.
-    let mut reader = lz4::Decoder::new(reader).context(error::Lz4Decode {
-        target: target.raw(),
-    })?;
-    let mut f = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(disk_path.as_ref())
-        .context(error::OpenPartition {
-            path: disk_path.as_ref(),
-        })?;
-    io::copy(&mut reader, &mut f).context(error::WriteUpdate)?;
-    Ok(())
+/// Returns the updates newer than the running version that `update_required` passes over, with
+/// the reason for each, newest first.
+fn skipped_updates<'a>(
+    manifest: &'a Manifest,
+    version: &Version,
+    variant: &str,
+    ignore_waves: bool,
+    seed: u32,
+    version_lock: Option<&Version>,
+    denylist: &[Version],
+) -> Vec<(&'a Update, SkipReason)> {
+    let now = Utc::now();
+    let mut skipped: Vec<(&Update, SkipReason)> = manifest
+        .updates
+        .iter()
+        .filter(|u| {
+            u.variant == *variant
+                && u.arch == TARGET_ARCH
+                && u.version <= u.max_version
+                && u.version > *version
+        })
+        .filter_map(|u| {
+            let reason = if denylist.contains(&u.version) {
+                SkipReason::Denylisted
+            } else if !ignore_waves && !u.update_ready(seed, now) {
+                SkipReason::WaveNotReached
+            } else {
+                match version_lock {
+                    Some(lock) if *lock != u.version => SkipReason::VersionLock(lock.clone()),
+                    _ => return None,
+                }
+            };
+            Some((u, reason))
+        })
+        .collect();
+    skipped.sort_unstable_by(|a, b| b.0.version.cmp(&a.0.version));
+    skipped
+}
+
+/// Returns whether `now` is in the maintenance window, that is, within the window's duration after
+/// a time matching its start schedule.  Without a maintenance window, it's always open.
+fn in_maintenance_window(window: Option<&MaintenanceWindow>, now: DateTime<Utc>) -> bool {
+    match window {
+        None => true,
+        Some(window) => (0..*window.duration_minutes).any(|ago| {
+            let time = now - chrono::Duration::minutes(i64::from(ago));
+            window.start.matches(
+                time.minute(),
+                time.hour(),
+                time.day(),
+                time.month(),
+                time.weekday().num_days_from_sunday(),
+            )
+        }),
+    }
 }
 
 /// Store required migrations for an update in persistent storage. All intermediate migrations
@@ -272,7 +414,137 @@ fn retrieve_migrations(
     Ok(())
 }
 
-fn update_image(update: &Update, repository: &Repository) -> Result<()> {
+/// An update with its images resolved against the signed targets metadata.  `updog prepare` saves
+/// this after downloading the images, so a later `update-image` can write them from the download
+/// cache without loading the repository.
+#[derive(Debug, Serialize, Deserialize)]
+struct StagedUpdate {
+    update: Update,
+    /// The version we were running when the update was staged; deltas only apply to it.
+    from: Version,
+    images: ImageSet,
+    /// Deltas from `from`, and the digests of the images they produce, if the update has them.
+    deltas: Option<(ImageSet, Images)>,
+}
+
+impl StagedUpdate {
+    fn new(update: &Update, repository: &Repository, current_version: &Version) -> Result<Self> {
+        let deltas = match update
+            .deltas
+            .iter()
+            .find(|delta| delta.from == *current_version)
+        {
+            Some(delta) => Some((
+                ImageSet::new(repository, &delta.images)?,
+                delta.sha256.clone(),
+            )),
+            None => None,
+        };
+        Ok(Self {
+            update: update.clone(),
+            from: current_version.clone(),
+            images: ImageSet::new(repository, &update.images)?,
+            deltas,
+        })
+    }
+
+    /// Returns whether the staged update can be applied to the running version and matches the
+    /// requested version, if any.
+    fn applies_to(
+        &self,
+        current_version: &Version,
+        variant: &str,
+        force_version: Option<&Version>,
+    ) -> bool {
+        self.from == *current_version
+            && self.update.variant == variant
+            && force_version.iter().all(|v| **v == self.update.version)
+    }
+
+    /// Returns why the staged update is no longer wanted under the current denylist and version
+    /// lock, if it isn't.
+    fn skip_reason(
+        &self,
+        version_lock: Option<&Version>,
+        denylist: &[Version],
+    ) -> Option<SkipReason> {
+        if denylist.contains(&self.update.version) {
+            return Some(SkipReason::Denylisted);
+        }
+        match version_lock {
+            Some(lock) if *lock != self.update.version => {
+                Some(SkipReason::VersionLock(lock.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn all_images(&self) -> Vec<&Image> {
+        let mut images = self.images.images().to_vec();
+        if let Some((deltas, _)) = &self.deltas {
+            images.extend_from_slice(&deltas.images());
+        }
+        images
+    }
+
+    /// Downloads the deltas if the update has them, and the full images otherwise, or if the
+    /// deltas can't be downloaded.
+    fn download(&self, downloader: &Downloader) -> Result<()> {
+        downloader.retain(&self.all_images())?;
+        if let Some((deltas, _)) = &self.deltas {
+            downloader.progress().track(deltas, false);
+            match deltas.download(downloader) {
+                Ok(_) => return Ok(()),
+                Err(e) => warn!(
+                    "Failed to download delta update from {}, downloading full images instead: {}",
+                    self.from, e
+                ),
+            }
+        }
+        downloader.progress().track(&self.images, false);
+        self.images.download(downloader)?;
+        Ok(())
+    }
+
+    /// Writes the update to the inactive partition set.  If the update has deltas from the running
+    /// version, we try those first; they only carry the blocks that changed, and are applied to
+    /// the partitions we're running from.  If anything goes wrong, we fall back to the full
+    /// images.  Images that are already in the download cache aren't downloaded again.
+    fn write(&self, downloader: &Downloader) -> Result<()> {
+        downloader.retain(&self.all_images())?;
+        let mut written = false;
+        if let Some((deltas, sha256)) = &self.deltas {
+            match write_deltas(deltas, sha256, downloader) {
+                Ok(()) => written = true,
+                Err(e) => {
+                    warn!(
+                        "Failed to apply delta update from {}, downloading full images instead: {}",
+                        self.from, e
+                    );
+                    // Don't keep deltas that failed their hash checks; they'd only fail again.
+                    for delta in &deltas.images() {
+                        downloader.remove(delta)?;
+                    }
+                }
+            }
+        }
+        if !written {
+            write_images(&self.images, downloader)?;
+        }
+        for image in self.all_images() {
+            downloader.remove(image)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the update's full images to the inactive partition set.
+fn write_images(images: &ImageSet, downloader: &Downloader) -> Result<()> {
+    downloader.progress().track(images, true);
+    // Download everything before touching the inactive partition set, so a failed download
+    // leaves it as it was.
+    let (root_path, boot_path, hash_path) = images.download(downloader)?;
+
     let mut gpt_state = State::load().context(error::PartitionTableRead)?;
     gpt_state.clear_inactive();
     // Write out the clearing of the inactive partition immediately, because we're about to
@@ -282,16 +554,141 @@ fn update_image(update: &Update, repository: &Repository) -> Result<()> {
 
     let inactive = gpt_state.inactive_set();
 
-    // TODO Do we want to recover the inactive side on an error?
-    write_target_to_disk(repository, &update.images.root, &inactive.root)?;
-    write_target_to_disk(repository, &update.images.boot, &inactive.boot)?;
-    write_target_to_disk(repository, &update.images.hash, &inactive.hash)?;
+    // If any image fails to write or verify, the inactive partition set stays marked invalid.
+    let progress = downloader.progress();
+    write_image_to_disk(&images.root, &root_path, &inactive.root, progress)?;
+    let boot_len = write_image_to_disk(&images.boot, &boot_path, &inactive.boot, progress)?;
+    write_image_to_disk(&images.hash, &hash_path, &inactive.hash, progress)?;
+    verity::check(&inactive.root, &inactive.hash, &inactive.boot, boot_len)?;
+
+    gpt_state.mark_inactive_valid();
+    gpt_state.write().context(error::PartitionTableWrite)?;
+    Ok(())
+}
+
+/// Rebuilds the update's images on the inactive partition set by applying deltas to the active
+/// partition set.  `sha256` holds the digests of the images the deltas should produce.
+fn write_deltas(deltas: &ImageSet, sha256: &Images, downloader: &Downloader) -> Result<()> {
+    downloader.progress().track(deltas, true);
+    let (root_path, boot_path, hash_path) = deltas.download(downloader)?;
+
+    let mut gpt_state = State::load().context(error::PartitionTableRead)?;
+    gpt_state.clear_inactive();
+    gpt_state.write().context(error::PartitionTableWrite)?;
+
+    let active = gpt_state.active_set();
+    let inactive = gpt_state.inactive_set();
+    write_delta_to_disk(
+        &deltas.root,
+        &root_path,
+        &active.root,
+        &inactive.root,
+        &sha256.root,
+        downloader.progress(),
+    )?;
+    let boot_len = write_delta_to_disk(
+        &deltas.boot,
+        &boot_path,
+        &active.boot,
+        &inactive.boot,
+        &sha256.boot,
+        downloader.progress(),
+    )?;
+    write_delta_to_disk(
+        &deltas.hash,
+        &hash_path,
+        &active.hash,
+        &inactive.hash,
+        &sha256.hash,
+        downloader.progress(),
+    )?;
+    verity::check(&inactive.root, &inactive.hash, &inactive.boot, boot_len)?;
 
     gpt_state.mark_inactive_valid();
     gpt_state.write().context(error::PartitionTableWrite)?;
     Ok(())
 }
 
+/// Saves an update downloaded by `updog prepare`.
+fn save_prepared(staged: &StagedUpdate) -> Result<()> {
+    let data = serde_json::to_vec_pretty(staged).context(error::UpdateSerialize)?;
+    fs::write(PREPARED_PATH, data).context(error::PreparedUpdate {
+        path: PREPARED_PATH,
+    })
+}
+
+/// Returns the update downloaded by `updog prepare`, if there is one that can be applied to the
+/// running version and matches the requested version, if any.  A prepared update that's since been
+/// denied or doesn't match the version lock is dropped.
+fn load_prepared(
+    current_version: &Version,
+    variant: &str,
+    force_version: Option<&Version>,
+    version_lock: Option<&Version>,
+    denylist: &[Version],
+) -> Result<Option<StagedUpdate>> {
+    let data = match fs::read(PREPARED_PATH) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).context(error::PreparedUpdate {
+                path: PREPARED_PATH,
+            })
+        }
+    };
+    let staged: StagedUpdate = match serde_json::from_slice(&data) {
+        Ok(staged) => staged,
+        Err(e) => {
+            warn!("Ignoring unreadable prepared update: {}", e);
+            return Ok(None);
+        }
+    };
+    if !staged.applies_to(current_version, variant, force_version) {
+        debug!(
+            "Ignoring prepared update from {} to {} {}",
+            staged.from, staged.update.variant, staged.update.version
+        );
+        return Ok(None);
+    }
+    if let Some(reason) = staged.skip_reason(version_lock, denylist) {
+        eprintln!(
+            "Dropping prepared update to {}: {}",
+            staged.update.version, reason
+        );
+        remove_prepared()?;
+        return Ok(None);
+    }
+    Ok(Some(staged))
+}
+
+/// Removes the record of a prepared update once it's been written.
+fn remove_prepared() -> Result<()> {
+    match fs::remove_file(PREPARED_PATH) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).context(error::PreparedUpdate {
+                path: PREPARED_PATH,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Finishes an update whose images have been written: for `update` and `auto-update`, marks the
+/// inactive partition set to boot next, rebooting if requested.
+fn finish_update(command: &Command, json: bool, reboot: bool, update: &Update) -> Result<()> {
+    if matches!(command, Command::Update | Command::AutoUpdate) {
+        update_flags()?;
+        if reboot {
+            initiate_reboot()?;
+        }
+    }
+    output(
+        json,
+        update,
+        &format!("Update applied: {}", fmt_full_version(update)),
+    )
+}
+
 fn update_flags() -> Result<()> {
     let mut gpt_state = State::load().context(error::PartitionTableRead)?;
     gpt_state
@@ -326,8 +723,9 @@ fn list_updates(
     json: bool,
     ignore_waves: bool,
     seed: u32,
+    denylist: &[Version],
 ) -> Result<()> {
-    let updates = applicable_updates(manifest, variant, ignore_waves, seed);
+    let updates = applicable_updates(manifest, variant, ignore_waves, seed, denylist);
     if json {
         println!(
             "{}",
@@ -497,8 +895,34 @@ fn main_inner() -> Result<()> {
 
     let command =
         serde_plain::from_str::<Command>(&arguments.subcommand).unwrap_or_else(|_| usage());
+    let reboot = arguments.reboot;
 
     let config = load_config()?;
+    match (command == Command::AutoUpdate, &config.mode) {
+        (true, UpdateMode::Automatic) => {
+            if !in_maintenance_window(config.maintenance_window.as_ref(), Utc::now()) {
+                eprintln!("Not updating: {}", SkipReason::OutsideWindow);
+                return Ok(());
+            }
+        }
+        (true, _) => {
+            eprintln!("Not updating: automatic updates are not enabled");
+            return Ok(());
+        }
+        (false, UpdateMode::Disabled)
+            if !matches!(
+                command,
+                Command::CheckUpdate
+                    | Command::Whats
+                    | Command::UpdateApply
+                    | Command::UpdateRevert
+            ) =>
+        {
+            return error::UpdatesDisabled.fail();
+        }
+        (false, _) => {}
+    }
+    let denylist = denied_versions(&config)?;
     set_https_proxy_environment_variables(&config.https_proxy, &config.no_proxy)?;
     let current_release = BottlerocketRelease::new().context(error::ReleaseVersion)?;
     let variant = arguments.variant.unwrap_or(current_release.variant_id);
@@ -507,46 +931,131 @@ fn main_inner() -> Result<()> {
     // the transport's HTTP calls.
     let mut query_params = transport.query_params();
     set_common_query_params(&mut query_params, &current_release.version_id, &config)?;
+    let (_, targets_url) = repository_urls(&config)?;
+    // Commands that download or write images report their progress for the update API.
+    let progress = if matches!(
+        command,
+        Command::Update | Command::UpdateImage | Command::AutoUpdate | Command::Prepare
+    ) {
+        Tracker::new(PROGRESS_PATH)
+    } else {
+        Tracker::default()
+    };
+    let downloader = Downloader::new(targets_url, query_params.clone(), DOWNLOAD_PATH, progress);
+
+    // An update downloaded by `updog prepare` is written from the download cache, without loading
+    // the repository, so it can be applied without network access.
+    if matches!(
+        command,
+        Command::Update | Command::UpdateImage | Command::AutoUpdate
+    ) {
+        // A requested version overrides the version lock.
+        let version_lock = match &arguments.force_version {
+            Some(_) => None,
+            None => parse_version_lock(&config.version_lock)?,
+        };
+        if let Some(staged) = load_prepared(
+            &current_release.version_id,
+            &variant,
+            arguments.force_version.as_ref(),
+            version_lock.as_ref(),
+            &denylist,
+        )? {
+            eprintln!("Starting prepared update to {}", staged.update.version);
+            staged.write(&downloader)?;
+            remove_prepared()?;
+            return finish_update(&command, arguments.json, reboot, &staged.update);
+        }
+    }
+
+    downloader.progress().phase(Phase::Metadata);
     let repository = load_repository(transport, &config)?;
     let manifest = load_manifest(&repository)?;
     let ignore_waves = arguments.ignore_waves || config.ignore_waves;
+    let policy = UpdatePolicy {
+        variant: &variant,
+        ignore_waves,
+        seed: config.seed,
+        version_lock: &config.version_lock,
+        denylist: &denylist,
+    };
     match command {
         Command::CheckUpdate | Command::Whats => {
             if arguments.all {
+                if config.mode == UpdateMode::Disabled {
+                    eprintln!("Not updating: {}", SkipReason::Disabled);
+                }
                 return list_updates(
                     &manifest,
                     &variant,
                     arguments.json,
                     ignore_waves,
                     config.seed,
+                    &denylist,
                 );
             }
 
-            let update = update_required(
+            // Explain why any newer updates won't be chosen.  A requested version overrides the
+            // version lock, and only that version matters.
+            let version_lock = match &arguments.force_version {
+                Some(_) => None,
+                None => parse_version_lock(&config.version_lock)?,
+            };
+            for (u, reason) in skipped_updates(
                 &manifest,
                 &current_release.version_id,
                 &variant,
                 ignore_waves,
                 config.seed,
-                &config.version_lock,
+                version_lock.as_ref(),
+                &denylist,
+            ) {
+                if arguments.force_version.is_none()
+                    || arguments.force_version.as_ref() == Some(&u.version)
+                {
+                    eprintln!("Skipped {}: {}", fmt_full_version(u), reason);
+                }
+            }
+
+            let update = update_required(
+                &manifest,
+                &current_release.version_id,
+                &policy,
                 arguments.force_version,
             )?
             .context(error::UpdateNotAvailable)?;
 
+            match config.mode {
+                UpdateMode::Automatic
+                    if !in_maintenance_window(config.maintenance_window.as_ref(), Utc::now()) =>
+                {
+                    eprintln!(
+                        "Automatic update to {} waits: {}",
+                        update.version,
+                        SkipReason::OutsideWindow
+                    );
+                }
+                UpdateMode::Disabled => {
+                    eprintln!(
+                        "Skipped {}: {}",
+                        fmt_full_version(update),
+                        SkipReason::Disabled
+                    );
+                }
+                UpdateMode::Automatic | UpdateMode::Managed => {}
+            }
             output(arguments.json, &update, &fmt_full_version(&update))?;
         }
-        Command::Update | Command::UpdateImage => {
+        Command::Update | Command::UpdateImage | Command::AutoUpdate => {
             if let Some(u) = update_required(
                 &manifest,
                 &current_release.version_id,
-                &variant,
-                ignore_waves,
-                config.seed,
-                &config.version_lock,
+                &policy,
                 arguments.force_version,
             )? {
                 eprintln!("Starting update to {}", u.version);
                 query_params.add("target", u.version.to_string());
+                downloader.progress().phase(Phase::Migrations);
                 retrieve_migrations(
                     &repository,
                     &mut query_params,
@@ -554,25 +1063,17 @@ fn main_inner() -> Result<()> {
                     u,
                     &current_release.version_id,
                 )?;
-                update_image(u, &repository)?;
-                if command == Command::Update {
-                    update_flags()?;
-                    if arguments.reboot {
-                        initiate_reboot()?;
-                    }
-                }
-                output(
-                    arguments.json,
-                    &u,
-                    &format!("Update applied: {}", fmt_full_version(&u)),
-                )?;
+                StagedUpdate::new(u, &repository, &current_release.version_id)?
+                    .write(&downloader)?;
+                remove_prepared()?;
+                finish_update(&command, arguments.json, reboot, u)?;
             } else {
                 eprintln!("No update required");
             }
         }
         Command::UpdateApply => {
             update_flags()?;
-            if arguments.reboot {
+            if reboot {
                 initiate_reboot()?;
             }
         }
@@ -580,7 +1081,31 @@ fn main_inner() -> Result<()> {
             revert_update_flags()?;
         }
         Command::Prepare => {
-            // TODO unimplemented
+            let u = update_required(
+                &manifest,
+                &current_release.version_id,
+                &policy,
+                arguments.force_version,
+            )?
+            .context(error::UpdateNotAvailable)?;
+            eprintln!("Preparing update to {}", u.version);
+            query_params.add("target", u.version.to_string());
+            downloader.progress().phase(Phase::Migrations);
+            retrieve_migrations(
+                &repository,
+                &mut query_params,
+                &manifest,
+                u,
+                &current_release.version_id,
+            )?;
+            let staged = StagedUpdate::new(u, &repository, &current_release.version_id)?;
+            staged.download(&downloader)?;
+            save_prepared(&staged)?;
+            output(
+                arguments.json,
+                u,
+                &format!("Update prepared: {}", fmt_full_version(u)),
+            )?;
         }
     }
 
@@ -673,6 +1198,10 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            local_repository: None,
+            mode: UpdateMode::Managed,
+            maintenance_window: None,
+            denylist: Vec::new(),
         };
         let version = Version::parse("1.18.0").unwrap();
         let variant = String::from("bottlerocket-aws-eks");
@@ -681,11 +1210,14 @@ mod tests {
             update_required(
                 &manifest,
                 &version,
-                &variant,
-                config.ignore_waves,
-                config.seed,
-                &config.version_lock,
-                None
+                &UpdatePolicy {
+                    variant: &variant,
+                    ignore_waves: config.ignore_waves,
+                    seed: config.seed,
+                    version_lock: &config.version_lock,
+                    denylist: &[],
+                },
+                None,
             )
             .unwrap()
             .is_none(),
@@ -706,6 +1238,10 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            local_repository: None,
+            mode: UpdateMode::Managed,
+            maintenance_window: None,
+            denylist: Vec::new(),
         };
 
         let version = Version::parse("0.1.3").unwrap();
@@ -713,10 +1249,13 @@ mod tests {
         let update = update_required(
             &manifest,
             &version,
-            &variant,
-            config.ignore_waves,
-            config.seed,
-            &config.version_lock,
+            &UpdatePolicy {
+                variant: &variant,
+                ignore_waves: config.ignore_waves,
+                seed: config.seed,
+                version_lock: &config.version_lock,
+                denylist: &[],
+            },
             None,
         )
         .unwrap();
@@ -744,6 +1283,10 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            local_repository: None,
+            mode: UpdateMode::Managed,
+            maintenance_window: None,
+            denylist: Vec::new(),
         };
 
         let version = Version::parse("1.10.0").unwrap();
@@ -751,10 +1294,13 @@ mod tests {
         let result = update_required(
             &manifest,
             &version,
-            &variant,
-            config.ignore_waves,
-            config.seed,
-            &config.version_lock,
+            &UpdatePolicy {
+                variant: &variant,
+                ignore_waves: config.ignore_waves,
+                seed: config.seed,
+                version_lock: &config.version_lock,
+                denylist: &[],
+            },
             None,
         )
         .unwrap();
@@ -770,6 +1316,76 @@ mod tests {
         }
     }
 
+    #[test]
+    fn denylist_and_skip_reasons() {
+        // With 1.15.0 denied, upgrading from 1.10.0 settles for 1.13.0.
+        let path = "tests/data/multiple.json";
+        let manifest: Manifest = serde_json::from_reader(File::open(path).unwrap()).unwrap();
+        let version = Version::parse("1.10.0").unwrap();
+        let variant = String::from("bottlerocket-aws-eks");
+        let denylist = vec![Version::parse("1.15.0").unwrap()];
+        let update = update_required(
+            &manifest,
+            &version,
+            &UpdatePolicy {
+                variant: &variant,
+                ignore_waves: false,
+                seed: 123,
+                version_lock: "latest",
+                denylist: &denylist,
+            },
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(update.version, Version::parse("1.13.0").unwrap());
+
+        let skipped = skipped_updates(&manifest, &version, &variant, false, 123, None, &denylist);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0.version, denylist[0]);
+        assert_eq!(skipped[0].1, SkipReason::Denylisted);
+
+        // A version lock passes over the other newer updates.
+        let lock = Version::parse("1.13.0").unwrap();
+        let skipped = skipped_updates(&manifest, &version, &variant, false, 123, Some(&lock), &[]);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0.version, Version::parse("1.15.0").unwrap());
+        assert_eq!(skipped[0].1, SkipReason::VersionLock(lock));
+    }
+
+    #[test]
+    fn maintenance_window() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        assert!(in_maintenance_window(None, at("2021-06-01T12:00:00Z")));
+
+        // An hour starting at 02:00 on Saturdays and Sundays.
+        let window = MaintenanceWindow {
+            start: CronSchedule::try_from("0 2 * * 6,0").unwrap(),
+            duration_minutes: MaintenanceWindowMinutes::try_from(60).unwrap(),
+        };
+        // 2021-06-05 was a Saturday.
+        assert!(in_maintenance_window(
+            Some(&window),
+            at("2021-06-05T02:00:00Z")
+        ));
+        assert!(in_maintenance_window(
+            Some(&window),
+            at("2021-06-06T02:59:59Z")
+        ));
+        assert!(!in_maintenance_window(
+            Some(&window),
+            at("2021-06-05T01:59:00Z")
+        ));
+        assert!(!in_maintenance_window(
+            Some(&window),
+            at("2021-06-05T03:00:00Z")
+        ));
+        assert!(!in_maintenance_window(
+            Some(&window),
+            at("2021-06-07T02:30:00Z")
+        ));
+    }
+
     #[test]
     fn force_update_version() {
         // A manifest with four updates; two valid, one which exceeds the max
@@ -786,6 +1402,10 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            local_repository: None,
+            mode: UpdateMode::Managed,
+            maintenance_window: None,
+            denylist: Vec::new(),
         };
 
         let version = Version::parse("1.10.0").unwrap();
@@ -794,10 +1414,13 @@ mod tests {
         let result = update_required(
             &manifest,
             &version,
-            &variant,
-            config.ignore_waves,
-            config.seed,
-            &config.version_lock,
+            &UpdatePolicy {
+                variant: &variant,
+                ignore_waves: config.ignore_waves,
+                seed: config.seed,
+                version_lock: &config.version_lock,
+                denylist: &[],
+            },
             Some(forced),
         )
         .unwrap();
@@ -856,6 +1479,7 @@ mod tests {
                 root: String::from("boot"),
                 hash: String::from("boot"),
             },
+            deltas: Vec::new(),
         };
 
         let current_version = Version::parse("1.0.0").unwrap();
@@ -869,6 +1493,10 @@ mod tests {
             ignore_waves: false,
             https_proxy: None,
             no_proxy: None,
+            local_repository: None,
+            mode: UpdateMode::Managed,
+            maintenance_window: None,
+            denylist: Vec::new(),
         };
 
         // Two waves; the 1st wave that starts immediately, and the final wave which starts in one hour
@@ -882,10 +1510,13 @@ mod tests {
             update_required(
                 &manifest,
                 &current_version,
-                &variant,
-                config.ignore_waves,
-                config.seed,
-                &config.version_lock,
+                &UpdatePolicy {
+                    variant: &variant,
+                    ignore_waves: config.ignore_waves,
+                    seed: config.seed,
+                    version_lock: &config.version_lock,
+                    denylist: &[],
+                },
                 None,
             )
             .unwrap()
@@ -897,10 +1528,13 @@ mod tests {
             update_required(
                 &manifest,
                 &current_version,
-                &variant,
-                config.ignore_waves,
-                2000,
-                &config.version_lock,
+                &UpdatePolicy {
+                    variant: &variant,
+                    ignore_waves: config.ignore_waves,
+                    seed: 2000,
+                    version_lock: &config.version_lock,
+                    denylist: &[],
+                },
                 None,
             )
             .unwrap()
@@ -908,4 +1542,136 @@ mod tests {
             "Later wave incorrectly sees update"
         );
     }
+
+    #[test]
+    fn local_repository_urls() {
+        let mut config = Config {
+            metadata_base_url: String::from("https://example.com/metadata/"),
+            targets_base_url: String::from("https://example.com/targets/"),
+            seed: 123,
+            version_lock: "latest".to_string(),
+            ignore_waves: false,
+            https_proxy: None,
+            no_proxy: None,
+            local_repository: None,
+            mode: UpdateMode::Managed,
+            maintenance_window: None,
+            denylist: Vec::new(),
+        };
+        let (metadata_url, _) = repository_urls(&config).unwrap();
+        assert_eq!(metadata_url.as_str(), "https://example.com/metadata/");
+
+        // A local repository replaces the base URLs.
+        config.local_repository = Some(PathBuf::from("/mnt/media/repo"));
+        let (metadata_url, targets_url) = repository_urls(&config).unwrap();
+        assert_eq!(metadata_url.as_str(), "file:///mnt/media/repo/metadata/");
+        assert_eq!(targets_url.as_str(), "file:///mnt/media/repo/targets/");
+
+        config.local_repository = Some(PathBuf::from("relative/repo"));
+        assert!(repository_urls(&config).is_err());
+    }
+
+    fn staged_update() -> StagedUpdate {
+        let image = |name: &str| {
+            serde_json::json!({
+                "name": name,
+                "filename": name,
+                "length": 1,
+                "sha256": [0],
+            })
+        };
+        serde_json::from_value(serde_json::json!({
+            "update": {
+                "variant": "aws-k8s-1.21",
+                "arch": TARGET_ARCH,
+                "version": "1.2.0",
+                "max_version": "1.2.0",
+                "waves": {},
+                "images": {"boot": "boot", "root": "root", "hash": "hash"},
+            },
+            "from": "1.1.0",
+            "images": {"root": image("root"), "boot": image("boot"), "hash": image("hash")},
+            "deltas": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn prepared_update_applies() {
+        let staged = staged_update();
+        let current = Version::parse("1.1.0").unwrap();
+        assert!(staged.applies_to(&current, "aws-k8s-1.21", None));
+        assert!(staged.applies_to(
+            &current,
+            "aws-k8s-1.21",
+            Some(&Version::parse("1.2.0").unwrap())
+        ));
+        // Not if another version was requested, or we're no longer running the version it was
+        // prepared on.
+        assert!(!staged.applies_to(
+            &current,
+            "aws-k8s-1.21",
+            Some(&Version::parse("1.3.0").unwrap())
+        ));
+        assert!(!staged.applies_to(&Version::parse("1.2.0").unwrap(), "aws-k8s-1.21", None));
+        assert!(!staged.applies_to(&current, "aws-dev", None));
+        // Only the full images are needed without deltas.
+        assert_eq!(staged.all_images().len(), 3);
+    }
+
+    #[test]
+    fn prepared_update_denylisted() {
+        let staged = staged_update();
+        assert_eq!(staged.skip_reason(None, &[]), None);
+        assert_eq!(
+            staged.skip_reason(None, &[Version::parse("1.2.0").unwrap()]),
+            Some(SkipReason::Denylisted)
+        );
+        // The denylist wins even if the version lock names the prepared version.
+        let lock = Version::parse("1.2.0").unwrap();
+        assert_eq!(
+            staged.skip_reason(Some(&lock), &[Version::parse("1.2.0").unwrap()]),
+            Some(SkipReason::Denylisted)
+        );
+    }
+
+    #[test]
+    fn prepared_update_version_lock() {
+        let staged = staged_update();
+        let lock = Version::parse("1.2.0").unwrap();
+        assert_eq!(staged.skip_reason(Some(&lock), &[]), None);
+        let lock = Version::parse("1.3.0").unwrap();
+        assert_eq!(
+            staged.skip_reason(Some(&lock), &[]),
+            Some(SkipReason::VersionLock(lock.clone()))
+        );
+    }
+
+    #[test]
+    fn update_mode() {
+        let config = |mode: &str| {
+            toml::from_str::<Config>(&format!(
+                r#"
+                metadata_base_url = "foo"
+                targets_base_url = "bar"
+                seed = 123
+                version_lock = "latest"
+                ignore_waves = false
+                {}
+                "#,
+                mode
+            ))
+        };
+        assert_eq!(config("").unwrap().mode, UpdateMode::Managed);
+        assert_eq!(
+            config(r#"mode = "automatic""#).unwrap().mode,
+            UpdateMode::Automatic
+        );
+        assert_eq!(
+            config(r#"mode = "disabled""#).unwrap().mode,
+            UpdateMode::Disabled
+        );
+        // A misspelled mode is an error rather than the default.
+        config(r#"mode = "automatc""#).unwrap_err();
+    }
 }
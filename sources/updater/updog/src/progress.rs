@@ -0,0 +1,181 @@
+//! The progress module keeps the progress file up to date as updog downloads and writes images.
+//! Progress is informational, so failing to save it is only logged.
+
+use crate::download::{Image, ImageSet};
+use chrono::Utc;
+use log::warn;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use update_metadata::progress::{Phase, Progress, TargetProgress};
+
+/// How often we save progress while bytes are moving.
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks progress and saves it to a file, if given one.  The file is removed when the tracker is
+/// dropped, so it only exists while updog is working.
+#[derive(Debug, Default)]
+pub(crate) struct Tracker {
+    path: Option<PathBuf>,
+    progress: RefCell<Progress>,
+    last_saved: Cell<Option<Instant>>,
+}
+
+impl Tracker {
+    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: Some(path.as_ref().to_path_buf()),
+            progress: RefCell::default(),
+            last_saved: Cell::default(),
+        }
+    }
+
+    pub(crate) fn phase(&self, phase: Phase) {
+        self.progress.borrow_mut().phase = phase;
+        self.save(true);
+    }
+
+    /// Starts tracking the images in the given set, replacing any images tracked before.  Images
+    /// that will be written to disk count twice, once for downloading and once for writing.
+    pub(crate) fn track(&self, images: &ImageSet, write: bool) {
+        let phases = [Phase::Root, Phase::Boot, Phase::Hash];
+        self.progress.borrow_mut().targets = images
+            .images()
+            .iter()
+            .zip(phases.iter())
+            .map(|(image, phase)| TargetProgress {
+                name: image.name().to_string(),
+                phase: *phase,
+                total: image.length(),
+                downloaded: 0,
+                written: if write { Some(0) } else { None },
+            })
+            .collect();
+        self.save(true);
+    }
+
+    /// Records that the first `downloaded` bytes of the image are in the download cache, `new` of
+    /// them from this run.
+    pub(crate) fn downloaded(&self, image: &Image, downloaded: u64, new: u64) {
+        self.update(image, new, |target| target.downloaded = downloaded);
+    }
+
+    /// Records that `new` more bytes of the image were written to disk.
+    pub(crate) fn written(&self, image: &Image, new: u64) {
+        self.update(image, new, |target| {
+            target.written = Some(target.written.unwrap_or(0) + new);
+        });
+    }
+
+    fn update<F>(&self, image: &Image, new: u64, f: F)
+    where
+        F: FnOnce(&mut TargetProgress),
+    {
+        {
+            let mut progress = self.progress.borrow_mut();
+            progress.transferred += new;
+            let target = progress
+                .targets
+                .iter_mut()
+                .find(|target| target.name == image.name());
+            if let Some(target) = target {
+                f(target);
+                let phase = target.phase;
+                progress.phase = phase;
+            }
+        }
+        self.save(false);
+    }
+
+    /// Saves progress to the file, if we have one.  Unless `force` is set, saves are limited to
+    /// one per `SAVE_INTERVAL`.
+    fn save(&self, force: bool) {
+        if !force && matches!(self.last_saved.get(), Some(t) if t.elapsed() < SAVE_INTERVAL) {
+            return;
+        }
+        if let Some(path) = &self.path {
+            let mut progress = self.progress.borrow_mut();
+            progress.updated = Utc::now();
+            if let Err(e) = progress.save(path) {
+                warn!("Unable to save update progress: {}", e);
+            }
+            self.last_saved.set(Some(Instant::now()));
+        }
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = fs::remove_file(path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Unable to remove '{}': {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
+
+/// Wraps a reader of a cached image, recording the bytes read as written to disk.
+pub(crate) struct WriteProgress<'a, R> {
+    inner: R,
+    image: &'a Image,
+    tracker: &'a Tracker,
+}
+
+impl<'a, R: Read> WriteProgress<'a, R> {
+    pub(crate) fn new(inner: R, image: &'a Image, tracker: &'a Tracker) -> Self {
+        Self {
+            inner,
+            image,
+            tracker,
+        }
+    }
+}
+
+impl<R: Read> Read for WriteProgress<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.tracker.written(self.image, n as u64);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+
+    #[test]
+    fn tracks_images() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("progress.json");
+        let image = |name: &str| Image::from_parts(name, 100, &Sha256::digest(name.as_bytes()));
+        let images = ImageSet {
+            root: image("root"),
+            boot: image("boot"),
+            hash: image("hash"),
+        };
+
+        let tracker = Tracker::new(&path);
+        tracker.phase(Phase::Migrations);
+        tracker.track(&images, true);
+        tracker.downloaded(&images.root, 100, 60);
+        tracker.downloaded(&images.boot, 100, 100);
+        tracker.downloaded(&images.hash, 100, 100);
+        tracker.written(&images.boot, 50);
+        // Force a save, since the last one may have been too recent.
+        tracker.phase(Phase::Boot);
+
+        let progress = Progress::load(&path).unwrap().unwrap();
+        assert_eq!(progress.phase, Phase::Boot);
+        assert_eq!(progress.transferred, 310);
+        assert!((progress.percent_complete() - 350.0 / 6.0).abs() < 0.001);
+
+        drop(tracker);
+        assert!(!path.exists());
+    }
+}
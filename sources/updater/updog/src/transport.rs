@@ -1,14 +1,15 @@
 use std::sync::{Arc, RwLock};
 
 use log::error;
-use tough::{HttpTransport, Transport, TransportError};
+use tough::{FilesystemTransport, HttpTransport, Transport, TransportError};
 use url::Url;
 
 /// A shared pointer to a list of query params that the transport will add to HTTP calls.
 #[derive(Debug, Clone, Default)]
 pub(crate) struct QueryParams(Arc<RwLock<Vec<(String, String)>>>);
 
-/// A `tough` `Transport` that allows us to add query parameters to HTTP calls.
+/// A `tough` `Transport` that allows us to add query parameters to HTTP calls.  `file://` URLs,
+/// used for local copies of the repository, are read from the filesystem without parameters.
 #[derive(Debug, Clone)]
 #[allow(clippy::module_name_repetitions)]
 pub(crate) struct HttpQueryTransport {
@@ -72,6 +73,9 @@ impl Transport for HttpQueryTransport {
         &self,
         url: Url,
     ) -> std::result::Result<Box<dyn std::io::Read + Send>, TransportError> {
+        if url.scheme() == "file" {
+            return FilesystemTransport.fetch(url);
+        }
         self.inner.fetch(self.parameters.add_params_to_url(url))
     }
 }
@@ -0,0 +1,271 @@
+//! The verity module checks that the images written to the inactive partition set agree with each
+//! other.  Each image was checked against the signed metadata on its own, but the root image is
+//! only usable if the dm-verity hash tree in the hash image was built from it, and if the boot
+//! image gives the kernel the root hash of that tree.  We rebuild the tree from the root image,
+//! compare it to the hash image, and look for its root hash in the boot image.
+
+use crate::download::open_uncached;
+use crate::error::{self, Result};
+use sha2::{Digest, Sha256};
+use snafu::{ensure, ResultExt};
+use std::convert::TryInto;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The magic bytes at the start of a dm-verity hash device's superblock.
+const MAGIC: &[u8] = b"verity\0\0";
+
+/// The size of the superblock at the start of the hash device.
+const SUPERBLOCK_SIZE: usize = 512;
+
+/// The parts of a dm-verity superblock needed to rebuild the hash tree.
+#[derive(Debug, PartialEq)]
+struct Superblock {
+    data_block_size: usize,
+    hash_block_size: usize,
+    data_blocks: u64,
+    salt: Vec<u8>,
+}
+
+impl Superblock {
+    /// Parses a version 1 superblock, as written by `veritysetup format`.
+    fn parse(buf: &[u8], path: &Path) -> Result<Self> {
+        ensure!(
+            buf.len() == SUPERBLOCK_SIZE && &buf[..MAGIC.len()] == MAGIC,
+            error::VerityHeader { path }
+        );
+        let u16_at = |i: usize| u16::from_le_bytes(buf[i..i + 2].try_into().unwrap());
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+        let unsupported = |reason: &'static str| error::VerityUnsupported { path, reason };
+
+        ensure!(u32_at(8) == 1, unsupported("version isn't 1"));
+        ensure!(u32_at(12) == 1, unsupported("hash type isn't 1"));
+        let algorithm = &buf[32..64];
+        ensure!(
+            algorithm.split(|b| *b == 0).next() == Some(b"sha256"),
+            unsupported("hash algorithm isn't sha256")
+        );
+        let data_block_size = u32_at(64) as usize;
+        let hash_block_size = u32_at(68) as usize;
+        for size in &[data_block_size, hash_block_size] {
+            ensure!(
+                size.is_power_of_two() && *size >= SUPERBLOCK_SIZE,
+                unsupported("block size isn't a power of two of at least 512 bytes")
+            );
+        }
+        let data_blocks = u64_at(72);
+        ensure!(data_blocks > 0, unsupported("no data blocks"));
+        let salt_size = u16_at(80) as usize;
+        ensure!(salt_size <= 256, unsupported("salt is too long"));
+
+        Ok(Self {
+            data_block_size,
+            hash_block_size,
+            data_blocks,
+            salt: buf[88..88 + salt_size].to_vec(),
+        })
+    }
+
+    /// Returns the digest of a data or hash block.
+    fn digest(&self, block: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.salt);
+        hasher.update(block);
+        hasher.finalize().to_vec()
+    }
+
+    /// Reads `count` blocks of `block_size` bytes from `reader` and returns a level of the hash
+    /// tree: their digests, packed into hash blocks and padded with zeroes.
+    fn hash_level(
+        &self,
+        reader: &mut dyn Read,
+        block_size: usize,
+        count: u64,
+    ) -> io::Result<Vec<u8>> {
+        let mut level = Vec::new();
+        let mut block = vec![0; block_size];
+        for _ in 0..count {
+            reader.read_exact(&mut block)?;
+            level.extend(self.digest(&block));
+        }
+        let partial = level.len() % self.hash_block_size;
+        if partial != 0 {
+            level.resize(level.len() + self.hash_block_size - partial, 0);
+        }
+        Ok(level)
+    }
+}
+
+/// Checks that the hash image at `hash_path` holds the dm-verity hash tree of the root image at
+/// `root_path`, and that the first `boot_len` bytes of the boot image at `boot_path` include the
+/// tree's root hash.  The images must already be synced to disk.
+pub(crate) fn check(
+    root_path: &Path,
+    hash_path: &Path,
+    boot_path: &Path,
+    boot_len: u64,
+) -> Result<()> {
+    let mut hash_file =
+        open_uncached(hash_path).context(error::OpenPartition { path: hash_path })?;
+    let mut buf = vec![0; SUPERBLOCK_SIZE];
+    hash_file
+        .read_exact(&mut buf)
+        .context(error::VerifyPartition { path: hash_path })?;
+    let superblock = Superblock::parse(&buf, hash_path)?;
+
+    // Build the tree from the bottom up, until a level fits in one block.
+    let root_file = open_uncached(root_path).context(error::OpenPartition { path: root_path })?;
+    let mut reader = BufReader::new(root_file);
+    let (data_block_size, hash_block_size) =
+        (superblock.data_block_size, superblock.hash_block_size);
+    let mut levels: Vec<Vec<u8>> = Vec::new();
+    let top = if superblock.data_blocks == 1 {
+        // With only one data block, there's no tree; the root hash is the block's digest.
+        let mut block = vec![0; data_block_size];
+        reader
+            .read_exact(&mut block)
+            .context(error::VerifyPartition { path: root_path })?;
+        block
+    } else {
+        let level = superblock
+            .hash_level(&mut reader, data_block_size, superblock.data_blocks)
+            .context(error::VerifyPartition { path: root_path })?;
+        levels.push(level);
+        while levels[levels.len() - 1].len() > hash_block_size {
+            let below = &levels[levels.len() - 1];
+            let count = (below.len() / hash_block_size) as u64;
+            let level = superblock
+                .hash_level(&mut below.as_slice(), hash_block_size, count)
+                .context(error::VerifyPartition { path: root_path })?;
+            levels.push(level);
+        }
+        levels[levels.len() - 1].clone()
+    };
+    let root_hash = hex::encode(superblock.digest(&top));
+
+    // The tree starts in the block after the superblock, with the top level first.
+    let expected: Vec<u8> = levels.into_iter().rev().flatten().collect();
+    let mut tree = vec![0; expected.len()];
+    hash_file
+        .seek(SeekFrom::Start(hash_block_size as u64))
+        .and_then(|_| hash_file.read_exact(&mut tree))
+        .context(error::VerifyPartition { path: hash_path })?;
+    ensure!(
+        tree == expected,
+        error::VerityHashTree {
+            hash_path,
+            root_path,
+        }
+    );
+
+    let boot_file = open_uncached(boot_path).context(error::OpenPartition { path: boot_path })?;
+    ensure!(
+        contains(boot_file.take(boot_len), root_hash.as_bytes())
+            .context(error::VerifyPartition { path: boot_path })?,
+        error::VerityRootHash {
+            path: boot_path,
+            root_hash,
+        }
+    );
+    Ok(())
+}
+
+/// Returns whether the data from `reader` contains `needle`.
+fn contains<R: Read>(mut reader: R, needle: &[u8]) -> io::Result<bool> {
+    let mut window = Vec::new();
+    let mut buf = vec![0; 1024 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        window.extend_from_slice(&buf[..n]);
+        if window.windows(needle.len()).any(|w| w == needle) {
+            return Ok(true);
+        }
+        // Keep enough of the end to find a match that spans reads.
+        let keep = std::cmp::min(window.len(), needle.len() - 1);
+        window.drain(..window.len() - keep);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Builds a hash image for `data` the way `veritysetup format` does, with 512-byte blocks so
+    /// the tree has a few levels, and returns it with the root hash.
+    fn hash_image(data: &[u8], salt: &[u8]) -> (Vec<u8>, String) {
+        let mut image = vec![0; SUPERBLOCK_SIZE];
+        image[..MAGIC.len()].copy_from_slice(MAGIC);
+        image[8..12].copy_from_slice(&1u32.to_le_bytes());
+        image[12..16].copy_from_slice(&1u32.to_le_bytes());
+        image[32..38].copy_from_slice(b"sha256");
+        image[64..68].copy_from_slice(&512u32.to_le_bytes());
+        image[68..72].copy_from_slice(&512u32.to_le_bytes());
+        image[72..80].copy_from_slice(&(data.len() as u64 / 512).to_le_bytes());
+        image[80..82].copy_from_slice(&u16::try_from(salt.len()).unwrap().to_le_bytes());
+        image[88..88 + salt.len()].copy_from_slice(salt);
+
+        let superblock = Superblock::parse(&image, Path::new("test")).unwrap();
+        let mut levels = vec![superblock
+            .hash_level(&mut &data[..], 512, superblock.data_blocks)
+            .unwrap()];
+        while levels.last().unwrap().len() > 512 {
+            let below = levels.last().unwrap();
+            let count = (below.len() / 512) as u64;
+            levels.push(superblock.hash_level(&mut &below[..], 512, count).unwrap());
+        }
+        let root_hash = hex::encode(superblock.digest(levels.last().unwrap()));
+        for level in levels.iter().rev() {
+            image.extend(level);
+        }
+        (image, root_hash)
+    }
+
+    #[test]
+    fn checks_images_agree() {
+        let dir = TempDir::new().unwrap();
+        // 16 hashes fit in a 512-byte block, so this takes three levels.
+        let data: Vec<u8> = (0..300 * 512)
+            .map(|i| u8::try_from(i / 512 % 256).unwrap())
+            .collect();
+        let (hash, root_hash) = hash_image(&data, b"pepper");
+        let boot = format!(
+            "linux /vmlinuz dm-mod.create=\"... sha256 {} 706570706572\"",
+            root_hash
+        );
+
+        let root_path = dir.path().join("root");
+        let hash_path = dir.path().join("hash");
+        let boot_path = dir.path().join("boot");
+        fs::write(&root_path, &data).unwrap();
+        fs::write(&hash_path, &hash).unwrap();
+        fs::write(&boot_path, format!("{}{}", "x".repeat(2048), boot)).unwrap();
+        let boot_len = 2048 + boot.len() as u64;
+        check(&root_path, &hash_path, &boot_path, boot_len).unwrap();
+        // Stale data after the written boot image doesn't count.
+        check(&root_path, &hash_path, &boot_path, 2048).unwrap_err();
+
+        // A hash image for other data doesn't match.
+        let (other_hash, _) = hash_image(&vec![7; 300 * 512], b"pepper");
+        fs::write(&hash_path, &other_hash).unwrap();
+        check(&root_path, &hash_path, &boot_path, boot_len).unwrap_err();
+
+        // A hash image without a superblock isn't accepted.
+        fs::write(&hash_path, &hash[SUPERBLOCK_SIZE..]).unwrap();
+        check(&root_path, &hash_path, &boot_path, boot_len).unwrap_err();
+    }
+
+    #[test]
+    fn finds_needle_across_reads() {
+        let data = b"0123456789";
+        let reader = io::Read::chain(&data[..4], &data[4..]);
+        assert!(contains(reader, b"345").unwrap());
+        assert!(!contains(&data[..], b"abc").unwrap());
+    }
+}